@@ -0,0 +1,116 @@
+public class ClassLoaders {
+
+    public static void main(String[] args) throws Exception {
+        BytesLoader first = new BytesLoader(BAR_VERSION_1);
+        BytesLoader second = new BytesLoader(BAR_VERSION_2);
+
+        System.out.println(first.findLoaded());
+
+        Class<?> firstBar = first.loadClass("com.foo.Bar");
+        Class<?> secondBar = second.loadClass("com.foo.Bar");
+
+        System.out.println(firstBar.getName() + " " + secondBar.getName());
+        System.out.println(firstBar == secondBar);
+        System.out.println(firstBar.getClassLoader() == first);
+        System.out.println(secondBar.getClassLoader() == second);
+        System.out.println(firstBar.isAssignableFrom(secondBar));
+        System.out.println(first.findLoaded() == firstBar);
+        System.out.println(first.loadClass("com.foo.Bar") == firstBar);
+
+        System.out.println(firstBar.newInstance());
+        System.out.println(secondBar.newInstance());
+
+        System.out.println(first.loadClass("java.lang.String") == String.class);
+        System.out.println(String.class.getClassLoader());
+
+        try {
+            first.define();
+        } catch (LinkageError error) {
+            System.out.println("Caught " + error.getClass().getName());
+        }
+
+        try {
+            first.defineOutOfBounds();
+        } catch (IndexOutOfBoundsException error) {
+            System.out.println("Caught IndexOutOfBoundsException");
+        }
+
+        try {
+            first.loadClass("com.foo.Baz");
+        } catch (ClassNotFoundException error) {
+            System.out.println("Caught " + error.getClass().getName() + ": " + error.getMessage());
+        }
+    }
+
+    /**
+     * A class loader that defines com.foo.Bar from a class file, and delegates every other
+     * class to the bootstrap loader.
+     */
+    static class BytesLoader extends ClassLoader {
+
+        private final byte[] bytes;
+
+        BytesLoader(byte[] bytes) {
+            super(null);
+            this.bytes = bytes;
+        }
+
+        @Override
+        protected Class<?> findClass(String name) throws ClassNotFoundException {
+            if (name.equals("com.foo.Bar")) {
+                return define();
+            }
+            throw new ClassNotFoundException(name);
+        }
+
+        Class<?> findLoaded() {
+            return findLoadedClass("com.foo.Bar");
+        }
+
+        Class<?> define() {
+            return defineClass("com.foo.Bar", bytes, 0, bytes.length);
+        }
+
+        Class<?> defineOutOfBounds() {
+            return defineClass("com.foo.Bar", bytes, 1, bytes.length);
+        }
+    }
+
+    /**
+     * com.foo.Bar, with toString() returning "Bar version 1".
+     */
+    static final byte[] BAR_VERSION_1 = {
+            -54, -2, -70, -66, 0, 0, 0, 52, 0, 14, 10, 0, 2, 0, 3, 7,
+            0, 4, 12, 0, 5, 0, 6, 1, 0, 16, 106, 97, 118, 97, 47, 108,
+            97, 110, 103, 47, 79, 98, 106, 101, 99, 116, 1, 0, 6, 60, 105, 110,
+            105, 116, 62, 1, 0, 3, 40, 41, 86, 8, 0, 8, 1, 0, 13, 66,
+            97, 114, 32, 118, 101, 114, 115, 105, 111, 110, 32, 49, 7, 0, 10, 1,
+            0, 11, 99, 111, 109, 47, 102, 111, 111, 47, 66, 97, 114, 1, 0, 4,
+            67, 111, 100, 101, 1, 0, 8, 116, 111, 83, 116, 114, 105, 110, 103, 1,
+            0, 20, 40, 41, 76, 106, 97, 118, 97, 47, 108, 97, 110, 103, 47, 83,
+            116, 114, 105, 110, 103, 59, 0, 33, 0, 9, 0, 2, 0, 0, 0, 0,
+            0, 2, 0, 1, 0, 5, 0, 6, 0, 1, 0, 11, 0, 0, 0, 17,
+            0, 1, 0, 1, 0, 0, 0, 5, 42, -73, 0, 1, -79, 0, 0, 0,
+            0, 0, 1, 0, 12, 0, 13, 0, 1, 0, 11, 0, 0, 0, 15, 0,
+            1, 0, 1, 0, 0, 0, 3, 18, 7, -80, 0, 0, 0, 0, 0, 0,
+    };
+
+    /**
+     * com.foo.Bar, with toString() returning "Bar version 2".
+     */
+    static final byte[] BAR_VERSION_2 = {
+            -54, -2, -70, -66, 0, 0, 0, 52, 0, 14, 10, 0, 2, 0, 3, 7,
+            0, 4, 12, 0, 5, 0, 6, 1, 0, 16, 106, 97, 118, 97, 47, 108,
+            97, 110, 103, 47, 79, 98, 106, 101, 99, 116, 1, 0, 6, 60, 105, 110,
+            105, 116, 62, 1, 0, 3, 40, 41, 86, 8, 0, 8, 1, 0, 13, 66,
+            97, 114, 32, 118, 101, 114, 115, 105, 111, 110, 32, 50, 7, 0, 10, 1,
+            0, 11, 99, 111, 109, 47, 102, 111, 111, 47, 66, 97, 114, 1, 0, 4,
+            67, 111, 100, 101, 1, 0, 8, 116, 111, 83, 116, 114, 105, 110, 103, 1,
+            0, 20, 40, 41, 76, 106, 97, 118, 97, 47, 108, 97, 110, 103, 47, 83,
+            116, 114, 105, 110, 103, 59, 0, 33, 0, 9, 0, 2, 0, 0, 0, 0,
+            0, 2, 0, 1, 0, 5, 0, 6, 0, 1, 0, 11, 0, 0, 0, 17,
+            0, 1, 0, 1, 0, 0, 0, 5, 42, -73, 0, 1, -79, 0, 0, 0,
+            0, 0, 1, 0, 12, 0, 13, 0, 1, 0, 11, 0, 0, 0, 15, 0,
+            1, 0, 1, 0, 0, 0, 3, 18, 7, -80, 0, 0, 0, 0, 0, 0,
+    };
+}
@@ -0,0 +1,21 @@
+public class ClassUnloading {
+
+    public static void main(String[] args) throws Exception {
+        ClassLoaders.BytesLoader kept = new ClassLoaders.BytesLoader(ClassLoaders.BAR_VERSION_1);
+        Object bar = kept.loadClass("com.foo.Bar").newInstance();
+
+        for (int i = 0; i < 10; i++) {
+            ClassLoaders.BytesLoader loader = new ClassLoaders.BytesLoader(ClassLoaders.BAR_VERSION_2);
+            System.out.println(loader.loadClass("com.foo.Bar").newInstance());
+        }
+
+        // Fill the old generation with garbage, too large for the nursery, so that a full
+        // collection collects the loaders above, and their classes are unloaded.
+        for (int i = 0; i < 64; i++) {
+            byte[] garbage = new byte[8 * 1024 * 1024];
+        }
+
+        System.out.println(bar);
+        System.out.println(kept.loadClass("com.foo.Bar") == bar.getClass());
+    }
+}
@@ -0,0 +1,86 @@
+public class Conversions {
+
+    public static void main(String[] args) {
+        longs();
+        floats();
+        doubles();
+    }
+
+    private static void longs() {
+        long max = Long.MAX_VALUE;
+        long min = Long.MIN_VALUE;
+        long l = 123456789123L;
+
+        // l2f
+        System.out.println((float) max);
+        System.out.println((float) min);
+        System.out.println((float) l);
+
+        // l2d
+        System.out.println((double) max);
+        System.out.println((double) min);
+        System.out.println((double) l);
+    }
+
+    private static void floats() {
+        float zero = 0.0f;
+        float nan = zero / zero;
+        float infinity = 1.0f / zero;
+        float f = -12345.678f;
+
+        // f2i
+        System.out.println((int) nan);
+        System.out.println((int) infinity);
+        System.out.println((int) -infinity);
+        System.out.println((int) 3.0e10f);
+        System.out.println((int) f);
+
+        // f2l
+        System.out.println((long) nan);
+        System.out.println((long) infinity);
+        System.out.println((long) -infinity);
+        System.out.println((long) 1.0e19f);
+        System.out.println((long) -1.0e19f);
+        System.out.println((long) f);
+        System.out.println((long) -0.5f);
+
+        // f2d
+        System.out.println((double) nan);
+        System.out.println((double) infinity);
+        System.out.println((double) -zero);
+        System.out.println((double) f);
+    }
+
+    private static void doubles() {
+        double zero = 0.0;
+        double nan = zero / zero;
+        double infinity = 1.0 / zero;
+        double d = 98765.4321;
+
+        // d2i
+        System.out.println((int) nan);
+        System.out.println((int) infinity);
+        System.out.println((int) -infinity);
+        System.out.println((int) 1.0e10);
+        System.out.println((int) -1.0e10);
+        System.out.println((int) -d);
+
+        // d2l
+        System.out.println((long) nan);
+        System.out.println((long) infinity);
+        System.out.println((long) -infinity);
+        System.out.println((long) 1.0e19);
+        System.out.println((long) -1.0e19);
+        System.out.println((long) d);
+        System.out.println((long) -0.0);
+
+        // d2f
+        System.out.println((float) nan);
+        System.out.println((float) infinity);
+        System.out.println((float) -zero);
+        System.out.println((float) 1.0e40);
+        System.out.println((float) -1.0e40);
+        System.out.println((float) 1.0e-50);
+        System.out.println((float) d);
+    }
+}
@@ -0,0 +1,50 @@
+public class Finalization {
+
+    private static int finalized;
+    private static Finalization resurrected;
+
+    private final int id;
+
+    private Finalization(int id) {
+        this.id = id;
+    }
+
+    /**
+     * Make objects with finalizers, and allocate garbage until they've all been collected and
+     * finalized, one of them resurrecting itself.
+     */
+    public static void main(String[] args) throws Exception {
+        for (int i = 0; i < 10; i++) {
+            new Finalization(i);
+        }
+
+        for (int i = 0; i < 100 && finalized() < 10; i++) {
+            garbage();
+            System.runFinalization();
+            Thread.sleep(10);
+        }
+
+        System.out.println("Finalized " + finalized());
+        System.out.println("Resurrected " + resurrected.id);
+    }
+
+    @Override
+    protected void finalize() {
+        synchronized (Finalization.class) {
+            finalized++;
+            if (id == 3) {
+                resurrected = this;
+            }
+        }
+    }
+
+    private static synchronized int finalized() {
+        return finalized;
+    }
+
+    private static void garbage() {
+        for (int i = 0; i < 32; i++) {
+            byte[] garbage = new byte[64 * 1024];
+        }
+    }
+}
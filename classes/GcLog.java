@@ -0,0 +1,14 @@
+public class GcLog {
+
+    /**
+     * Fill the nursery until it's collected, then collect the whole heap, for the collections
+     * to be logged.
+     */
+    public static void main(String[] args) {
+        for (int i = 0; i < 256; i++) {
+            byte[] garbage = new byte[64 * 1024];
+        }
+        System.gc();
+        System.out.println("Done");
+    }
+}
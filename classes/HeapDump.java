@@ -0,0 +1,27 @@
+import com.sun.management.HotSpotDiagnosticMXBean;
+import java.io.IOException;
+import java.lang.management.ManagementFactory;
+
+public class HeapDump {
+
+    private static final Retained retained = new Retained();
+
+    /**
+     * Dump the heap to the file named by the argument, which is then there to dump it to again.
+     */
+    public static void main(String[] args) throws Exception {
+        HotSpotDiagnosticMXBean diagnostic = ManagementFactory.getPlatformMXBean(HotSpotDiagnosticMXBean.class);
+        diagnostic.dumpHeap(args[0], true);
+        System.out.println("Dumped heap");
+
+        try {
+            diagnostic.dumpHeap(args[0], true);
+        } catch (IOException error) {
+            System.out.println("Caught " + error.getClass().getName());
+        }
+    }
+
+    private static class Retained {
+        private final int[] values = new int[16];
+    }
+}
@@ -0,0 +1,13 @@
+/**
+ * Packaged in {@code JarApp.jar}, with {@link JarLib} in {@code lib/JarLib.jar} on the manifest
+ * class path.
+ */
+public class JarApp {
+
+    public static void main(String[] args) {
+        System.out.println(JarLib.greeting());
+        for (String arg : args) {
+            System.out.println(arg);
+        }
+    }
+}
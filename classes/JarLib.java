@@ -0,0 +1,9 @@
+/**
+ * Packaged in {@code lib/JarLib.jar}.
+ */
+public class JarLib {
+
+    public static String greeting() {
+        return "Hello from JarLib";
+    }
+}
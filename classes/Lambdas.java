@@ -0,0 +1,72 @@
+import java.util.ArrayList;
+import java.util.List;
+import java.util.function.BiFunction;
+import java.util.function.Consumer;
+import java.util.function.Function;
+import java.util.function.IntBinaryOperator;
+import java.util.function.Supplier;
+import java.util.function.ToIntFunction;
+
+public class Lambdas {
+
+    public static void main(String[] args) {
+        // Non capturing lambda
+        Runnable runnable = () -> System.out.println("Hello from a lambda");
+        runnable.run();
+
+        // Bound method reference
+        List<String> list = new ArrayList<>();
+        list.add("one");
+        list.add("two");
+        list.add("three");
+        list.forEach(System.out::println);
+
+        // Capturing lambda
+        String prefix = "captured ";
+        Consumer<String> consumer = s -> System.out.println(prefix + s);
+        consumer.accept("value");
+
+        // Capturing primitives
+        int base = 10;
+        IntBinaryOperator add = (a, b) -> a + b + base;
+        System.out.println(add.applyAsInt(1, 2));
+
+        // Boxed lambda
+        Function<Integer, Integer> square = x -> x * x;
+        System.out.println(square.apply(7));
+
+        // Default method
+        Function<Integer, Integer> plusOne = x -> x + 1;
+        System.out.println(square.andThen(plusOne).apply(5));
+
+        // Constructor reference
+        Supplier<List<String>> supplier = ArrayList::new;
+        System.out.println(supplier.get().size());
+
+        // Unbound method reference, boxing the result
+        Function<String, Integer> length = String::length;
+        System.out.println(length.apply("robusta"));
+
+        // Static method reference, unboxing the arguments
+        BiFunction<Integer, Integer, Integer> max = Math::max;
+        System.out.println(max.apply(3, 9));
+
+        // Static method reference, without boxing
+        ToIntFunction<String> parse = Integer::parseInt;
+        System.out.println(parse.applyAsInt("42"));
+
+        // Capturing this
+        new Lambdas(5).run();
+    }
+
+    private final int value;
+
+    private Lambdas(int value) {
+        this.value = value;
+    }
+
+    private void run() {
+        Supplier<Integer> supplier = () -> value * 2;
+        System.out.println(supplier.get());
+    }
+}
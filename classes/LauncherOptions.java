@@ -0,0 +1,10 @@
+public class LauncherOptions {
+
+    public static void main(String[] args) {
+        System.out.println(System.getProperty("robusta.test"));
+
+        boolean enabled = false;
+        assert enabled = true;
+        System.out.println("Assertions " + (enabled ? "enabled" : "disabled"));
+    }
+}
@@ -0,0 +1,74 @@
+public class LoadErrors {
+
+    public static void main(String[] args) {
+        try {
+            Class.forName("LoadErrors$DoesNotExist");
+        } catch (ClassNotFoundException error) {
+            System.out.println("Caught " + error.getClass().getName() + ": " + error.getMessage());
+        }
+
+        try {
+            System.out.println(Missing.value());
+        } catch (NoClassDefFoundError error) {
+            System.out.println("Caught " + error.getClass().getName() + ": " + error.getMessage());
+        }
+
+        try {
+            System.out.println(Truncated.value());
+        } catch (ClassFormatError error) {
+            System.out.println("Caught " + error.getClass().getName());
+        }
+
+        try {
+            System.out.println(Malformed.value());
+        } catch (ClassFormatError error) {
+            System.out.println("Caught " + error.getClass().getName() + ": " + error.getMessage());
+        }
+
+        try {
+            System.out.println(Unsupported.value());
+        } catch (UnsupportedClassVersionError error) {
+            System.out.println("Caught " + error.getClass().getName());
+        }
+    }
+
+    /**
+     * The compiled class file is deleted.
+     */
+    static class Missing {
+
+        static int value() {
+            return 1;
+        }
+    }
+
+    /**
+     * The compiled class file is cut short.
+     */
+    static class Truncated {
+
+        static int value() {
+            return 2;
+        }
+    }
+
+    /**
+     * The compiled class file is edited to name itself with a constant that isn't a class.
+     */
+    static class Malformed {
+
+        static int value() {
+            return 4;
+        }
+    }
+
+    /**
+     * The compiled class file is edited to have a major version newer than this JVM supports.
+     */
+    static class Unsupported {
+
+        static int value() {
+            return 3;
+        }
+    }
+}
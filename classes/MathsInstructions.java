@@ -2,6 +2,8 @@ public class MathsInstructions {
 
     public static void main(String[] args) {
         System.out.println(ints());
+        System.out.println(floats());
+        System.out.println(doubles());
     }
 
     private static int ints() {
@@ -152,4 +154,170 @@ public class MathsInstructions {
 
         return i;
     }
+
+    private static float floats() {
+        // fconst_2
+        float f = 2.0f;
+        System.out.println(f);
+
+        // ldc
+        float g = 1.5f;
+        System.out.println(g);
+
+        // fadd
+        f += g;
+        System.out.println(f);
+
+        // fsub
+        f -= 5.25f;
+        System.out.println(f);
+
+        // fmul
+        f *= 3.0f;
+        System.out.println(f);
+
+        // fdiv
+        f /= 0.5f;
+        System.out.println(f);
+
+        // frem truncates towards zero
+        f %= 4.0f;
+        System.out.println(f);
+
+        // frem positive
+        g = 7.5f;
+        g %= 2.0f;
+        System.out.println(g);
+
+        // fneg
+        f = -f;
+        System.out.println(f);
+
+        // fneg 0
+        float zero = 0.0f;
+        float negativeZero = -zero;
+        System.out.println(negativeZero);
+
+        // fdiv by signed zeros
+        System.out.println(1.0f / zero);
+        System.out.println(1.0f / negativeZero);
+
+        // fdiv 0 / 0
+        float nan = zero / zero;
+        System.out.println(nan);
+
+        // frem by 0
+        System.out.println(f % zero);
+
+        // frem of infinity
+        System.out.println((1.0f / zero) % 2.0f);
+
+        // frem by infinity
+        System.out.println(f % (1.0f / zero));
+
+        // fneg NaN
+        System.out.println(-nan);
+
+        // fcmpl, fcmpg with NaN
+        System.out.println(nan < 1.0f);
+        System.out.println(nan > 1.0f);
+        System.out.println(nan == nan);
+        System.out.println(zero == negativeZero);
+
+        // fcmpl, fcmpg
+        System.out.println(f < g);
+        System.out.println(f > g);
+
+        // fastore, faload
+        float[] floats = new float[3];
+        floats[0] = f;
+        floats[1] = g;
+        floats[2] = floats[0] * floats[1];
+        System.out.println(floats[2]);
+
+        return floats[2];
+    }
+
+    private static double doubles() {
+        // dconst_1
+        double d = 1.0;
+        System.out.println(d);
+
+        // ldc2_w
+        double e = 2.25;
+        System.out.println(e);
+
+        // dadd
+        d += e;
+        System.out.println(d);
+
+        // dsub
+        d -= 10.5;
+        System.out.println(d);
+
+        // dmul
+        d *= 4.0;
+        System.out.println(d);
+
+        // ddiv
+        d /= 3.0;
+        System.out.println(d);
+
+        // drem truncates towards zero
+        d %= 2.5;
+        System.out.println(d);
+
+        // drem positive
+        e = 10.75;
+        e %= 3.0;
+        System.out.println(e);
+
+        // dneg
+        d = -d;
+        System.out.println(d);
+
+        // dneg 0
+        double zero = 0.0;
+        double negativeZero = -zero;
+        System.out.println(negativeZero);
+
+        // ddiv by signed zeros
+        System.out.println(1.0 / zero);
+        System.out.println(-1.0 / negativeZero);
+
+        // ddiv 0 / 0
+        double nan = zero / zero;
+        System.out.println(nan);
+
+        // drem by 0
+        System.out.println(d % zero);
+
+        // drem of infinity
+        System.out.println((1.0 / zero) % 2.0);
+
+        // drem by infinity
+        System.out.println(d % (1.0 / zero));
+
+        // dneg NaN
+        System.out.println(-nan);
+
+        // dcmpl, dcmpg with NaN
+        System.out.println(nan < 1.0);
+        System.out.println(nan > 1.0);
+        System.out.println(nan == nan);
+        System.out.println(zero == negativeZero);
+
+        // dcmpl, dcmpg
+        System.out.println(d < e);
+        System.out.println(d > e);
+
+        // dastore, daload
+        double[] doubles = new double[3];
+        doubles[0] = d;
+        doubles[1] = e;
+        doubles[2] = doubles[0] / doubles[1];
+        System.out.println(doubles[2]);
+
+        return doubles[2];
+    }
 }
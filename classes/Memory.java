@@ -0,0 +1,48 @@
+import java.lang.management.GarbageCollectorMXBean;
+import java.lang.management.ManagementFactory;
+import java.lang.management.MemoryUsage;
+import java.util.ArrayList;
+import java.util.List;
+
+public class Memory {
+
+    private static byte[] retained;
+
+    /**
+     * Check the sizes of the heap reported by `Runtime` and the memory bean against each other,
+     * and count the collections of each garbage collector bean.
+     */
+    public static void main(String[] args) {
+        Runtime runtime = Runtime.getRuntime();
+        System.out.println("Max " + runtime.maxMemory() / (1024 * 1024) + "M");
+        System.out.println("Total within max " + (runtime.totalMemory() <= runtime.maxMemory()));
+        System.out.println("Free within total " + (runtime.freeMemory() <= runtime.totalMemory()));
+
+        retained = new byte[2 * 1024 * 1024];
+        long before = used();
+        retained = null;
+        System.gc();
+        System.out.println("Freed " + (used() < before));
+
+        for (int i = 0; i < 64; i++) {
+            byte[] garbage = new byte[64 * 1024];
+        }
+
+        MemoryUsage heap = ManagementFactory.getMemoryMXBean().getHeapMemoryUsage();
+        System.out.println("Heap max " + (heap.getMax() == runtime.maxMemory()));
+        System.out.println("Heap used within committed " + (heap.getUsed() <= heap.getCommitted()));
+
+        List<String> names = new ArrayList<>();
+        for (GarbageCollectorMXBean collector : ManagementFactory.getGarbageCollectorMXBeans()) {
+            names.add(collector.getName());
+            System.out.println(collector.getName() + " collected " + (collector.getCollectionCount() > 0)
+                    + " in " + (collector.getCollectionTime() >= 0));
+        }
+        System.out.println("Collectors " + names);
+    }
+
+    private static long used() {
+        Runtime runtime = Runtime.getRuntime();
+        return runtime.totalMemory() - runtime.freeMemory();
+    }
+}
@@ -1,17 +1,32 @@
 public class MultiThreadedOutOfMemory {
 
+    static int caught = 0;
+
+    static synchronized void caught() {
+        caught++;
+    }
+
     static class OOMThread extends Thread {
 
         public void run() {
             for (int i = 0; i < 1024; i++) {
-                char[] chars = new char[9999999];
+                char[] chars = new char[99999];
+            }
+
+            try {
+                char[][] leaked = new char[1024][];
+                for (int i = 0; i < leaked.length; i++) {
+                    leaked[i] = new char[99999];
+                }
+            } catch (OutOfMemoryError error) {
+                caught();
             }
         }
     }
 
     /**
-     * Attempt to allocate 190Gb of heap memory, across 10
-     * threads, requiring multi-threaded garbage collection!
+     * Allocate 2GB of heap memory across 10 threads, requiring multi-threaded garbage
+     * collection, and then have every thread keep hold of its memory until it runs out!
      */
     public static void main(String[] args) {
         Thread[] threads = new Thread[10];
@@ -31,5 +46,7 @@ public class MultiThreadedOutOfMemory {
                 e.printStackTrace();
             }
         }
+
+        System.out.println("Caught " + caught + " OutOfMemoryErrors");
     }
-}
\ No newline at end of file
+}
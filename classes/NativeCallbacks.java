@@ -0,0 +1,65 @@
+import java.security.AccessController;
+import java.security.PrivilegedAction;
+
+public class NativeCallbacks {
+
+    private static int[] kept;
+
+    /**
+     * Call natives that call back into Java, allocating more than the nursery holds in each
+     * callback, so the garbage collector moves the objects the natives hold while they wait.
+     */
+    public static void main(String[] args) throws Exception {
+        kept = filled(7);
+
+        int survived = 0;
+        for (int i = 0; i < 20; i++) {
+            int[] result = AccessController.doPrivileged(new Allocate(i));
+            Allocated allocated = Allocated.class.newInstance();
+
+            if (result[100] == i && allocated.values[100] == 42) {
+                survived++;
+            }
+        }
+
+        System.out.println("Survived " + survived + " callbacks");
+        System.out.println("Kept " + kept[100]);
+    }
+
+    private static int[] filled(int value) {
+        int[] values = new int[1000];
+        for (int i = 0; i < values.length; i++) {
+            values[i] = value;
+        }
+        return values;
+    }
+
+    private static void garbage() {
+        for (int i = 0; i < 32; i++) {
+            byte[] garbage = new byte[64 * 1024];
+        }
+    }
+
+    private static class Allocate implements PrivilegedAction<int[]> {
+        private final int value;
+
+        Allocate(int value) {
+            this.value = value;
+        }
+
+        public int[] run() {
+            int[] values = filled(value);
+            garbage();
+            return values;
+        }
+    }
+
+    public static class Allocated {
+        private final int[] values;
+
+        public Allocated() {
+            values = filled(42);
+            garbage();
+        }
+    }
+}
@@ -1,10 +1,27 @@
 public class OutOfMemory {
 
+    /**
+     * Allocate 200MB of heap memory, many times the size of the heap it's run with, requiring
+     * garbage collection, and then keep hold of it, running out of memory!
+     */
     public static void main(String[] args) {
-        int heapSize = 1280 * 1024 * 1024;
-        int arrayLength = 1280 * 1024;
         for (int i = 0; i < 1024; i++) {
-            char[] chars = new char[9999999];
+            char[] chars = new char[99999];
+        }
+
+        try {
+            leak();
+        } catch (OutOfMemoryError error) {
+            System.out.println("Caught " + error);
+        }
+
+        leak();
+    }
+
+    private static void leak() {
+        char[][] leaked = new char[1024][];
+        for (int i = 0; i < leaked.length; i++) {
+            leaked[i] = new char[99999];
         }
     }
-}
\ No newline at end of file
+}
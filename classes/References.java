@@ -0,0 +1,54 @@
+import java.lang.ref.PhantomReference;
+import java.lang.ref.Reference;
+import java.lang.ref.ReferenceQueue;
+import java.lang.ref.SoftReference;
+import java.lang.ref.WeakReference;
+import java.util.WeakHashMap;
+
+public class References {
+
+    /**
+     * Make weak, soft and phantom references, and allocate enough garbage to collect the whole
+     * heap a few times, so the references to unreachable objects are cleared and enqueued by the
+     * reference handler thread.
+     */
+    public static void main(String[] args) throws Exception {
+        ReferenceQueue<Object> queue = new ReferenceQueue<>();
+        Object strong = new Object();
+        WeakReference<Object> reachable = new WeakReference<>(strong, queue);
+        WeakReference<Object> weak = new WeakReference<>(new int[1000], queue);
+        PhantomReference<Object> phantom = new PhantomReference<>(new int[1000], queue);
+        SoftReference<int[]> soft = new SoftReference<>(new int[1000]);
+
+        WeakHashMap<Object, String> cache = new WeakHashMap<>();
+        Object key = new Object();
+        cache.put(key, "kept");
+        for (int i = 0; i < 100; i++) {
+            cache.put(new Object(), "value " + i);
+        }
+
+        garbage();
+
+        int enqueued = 0;
+        for (Reference<?> reference; (reference = queue.remove(5000)) != null; ) {
+            if (reference == weak || reference == phantom) {
+                enqueued++;
+            }
+            if (enqueued == 2) {
+                break;
+            }
+        }
+
+        System.out.println("Enqueued " + enqueued + " references");
+        System.out.println("Reachable " + (reachable.get() == strong));
+        System.out.println("Cleared " + (weak.get() == null));
+        System.out.println("Soft " + (soft.get() != null));
+        System.out.println("Cached " + cache.size() + " " + cache.get(key));
+    }
+
+    private static void garbage() {
+        for (int i = 0; i < 256; i++) {
+            byte[] garbage = new byte[64 * 1024];
+        }
+    }
+}
@@ -0,0 +1,111 @@
+public class Switches {
+
+    enum Colour {
+        RED, GREEN, BLUE, YELLOW
+    }
+
+    public static void main(String[] args) {
+        for (int i = -2; i < 7; i++) {
+            System.out.println(denseInt(i));
+        }
+
+        for (int i = -1000; i <= 1000000; i *= -10) {
+            System.out.println(sparseInt(i));
+        }
+
+        char[] chars = {'a', 'e', 'x', 'o', 'z', 'U'};
+        for (char c : chars) {
+            System.out.println(character(c));
+        }
+
+        for (Colour colour : Colour.values()) {
+            System.out.println(enumeration(colour));
+        }
+
+        String[] strings = {"apple", "banana", "cherry", "Aa", "BB", "durian"};
+        for (String s : strings) {
+            System.out.println(string(s));
+        }
+    }
+
+    private static String denseInt(int i) {
+        switch (i) {
+            case -1:
+                return "minus one";
+            case 0:
+                return "zero";
+            case 1:
+                return "one";
+            case 2:
+                return "two";
+            case 3:
+            case 4:
+                return "three or four";
+            case 5:
+                return "five";
+            default:
+                return "other";
+        }
+    }
+
+    private static String sparseInt(int i) {
+        switch (i) {
+            case -1000:
+                return "minus one thousand";
+            case 100000:
+                return "one hundred thousand";
+            case 1000000:
+                return "one million";
+            default:
+                return "other";
+        }
+    }
+
+    private static int character(char c) {
+        switch (c) {
+            case 'a':
+            case 'e':
+            case 'i':
+            case 'o':
+            case 'u':
+                return 1;
+            case 'x':
+            case 'y':
+            case 'z':
+                return 2;
+            default:
+                return 0;
+        }
+    }
+
+    private static String enumeration(Colour colour) {
+        switch (colour) {
+            case RED:
+                return "red";
+            case GREEN:
+                return "green";
+            case BLUE:
+                return "blue";
+            default:
+                return "unknown";
+        }
+    }
+
+    private static int string(String s) {
+        // "Aa" and "BB" share a hash code
+        switch (s) {
+            case "apple":
+                return 1;
+            case "banana":
+                return 2;
+            case "cherry":
+                return 3;
+            case "Aa":
+                return 4;
+            case "BB":
+                return 5;
+            default:
+                return -1;
+        }
+    }
+}
@@ -0,0 +1,58 @@
+public class VerifyErrors {
+
+    public static void main(String[] args) {
+        try {
+            System.out.println(Invalid.value());
+        } catch (VerifyError error) {
+            System.out.println("Caught " + error.getClass().getName());
+        }
+
+        try {
+            System.out.println(Old.value());
+        } catch (VerifyError error) {
+            System.out.println("Caught " + error.getClass().getName());
+        }
+
+        try {
+            System.out.println(new Implementation().value());
+        } catch (VerifyError error) {
+            System.out.println("Caught " + error.getClass().getName());
+        }
+    }
+
+    /**
+     * The compiled class file is edited to give {@code value} a max stack size of 0, so it fails
+     * verification, but otherwise runs as normal.
+     */
+    static class Invalid {
+
+        static int value() {
+            return 1;
+        }
+    }
+
+    /**
+     * The compiled class file is edited like {@link Invalid}, and to have the major version of
+     * Java 5, so it has to be verified without stack map frames.
+     */
+    static class Old {
+
+        static int value() {
+            return 2;
+        }
+    }
+
+    /**
+     * The compiled class file is edited like {@link Invalid}, so the interface fails verification
+     * when a class implementing it is linked.
+     */
+    interface Defaults {
+
+        default int value() {
+            return 3;
+        }
+    }
+
+    static class Implementation implements Defaults {
+    }
+}
@@ -1,17 +1,17 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::process::id;
-use std::sync::mpsc::channel;
+use std::thread::scope;
 
-use criterion::{BatchSize, BenchmarkId, black_box, Criterion, criterion_group, criterion_main};
-use nohash_hasher::BuildNoHashHasher;
+use criterion::{BatchSize, BenchmarkId, black_box, Criterion, criterion_group, criterion_main, Throughput};
 use rand::{random, Rng, thread_rng};
-use robusta::heap::garbage_collector::CopyCollector;
+use robusta::heap::garbage_collector::GenerationalCollector;
+use robusta::heap::reference::SoftPolicy;
 
 use robusta::java::{Int, MethodType, Reference, Value};
 use robusta::loader::{ClassFileLoader, Loader};
-use robusta::method_area::{Class, Method};
+use robusta::method_area::{Class, Method, Primitive};
 use robusta::method_area::const_pool::MethodKey;
 use robusta::runtime::Runtime;
 
@@ -112,35 +112,74 @@ fn allocation(c: &mut Criterion) {
     }
 }
 
-fn copy_collection(c: &mut Criterion) {
+fn multi_threaded_allocation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Multi-threaded Heap Allocation");
+    let runtime = Runtime::new();
+
+    // The same number of objects are allocated, split between the threads.
+    const OBJECTS: usize = 64_000;
+    group.throughput(Throughput::Elements(OBJECTS as u64));
+
+    for threads in [1, 2, 4, 8] {
+        group.bench_with_input(BenchmarkId::from_parameter(threads), &threads, |b, threads| {
+            b.iter_batched(
+                || {
+                    // Empty the heap of the objects from the last iteration.
+                    let mut collector = GenerationalCollector::new();
+                    collector.full_gc(&runtime, &mut [], SoftPolicy::LeastRecentlyUsed);
+                },
+                |_| {
+                    scope(|s| {
+                        for _ in 0..*threads {
+                            s.spawn(|| {
+                                let object_class = runtime.method_area.load_class("java.lang.Object");
+                                for idx in 0..(OBJECTS / threads) {
+                                    if idx % 2 == 0 {
+                                        black_box(runtime.heap.new_object(object_class.deref()));
+                                    } else {
+                                        black_box(runtime.heap.new_array(Class::Primitive(Primitive::Int), Int(8)));
+                                    }
+                                }
+                            });
+                        }
+                    });
+                },
+                BatchSize::PerIteration
+            );
+        });
+    }
+}
+
+fn minor_collection(c: &mut Criterion) {
     let runtime = Runtime::new();
     let object_class = runtime.method_area.load_class("java.lang.Object");
 
-    c.bench_function("Copy Collection", |b| {
+    c.bench_function("Minor Collection", |b| {
         b.iter_batched(
             || {
-                let mut refs: HashSet<u32, BuildNoHashHasher<u32>> = HashSet::with_hasher(BuildNoHashHasher::default());
-                runtime.heap.clear();
-                runtime.heap.allocator.gen.swap();
+                let mut collector = GenerationalCollector::new();
+
+                // Empty the heap of the objects from the last iteration.
+                let mut refs: Vec<Reference> = Vec::new();
+                collector.full_gc(&runtime, &mut [], SoftPolicy::LeastRecentlyUsed);
+
                 for _ in 0..2000 {
-                    refs.insert(runtime.heap.new_object(object_class.deref()).0);
+                    refs.push(runtime.heap.new_object(object_class.deref()));
                 }
                 for _ in 0..2000 {
                     let arr_ref = runtime.heap.new_array(Class::Object(object_class), Int(500));
                     let mut obj_arr = runtime.heap.get_array(arr_ref);
                     for (idx, value) in refs.iter().take(500).enumerate() {
-                        obj_arr.set_element(Int(idx as i32), Value::Reference(Reference(*value)));
+                        obj_arr.set_element(Int(idx as i32), Value::Reference(*value));
                     }
-                    refs.insert(arr_ref.0);
+                    refs.push(arr_ref);
                 }
 
-                let (_, receiver) = channel();
-                let collector = CopyCollector::new(receiver);
-
                 (refs, collector)
             },
-            |(references, mut collector)| {
-                collector.visiting(&runtime, references);
+            |(mut references, mut collector)| {
+                let mut roots: Vec<&mut Reference> = references.iter_mut().collect();
+                collector.minor_gc(&runtime, &mut roots);
             },
             BatchSize::SmallInput
         );
@@ -150,6 +189,6 @@ fn copy_collection(c: &mut Criterion) {
 criterion_group!(benches, load_benchmark);
 criterion_group!(load_classes, load_class);
 criterion_group!(natives, native_methods);
-criterion_group!(allocate, allocation);
-criterion_group!(gc, copy_collection);
+criterion_group!(allocate, allocation, multi_threaded_allocation);
+criterion_group!(gc, minor_collection);
 criterion_main!(benches, load_classes, natives, allocate, gc);
\ No newline at end of file
@@ -0,0 +1,551 @@
+//! The `robusta` command line, parsed the same way as the options of the `java` launcher.
+
+use std::fmt::{Display, Formatter};
+use std::iter::once;
+use std::path::PathBuf;
+
+use robusta::loader::{Manifest, parse_class_path};
+use robusta::runtime::{Decorations, GcLogging, LogOutput, Options, Verify};
+use tracing::metadata::LevelFilter;
+
+const USAGE: &str = "Usage: robusta [options] <main class> [args...]
+   or  robusta [options] -jar <jar file> [args...]";
+
+const HELP: &str = "
+where options include:
+    -cp <class search path of directories and zip/jar files>
+    -classpath <class search path of directories and zip/jar files>
+                  A : separated list of directories, JAR archives,
+                  and ZIP archives to search for class files.
+    -D<name>=<value>
+                  set a system property
+    -verbose:[class|gc]
+                  enable verbose output
+    -version      print product version and exit
+    -? -help      print this help message
+    -ea[:<packagename>...|:<classname>]
+    -enableassertions[:<packagename>...|:<classname>]
+                  enable assertions with specified granularity
+    -da[:<packagename>...|:<classname>]
+    -disableassertions[:<packagename>...|:<classname>]
+                  disable assertions with specified granularity
+    -esa | -enablesystemassertions
+                  enable system assertions
+    -dsa | -disablesystemassertions
+                  disable system assertions
+    -Xmx<size>    set maximum Java heap size
+    -Xms<size>    set initial Java heap size
+    -Xverify:[none|remote|all]
+                  set which classes are verified
+    -Xbootclasspath[/a|/p]:<class search path>
+                  set, append to or prepend to the boot class path
+    -Xlog:<gc|gc*|safepoint>[:<stdout|stderr|file=<file>>[:<decorators>]]
+                  log garbage collections in the unified logging format
+    --            end of options, the next argument is the main class";
+
+/// What the launcher has been asked to do.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    /// Run a Java program.
+    Run(Box<Launch>),
+    /// Print the version and exit, as with `-version`.
+    Version,
+    /// Print the help message and exit, as with `-help`.
+    Help,
+}
+
+impl Command {
+    pub fn version() -> String {
+        format!("robusta version \"{}\"\nRobusta Java Virtual Machine", env!("CARGO_PKG_VERSION"))
+    }
+
+    pub fn help() -> String {
+        format!("{}{}", USAGE, HELP)
+    }
+}
+
+/// Where the main class of the program comes from.
+#[derive(Debug, PartialEq)]
+pub enum Main {
+    Class(String),
+    /// The `Main-Class` of an executable jar, as with `-jar`.
+    Jar(PathBuf),
+}
+
+/// A Java program to run, along with the options to run it with.
+#[derive(Debug, PartialEq)]
+pub struct Launch {
+    pub main: Main,
+    pub options: Options,
+    pub args: Vec<String>,
+    pub log_level: LevelFilter,
+}
+
+impl Launch {
+    /// The name of the main class, reading it from the jar manifest if running a jar.
+    ///
+    /// Like `java`, running a jar replaces the class path with the jar and its manifest `Class-Path`.
+    pub fn main_class(&mut self) -> Result<String, LaunchError> {
+        let jar = match &self.main {
+            Main::Class(main_class) => return Ok(main_class.clone()),
+            Main::Jar(jar) => jar.clone(),
+        };
+        if !jar.is_file() {
+            return Err(LaunchError::JarNotFound(jar));
+        }
+        let manifest = Manifest::read(&jar).map_err(|_| LaunchError::InvalidJar(jar.clone()))?;
+        let main_class = manifest.main_class().ok_or_else(|| LaunchError::NoMainClassAttribute(jar.clone()))?;
+
+        self.options.class_path = once(jar.clone()).chain(manifest.class_path(&jar)).collect();
+        Ok(main_class)
+    }
+}
+
+/// The ways the command line can be invalid.
+#[derive(Debug, PartialEq)]
+pub enum LaunchError {
+    /// No main class or jar file was given.
+    MissingMain,
+    /// An option that needs a value was the last argument, such as `-cp`.
+    MissingValue { option: String, value: &'static str },
+    UnrecognizedOption(String),
+    /// An unknown `-XX` option.
+    UnrecognizedVmOption(String),
+    /// A `-XX` option with a missing or invalid value.
+    ImproperVmOption(String),
+    /// An `-Xlog` option with unknown tags, levels or decorators.
+    InvalidLogOption(String),
+    InvalidMaxHeapSize(String),
+    InvalidInitialHeapSize(String),
+    /// The initial heap size is larger than the maximum heap size.
+    IncompatibleHeapSizes,
+    JarNotFound(PathBuf),
+    InvalidJar(PathBuf),
+    NoMainClassAttribute(PathBuf),
+}
+
+impl Display for LaunchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let create_vm_failed = "Error: Could not create the Java Virtual Machine.\n\
+            Error: A fatal exception has occurred. Program will exit.";
+        match self {
+            LaunchError::MissingMain => write!(f, "{}", USAGE),
+            LaunchError::MissingValue { option, value } =>
+                write!(f, "Error: {} requires {} specification", option, value),
+            LaunchError::UnrecognizedOption(option) =>
+                write!(f, "Unrecognized option: {}\n{}", option, create_vm_failed),
+            LaunchError::UnrecognizedVmOption(option) =>
+                write!(f, "Unrecognized VM option '{}'\n{}", option, create_vm_failed),
+            LaunchError::ImproperVmOption(option) =>
+                write!(f, "Improperly specified VM option '{}'\n{}", option, create_vm_failed),
+            LaunchError::InvalidLogOption(option) =>
+                write!(f, "Invalid -Xlog option '{}'\n{}", option, create_vm_failed),
+            LaunchError::InvalidMaxHeapSize(option) =>
+                write!(f, "Invalid maximum heap size: {}\n{}", option, create_vm_failed),
+            LaunchError::InvalidInitialHeapSize(option) =>
+                write!(f, "Invalid initial heap size: {}\n{}", option, create_vm_failed),
+            LaunchError::IncompatibleHeapSizes =>
+                write!(f, "Incompatible minimum and maximum heap sizes specified\n{}", create_vm_failed),
+            LaunchError::JarNotFound(jar) =>
+                write!(f, "Error: Unable to access jarfile {}", jar.display()),
+            LaunchError::InvalidJar(jar) =>
+                write!(f, "Error: Invalid or corrupt jarfile {}", jar.display()),
+            LaunchError::NoMainClassAttribute(jar) =>
+                write!(f, "no main manifest attribute, in {}", jar.display()),
+        }
+    }
+}
+
+/// Parse the command line arguments, not including the program name.
+///
+/// The `CLASSPATH` environment variable is used if no class path option is given.
+pub fn parse(args: &[String], class_path_env: Option<String>) -> Result<Command, LaunchError> {
+    let mut options = Options::default();
+    let mut log_level = LevelFilter::OFF;
+    let mut is_jar = false;
+
+    let mut class_path = class_path_env;
+    let mut boot_class_path = None;
+    let mut boot_prepend = vec![];
+    let mut boot_append = vec![];
+
+    let mut idx = 0;
+    while idx < args.len() && args[idx].starts_with('-') {
+        let arg = args[idx].as_str();
+        idx += 1;
+        match arg {
+            "--" => break,
+            "-version" => return Ok(Command::Version),
+            "-help" | "-h" | "-?" => return Ok(Command::Help),
+            "-d" => log_level = LevelFilter::DEBUG,
+            "-t" => log_level = LevelFilter::TRACE,
+            "-jar" => is_jar = true,
+            "-verbose" | "-verbose:class" => options.verbose_class = true,
+            "-verbose:gc" => options.verbose_gc = true,
+            "-esa" | "-enablesystemassertions" => options.assertions.system = true,
+            "-dsa" | "-disablesystemassertions" => options.assertions.system = false,
+            "-Xverify:none" => options.verify = Verify::None,
+            "-Xverify:remote" => options.verify = Verify::Remote,
+            "-Xverify:all" => options.verify = Verify::All,
+            "-cp" | "-classpath" | "--class-path" => {
+                let Some(path) = args.get(idx) else {
+                    return Err(LaunchError::MissingValue { option: arg.to_string(), value: "class path" });
+                };
+                class_path = Some(path.clone());
+                idx += 1;
+            }
+            _ => {
+                if let Some(property) = arg.strip_prefix("-D").filter(|p| !p.is_empty()) {
+                    let (key, value) = property.split_once('=').unwrap_or((property, ""));
+                    options.properties.insert(key.to_string(), value.to_string());
+                } else if let Some(size) = arg.strip_prefix("-Xmx") {
+                    let size = parse_size(size).ok_or_else(|| LaunchError::InvalidMaxHeapSize(arg.to_string()))?;
+                    options.max_heap_size = Some(size);
+                } else if let Some(size) = arg.strip_prefix("-Xms") {
+                    let size = parse_size(size).ok_or_else(|| LaunchError::InvalidInitialHeapSize(arg.to_string()))?;
+                    options.initial_heap_size = Some(size);
+                } else if let Some(path) = arg.strip_prefix("-Xbootclasspath:") {
+                    boot_class_path = Some(parse_class_path(path));
+                } else if let Some(path) = arg.strip_prefix("-Xbootclasspath/a:") {
+                    boot_append.extend(parse_class_path(path));
+                } else if let Some(path) = arg.strip_prefix("-Xbootclasspath/p:") {
+                    boot_prepend.splice(0..0, parse_class_path(path));
+                } else if let Some(log) = arg.strip_prefix("-Xlog:") {
+                    parse_log(log, &mut options).ok_or_else(|| LaunchError::InvalidLogOption(arg.to_string()))?;
+                } else if let Some(option) = arg.strip_prefix("-XX:") {
+                    parse_vm_option(option, &mut options)?;
+                } else if !parse_assertions(arg, &mut options) {
+                    return Err(LaunchError::UnrecognizedOption(arg.to_string()));
+                }
+            }
+        }
+    }
+
+    if let (Some(initial), Some(max)) = (options.initial_heap_size, options.max_heap_size) {
+        if initial > max {
+            return Err(LaunchError::IncompatibleHeapSizes);
+        }
+    }
+
+    if let Some(class_path) = class_path {
+        options.class_path = parse_class_path(&class_path);
+    }
+    if let Some(boot_class_path) = boot_class_path {
+        options.boot_class_path = boot_class_path;
+    }
+    options.boot_class_path.splice(0..0, boot_prepend);
+    options.boot_class_path.extend(boot_append);
+
+    let Some(main) = args.get(idx) else {
+        return match is_jar {
+            true => Err(LaunchError::MissingValue { option: "-jar".to_string(), value: "jar file" }),
+            false => Err(LaunchError::MissingMain),
+        };
+    };
+    let main = match is_jar {
+        true => Main::Jar(PathBuf::from(main)),
+        false => Main::Class(main.clone()),
+    };
+
+    Ok(Command::Run(Box::new(Launch { main, options, args: args[idx + 1..].to_vec(), log_level })))
+}
+
+/// Parse a `-XX` option, either `+<flag>` or `-<flag>` to turn a flag on or off, or
+/// `<name>=<value>`.
+fn parse_vm_option(option: &str, options: &mut Options) -> Result<(), LaunchError> {
+    let unrecognized = || LaunchError::UnrecognizedVmOption(option.trim_start_matches(['+', '-']).to_string());
+    if let Some((name, value)) = option.split_once('=') {
+        let improper = || LaunchError::ImproperVmOption(option.to_string());
+        match name {
+            "GCStressInterval" => {
+                let interval = value.parse().ok().filter(|interval| *interval > 0).ok_or_else(improper)?;
+                options.gc_stress_interval = interval;
+            }
+            "HeapDumpPath" => {
+                let path = Some(value).filter(|path| !path.is_empty()).ok_or_else(improper)?;
+                options.heap_dump_path = Some(PathBuf::from(path));
+            }
+            _ => return Err(unrecognized()),
+        }
+        return Ok(());
+    }
+
+    let enabled = match option.chars().next() {
+        Some('+') => true,
+        Some('-') => false,
+        _ => return Err(unrecognized()),
+    };
+    match &option[1..] {
+        "GCStress" => options.gc_stress = enabled,
+        "VerifyHeap" => options.verify_heap = enabled,
+        "HeapDumpOnOutOfMemoryError" => options.heap_dump_on_out_of_memory_error = enabled,
+        "PrintGCApplicationStoppedTime" => options.print_gc_application_stopped_time = enabled,
+        _ => return Err(unrecognized()),
+    }
+    Ok(())
+}
+
+/// Parse an `-Xlog` option, `<selections>[:<output>[:<decorators>]]`, or `disable` to turn off
+/// the logs of earlier options, returning `None` if it's invalid.
+///
+/// Only the `gc` and `safepoint` tags are logged, at the info level. Like `java`, the output is
+/// stdout by default, and is a file unless it's `stdout` or `stderr`, whether or not it starts
+/// with `file=`. Options for rotating log files aren't supported.
+fn parse_log(option: &str, options: &mut Options) -> Option<()> {
+    if option == "disable" {
+        options.gc_logs.clear();
+        return Some(());
+    }
+
+    let mut parts = option.splitn(4, ':');
+    let mut logging = GcLogging {
+        gc: false,
+        details: false,
+        safepoint: false,
+        output: LogOutput::Stdout,
+        decorations: Decorations::default(),
+    };
+    for selection in parts.next()?.split(',') {
+        let (tags, level) = selection.split_once('=').unwrap_or((selection, "info"));
+        let enabled = match level {
+            "off" | "error" | "warning" => false,
+            "info" | "debug" | "trace" => true,
+            _ => return None,
+        };
+        match tags {
+            "gc" => logging.gc = enabled,
+            "gc*" => (logging.gc, logging.details) = (enabled, enabled),
+            "safepoint" | "safepoint*" => logging.safepoint = enabled,
+            _ => return None,
+        }
+    }
+
+    match parts.next().unwrap_or("") {
+        "" | "stdout" => {}
+        "stderr" => logging.output = LogOutput::Stderr,
+        file => {
+            let file = file.strip_prefix("file=").unwrap_or(file);
+            if file.is_empty() {
+                return None;
+            }
+            logging.output = LogOutput::File(PathBuf::from(file));
+        }
+    }
+
+    if let Some(decorators) = parts.next().filter(|decorators| !decorators.is_empty()) {
+        let mut decorations = Decorations { uptime: false, uptime_millis: false, uptime_nanos: false, level: false, tags: false };
+        if decorators != "none" {
+            for decorator in decorators.split(',') {
+                match decorator {
+                    "uptime" | "u" => decorations.uptime = true,
+                    "uptimemillis" | "um" => decorations.uptime_millis = true,
+                    "uptimenanos" | "un" => decorations.uptime_nanos = true,
+                    "level" | "l" => decorations.level = true,
+                    "tags" | "tg" => decorations.tags = true,
+                    _ => return None,
+                }
+            }
+        }
+        logging.decorations = decorations;
+    }
+    if parts.next().is_some_and(|output_options| !output_options.is_empty()) {
+        return None;
+    }
+
+    options.gc_logs.push(logging);
+    Some(())
+}
+
+/// Parse an `-ea` or `-da` option, returning false if the option isn't one of them.
+///
+/// Without an argument all application classes are affected, `pkg...` affects a package and its
+/// sub packages, `...` affects the unnamed package, and anything else names a single class.
+fn parse_assertions(arg: &str, options: &mut Options) -> bool {
+    let (option, target) = arg.split_once(':').map_or((arg, None), |(option, target)| (option, Some(target)));
+    let enabled = match option {
+        "-ea" | "-enableassertions" => true,
+        "-da" | "-disableassertions" => false,
+        _ => return false,
+    };
+
+    let assertions = &mut options.assertions;
+    match target {
+        None | Some("") => assertions.user = enabled,
+        Some(target) => match target.strip_suffix("...") {
+            Some(package) => { assertions.packages.insert(package.to_string(), enabled); }
+            None => { assertions.classes.insert(target.to_string(), enabled); }
+        }
+    }
+    true
+}
+
+/// Parse a memory size, such as `512k`, `64m` or `1G`, into a number of bytes.
+fn parse_size(size: &str) -> Option<usize> {
+    let (digits, multiplier) = match size.chars().last()? {
+        'k' | 'K' => (&size[..size.len() - 1], 1 << 10),
+        'm' | 'M' => (&size[..size.len() - 1], 1 << 20),
+        'g' | 'G' => (&size[..size.len() - 1], 1 << 30),
+        't' | 'T' => (&size[..size.len() - 1], 1 << 40),
+        _ => (size, 1),
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(args: &[&str]) -> Launch {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        match parse(&args, None) {
+            Ok(Command::Run(launch)) => *launch,
+            other => panic!("expected launch, got {:?}", other),
+        }
+    }
+
+    fn error(args: &[&str]) -> LaunchError {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse(&args, None).unwrap_err()
+    }
+
+    #[test]
+    fn main_class_and_args() {
+        let launch = run(&["-Dfoo=bar", "Main", "-Dnot=option", "b"]);
+
+        assert_eq!(launch.main, Main::Class("Main".to_string()));
+        assert_eq!(launch.args, vec!["-Dnot=option", "b"]);
+        assert_eq!(launch.options.properties.get("foo"), Some(&"bar".to_string()));
+        assert_eq!(launch.options.properties.get("not"), None);
+    }
+
+    #[test]
+    fn end_of_options() {
+        let launch = run(&["-ea", "--", "-Main", "a"]);
+
+        assert_eq!(launch.main, Main::Class("-Main".to_string()));
+        assert_eq!(launch.args, vec!["a"]);
+        assert!(launch.options.assertions.user);
+    }
+
+    #[test]
+    fn properties() {
+        let launch = run(&["-Da=1", "-Db", "-Dc=x=y", "-Da=2", "Main"]);
+        let properties = launch.options.properties;
+
+        assert_eq!(properties.get("a"), Some(&"2".to_string()));
+        assert_eq!(properties.get("b"), Some(&"".to_string()));
+        assert_eq!(properties.get("c"), Some(&"x=y".to_string()));
+    }
+
+    #[test]
+    fn heap_sizes() {
+        let launch = run(&["-Xms512k", "-Xmx64M", "Main"]);
+
+        assert_eq!(launch.options.initial_heap_size, Some(512 * 1024));
+        assert_eq!(launch.options.max_heap_size, Some(64 * 1024 * 1024));
+        assert_eq!(parse_size("1g"), Some(1 << 30));
+        assert_eq!(parse_size("100"), Some(100));
+        assert_eq!(parse_size("m"), None);
+        assert_eq!(parse_size("1x"), None);
+
+        assert_eq!(error(&["-Xmxlots", "Main"]), LaunchError::InvalidMaxHeapSize("-Xmxlots".to_string()));
+        assert_eq!(error(&["-Xms", "Main"]), LaunchError::InvalidInitialHeapSize("-Xms".to_string()));
+        assert_eq!(error(&["-Xms2m", "-Xmx1m", "Main"]), LaunchError::IncompatibleHeapSizes);
+    }
+
+    #[test]
+    fn vm_options() {
+        let launch = run(&["-XX:+GCStress", "-XX:GCStressInterval=100", "-XX:+VerifyHeap", "Main"]);
+
+        assert!(launch.options.gc_stress);
+        assert_eq!(launch.options.gc_stress_interval, 100);
+        assert!(launch.options.verify_heap);
+
+        let launch = run(&["-XX:+VerifyHeap", "-XX:-VerifyHeap", "Main"]);
+        assert!(!launch.options.verify_heap);
+        assert!(!launch.options.gc_stress);
+        assert_eq!(launch.options.gc_stress_interval, 1);
+
+        let launch = run(&["-XX:+HeapDumpOnOutOfMemoryError", "-XX:HeapDumpPath=dumps", "Main"]);
+        assert!(launch.options.heap_dump_on_out_of_memory_error);
+        assert_eq!(launch.options.heap_dump_path, Some(PathBuf::from("dumps")));
+
+        assert_eq!(error(&["-XX:GCStressInterval=0", "Main"]), LaunchError::ImproperVmOption("GCStressInterval=0".to_string()));
+        assert_eq!(error(&["-XX:GCStressInterval=", "Main"]), LaunchError::ImproperVmOption("GCStressInterval=".to_string()));
+        assert_eq!(error(&["-XX:HeapDumpPath=", "Main"]), LaunchError::ImproperVmOption("HeapDumpPath=".to_string()));
+        assert_eq!(error(&["-XX:GCStress", "Main"]), LaunchError::UnrecognizedVmOption("GCStress".to_string()));
+        assert_eq!(error(&["-XX:Foo=1", "Main"]), LaunchError::UnrecognizedVmOption("Foo=1".to_string()));
+    }
+
+    #[test]
+    fn gc_logs() {
+        let launch = run(&["-verbose:gc", "-XX:+PrintGCApplicationStoppedTime", "-Xlog:gc", "-Xlog:gc*,safepoint:file=gc.log:uptimemillis,tags", "Main"]);
+        assert!(launch.options.verbose_gc);
+        assert!(launch.options.print_gc_application_stopped_time);
+        assert_eq!(launch.options.gc_logs, vec![
+            GcLogging { gc: true, details: false, safepoint: false, output: LogOutput::Stdout, decorations: Decorations::default() },
+            GcLogging {
+                gc: true,
+                details: true,
+                safepoint: true,
+                output: LogOutput::File(PathBuf::from("gc.log")),
+                decorations: Decorations { uptime: false, uptime_millis: true, uptime_nanos: false, level: false, tags: true },
+            },
+        ]);
+
+        let launch = run(&["-Xlog:gc*", "-Xlog:disable", "-Xlog:safepoint=info,gc=off:stderr", "Main"]);
+        assert_eq!(launch.options.gc_logs, vec![
+            GcLogging { gc: false, details: false, safepoint: true, output: LogOutput::Stderr, decorations: Decorations::default() },
+        ]);
+
+        assert_eq!(run(&["-Xlog:gc:gc.log::", "Main"]).options.gc_logs[0].output, LogOutput::File(PathBuf::from("gc.log")));
+        for option in ["-Xlog:class+load", "-Xlog:gc=loud", "-Xlog:gc:file=", "-Xlog:gc::pid", "-Xlog:gc:gc.log::filecount=5"] {
+            assert_eq!(error(&[option, "Main"]), LaunchError::InvalidLogOption(option.to_string()));
+        }
+    }
+
+    #[test]
+    fn assertions() {
+        let launch = run(&["-ea", "-da:com.foo...", "-ea:com.foo.Bar", "-enableassertions:...", "-esa", "Main"]);
+        let assertions = launch.options.assertions;
+
+        assert!(assertions.user);
+        assert!(assertions.system);
+        assert_eq!(assertions.packages.get("com.foo"), Some(&false));
+        assert_eq!(assertions.packages.get(""), Some(&true));
+        assert_eq!(assertions.classes.get("com.foo.Bar"), Some(&true));
+    }
+
+    #[test]
+    fn commands() {
+        let args = vec!["-version".to_string(), "Main".to_string()];
+        assert_eq!(parse(&args, None), Ok(Command::Version));
+        let args = vec!["-?".to_string()];
+        assert_eq!(parse(&args, None), Ok(Command::Help));
+    }
+
+    #[test]
+    fn usage_errors() {
+        assert_eq!(error(&[]), LaunchError::MissingMain);
+        assert_eq!(error(&["-ea"]), LaunchError::MissingMain);
+        assert_eq!(error(&["-jar"]), LaunchError::MissingValue { option: "-jar".to_string(), value: "jar file" });
+        assert_eq!(error(&["-cp"]), LaunchError::MissingValue { option: "-cp".to_string(), value: "class path" });
+        assert_eq!(error(&["-foo", "Main"]), LaunchError::UnrecognizedOption("-foo".to_string()));
+        assert_eq!(error(&["-D", "Main"]), LaunchError::UnrecognizedOption("-D".to_string()));
+        assert_eq!(error(&["-XX:+Foo", "Main"]), LaunchError::UnrecognizedVmOption("Foo".to_string()));
+    }
+
+    #[test]
+    fn class_path_env() {
+        let args = vec!["Main".to_string()];
+        let Ok(Command::Run(launch)) = parse(&args, Some("a:b".to_string())) else { panic!() };
+        assert_eq!(launch.options.class_path, vec![PathBuf::from("a"), PathBuf::from("b")]);
+
+        let args = vec!["-cp".to_string(), "c".to_string(), "Main".to_string()];
+        let Ok(Command::Run(launch)) = parse(&args, Some("a:b".to_string())) else { panic!() };
+        assert_eq!(launch.options.class_path, vec![PathBuf::from("c")]);
+    }
+}
@@ -1,7 +1,45 @@
+use std::env::{args, var};
+use std::process::exit;
+
 use robusta::VirtualMachine;
 
+use crate::launcher::Command;
+
+mod launcher;
+
 fn main() {
-    let mut jvm = VirtualMachine::new();
+    let args: Vec<String> = args().skip(1).collect();
+
+    let mut launch = match launcher::parse(&args, var("CLASSPATH").ok()) {
+        Ok(Command::Run(launch)) => launch,
+        Ok(Command::Version) => {
+            eprintln!("{}", Command::version());
+            exit(0);
+        }
+        Ok(Command::Help) => {
+            println!("{}", Command::help());
+            exit(0);
+        }
+        Err(error) => {
+            eprintln!("{}", error);
+            exit(1);
+        }
+    };
+
+    let main_class = launch.main_class().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        exit(1);
+    });
+
+    let mut jvm = VirtualMachine::builder(&main_class)
+        .options(launch.options)
+        .args(launch.args)
+        .log_level(launch.log_level)
+        .build()
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            exit(1);
+        });
 
     jvm.start();
-}
\ No newline at end of file
+}
@@ -1,7 +1,8 @@
 #[derive(Debug, PartialEq)]
 /// The `CONSTANT_Utf8_info` structure is used to represent constant string values.
 pub struct Utf8 {
-    /// The modified UTF-8 string, for more details on this specific format, see
+    /// The string, decoded from the modified UTF-8 of the class file into standard UTF-8, for
+    /// more details on the modified format, see
     /// [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.4.7).
     pub bytes: Vec<u8>,
 }
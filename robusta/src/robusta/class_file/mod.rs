@@ -8,6 +8,11 @@ pub mod const_pool;
 /// The expected value at the start of a class file, identifying the class file format.
 pub const MAGIC: u32 = 0xCAFE_BABE;
 
+/// The oldest class file version supported, from JDK 1.1.
+pub const MIN_MAJOR_VERSION: u16 = 45;
+/// The newest class file version supported, from Java SE 8.
+pub const MAX_MAJOR_VERSION: u16 = 52;
+
 /// Static access flag.
 pub const ACCESS_FLAG_STATIC: u16 = 0x0008;
 pub const ACCESS_FLAG_NATIVE: u16 = 0x0100;
@@ -98,6 +103,23 @@ impl ClassFile {
         }
     }
 
+    pub fn get_const_method_handle(&self, index: u16) -> &const_pool::MethodHandle {
+        let con = self.const_pool.get(&index).unwrap();
+        match con {
+            Const::MethodHandle(method_handle) => method_handle,
+            other => panic!("Expected const method handle, got {:?}", other)
+        }
+    }
+
+    pub fn bootstrap_methods(&self) -> Option<&BootstrapMethods> {
+        self.attributes.iter().find_map(|attr| {
+            match attr {
+                ClassAttribute::BootstrapMethods(methods) => Some(methods),
+                _ => None
+            }
+        })
+    }
+
     pub fn get_const_name_and_type(&self, index: u16) -> &const_pool::NameAndType {
         let con = self.const_pool.get(&index).unwrap();
         match con {
@@ -172,6 +194,15 @@ impl Code {
             }
         })
     }
+
+    pub fn stack_map_table(&self) -> Option<&StackMapTable> {
+        self.attributes.iter().find_map(|a| {
+            match a {
+                CodeAttribute::StackMapTable(smt) => Some(smt),
+                _ => None
+            }
+        })
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -185,6 +216,7 @@ pub struct ExHandler {
 #[derive(Clone, Debug, PartialEq)]
 pub enum ClassAttribute {
     SourceFile(SourceFile),
+    BootstrapMethods(BootstrapMethods),
     Unknown(UnknownAttribute),
 }
 
@@ -198,6 +230,7 @@ pub enum MethodAttribute {
 #[derive(Clone, Debug, PartialEq)]
 pub enum CodeAttribute {
     LineNumberTable(LineNumberTable),
+    StackMapTable(StackMapTable),
     Unknown(UnknownAttribute),
 }
 
@@ -206,6 +239,22 @@ pub struct SourceFile {
     pub source_file: u16,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+/// The `BootstrapMethods` attribute, recording the bootstrap methods used by `invokedynamic`.
+///
+/// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.7.23).
+pub struct BootstrapMethods {
+    pub methods: Vec<BootstrapMethod>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BootstrapMethod {
+    /// A valid index into `const_pool`, which must be a `Const::MethodHandle`.
+    pub method_ref: u16,
+    /// Valid indices into `const_pool` of the static arguments passed to the bootstrap method.
+    pub arguments: Vec<u16>,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct UnknownAttribute {
     pub name_idx: u16,
@@ -221,4 +270,60 @@ pub struct LineNumberTable {
 pub struct LineNumber {
     pub start_pc: u16,
     pub line_number: u16,
-}
\ No newline at end of file
+}
+#[derive(Clone, Debug, PartialEq)]
+/// The `StackMapTable` attribute, giving the types of the local variables and operand stack at
+/// the start of chosen instructions, used by the type checking verifier.
+///
+/// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.7.4).
+pub struct StackMapTable {
+    pub entries: Vec<StackMapFrame>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// A single frame of a `StackMapTable`.
+///
+/// The offset of each frame is given relative to the frame before it, and all but a full frame
+/// describe their local variables relative to the frame before them.
+pub enum StackMapFrame {
+    /// The same locals as the previous frame, and an empty stack.
+    Same { offset_delta: u16 },
+    /// The same locals as the previous frame, and a single value on the stack.
+    SameLocals1StackItem { offset_delta: u16, stack: VerificationType },
+    /// The same locals as the previous frame without the last `k` locals, and an empty stack.
+    Chop { offset_delta: u16, k: u8 },
+    /// The same locals as the previous frame with additional locals, and an empty stack.
+    Append { offset_delta: u16, locals: Vec<VerificationType> },
+    /// A complete description of the locals and stack.
+    Full { offset_delta: u16, locals: Vec<VerificationType>, stack: Vec<VerificationType> },
+}
+
+impl StackMapFrame {
+    pub fn offset_delta(&self) -> u16 {
+        match self {
+            StackMapFrame::Same { offset_delta } |
+            StackMapFrame::SameLocals1StackItem { offset_delta, .. } |
+            StackMapFrame::Chop { offset_delta, .. } |
+            StackMapFrame::Append { offset_delta, .. } |
+            StackMapFrame::Full { offset_delta, .. } => *offset_delta,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The `verification_type_info` of a local or stack value in a `StackMapFrame`.
+///
+/// `Long` and `Double` values take up two local variables, but a single entry in a frame.
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    /// A valid index into `const_pool`, which must be a `Const::Class`.
+    Object(u16),
+    /// The offset of the `new` instruction that created the uninitialized object.
+    Uninitialized(u16),
+}
@@ -1,16 +1,26 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
 use std::sync::Arc;
 use std::sync::mpsc::{Receiver, sync_channel, SyncSender};
 
 use parking_lot::RwLock;
 
-use crate::method_area::ObjectClass;
+use crate::java::Reference;
+use crate::method_area::{LoadClassError, ObjectClass};
+
+/// Classes are keyed by their defining loader and their name, the loader is `null` for the
+/// bootstrap loader.
+type Key = (Reference, String);
 
 pub struct Classes {
-    loading: RwLock<HashMap<String, ClassLoad>>,
-    initialized: RwLock<HashMap<String, ClassLoad>>,
-    classes: RwLock<HashMap<String, Value>>,
+    loading: RwLock<HashMap<Key, ClassLoad>>,
+    initialized: RwLock<HashMap<Key, ClassLoad>>,
+    classes: RwLock<HashMap<Key, Value>>,
+    /// Classes that a loader has loaded by delegating to another loader, keyed by the initiating
+    /// loader.
+    initiated: RwLock<HashMap<Key, ClassRef>>,
+    /// Classes that failed to load, every later attempt to load them fails with the same error.
+    failed: RwLock<HashMap<Key, LoadClassError>>,
 }
 
 impl Classes {
@@ -19,33 +29,121 @@ impl Classes {
             loading: RwLock::new(HashMap::new()),
             initialized: RwLock::new(HashMap::new()),
             classes: RwLock::new(HashMap::new()),
+            initiated: RwLock::new(HashMap::new()),
+            failed: RwLock::new(HashMap::new()),
         }
     }
 
-    pub fn load_class<F>(&self, name: &str, load_class: F) -> ClassRef
-        where F: FnOnce(&str) -> ObjectClass
+    pub fn load_class<F>(&self, loader: Reference, name: &str, load_class: F) -> Result<ClassRef, LoadClassError>
+        where F: FnOnce(&str) -> Result<ObjectClass, LoadClassError>
     {
-        let (creator, waiter) = self.find_status(name);
+        let key = (loader, name.to_string());
+        let (creator, waiter) = self.find_status(&key);
         if let Some(creator) = creator {
             // no other thread will read the status of the class, so we can insert it into the
             // data structures that we want here!
-            let class = load_class(name);
-            let mut classes = self.classes.write();
-            classes.insert(name.to_string(), class.into());
+            match load_class(name) {
+                Ok(class) => {
+                    let mut classes = self.classes.write();
+                    classes.insert(key.clone(), class.into());
+                }
+                Err(error) => {
+                    let mut failed = self.failed.write();
+                    failed.insert(key.clone(), error);
+                }
+            }
             creator.done();
         }
 
         // At this point, we simply need to wait for the status to be good.
         waiter.wait();
-        let classes = self.classes.read();
-        let class = classes.get(name).unwrap();
-        class.borrow()
+        if let Some(class) = self.classes.read().get(&key) {
+            return Ok(class.borrow());
+        }
+        let failed = self.failed.read();
+        Err(failed.get(&key).unwrap().clone())
+    }
+
+    /// Define a class for a user defined loader, returning `None` if the loader has already
+    /// defined or loaded a class with the same name.
+    pub fn define(&self, loader: Reference, class: ObjectClass) -> Option<ClassRef> {
+        let key = (loader, class.name.clone());
+        if self.initiated.read().contains_key(&key) {
+            return None;
+        }
+        let mut classes = self.classes.write();
+        if classes.contains_key(&key) {
+            return None;
+        }
+        let value: Value = class.into();
+        let class = value.borrow();
+        classes.insert(key, value);
+        Some(class)
+    }
+
+    /// Find a class that the loader has either defined, or been recorded as an initiating loader of.
+    pub fn find(&self, loader: Reference, name: &str) -> Option<ClassRef> {
+        let key = (loader, name.to_string());
+        if let Some(class) = self.classes.read().get(&key) {
+            return Some(class.borrow());
+        }
+        self.initiated.read().get(&key).copied()
+    }
+
+    /// Every class that has been defined.
+    pub fn all(&self) -> HashSet<ClassRef> {
+        self.classes.read().values().map(Value::borrow).collect()
+    }
+
+    /// Record the loader as an initiating loader of the class, returning the class recorded
+    /// for the loader, which is a different class only if another thread got there first.
+    pub fn initiate(&self, loader: Reference, class: ClassRef) -> ClassRef {
+        if class.loader == loader {
+            return class;
+        }
+        let mut initiated = self.initiated.write();
+        *initiated.entry((loader, class.name.clone())).or_insert(class)
+    }
+
+    /// The classes of each user defined loader, both the classes it defined and the classes it
+    /// has been recorded as an initiating loader of.
+    pub fn user_loaders(&self) -> HashMap<Reference, Vec<ClassRef>> {
+        let mut loaders: HashMap<Reference, Vec<ClassRef>> = HashMap::new();
+        for ((loader, _), class) in self.classes.read().iter() {
+            if loader.0 != 0 {
+                loaders.entry(*loader).or_default().push(class.borrow());
+            }
+        }
+        for ((loader, _), class) in self.initiated.read().iter() {
+            loaders.entry(*loader).or_default().push(*class);
+        }
+        loaders
+    }
+
+    /// Remove every class of the loader, returning the classes it defined, which are dropped
+    /// once the caller is done with them.
+    pub fn unload(&self, loader: Reference) -> Vec<Arc<ObjectClass>> {
+        self.loading.write().retain(|(other, _), _| *other != loader);
+        self.initialized.write().retain(|(other, _), _| *other != loader);
+        self.initiated.write().retain(|(other, _), _| *other != loader);
+        self.failed.write().retain(|(other, _), _| *other != loader);
+
+        let mut classes = self.classes.write();
+        let names: Vec<Key> = classes.keys()
+            .filter(|(other, _)| *other == loader)
+            .cloned()
+            .collect();
+        names.iter()
+            .filter_map(|key| classes.remove(key))
+            .map(|value| value.class)
+            .collect()
     }
 
-    pub fn initialize<F>(&self, name: &str, initialize: F)
+    pub fn initialize<F>(&self, loader: Reference, name: &str, initialize: F)
         where F: FnOnce(&str)
     {
-        let (creator, waiter) = self.find_init(name);
+        let key = (loader, name.to_string());
+        let (creator, waiter) = self.find_init(&key);
         if let Some(creator) = creator {
             // no other thread will read the status of the class, so we can insert it into the
             // data structures that we want here!
@@ -60,30 +158,30 @@ impl Classes {
     /// If the class is loaded, return a loading status that tells us it is ready!
     /// Else if nobody else has started, give us that indicator, else give us a blocking
     /// wait upon it being finished!
-    fn find_status(&self, name: &str) -> (Option<ClassCreator>, ClassWaiter) {
+    fn find_status(&self, key: &Key) -> (Option<ClassCreator>, ClassWaiter) {
         let mut loading = self.loading.write();
-        if !loading.contains_key(name) {
+        if !loading.contains_key(key) {
             let load = ClassLoad::new();
             let creator = load.creator();
             let waiter = load.waiter();
-            loading.insert(name.to_string(), load);
+            loading.insert(key.clone(), load);
             (Some(creator), waiter)
         } else {
-            let load = loading.get(name).unwrap();
+            let load = loading.get(key).unwrap();
             (None, load.waiter())
         }
     }
 
-    fn find_init(&self, name: &str) -> (Option<ClassCreator>, ClassWaiter) {
+    fn find_init(&self, key: &Key) -> (Option<ClassCreator>, ClassWaiter) {
         let mut initialized = self.initialized.write();
-        if !initialized.contains_key(name) {
+        if !initialized.contains_key(key) {
             let load = ClassLoad::new();
             let creator = load.creator();
             let waiter = load.waiter();
-            initialized.insert(name.to_string(), load);
+            initialized.insert(key.clone(), load);
             (Some(creator), waiter)
         } else {
-            let load = initialized.get(name).unwrap();
+            let load = initialized.get(key).unwrap();
             (None, load.waiter())
         }
     }
@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::collections::HashSet;
 use std::hash::Hash;
 use std::sync::{RwLock, RwLockReadGuard};
@@ -35,6 +36,21 @@ impl<T> Once<T> {
         unsafe { ptr.as_ref().unwrap() }
     }
 
+    /// Get the value in the once, or if nobody has set the value, try to set the value
+    /// using the supplied [`F`].
+    ///
+    /// If [`F`] fails, the value is left unset, and the next call will try again.
+    pub fn try_get_or_init<F, E>(&self, f: F) -> Result<&T, E>
+        where F: FnOnce() -> Result<T, E>
+    {
+        let mut value = self.value.write().unwrap();
+        if value.is_none() {
+            *value = Some(f()?);
+        }
+        let ptr = value.as_mut().unwrap() as *mut T;
+        Ok(unsafe { ptr.as_ref().unwrap() })
+    }
+
     pub fn current(&self) -> RwLockReadGuard<Option<T>> {
         self.value.read().unwrap()
     }
@@ -73,7 +89,28 @@ impl<K: Eq + Hash + Clone, V> OnceMap<K, V> {
     }
 }
 
-impl OnceMap<String, Reference> {
+impl<K: Eq + Hash + Clone, V: Clone> OnceMap<K, V> {
+    /// The keys and values of all the entries that have been initialized.
+    pub fn current_entries(&self) -> Vec<(K, V)> {
+        let entries = RefCell::new(Vec::with_capacity(self.map.len()));
+        self.map.retain(|key, value| {
+            if let Some(value) = value.current().as_ref() {
+                entries.borrow_mut().push((key.clone(), value.as_ref().clone()));
+            }
+            true
+        });
+        entries.into_inner()
+    }
+
+    /// Remove every entry whose key doesn't match the predicate.
+    pub fn retain<F>(&self, f: F)
+        where F: Fn(&K) -> bool
+    {
+        self.map.retain(|key, _| f(key));
+    }
+}
+
+impl<K: Eq + Hash + Clone> OnceMap<K, Reference> {
     pub fn current_values(&self) -> HashSet<u32, BuildNoHashHasher<u32>> {
         // TODO: Only way I can see of getting all the entries?
         let references: Vec<u32> = vec![0; self.map.len()];
@@ -135,4 +172,18 @@ mod tests {
         let value = map.get_or_init(10, |x| x * 4);
         assert!(*value == 10 || *value == 20 || *value == 30);
     }
+
+    #[test]
+    fn once_map_retain() {
+        let map: OnceMap<i32, i32> = OnceMap::new();
+        for key in 0..4 {
+            map.get_or_init(key, |x| x * 10);
+        }
+
+        map.retain(|key| key % 2 == 0);
+
+        let mut entries = map.current_entries();
+        entries.sort();
+        assert_eq!(entries, vec![(0, 0), (2, 20)]);
+    }
 }
\ No newline at end of file
@@ -1,11 +1,14 @@
+use std::cell::Cell;
 use std::mem::size_of;
 use std::ptr;
 use std::slice::{from_raw_parts, from_raw_parts_mut};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use tracing::trace;
+use tracing::{debug, trace};
 
-use crate::heap::garbage_collector::CopyGeneration;
+use crate::heap::garbage_collector::{Collection, Collector, CompactGeneration, CopyGeneration, page_align, Reservation};
+use crate::heap::gc_log::GcCause;
 use crate::heap::hash_code::HashCode;
 use crate::heap::Heap;
 use crate::heap::sync::ObjectLock;
@@ -13,7 +16,7 @@ use crate::java::{Double, FieldType, Float, Int, Long, Reference, Value};
 use crate::log;
 use crate::method_area::{ObjectClass, Field, Class, Primitive};
 use crate::method_area::const_pool::FieldKey;
-use crate::runtime::Runtime;
+use crate::runtime::{Options, Runtime};
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -106,15 +109,33 @@ impl Object {
     }
 }
 
+/// What a block of memory in the heap holds, the first byte of every header.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Kind {
+    /// An instance of a class.
+    Object = 1,
+    /// An instance of a class that the JVM refers to by its reference, such as a class loader
+    /// or a `java.lang.Class` object, which the garbage collector never moves.
+    Pinned = 2,
+    /// The static fields of a class, which are never moved either.
+    Static = 3,
+    /// An array.
+    Array = 4,
+    /// An object that the garbage collector has moved, which has left its new reference behind.
+    Forwarded = 5,
+}
+
 #[repr(C)]
 // #[derive(Clone)]
 /// The object header is used to index into an object.
-///
-/// TODO: This will be used for GC information.
 pub struct ObjectHeader {
+    pub kind: Kind,
+    /// The number of minor collections that the object has survived.
+    pub age: u8,
+    pub hash_code: Int,
     /// The class of this object
     pub class: *const ObjectClass,
-    pub hash_code: Int,
     pub lock: ObjectLock,
 }
 
@@ -131,7 +152,7 @@ unsafe impl Send for Array {}
 unsafe impl Sync for Array {}
 
 impl Array {
-    fn header(&self) -> &ArrayHeader {
+    pub fn header(&self) -> &ArrayHeader {
         unsafe {
             self.header.as_ref().unwrap()
         }
@@ -204,13 +225,17 @@ impl Array {
 // #[derive(Clone)]
 /// The array header is used to index into the array.
 ///
-/// TODO: This will be used for GC information.
+/// The header starts like an [`ObjectHeader`], so the kind of anything in the heap can be read
+/// from its first byte.
 pub struct ArrayHeader {
+    pub kind: Kind,
+    /// The number of minor collections that the array has survived.
+    pub age: u8,
+    pub hash_code: Int,
     /// The type of the values in the array.
     pub component: Class,
     /// The length (in bytes) of the array data.
     pub length: usize,
-    pub hash_code: Int,
     pub lock: ObjectLock,
 }
 
@@ -282,29 +307,142 @@ impl ArrayType {
     }
 }
 
-/// The default heap size of the openjdk is 1280MB
-pub const HEAP_SIZE: usize = 1280 * 1024 * 1024;
+/// The default maximum heap size of the openjdk is 1280MB
+pub const DEFAULT_MAX_HEAP_SIZE: usize = 1280 * 1024 * 1024;
+
+/// The heap size committed on startup, unless set with `-Xms`.
+pub const DEFAULT_INITIAL_HEAP_SIZE: usize = 16 * 1024 * 1024;
+
+/// The largest heap that references can address, as a reference is the offset of an object in
+/// units of 8 bytes, leaving some space for the pages lost to alignment.
+pub const MAX_HEAP_SIZE: usize = (1 << 35) - 1024 * 1024;
+
+/// Every object in the heap starts on an 8 byte boundary.
+pub const OBJECT_ALIGNMENT: usize = 8;
+
+/// With `-XX:+GCStress`, every this many stress collections is a full collection, rather than
+/// a minor collection.
+const FULL_GC_STRESS_RATIO: usize = 10;
+
+/// The heap doesn't have space for an allocation, even after a full garbage collection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct OutOfMemory;
+
+thread_local! {
+    /// Whether allocations on this thread can use the space the heap keeps in reserve.
+    static USE_RESERVE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Run `f`, letting allocations on this thread use the space that the heap keeps in reserve
+/// for the JVM itself, such as for filling in the stack trace of an `OutOfMemoryError`.
+pub fn with_reserve<T>(f: impl FnOnce() -> T) -> T {
+    let previous = USE_RESERVE.replace(true);
+    let result = f();
+    USE_RESERVE.set(previous);
+    result
+}
+
+/// Round a size up to a whole number of [`OBJECT_ALIGNMENT`] units.
+pub fn object_align(size: usize) -> usize {
+    size.next_multiple_of(OBJECT_ALIGNMENT)
+}
+
+/// Whether allocations on this thread are currently using the reserve.
+pub fn using_reserve() -> bool {
+    USE_RESERVE.get()
+}
 
 /// The allocator is the actual heap memory that is used for storing objects.
 pub struct Allocator {
     pub rt: Option<Arc<Runtime>>,
-    pub gen: CopyGeneration,
+    /// The address space of the whole heap, that references are offsets into.
+    reservation: Reservation,
+    /// The nursery that new objects are allocated in.
+    pub young: CopyGeneration,
+    /// The objects that have survived the nursery, and objects too large for it.
+    pub old: CompactGeneration,
+    pub collector: Collector,
+    /// The size of the heap committed up front, for the `-Xms` option.
+    initial: usize,
     hash_code: HashCode,
+    /// The number of allocations between stress collections, if `-XX:+GCStress` is on.
+    gc_stress_interval: Option<usize>,
+    /// The number of allocations that could have collected garbage so far.
+    allocations: AtomicUsize,
     // pub safe_point: SafePoint,
 }
 
 impl Allocator {
-    pub fn new() -> Self {
+    /// Create an allocator for a heap sized by the `-Xmx` and `-Xms` options.
+    ///
+    /// Like `java`, an initial size larger than the default maximum size raises the maximum.
+    /// A third of the heap is the nursery, split into its two semispaces, and the rest is the
+    /// old generation.
+    pub fn new(options: &Options) -> Self {
+        let max_size = options.max_heap_size
+            .unwrap_or(DEFAULT_MAX_HEAP_SIZE.max(options.initial_heap_size.unwrap_or(0)))
+            .min(MAX_HEAP_SIZE);
+        let initial_size = options.initial_heap_size
+            .unwrap_or(DEFAULT_INITIAL_HEAP_SIZE)
+            .min(max_size);
+
+        let young_size = page_align(max_size / 6);
+        let old_size = page_align(max_size - 2 * (max_size / 6));
+        let mut reservation = Reservation::new(2 * young_size + old_size);
+        let young = CopyGeneration::new(&mut reservation, young_size, initial_size / 6);
+        let old = CompactGeneration::new(&mut reservation, old_size, initial_size - 2 * (initial_size / 6));
+        let initial = young.committed() + old.committed();
+
         Allocator {
             rt: None,
-            gen: CopyGeneration::new(),
+            young,
+            old,
+            reservation,
+            collector: Collector::new(options),
+            initial,
             hash_code: HashCode::new(),
+            gc_stress_interval: options.gc_stress.then_some(options.gc_stress_interval),
+            allocations: AtomicUsize::new(0),
             // safe_point: SafePoint::new(),
         }
     }
 
     pub fn swap(&self) {
-        self.gen.swap();
+        self.young.swap();
+    }
+
+    /// The reference to the object that starts at the given address.
+    pub fn reference(&self, start: usize) -> Reference {
+        Reference(((start - self.reservation.start()) / OBJECT_ALIGNMENT) as u32)
+    }
+
+    /// The address of the start of the referenced object.
+    pub fn start(&self, reference: Reference) -> usize {
+        if reference.0 == 0 {
+            panic!("Dereferenced a null reference");
+        }
+        self.reservation.start() + reference.0 as usize * OBJECT_ALIGNMENT
+    }
+
+    /// The space used by objects in the heap.
+    pub fn used(&self) -> usize {
+        self.young.used() + self.old.used()
+    }
+
+    /// The maximum size that the heap can grow to, not counting the semispace of the nursery
+    /// that is empty.
+    pub fn capacity(&self) -> usize {
+        self.young.capacity() + self.old.capacity()
+    }
+
+    /// The size of the heap that has been committed so far.
+    pub fn committed(&self) -> usize {
+        self.young.committed() + self.old.committed()
+    }
+
+    /// The size of the heap that was committed when it was created.
+    pub fn initial(&self) -> usize {
+        self.initial
     }
 
     pub fn set_rt(&self, rt: Arc<Runtime>) {
@@ -317,51 +455,52 @@ impl Allocator {
     }
 
     pub fn print_stats(&self) {
-        let used = self.gen.used();
+        let used = self.used();
         let used_mbs = (used / 1024) / 1024;
-        let max = HEAP_SIZE;
+        let max = self.capacity();
         let percentage = 100.0 * (used as f64) / (max as f64);
         trace!(target: log::HEAP, used=format!("{}mb {:.2}%", used_mbs, percentage));
     }
 
-    pub fn copy_array(&self, array: Array) -> Array {
+    /// Allocate an empty array of the same type and length as the given array.
+    pub fn new_array_like(&self, array: Array) -> Result<Array, OutOfMemory> {
         let header = array.header();
         let array_length = Int((header.length / header.component.component_width()) as i32);
 
-        let new_arr = self.new_array(header.component.clone(), array_length);
+        self.new_array(header.component.clone(), array_length)
+    }
+
+    /// Copy the elements of an array into another array of the same type and length.
+    pub fn copy_array(&self, array: Array, new_arr: Array) {
+        let header = array.header();
 
         unsafe {
             ptr::copy_nonoverlapping(array.data.cast_const(), new_arr.data, header.length);
         }
-
-        new_arr
     }
 
-    pub fn copy_object(&self, object: Object) -> Object {
-        let header = object.header();
-        let class = unsafe { header.class.as_ref().unwrap() };
-
-        let new_object = self.new_object(class);
+    /// Copy the fields of an object into another object of the same class.
+    pub fn copy_object(&self, object: Object, new_object: Object) {
+        let class = object.class();
 
         unsafe {
             ptr::copy_nonoverlapping(object.data.cast_const(), new_object.data, class.instance_width);
         }
-
-        new_object
     }
 
-    pub fn raw(&self, bytes: usize) -> *mut u8 {
-        let start_ptr = self.allocate(self.rt.as_ref().unwrap().clone(), bytes);
-        start_ptr
+    pub fn raw(&self, bytes: usize) -> Result<*mut u8, OutOfMemory> {
+        self.allocate(self.rt.as_ref().unwrap().clone(), bytes, false)
     }
 
-    pub fn new_object(&self, class: &ObjectClass) -> Object {
+    /// Allocate an object, which is pinned in the old generation if its kind is
+    /// [`Kind::Pinned`].
+    pub fn new_object(&self, class: &ObjectClass, kind: Kind) -> Result<Object, OutOfMemory> {
         trace!(target: log::HEAP, class=class.name.as_str(), "Allocating object");
         let header_size = size_of::<ObjectHeader>();
         let data_size = class.instance_width;
         let size = header_size + data_size;
 
-        let start_ptr = self.allocate(self.rt.as_ref().unwrap().clone(), size);
+        let start_ptr = self.allocate(self.rt.as_ref().unwrap().clone(), size, kind == Kind::Pinned)?;
 
         let class_ptr = class as *const ObjectClass;
 
@@ -372,25 +511,27 @@ impl Allocator {
             };
 
             object.header.write(ObjectHeader {
-                class: class_ptr,
+                kind,
+                age: 0,
                 hash_code: self.hash_code.next(),
+                class: class_ptr,
                 lock: ObjectLock::new(),
             });
             object.data.write_bytes(0, class.instance_width);
 
             self.print_stats();
 
-            object
+            Ok(object)
         }
     }
 
-    pub fn new_static_object(&self, class: &ObjectClass) -> Object {
+    pub fn new_static_object(&self, class: &ObjectClass) -> Result<Object, OutOfMemory> {
         trace!(target: log::HEAP, class=class.name.as_str(), "Allocating static object");
         let header_size = size_of::<ObjectHeader>();
         let data_size = class.static_width;
         let size = header_size + data_size;
 
-        let start_ptr = self.allocate(self.rt.as_ref().unwrap().clone(), size);
+        let start_ptr = self.allocate(self.rt.as_ref().unwrap().clone(), size, true)?;
 
         let class_ptr = class as *const ObjectClass;
 
@@ -401,25 +542,27 @@ impl Allocator {
             };
 
             object.header.write(ObjectHeader {
-                class: class_ptr,
+                kind: Kind::Static,
+                age: 0,
                 hash_code: Int(0),
+                class: class_ptr,
                 lock: ObjectLock::new(),
             });
             object.data.write_bytes(0, class.static_width);
 
             self.print_stats();
 
-            object
+            Ok(object)
         }
     }
 
-    pub fn new_array(&self, component: Class, length: Int) -> Array {
+    pub fn new_array(&self, component: Class, length: Int) -> Result<Array, OutOfMemory> {
         trace!(target: log::HEAP, component=component.name(), length=length.0, "Allocating array");
         let header_size = size_of::<ArrayHeader>();
         let data_size = length.0 as usize * component.component_width();
         let size = header_size + data_size;
 
-        let start_ptr = self.allocate(self.rt.as_ref().unwrap().clone(), size);
+        let start_ptr = self.allocate(self.rt.as_ref().unwrap().clone(), size, false)?;
 
         unsafe {
             let array = Array {
@@ -428,27 +571,82 @@ impl Allocator {
             };
 
             array.header.write(ArrayHeader {
+                kind: Kind::Array,
+                age: 0,
+                hash_code: self.hash_code.next(),
                 component,
                 length: data_size,
-                hash_code: self.hash_code.next(),
                 lock: ObjectLock::new(),
             });
-            array.data.write_bytes(0, size);
+            array.data.write_bytes(0, data_size);
 
             self.print_stats();
 
-            array
+            Ok(array)
         }
     }
 
     /// Allocate the given number of bytes, returning a pointer to the start.
-    fn allocate(&self, rt: Arc<Runtime>, size: usize) -> *mut u8 {
-        self.gen.allocate(rt, size)
+    ///
+    /// Objects are allocated in the nursery, collecting it when it's full, and objects that
+    /// don't fit in the nursery, or are pinned, are allocated in the old generation. If the heap
+    /// is full, a full garbage collection is run before giving up, unless the allocation can use
+    /// the reserve, as the JVM can't always wait for a collection.
+    fn allocate(&self, rt: Arc<Runtime>, size: usize, pinned: bool) -> Result<*mut u8, OutOfMemory> {
+        let size = object_align(size);
+        let use_reserve = USE_RESERVE.get();
+        if let Some(collection) = self.stress_collection(use_reserve) {
+            let cause = if collection == Collection::Full { GcCause::FullGcAlot } else { GcCause::ScavengeAlot };
+            self.collector.collect(rt.clone(), collection, cause);
+        }
+        let fits_young = !pinned && size <= self.young.capacity() / 2;
+        if fits_young {
+            if let Some(allocated) = self.young.allocate(size) {
+                return Ok(allocated);
+            }
+            if !use_reserve {
+                self.collector.collect(rt.clone(), Collection::Minor, GcCause::AllocationFailure);
+                if let Some(allocated) = self.young.allocate(size) {
+                    return Ok(allocated);
+                }
+            }
+        }
+        if let Some(allocated) = self.old.allocate(size, use_reserve) {
+            return Ok(allocated);
+        }
+        if !use_reserve {
+            // Every soft reference is cleared before giving up.
+            for (collection, cause) in [(Collection::Full, GcCause::AllocationFailure), (Collection::Exhaustive, GcCause::LastDitch)] {
+                self.collector.collect(rt.clone(), collection, cause);
+                if let Some(allocated) = self.old.allocate(size, false) {
+                    return Ok(allocated);
+                }
+            }
+        }
+        debug!(target: log::HEAP, size, "Out of memory");
+        Err(OutOfMemory)
+    }
+
+    /// The collection to run before an allocation with `-XX:+GCStress`, if it's time for one.
+    ///
+    /// Most are minor collections, but the whole heap is collected every so often, so that
+    /// compacting the old generation is stressed too.
+    fn stress_collection(&self, use_reserve: bool) -> Option<Collection> {
+        let interval = self.gc_stress_interval.filter(|_| !use_reserve)?;
+        let allocations = self.allocations.fetch_add(1, Ordering::Relaxed) + 1;
+        if !allocations.is_multiple_of(interval) {
+            return None;
+        }
+        if (allocations / interval).is_multiple_of(FULL_GC_STRESS_RATIO) {
+            Some(Collection::Full)
+        } else {
+            Some(Collection::Minor)
+        }
     }
 
     // pub fn gc(&self, thread: &Thread) {
     //
-    //     let percentage = (100 * self.gen.used()) / HEAP_SIZE;
+    //     let percentage = (100 * self.used()) / HEAP_SIZE;
     //     if percentage > 25 {
     //         // self.safe_point.start_gc(thread);
     //
@@ -0,0 +1,772 @@
+//! Dumping the heap to a file in the HPROF binary format, that heap analyzers like Eclipse MAT
+//! and VisualVM open, with `-XX:+HeapDumpOnOutOfMemoryError`, or
+//! `HotSpotDiagnosticMXBean.dumpHeap`.
+//!
+//! The heap is dumped by the GC thread at the end of a collection, while every thread is
+//! stopped and the survivors in the nursery are packed together, so every object can be walked
+//! in turn. After a full collection only the live objects are left, after a minor collection the
+//! old generation still holds the objects that have died there since the last full collection.
+//!
+//! Like HotSpot, a `java.lang.Class` object is written as the class it represents, along with
+//! the static fields of the class, and the static storage of a class isn't written as an object
+//! at all. Classes that don't have a `java.lang.Class` object yet are given an ID of their own.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process::id;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::collection::classes::ClassRef;
+use crate::heap::allocator::Kind;
+use crate::heap::Heaped;
+use crate::java::{FieldType, Reference, Value};
+use crate::method_area::{Class, Field, ObjectClass, Primitive};
+use crate::runtime::{Options, Runtime};
+use crate::thread::Thread;
+
+const HEADER: &[u8] = b"JAVA PROFILE 1.0.2\0";
+
+/// IDs are 8 bytes, so the IDs of the names and of the classes without a class object can be
+/// kept apart from the references of objects.
+const ID_SIZE: u32 = 8;
+
+/// The first ID that isn't the reference of an object.
+const FIRST_ID: u64 = 1 << 32;
+
+/// The size that a heap dump segment is written out at.
+const SEGMENT_SIZE: usize = 1024 * 1024;
+
+/// The serial number of the empty stack trace that every object and thread is given, as the
+/// JVM doesn't record where objects were allocated.
+const STACK_TRACE_SERIAL: u32 = 1;
+
+// The tags of the records.
+const STRING: u8 = 0x01;
+const LOAD_CLASS: u8 = 0x02;
+const STACK_TRACE: u8 = 0x05;
+const HEAP_DUMP_SEGMENT: u8 = 0x1C;
+const HEAP_DUMP_END: u8 = 0x2C;
+
+// The tags of the sub-records of a heap dump segment.
+const ROOT_UNKNOWN: u8 = 0xFF;
+const ROOT_JNI_GLOBAL: u8 = 0x01;
+const ROOT_JNI_LOCAL: u8 = 0x02;
+const ROOT_JAVA_FRAME: u8 = 0x03;
+const ROOT_STICKY_CLASS: u8 = 0x05;
+const ROOT_THREAD_OBJECT: u8 = 0x08;
+const CLASS_DUMP: u8 = 0x20;
+const INSTANCE_DUMP: u8 = 0x21;
+const OBJECT_ARRAY_DUMP: u8 = 0x22;
+const PRIMITIVE_ARRAY_DUMP: u8 = 0x23;
+
+// The basic types of fields and array elements.
+const OBJECT: u8 = 2;
+const BOOLEAN: u8 = 4;
+const CHAR: u8 = 5;
+const FLOAT: u8 = 6;
+const DOUBLE: u8 = 7;
+const BYTE: u8 = 8;
+const SHORT: u8 = 9;
+const INT: u8 = 10;
+const LONG: u8 = 11;
+
+/// The file to dump the heap to when the JVM runs out of memory, `java_pid<pid>.hprof` in the
+/// working directory, or in the directory given by `-XX:HeapDumpPath`, unless that names a file.
+pub fn out_of_memory_path(options: &Options) -> PathBuf {
+    let file_name = format!("java_pid{}.hprof", id());
+    match &options.heap_dump_path {
+        Some(path) if path.is_dir() => path.join(file_name),
+        Some(path) => path.clone(),
+        None => PathBuf::from(file_name),
+    }
+}
+
+/// Dump the heap the first time the JVM runs out of memory, with
+/// `-XX:+HeapDumpOnOutOfMemoryError`.
+pub fn dump_on_out_of_memory(runtime: &Arc<Runtime>) {
+    if !runtime.options.heap_dump_on_out_of_memory_error ||
+        runtime.heap.dumped_on_out_of_memory.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let path = out_of_memory_path(&runtime.options);
+    println!("Dumping heap to {} ...", path.display());
+    let start = Instant::now();
+    match runtime.heap.allocator.collector.dump_heap(runtime.clone(), path.clone(), false) {
+        Ok(size) => println!("Heap dump file created [{} bytes in {:.3} secs]", size, start.elapsed().as_secs_f64()),
+        Err(error) => println!("Unable to create {}: {}", path.display(), error),
+    }
+}
+
+/// Write every object in the heap, and the classes and roots that refer to them, to a new
+/// file, returning the size of the file.
+///
+/// Every thread must be stopped, with the nursery packed by a collection.
+pub fn dump_heap(runtime: &Runtime, threads: &[Arc<Thread>], path: &Path) -> io::Result<u64> {
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    let mut dump = Dump {
+        runtime,
+        out: BufWriter::new(file),
+        names: HashMap::new(),
+        classes: HashMap::new(),
+        object_classes: HashMap::new(),
+        class_objects: HashSet::new(),
+        segment: Vec::new(),
+        next_id: FIRST_ID,
+    };
+
+    let objects = objects(runtime);
+    dump.header()?;
+    dump.classes(&objects)?;
+    dump.roots(threads)?;
+    for start in objects {
+        dump.object(start)?;
+    }
+    dump.end()
+}
+
+/// The addresses of the objects in the heap, the survivors in the nursery are packed together
+/// at its start.
+fn objects(runtime: &Runtime) -> Vec<usize> {
+    let allocator = &runtime.heap.allocator;
+    let mut objects = Vec::new();
+    let mut start = allocator.young.start();
+    while allocator.young.contains(start) {
+        objects.push(start);
+        start += Heaped::at(start).size();
+    }
+    objects.extend(allocator.old.objects());
+    objects
+}
+
+struct Dump<'a> {
+    runtime: &'a Runtime,
+    out: BufWriter<File>,
+    /// The IDs of the names written so far.
+    names: HashMap<String, u64>,
+    /// The IDs of the classes, by their defining loader and name, which are the references of
+    /// their class objects where they have one.
+    classes: HashMap<(Reference, String), u64>,
+    /// The IDs of the loaded classes.
+    object_classes: HashMap<*const ObjectClass, u64>,
+    /// The references of the class objects, which are written as the classes themselves.
+    class_objects: HashSet<u32>,
+    /// The sub-records of the heap dump segment being written.
+    segment: Vec<u8>,
+    next_id: u64,
+}
+
+impl Dump<'_> {
+    fn header(&mut self) -> io::Result<()> {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        self.out.write_all(HEADER)?;
+        self.out.write_all(&ID_SIZE.to_be_bytes())?;
+        self.out.write_all(&millis.to_be_bytes())?;
+
+        let mut trace = Vec::new();
+        put_u4(&mut trace, STACK_TRACE_SERIAL);
+        put_u4(&mut trace, 0);
+        put_u4(&mut trace, 0);
+        self.record(STACK_TRACE, &trace)
+    }
+
+    /// Write the loaded classes, the classes that have class objects, and the array classes of
+    /// the arrays in the heap.
+    fn classes(&mut self, objects: &[usize]) -> io::Result<()> {
+        let heap = &self.runtime.heap;
+        for (key, class_object) in heap.class_objects.current_entries() {
+            self.classes.insert(key, class_object.0 as u64);
+            self.class_objects.insert(class_object.0);
+        }
+
+        let loaded: HashMap<(Reference, String), ClassRef> = self.runtime.method_area.loaded_classes().into_iter()
+            .map(|class| ((class.loader, class.name.clone()), class))
+            .collect();
+        for (key, class) in loaded.iter() {
+            let id = self.class_id(key.clone());
+            self.object_classes.insert(&**class as *const ObjectClass, id);
+        }
+        for start in objects {
+            if let Heaped::Array(array) = Heaped::at(*start) {
+                let component = &array.header().component;
+                self.class_id((component.loader(), format!("[{}", component.binary_name())));
+            }
+        }
+
+        let mut classes: Vec<((Reference, String), u64)> = self.classes.clone().into_iter().collect();
+        classes.sort_by_key(|(_, id)| *id);
+        for (serial, ((_, name), id)) in classes.iter().enumerate() {
+            let name = self.name(&name.replace('.', "/"))?;
+            let mut load = Vec::new();
+            put_u4(&mut load, serial as u32 + 1);
+            put_id(&mut load, *id);
+            put_u4(&mut load, STACK_TRACE_SERIAL);
+            put_id(&mut load, name);
+            self.record(LOAD_CLASS, &load)?;
+        }
+
+        let statics: HashMap<(Reference, String), Reference> = heap.static_objects.current_entries().into_iter().collect();
+        let object = self.classes.get(&(Reference(0), "java.lang.Object".to_string())).copied().unwrap_or(0);
+        for (key, id) in classes {
+            let (loader, name) = &key;
+            let mut class = Vec::new();
+            class.push(CLASS_DUMP);
+            put_id(&mut class, id);
+            put_u4(&mut class, STACK_TRACE_SERIAL);
+            match loaded.get(&key) {
+                Some(loaded) => {
+                    let super_class = loaded.super_class
+                        .map_or(0, |super_class| self.object_classes[&(&*super_class as *const ObjectClass)]);
+                    put_id(&mut class, super_class);
+                    put_id(&mut class, loader.0 as u64);
+                    // The signers, protection domain, and two reserved IDs.
+                    class.extend([0; 4 * ID_SIZE as usize]);
+                    put_u4(&mut class, loaded.instance_width as u32);
+                    put_u2(&mut class, 0);
+
+                    let statics = statics.get(&key).filter(|statics| statics.0 != 0).copied();
+                    put_u2(&mut class, loaded.static_fields.len() as u16);
+                    for field in loaded.static_fields.iter() {
+                        put_id(&mut class, self.name(&field.name)?);
+                        class.push(basic_type(&field.descriptor));
+                        match statics {
+                            Some(statics) => put_field(&mut class, heap.get_object(statics).data, field),
+                            None => class.extend(vec![0; field_size(&field.descriptor)]),
+                        }
+                    }
+
+                    put_u2(&mut class, loaded.instance_fields.len() as u16);
+                    for field in loaded.instance_fields.iter() {
+                        put_id(&mut class, self.name(&field.name)?);
+                        class.push(basic_type(&field.descriptor));
+                    }
+                }
+                None => {
+                    // An array class extends `java.lang.Object`, a primitive class is a root.
+                    put_id(&mut class, if name.starts_with('[') { object } else { 0 });
+                    put_id(&mut class, loader.0 as u64);
+                    class.extend([0; 4 * ID_SIZE as usize]);
+                    put_u4(&mut class, 0);
+                    put_u2(&mut class, 0);
+                    put_u2(&mut class, 0);
+                    put_u2(&mut class, 0);
+                }
+            }
+            self.sub_record(&class)?;
+
+            if loader.0 == 0 {
+                let mut root = vec![ROOT_STICKY_CLASS];
+                put_id(&mut root, id);
+                self.sub_record(&root)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The ID of a class, giving the class an ID of its own if it doesn't have a class object.
+    fn class_id(&mut self, key: (Reference, String)) -> u64 {
+        if let Some(id) = self.classes.get(&key) {
+            return *id;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.classes.insert(key, id);
+        id
+    }
+
+    /// Write the roots held by the JVM, and by each thread.
+    fn roots(&mut self, threads: &[Arc<Thread>]) -> io::Result<()> {
+        let heap = &self.runtime.heap;
+
+        let mut unknown: Vec<u32> = heap.string_constants.current_values().into_iter().collect();
+        unknown.extend(heap.out_of_memory_error().map(|error| error.0));
+        unknown.extend(heap.pending_references.lock().unwrap().iter().map(|reference| reference.0));
+        for reference in unknown {
+            self.root(ROOT_UNKNOWN, reference, &[])?;
+        }
+
+        let globals: Vec<u32> = heap.global_refs.lock().unwrap().iter().flatten().map(|reference| reference.0).collect();
+        for reference in globals {
+            self.root(ROOT_JNI_GLOBAL, reference, &[0; ID_SIZE as usize])?;
+        }
+
+        for (index, thread) in threads.iter().enumerate() {
+            // The thread is stopped, so the collector has the only access to its stack.
+            let thread = unsafe { Arc::as_ptr(thread).cast_mut().as_mut().unwrap() };
+            let serial = index as u32 + 1;
+            if let Some(reference) = thread.reference {
+                let mut info = Vec::new();
+                put_u4(&mut info, serial);
+                put_u4(&mut info, STACK_TRACE_SERIAL);
+                self.root(ROOT_THREAD_OBJECT, reference.0, &info)?;
+            }
+
+            // The frames are numbered from the top of the stack.
+            for (depth, frame) in thread.stack.iter_mut().rev().enumerate() {
+                let mut info = Vec::new();
+                put_u4(&mut info, serial);
+                put_u4(&mut info, depth as u32);
+
+                let locals: Vec<u32> = frame.local_vars.roots()
+                    .chain(frame.operand_stack.roots())
+                    .map(|reference| reference.0)
+                    .collect();
+                for reference in locals {
+                    self.root(ROOT_JAVA_FRAME, reference, &info)?;
+                }
+
+                let natives: Vec<u32> = frame.native_args.iter()
+                    .filter_map(|value| match value {
+                        Value::Reference(reference) => Some(reference.0),
+                        _ => None,
+                    })
+                    .chain(frame.native_roots.iter().map(|reference| reference.0))
+                    .chain(frame.native_ex.map(|reference| reference.0))
+                    .collect();
+                for reference in natives {
+                    self.root(ROOT_JNI_LOCAL, reference, &info)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn root(&mut self, tag: u8, reference: u32, info: &[u8]) -> io::Result<()> {
+        if reference == 0 {
+            return Ok(());
+        }
+        let mut root = vec![tag];
+        put_id(&mut root, reference as u64);
+        root.extend(info);
+        self.sub_record(&root)
+    }
+
+    /// Write an instance or an array, unless it's a class object or the static storage of a
+    /// class, which are written with the class.
+    fn object(&mut self, start: usize) -> io::Result<()> {
+        let heaped = Heaped::at(start);
+        let reference = self.runtime.heap.allocator.reference(start).0;
+        if heaped.kind() == Kind::Static || self.class_objects.contains(&reference) {
+            return Ok(());
+        }
+
+        let mut record = Vec::new();
+        match heaped {
+            Heaped::Object(object) => {
+                let class = object.class();
+                record.push(INSTANCE_DUMP);
+                put_id(&mut record, reference as u64);
+                put_u4(&mut record, STACK_TRACE_SERIAL);
+                put_id(&mut record, self.object_classes[&(class as *const ObjectClass)]);
+
+                // The fields of the class come first, then those of each super class in turn.
+                let mut values = Vec::new();
+                for class in class.parents() {
+                    for field in class.instance_fields.iter() {
+                        put_field(&mut values, object.data, field);
+                    }
+                }
+                put_u4(&mut record, values.len() as u32);
+                record.extend(values);
+            }
+            Heaped::Array(array) => {
+                let header = array.header();
+                let width = header.component.component_width();
+                let data = unsafe { std::slice::from_raw_parts(array.data.cast_const(), header.length) };
+                match &header.component {
+                    Class::Primitive(primitive) => {
+                        record.push(PRIMITIVE_ARRAY_DUMP);
+                        put_id(&mut record, reference as u64);
+                        put_u4(&mut record, STACK_TRACE_SERIAL);
+                        put_u4(&mut record, (header.length / width) as u32);
+                        record.push(primitive_type(primitive));
+                        for element in data.chunks(width) {
+                            put_primitive(&mut record, element);
+                        }
+                    }
+                    component => {
+                        let class = self.classes[&(component.loader(), format!("[{}", component.binary_name()))];
+                        record.push(OBJECT_ARRAY_DUMP);
+                        put_id(&mut record, reference as u64);
+                        put_u4(&mut record, STACK_TRACE_SERIAL);
+                        put_u4(&mut record, (header.length / width) as u32);
+                        put_id(&mut record, class);
+                        for element in array.as_ref_slice() {
+                            put_id(&mut record, *element as u64);
+                        }
+                    }
+                }
+            }
+        }
+        self.sub_record(&record)
+    }
+
+    /// Write the last heap dump segment, and the end of the dump, returning the size of the
+    /// file.
+    fn end(mut self) -> io::Result<u64> {
+        self.flush_segment()?;
+        self.record(HEAP_DUMP_END, &[])?;
+        let file = self.out.into_inner().map_err(|error| error.into_error())?;
+        file.sync_all()?;
+        Ok(file.metadata()?.len())
+    }
+
+    /// The ID of a name, writing it out the first time it's used.
+    fn name(&mut self, name: &str) -> io::Result<u64> {
+        if let Some(id) = self.names.get(name) {
+            return Ok(*id);
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.names.insert(name.to_string(), id);
+
+        let mut string = Vec::new();
+        put_id(&mut string, id);
+        string.extend(name.as_bytes());
+        self.record(STRING, &string)?;
+        Ok(id)
+    }
+
+    fn record(&mut self, tag: u8, body: &[u8]) -> io::Result<()> {
+        self.out.write_all(&[tag])?;
+        // The time since the header's timestamp.
+        self.out.write_all(&0u32.to_be_bytes())?;
+        self.out.write_all(&(body.len() as u32).to_be_bytes())?;
+        self.out.write_all(body)
+    }
+
+    fn sub_record(&mut self, sub_record: &[u8]) -> io::Result<()> {
+        self.segment.extend(sub_record);
+        if self.segment.len() >= SEGMENT_SIZE {
+            self.flush_segment()?;
+        }
+        Ok(())
+    }
+
+    fn flush_segment(&mut self) -> io::Result<()> {
+        if self.segment.is_empty() {
+            return Ok(());
+        }
+        let segment = std::mem::take(&mut self.segment);
+        self.record(HEAP_DUMP_SEGMENT, &segment)
+    }
+}
+
+fn put_u2(out: &mut Vec<u8>, value: u16) {
+    out.extend(value.to_be_bytes());
+}
+
+fn put_u4(out: &mut Vec<u8>, value: u32) {
+    out.extend(value.to_be_bytes());
+}
+
+fn put_id(out: &mut Vec<u8>, id: u64) {
+    out.extend(id.to_be_bytes());
+}
+
+/// Write the value of a field of the object or static storage whose fields start at `data`.
+fn put_field(out: &mut Vec<u8>, data: *mut u8, field: &Field) {
+    let size = match field.descriptor {
+        FieldType::Reference(_) | FieldType::Array(_) => 4,
+        ref descriptor => field_size(descriptor),
+    };
+    let value = unsafe { std::slice::from_raw_parts(data.add(field.offset).cast_const(), size) };
+    match field.descriptor {
+        FieldType::Reference(_) | FieldType::Array(_) => {
+            put_id(out, u32::from_ne_bytes(value.try_into().unwrap()) as u64);
+        }
+        _ => put_primitive(out, value),
+    }
+}
+
+/// Write a primitive value, stored in the JVM's byte order, in big-endian order.
+fn put_primitive(out: &mut Vec<u8>, value: &[u8]) {
+    if cfg!(target_endian = "little") {
+        out.extend(value.iter().rev());
+    } else {
+        out.extend(value);
+    }
+}
+
+/// The size of the value of a field in the dump.
+fn field_size(descriptor: &FieldType) -> usize {
+    match descriptor {
+        FieldType::Boolean | FieldType::Byte => 1,
+        FieldType::Char | FieldType::Short => 2,
+        FieldType::Int | FieldType::Float => 4,
+        FieldType::Long | FieldType::Double => 8,
+        FieldType::Reference(_) | FieldType::Array(_) => ID_SIZE as usize,
+    }
+}
+
+fn basic_type(descriptor: &FieldType) -> u8 {
+    match descriptor {
+        FieldType::Boolean => BOOLEAN,
+        FieldType::Byte => BYTE,
+        FieldType::Char => CHAR,
+        FieldType::Short => SHORT,
+        FieldType::Int => INT,
+        FieldType::Float => FLOAT,
+        FieldType::Long => LONG,
+        FieldType::Double => DOUBLE,
+        FieldType::Reference(_) | FieldType::Array(_) => OBJECT,
+    }
+}
+
+fn primitive_type(primitive: &Primitive) -> u8 {
+    match primitive {
+        Primitive::Boolean => BOOLEAN,
+        Primitive::Byte => BYTE,
+        Primitive::Char => CHAR,
+        Primitive::Short => SHORT,
+        Primitive::Int => INT,
+        Primitive::Float => FLOAT,
+        Primitive::Long => LONG,
+        Primitive::Double => DOUBLE,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env::temp_dir;
+    use std::fs::{read, remove_file};
+
+    use nohash_hasher::BuildNoHashHasher;
+
+    use crate::heap::garbage_collector::{Collection, GenerationalCollector};
+    use crate::heap::gc_log::GcCause;
+    use crate::java::Int;
+    use crate::method_area::ClassFlags;
+    use crate::method_area::const_pool::ConstPool;
+    use crate::method_area::Primitive::Char;
+
+    use super::*;
+
+    /// The records of a heap dump that the tests look at.
+    #[derive(Default)]
+    struct Parsed {
+        /// The IDs of the classes, by name.
+        classes: HashMap<String, u64>,
+        roots: Vec<(u8, u64)>,
+        /// The IDs of the instances and their classes.
+        instances: Vec<(u64, u64)>,
+        /// The IDs of the object arrays, their classes, and their elements.
+        object_arrays: Vec<(u64, u64, Vec<u64>)>,
+        /// The IDs of the primitive arrays, their element types, and their data.
+        primitive_arrays: Vec<(u64, u8, Vec<u8>)>,
+    }
+
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl Reader<'_> {
+        fn bytes(&mut self, length: usize) -> &[u8] {
+            let bytes = &self.bytes[self.position..self.position + length];
+            self.position += length;
+            bytes
+        }
+
+        fn u1(&mut self) -> u8 {
+            self.bytes(1)[0]
+        }
+
+        fn u2(&mut self) -> u16 {
+            u16::from_be_bytes(self.bytes(2).try_into().unwrap())
+        }
+
+        fn u4(&mut self) -> u32 {
+            u32::from_be_bytes(self.bytes(4).try_into().unwrap())
+        }
+
+        fn id(&mut self) -> u64 {
+            u64::from_be_bytes(self.bytes(8).try_into().unwrap())
+        }
+
+        fn value(&mut self, basic_type: u8) {
+            let size = match basic_type {
+                BOOLEAN | BYTE => 1,
+                CHAR | SHORT => 2,
+                FLOAT | INT => 4,
+                _ => 8,
+            };
+            self.bytes(size);
+        }
+
+        fn is_empty(&self) -> bool {
+            self.position == self.bytes.len()
+        }
+    }
+
+    fn parse(bytes: &[u8]) -> Parsed {
+        assert!(bytes.starts_with(HEADER));
+        let mut reader = Reader { bytes, position: HEADER.len() };
+        assert_eq!(reader.u4(), ID_SIZE);
+        reader.bytes(8);
+
+        let mut parsed = Parsed::default();
+        let mut names = HashMap::new();
+        loop {
+            let tag = reader.u1();
+            reader.u4();
+            let length = reader.u4() as usize;
+            let body = reader.bytes(length);
+            let mut record = Reader { bytes: body, position: 0 };
+            match tag {
+                STRING => {
+                    let id = record.id();
+                    names.insert(id, String::from_utf8(body[8..].to_vec()).unwrap());
+                }
+                LOAD_CLASS => {
+                    record.u4();
+                    let id = record.id();
+                    record.u4();
+                    parsed.classes.insert(names[&record.id()].clone(), id);
+                }
+                HEAP_DUMP_SEGMENT => parse_segment(&mut record, &mut parsed),
+                HEAP_DUMP_END => break,
+                _ => {}
+            }
+        }
+        assert!(reader.is_empty());
+        parsed
+    }
+
+    fn parse_segment(segment: &mut Reader, parsed: &mut Parsed) {
+        while !segment.is_empty() {
+            let tag = segment.u1();
+            let id = segment.id();
+            match tag {
+                ROOT_UNKNOWN | ROOT_STICKY_CLASS => parsed.roots.push((tag, id)),
+                ROOT_JNI_GLOBAL => {
+                    segment.id();
+                    parsed.roots.push((tag, id));
+                }
+                ROOT_JNI_LOCAL | ROOT_JAVA_FRAME | ROOT_THREAD_OBJECT => {
+                    segment.bytes(8);
+                    parsed.roots.push((tag, id));
+                }
+                CLASS_DUMP => {
+                    segment.bytes(4 + 6 * 8 + 4);
+                    for _ in 0..segment.u2() {
+                        segment.u2();
+                        let basic_type = segment.u1();
+                        segment.value(basic_type);
+                    }
+                    for _ in 0..segment.u2() {
+                        segment.id();
+                        let basic_type = segment.u1();
+                        segment.value(basic_type);
+                    }
+                    for _ in 0..segment.u2() {
+                        segment.id();
+                        segment.u1();
+                    }
+                }
+                INSTANCE_DUMP => {
+                    segment.u4();
+                    let class = segment.id();
+                    let length = segment.u4() as usize;
+                    segment.bytes(length);
+                    parsed.instances.push((id, class));
+                }
+                OBJECT_ARRAY_DUMP => {
+                    segment.u4();
+                    let length = segment.u4();
+                    let class = segment.id();
+                    let elements = (0..length).map(|_| segment.id()).collect();
+                    parsed.object_arrays.push((id, class, elements));
+                }
+                PRIMITIVE_ARRAY_DUMP => {
+                    segment.u4();
+                    let length = segment.u4() as usize;
+                    let basic_type = segment.u1();
+                    let width = if basic_type == CHAR { 2 } else { 1 };
+                    let data = segment.bytes(length * width).to_vec();
+                    parsed.primitive_arrays.push((id, basic_type, data));
+                }
+                _ => panic!("unexpected sub-record {:#x}", tag),
+            }
+        }
+    }
+
+    fn object_class() -> ObjectClass {
+        ObjectClass {
+            name: "java.lang.Object".to_string(),
+            flags: ClassFlags { bits: 0 },
+            const_pool: ConstPool {
+                pool: HashMap::with_hasher(BuildNoHashHasher::default()),
+                loader: Reference(0),
+            },
+            super_class: None,
+            interfaces: vec![],
+            instance_fields: vec![],
+            static_fields: vec![],
+            methods: vec![],
+            attributes: vec![],
+            instance_width: 0,
+            static_width: 0,
+            source_file: None,
+            loader: Reference(0),
+            reference_type: None,
+            has_finalizer: false,
+        }
+    }
+
+    #[test]
+    fn dumps_live_objects_and_their_classes() {
+        let runtime = Runtime::with_options(Options::default());
+        let heap = &runtime.heap;
+        let object_class = runtime.method_area.insert_gen_class(object_class());
+        let object_class = Class::Object(ClassRef::new(object_class));
+
+        let object = heap.new_object(&object_class.obj());
+        let objects = heap.new_array(object_class.clone(), Int(2));
+        heap.get_array(objects).set_element(Int(0), Value::Reference(object));
+        let chars = heap.new_array(Class::Primitive(Char), Int(2));
+        heap.get_array(chars).as_chars_mut().copy_from_slice(&[0x48, 0x69]);
+        heap.new_object(&object_class.obj());
+        let objects = heap.new_global(objects);
+        let chars = heap.new_global(chars);
+
+        GenerationalCollector::new().gc(runtime.clone(), Collection::Minor, GcCause::HeapDump);
+        let objects = heap.global(objects).0 as u64;
+        let chars = heap.global(chars).0 as u64;
+        let object = heap.get_array(Reference(objects as u32)).get_element(Int(0)).reference().0 as u64;
+
+        let path = temp_dir().join(format!("robusta-dump-{}.hprof", id()));
+        let _ = remove_file(&path);
+        let size = dump_heap(&runtime, &[], &path).unwrap();
+        let bytes = read(&path).unwrap();
+        remove_file(&path).unwrap();
+        assert_eq!(size, bytes.len() as u64);
+
+        let parsed = parse(&bytes);
+        let class = parsed.classes["java/lang/Object"];
+        assert_eq!(parsed.instances, vec![(object, class)]);
+        assert_eq!(parsed.object_arrays, vec![(objects, parsed.classes["[Ljava/lang/Object;"], vec![object, 0])]);
+        assert_eq!(parsed.primitive_arrays, vec![(chars, CHAR, vec![0, 0x48, 0, 0x69])]);
+        assert!(parsed.roots.contains(&(ROOT_JNI_GLOBAL, objects)));
+        assert!(parsed.roots.contains(&(ROOT_JNI_GLOBAL, chars)));
+        assert!(parsed.roots.contains(&(ROOT_STICKY_CLASS, class)));
+        assert!(parsed.classes.contains_key("[C"));
+    }
+
+    #[test]
+    fn refuses_to_overwrite_files() {
+        let runtime = Runtime::with_options(Options::default());
+        let path = temp_dir().join(format!("robusta-existing-{}.hprof", id()));
+        File::create(&path).unwrap();
+
+        let error = dump_heap(&runtime, &[], &path).unwrap_err();
+        remove_file(&path).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+    }
+}
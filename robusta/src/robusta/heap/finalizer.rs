@@ -0,0 +1,58 @@
+//! Finalization of the objects of classes that override `Object.finalize`.
+//!
+//! Like HotSpot, an object with a finalizer is registered with `java.lang.ref.Finalizer` as it's
+//! allocated, which refers to it with a final reference. When the collector finds that the
+//! object is only reachable through its final reference, it keeps the object alive for another
+//! cycle, and puts the reference on the pending list. The `Reference Handler` thread adds it to
+//! the queue of the `Finalizer` thread, which runs the finalizer, and clears the reference, so
+//! the object is collected once it's unreachable again.
+
+use crate::heap::allocator::OutOfMemory;
+use crate::java::{MethodType, Reference, Value};
+use crate::method_area::const_pool::MethodKey;
+use crate::thread::Thread;
+
+/// Register an object whose class has a finalizer, in the thread that allocated it, returning
+/// its reference, as the object can move while it's registered.
+///
+/// The objects allocated while the JVM initializes, before any Java thread has started
+/// running, are never finalized.
+pub fn register(thread: &mut Thread, object: Reference) -> Result<Reference, OutOfMemory> {
+    if !thread.is_current() {
+        return Ok(object);
+    }
+
+    thread.push_root(object);
+    let thrown = invoke_finalizer(thread, "register", "(Ljava/lang/Object;)V", vec![Value::Reference(object)]);
+    let object = thread.pop_root();
+
+    // Registering allocates the final reference, so can only fail when the heap is full.
+    match thrown {
+        Some(_) => Err(OutOfMemory),
+        None => Ok(object),
+    }
+}
+
+/// Run the finalizers of the objects that are waiting to be finalized, on a secondary finalizer
+/// thread, for `Runtime.runFinalization`, returning the exception thrown, if any.
+pub fn run_finalization(thread: &mut Thread) -> Option<Value> {
+    invoke_finalizer(thread, "runFinalization", "()V", vec![])
+}
+
+/// Call a static method of `java.lang.ref.Finalizer`, initializing the class first, returning
+/// the exception thrown, if any.
+fn invoke_finalizer(thread: &mut Thread, name: &str, descriptor: &str, args: Vec<Value>) -> Option<Value> {
+    let runtime = thread.runtime.clone();
+    let finalizer = runtime.method_area.load_class("java.lang.ref.Finalizer");
+    if let Err(ex) = runtime.method_area.initialize(thread, &finalizer) {
+        return Some(ex);
+    }
+    let method = finalizer.find_method(&MethodKey {
+        class: finalizer.name.clone(),
+        name: name.to_string(),
+        descriptor: MethodType::from_descriptor(descriptor).unwrap(),
+    }).unwrap();
+
+    let (_, thrown) = thread.native_invoke(&*finalizer, method, args);
+    thrown
+}
@@ -1,65 +1,237 @@
-use std::collections::HashSet;
-use std::mem::size_of;
-use std::ptr::{slice_from_raw_parts, slice_from_raw_parts_mut};
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io;
+use std::mem::take;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::ptr::null_mut;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::thread;
-use std::thread::{Builder, current, scope};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use libc::{_SC_PAGESIZE, MADV_DONTNEED, MAP_ANONYMOUS, MAP_FAILED, MAP_NORESERVE, MAP_PRIVATE, madvise, mmap, mprotect, munmap, PROT_NONE, PROT_READ, PROT_WRITE, sysconf};
 use nohash_hasher::BuildNoHashHasher;
+use parking_lot::Mutex;
 use tracing::{debug, trace};
 
 use crate::heap::{Heap, Heaped};
-use crate::heap::allocator::{ArrayHeader, HEAP_SIZE, ObjectHeader};
-use crate::java::Reference;
+use crate::heap::dump::dump_heap;
+use crate::heap::gc_log::{CpuTime, GcCause, GcEvent, GcLog, Usage};
+use crate::heap::reference::{enqueue_pending, References, SoftPolicy, update_clock};
+use crate::heap::verify::verify_heap;
+use crate::java::{Reference, Value};
 use crate::log;
-use crate::runtime::Runtime;
+use crate::method_area::ReferenceType;
+use crate::runtime::{Options, Runtime};
 use crate::thread::Thread;
+use crate::thread::vm::{at_safepoint, SafepointTiming, VmOperation};
+
+/// The address space reserved for the whole heap, that the spaces of the generations are
+/// taken from.
+///
+/// A reference is the offset of an object from the start of the reservation, so the heap is
+/// reserved in one piece. The first page is never handed out, so that no object has the null
+/// reference.
+pub struct Reservation {
+    raw: *mut u8,
+    size: usize,
+    /// The size of the start of the reservation that has been handed out.
+    taken: usize,
+}
+
+unsafe impl Send for Reservation {}
+
+unsafe impl Sync for Reservation {}
+
+impl Reservation {
+    pub fn new(size: usize) -> Self {
+        let size = page_align(size) + page_align(1);
+        let raw = unsafe {
+            mmap(null_mut(), size, PROT_NONE, MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE, -1, 0)
+        };
+        if raw == MAP_FAILED {
+            panic!("Failed to reserve {}K for the heap", size / 1024);
+        }
+        Reservation { raw: raw.cast(), size, taken: page_align(1) }
+    }
 
+    /// The address of the start of the reservation, that references are offsets from.
+    pub fn start(&self) -> usize {
+        self.raw as usize
+    }
+
+    /// Hand out the next `size` bytes of the reservation.
+    fn take(&mut self, size: usize) -> *mut u8 {
+        assert!(self.taken + size <= self.size, "The heap reservation is too small");
+        let raw = unsafe { self.raw.add(self.taken) };
+        self.taken += size;
+        raw
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        unsafe { munmap(self.raw.cast(), self.size) };
+    }
+}
+
+/// A space that objects are bump allocated into, either a semispace of the nursery, or the old
+/// generation.
+///
+/// The address space for the maximum size is taken from the heap's reservation up front, but
+/// memory is only committed as the space grows into it, and is handed back to the OS once the
+/// space has been emptied.
 struct Data {
-    raw: Box<[u8]>,
+    raw: *mut u8,
+    /// The size of the reserved address space.
+    capacity: usize,
+    /// The size of the space that can be allocated in without committing more memory.
+    committed: AtomicUsize,
+    /// Serializes growing the committed space.
+    grow: Mutex<()>,
     used: AtomicUsize,
 }
 
 impl Data {
-    pub fn new() -> Self {
-        Data {
-            raw: vec![0; HEAP_SIZE].into_boxed_slice(),
+    pub fn new(reservation: &mut Reservation, capacity: usize, initial: usize) -> Self {
+        let capacity = page_align(capacity);
+        let data = Data {
+            raw: reservation.take(capacity),
+            capacity,
+            committed: AtomicUsize::new(0),
+            grow: Mutex::new(()),
             used: AtomicUsize::new(0),
-        }
+        };
+        data.commit(initial);
+        data
     }
 
-    pub fn allocate(&self, size: usize) -> *const u8 {
+    /// Allocate `size` bytes, as long as the semispace stays within `limit` bytes, returning
+    /// `None` if it doesn't fit.
+    pub fn allocate(&self, size: usize, limit: usize) -> Option<*const u8> {
+        let limit = limit.min(self.capacity);
         let result = self.used.fetch_update(
             Ordering::SeqCst, Ordering::SeqCst,
-            |used| used.checked_add(size));
+            |used| used.checked_add(size).filter(|end| *end <= limit));
+
+        let start_of_mem = result.ok()?;
 
-        let start_of_mem = result.expect("OOM");
+        let end_of_mem = start_of_mem + size;
+        if end_of_mem > self.committed.load(Ordering::SeqCst) {
+            // Grow geometrically, so that a growing heap doesn't commit memory on every allocation.
+            self.commit(end_of_mem.max(2 * self.committed.load(Ordering::SeqCst)));
+        }
+
+        Some(unsafe { self.raw.add(start_of_mem) })
+    }
+
+    /// Commit the memory for the first `size` bytes of the semispace, if not already committed.
+    fn commit(&self, size: usize) {
+        let _grow = self.grow.lock();
+        let size = page_align(size).min(self.capacity);
+        if size <= self.committed.load(Ordering::SeqCst) {
+            return;
+        }
 
-        unsafe { self.raw.as_ptr().add(start_of_mem) }
+        let result = unsafe { mprotect(self.raw.cast(), size, PROT_READ | PROT_WRITE) };
+        if result != 0 {
+            panic!("Failed to commit {}K for the heap", size / 1024);
+        }
+        debug!(target: log::GC, committed=format!("{}K", size / 1024), "Growing heap");
+        self.committed.store(size, Ordering::SeqCst);
+    }
+
+    /// Whether an object starting at the given address has been allocated in the space.
+    fn contains(&self, start: usize) -> bool {
+        let raw = self.raw as usize;
+        raw <= start && start < raw + self.used.load(Ordering::SeqCst)
+    }
+
+    /// Empty the semispace, handing its memory back to the OS, it reads as zeroes when it's
+    /// next used.
+    fn clear(&self) {
+        self.truncate(0);
+    }
+
+    /// Free everything after the first `used` bytes, handing the whole pages back to the OS.
+    fn truncate(&self, used: usize) {
+        let start = page_align(used).min(self.capacity);
+        let end = page_align(self.used.load(Ordering::SeqCst)).min(self.capacity);
+        if start < end {
+            unsafe { madvise(self.raw.add(start).cast(), end - start, MADV_DONTNEED) };
+        }
+        self.used.store(used, Ordering::SeqCst);
     }
 }
 
+/// Round up to a whole number of pages.
+pub fn page_align(size: usize) -> usize {
+    let page_size = unsafe { sysconf(_SC_PAGESIZE) } as usize;
+    size.div_ceil(page_size) * page_size
+}
+
+/// The most space kept in reserve for allocations the JVM makes itself.
+const MAX_RESERVE: usize = 1024 * 1024;
+
+/// The number of minor collections an object survives in the nursery before it's promoted to
+/// the old generation.
+const TENURING_THRESHOLD: u8 = 4;
+
+/// The size of the old generation covered by each card, as a power of two.
+const CARD_SHIFT: usize = 9;
+
+/// The most space a thread takes from the nursery at a time for its TLAB.
+const MAX_TLAB_SIZE: usize = 256 * 1024;
+
+/// A thread local allocation buffer, a chunk of the nursery that only one thread allocates
+/// objects in, so that most allocations don't contend with the other threads.
+#[derive(Clone, Copy)]
+struct Tlab {
+    /// The address of the generation the buffer was taken from.
+    generation: usize,
+    /// The number of collections of the generation when the buffer was taken from it.
+    epoch: usize,
+    top: usize,
+    end: usize,
+}
+
+impl Tlab {
+    const EMPTY: Tlab = Tlab { generation: 0, epoch: 0, top: 0, end: 0 };
+}
+
+thread_local! {
+    static TLAB: Cell<Tlab> = const { Cell::new(Tlab::EMPTY) };
+}
+
+/// The young generation, a nursery of two semispaces.
+///
+/// Objects are bump allocated into the source semispace, and a minor collection copies the
+/// live objects into the other semispace, or promotes them to the old generation once they are
+/// old enough.
+///
+/// Each thread allocates small objects in its own TLAB, a chunk of the source semispace. The
+/// collections happen at a safepoint, and retire every TLAB as they swap the semispaces, each
+/// thread then fills a new TLAB on its next allocation.
 pub struct CopyGeneration {
     blue: Data,
     green: Data,
     source: AtomicBool,
-    start_gc: Sender<Arc<Runtime>>,
+    /// The number of times the semispaces have been swapped, a TLAB taken before the last swap
+    /// has been retired.
+    epoch: AtomicUsize,
 }
 
 unsafe impl Sync for CopyGeneration {}
 
 impl CopyGeneration {
-    pub fn new() -> Self {
-        let start_gc = start_gc_thread();
-
+    /// Create a generation with semispaces that can grow from the initial size, up to the
+    /// maximum size.
+    pub fn new(reservation: &mut Reservation, max_size: usize, initial_size: usize) -> Self {
         CopyGeneration {
-            blue: Data::new(),
-            green: Data::new(),
+            blue: Data::new(reservation, max_size, initial_size),
+            green: Data::new(reservation, max_size, initial_size),
             source: AtomicBool::new(false),
-            start_gc,
+            epoch: AtomicUsize::new(0),
         }
     }
 
@@ -67,6 +239,16 @@ impl CopyGeneration {
         self.source_dest().0.used.load(Ordering::SeqCst)
     }
 
+    /// The maximum size that the nursery can grow to.
+    pub fn capacity(&self) -> usize {
+        self.source_dest().0.capacity
+    }
+
+    /// The size of the nursery that has been committed so far.
+    pub fn committed(&self) -> usize {
+        self.source_dest().0.committed.load(Ordering::SeqCst)
+    }
+
     fn source_dest(&self) -> (&Data, &Data) {
         if self.source.load(Ordering::SeqCst) {
             (&self.blue, &self.green)
@@ -75,261 +257,1286 @@ impl CopyGeneration {
         }
     }
 
-    pub fn allocate(&self, runtime: Arc<Runtime>, size: usize) -> *mut u8 {
-        let (source, _) = self.source_dest();
+    /// Whether the object starting at the given address is in the nursery.
+    pub fn contains(&self, start: usize) -> bool {
+        self.source_dest().0.contains(start)
+    }
 
+    /// The address of the start of the source semispace, straight after a collection the
+    /// survivors are packed together from here, before any TLABs leave gaps between objects.
+    pub fn start(&self) -> usize {
+        self.source_dest().0.raw as usize
+    }
 
-        let used = source.used.load(Ordering::SeqCst);
-        let percentage = 100.0 * (used as f64) / (HEAP_SIZE as f64);
-        if percentage > 25.0 {
-            // we're already trying to do GC, enter safe region here!
-            let current = current();
-            let thread_name = current.name().unwrap();
-            let all_threads = runtime.threads2.read().unwrap();
-            let our_thread = all_threads.iter().find(|t| t.name.eq(thread_name)).unwrap();
-            our_thread.safe.safe_region();
+    /// The size of the chunks of the nursery that are handed out as TLABs.
+    fn tlab_size(&self) -> usize {
+        (self.capacity() / 64).min(MAX_TLAB_SIZE)
+    }
+
+    /// Allocate `size` bytes, returning `None` if the nursery is full.
+    ///
+    /// Small objects are allocated in this thread's TLAB, refilling it from the nursery when
+    /// it's full, larger objects are allocated straight from the nursery.
+    pub fn allocate(&self, size: usize) -> Option<*mut u8> {
+        let tlab_size = self.tlab_size();
+        if size > tlab_size / 4 {
+            return self.allocate_shared(size);
         }
 
-        let allocated = source.allocate(size).cast_mut();
+        TLAB.with(|tlab| {
+            let generation = self as *const CopyGeneration as usize;
+            let epoch = self.epoch.load(Ordering::SeqCst);
+            let mut current = tlab.get();
+            if current.generation != generation || current.epoch != epoch {
+                current = Tlab::EMPTY;
+            }
 
-        // Start GC if we have used 25% of mem.
-        let used = source.used.load(Ordering::SeqCst);
-        let percentage = 100.0 * (used as f64) / (HEAP_SIZE as f64);
+            if current.end - current.top < size {
+                // The rest of the old TLAB is wasted until the next collection.
+                let Some(start) = self.allocate_shared(tlab_size) else {
+                    // The nursery is nearly full, there may still be space for this object.
+                    return self.allocate_shared(size);
+                };
+                let start = start as usize;
+                current = Tlab { generation, epoch, top: start, end: start + tlab_size };
+            }
 
-        if used > (HEAP_SIZE / 4) {
-            debug!(target: log::GC, "Used {:.2}% of Gen 1 Copy Space, starting GC", percentage);
-            self.start_gc.send(runtime).unwrap();
-            thread::sleep(Duration::from_millis(10));
-        }
+            let allocated = current.top;
+            current.top += size;
+            tlab.set(current);
+            Some(allocated as *mut u8)
+        })
+    }
 
-        allocated
+    /// Allocate `size` bytes from the nursery shared by all the threads.
+    fn allocate_shared(&self, size: usize) -> Option<*mut u8> {
+        let (source, _) = self.source_dest();
+        source.allocate(size, source.capacity).map(|allocated| allocated.cast_mut())
     }
 
     /// Copy the data at &data[start..(start+size)] from the live source set to the new set,
     /// and return the new start address for the object.
     pub fn copy(&self, start: usize, size: usize) -> *mut u8 {
         let (_, dest) = self.source_dest();
-        let source = unsafe { slice_from_raw_parts_mut(start as *mut u8, size).as_mut().unwrap() };
-        let dest_ptr = dest.allocate(size).cast_mut();
-
-        let dest = unsafe {
-            slice_from_raw_parts_mut(dest_ptr, size).as_mut().unwrap()
-        };
-
-        dest.copy_from_slice(source);
+        // Everything live was allocated in the source semispace, so it always fits.
+        let dest_ptr = dest.allocate(size, dest.capacity).unwrap().cast_mut();
+        unsafe { ptr::copy_nonoverlapping(start as *const u8, dest_ptr, size) };
         dest_ptr
     }
 
+    /// Empty the source semispace and swap the semispaces over, retiring every TLAB.
     pub fn swap(&self) {
         let (source, _) = self.source_dest();
-        source.used.store(0, Ordering::SeqCst);
+        source.clear();
         self.source.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |b| Some(!b))
             .unwrap();
+        self.epoch.fetch_add(1, Ordering::SeqCst);
     }
 }
 
-pub struct CopyCollector {
-    start: Receiver<Arc<Runtime>>,
-    gcs: usize,
+/// The old generation, holding the objects that have survived the nursery, and objects too
+/// large for it.
+///
+/// Objects are bump allocated, and a full collection slides the live objects down to the
+/// start of the space, in address order. Pinned objects are never moved, the objects after
+/// them slide down to their end instead.
+pub struct CompactGeneration {
+    data: Data,
+    /// The space at the end of the generation that only allocations made with
+    /// [`with_reserve`](crate::heap::allocator::with_reserve) can use, so that the JVM can still
+    /// allocate an `OutOfMemoryError` once Java code has filled the heap.
+    reserve: usize,
+    cards: CardTable,
+    /// The addresses of the objects in the generation, to find the objects on a card, and to
+    /// compact the objects in order.
+    objects: Mutex<BTreeSet<usize>>,
+}
+
+unsafe impl Sync for CompactGeneration {}
+
+impl CompactGeneration {
+    pub fn new(reservation: &mut Reservation, max_size: usize, initial_size: usize) -> Self {
+        let data = Data::new(reservation, max_size, initial_size);
+        CompactGeneration {
+            reserve: data.capacity.div_ceil(16).min(MAX_RESERVE),
+            cards: CardTable::new(data.capacity),
+            objects: Mutex::new(BTreeSet::new()),
+            data,
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.data.used.load(Ordering::SeqCst)
+    }
+
+    /// The maximum size that the old generation can grow to.
+    pub fn capacity(&self) -> usize {
+        self.data.capacity
+    }
+
+    /// The size of the old generation that has been committed so far.
+    pub fn committed(&self) -> usize {
+        self.data.committed.load(Ordering::SeqCst)
+    }
+
+    /// Whether the object starting at the given address is in the old generation.
+    pub fn contains(&self, start: usize) -> bool {
+        self.data.contains(start)
+    }
+
+    /// Allocate `size` bytes, returning `None` if the old generation is full.
+    pub fn allocate(&self, size: usize, use_reserve: bool) -> Option<*mut u8> {
+        let limit = if use_reserve { self.data.capacity } else { self.data.capacity - self.reserve };
+        self.data.allocate(size, limit).map(|allocated| allocated.cast_mut())
+    }
+
+    /// Keep track of a new object in the old generation.
+    pub fn insert(&self, start: usize) {
+        self.objects.lock().insert(start);
+    }
+
+    /// Record that a reference has been stored in the object starting at the given address,
+    /// so that the next minor collection finds any reference from it into the nursery.
+    pub fn write_barrier(&self, start: usize) {
+        if self.contains(start) {
+            self.cards.mark(start - self.data.raw as usize);
+        }
+    }
+
+    /// The addresses of the objects in the generation, in address order.
+    pub fn objects(&self) -> Vec<usize> {
+        self.objects.lock().iter().copied().collect()
+    }
+
+    /// Whether the card of the object starting at the given address is dirty, so the next
+    /// minor collection will scan it.
+    pub fn is_remembered(&self, start: usize) -> bool {
+        self.cards.is_dirty(start - self.data.raw as usize)
+    }
+
+    /// The addresses of the objects on dirty cards, those that might hold references into the
+    /// nursery.
+    fn dirty_objects(&self) -> Vec<usize> {
+        let base = self.data.raw as usize;
+        let objects = self.objects.lock();
+        self.cards.dirty(self.used())
+            .flat_map(|card| {
+                let start = base + (card << CARD_SHIFT);
+                objects.range(start..(start + (1 << CARD_SHIFT))).copied()
+            })
+            .collect()
+    }
+}
+
+/// A card table, a byte for each card of the old generation, marked dirty when a reference is
+/// stored in an object that starts on the card.
+struct CardTable {
+    cards: Box<[AtomicBool]>,
 }
 
-pub fn start_gc_thread() -> Sender<Arc<Runtime>> {
-    let (sender, receiver) = channel();
+impl CardTable {
+    fn new(capacity: usize) -> Self {
+        let cards = (0..capacity.div_ceil(1 << CARD_SHIFT)).map(|_| AtomicBool::new(false)).collect();
+        CardTable { cards }
+    }
+
+    fn mark(&self, offset: usize) {
+        self.cards[offset >> CARD_SHIFT].store(true, Ordering::Relaxed);
+    }
+
+    fn is_dirty(&self, offset: usize) -> bool {
+        self.cards[offset >> CARD_SHIFT].load(Ordering::Relaxed)
+    }
+
+    /// The dirty cards in the first `used` bytes of the generation.
+    fn dirty(&self, used: usize) -> impl Iterator<Item=usize> + '_ {
+        self.cards[..used.div_ceil(1 << CARD_SHIFT)].iter()
+            .enumerate()
+            .filter(|(_, card)| card.load(Ordering::Relaxed))
+            .map(|(card, _)| card)
+    }
+
+    fn clear(&self) {
+        for card in self.cards.iter() {
+            card.store(false, Ordering::Relaxed);
+        }
+    }
+}
 
-    Builder::new()
-        .name("GC-Copy".to_string())
-        .spawn(move || {
-            let mut collector = CopyCollector::new(receiver);
-            collector.run()
-        }).unwrap();
+/// The kinds of garbage collection.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Collection {
+    /// Collect the nursery only.
+    Minor,
+    /// Collect the whole heap, unloading classes and compacting the old generation.
+    Full,
+    /// Collect the whole heap, clearing every soft reference, as a last resort before running
+    /// out of memory.
+    Exhaustive,
+}
 
-    sender
+/// A collection, run by the VM thread at a safepoint.
+struct Collect {
+    collector: Arc<Mutex<GenerationalCollector>>,
+    /// The number of collections finished, updated once the collection has finished.
+    finished: Arc<Mutex<Collections>>,
+    collection: Collection,
+    cause: GcCause,
+    /// The number of collections that had finished when the collection was requested.
+    collections: Collections,
+    /// The file to dump the heap to once the collection has finished, if any.
+    dump: Option<PathBuf>,
+    /// The collection, to log once the threads have been restarted.
+    event: Option<GcEvent>,
+    /// The size of the heap dump, if one was asked for.
+    dumped: Option<io::Result<u64>>,
 }
 
-impl CopyCollector {
-    pub fn new(start: Receiver<Arc<Runtime>>) -> Self {
-        CopyCollector { start, gcs: 0 }
+impl VmOperation for Collect {
+    fn name(&self) -> &'static str {
+        self.cause.operation()
     }
 
-    pub fn run(&mut self) {
-        loop {
-            let runtime = self.start.recv().unwrap();
-            self.gc(runtime);
+    fn prologue(&mut self) -> bool {
+        // Other threads may have asked for the same collection, while it was running, but the
+        // heap can only be dumped straight after a collection.
+        let current = *self.finished.lock();
+        let redundant = self.dump.is_none() && (current.full != self.collections.full ||
+            (self.collection == Collection::Minor && current.minor != self.collections.minor));
+        if redundant {
+            debug!(target: log::GC, collection=?self.collection, "Skipping GC, already collected");
         }
+        !redundant
+    }
+
+    fn doit(&mut self, runtime: &Arc<Runtime>, threads: &[Arc<Thread>], timing: SafepointTiming) {
+        let mut collector = self.collector.lock();
+        let (event, dumped) = collector.collect_at_safepoint(runtime, threads, timing, self.collection, self.cause, self.dump.as_deref());
+        self.event = Some(event);
+        self.dumped = dumped;
     }
 
-    pub fn visiting(&mut self, runtime: &Arc<Runtime>, roots: HashSet<u32, BuildNoHashHasher<u32>>) -> HashSet<u32, BuildNoHashHasher<u32>> {
+    fn epilogue(&mut self, restarted: Instant) {
+        let Some(mut event) = self.event else {
+            return;
+        };
+        event.restarted = restarted;
+        self.collector.lock().log.log(&event);
+
+        let time = event.restarted - event.stopping;
+        let mut finished = self.finished.lock();
+        finished.minor += 1;
+        if event.full {
+            finished.full += 1;
+            finished.full_time += time;
+        } else {
+            finished.minor_time += time;
+        }
+    }
+}
+
+/// The number of collections finished, of each kind.
+#[derive(Clone, Copy, Default, Eq, PartialEq)]
+struct Collections {
+    /// The number of collections of any kind, as a full collection also collects the nursery.
+    minor: usize,
+    full: usize,
+    /// The time spent in collections of the nursery alone.
+    minor_time: Duration,
+    /// The time spent in collections of the whole heap.
+    full_time: Duration,
+}
+
+/// Requests collections from the VM thread.
+pub struct Collector {
+    collector: Arc<Mutex<GenerationalCollector>>,
+    collections: Arc<Mutex<Collections>>,
+}
+
+impl Collector {
+    /// Create a collector, which logs collections to the logs enabled by the options.
+    pub fn new(options: &Options) -> Self {
+        let collector = GenerationalCollector { log: GcLog::open(options), ..GenerationalCollector::new() };
+        Collector {
+            collector: Arc::new(Mutex::new(collector)),
+            collections: Arc::new(Mutex::new(Collections::default())),
+        }
+    }
+
+    /// Run a garbage collection, waiting for it to finish.
+    ///
+    /// The current thread is stopped like any other, so it must not be holding any references
+    /// that aren't visible to the collector.
+    pub fn collect(&self, runtime: Arc<Runtime>, collection: Collection, cause: GcCause) {
+        self.request(runtime, collection, cause, None);
+    }
+
+    /// Collect garbage, and dump the heap to a new file in the HPROF format, returning the size
+    /// of the file.
+    ///
+    /// The whole heap is collected first if only the live objects are wanted, otherwise just the
+    /// nursery is, so that its objects can be walked.
+    pub fn dump_heap(&self, runtime: Arc<Runtime>, path: PathBuf, live: bool) -> io::Result<u64> {
+        let collection = if live { Collection::Full } else { Collection::Minor };
+        self.request(runtime, collection, GcCause::HeapDump, Some(path))
+            .unwrap_or_else(|| Err(io::Error::other("the heap can only be dumped from a Java thread")))
+    }
+
+    /// Ask the VM thread for a collection, waiting for it to finish, returning the result of
+    /// dumping the heap, if it was asked for and the current thread is a Java thread.
+    fn request(&self, runtime: Arc<Runtime>, collection: Collection, cause: GcCause, dump: Option<PathBuf>) -> Option<io::Result<u64>> {
+        let our_thread = Thread::current()?;
+
+        debug!(target: log::GC, ?collection, ?cause, "Requesting GC");
+        let collect = Collect {
+            collector: self.collector.clone(),
+            finished: self.collections.clone(),
+            collection,
+            cause,
+            collections: *self.collections.lock(),
+            dump,
+            event: None,
+            dumped: None,
+        };
+        let collect = runtime.vm_thread.execute(runtime.clone(), collect);
+
+        enqueue_pending(our_thread.as_mut());
+        collect.dumped
+    }
+
+    /// The number of collections that have finished and the time spent in them, either of the
+    /// nursery alone, or of the whole heap, for `GarbageCollectorMXBean`.
+    pub fn statistics(&self, full: bool) -> (usize, Duration) {
+        let collections = *self.collections.lock();
+        if full {
+            (collections.full, collections.full_time)
+        } else {
+            (collections.minor - collections.full, collections.minor_time)
+        }
+    }
+}
+
+pub struct GenerationalCollector {
+    gcs: usize,
+    /// The references cleared by the collection so far, which go on the heap's pending list
+    /// once it's over.
+    pending: Vec<Reference>,
+    log: GcLog,
+}
+
+impl Default for GenerationalCollector {
+    fn default() -> Self {
+        GenerationalCollector::new()
+    }
+}
+
+impl GenerationalCollector {
+    pub fn new() -> Self {
+        GenerationalCollector { gcs: 0, pending: Vec::new(), log: GcLog::disabled() }
+    }
+
+    /// Stop every thread, and collect garbage, returning whether the whole heap was collected.
+    ///
+    /// This runs on the current thread, rather than the VM thread, for tests of the collector.
+    pub fn gc(&mut self, runtime: Arc<Runtime>, collection: Collection, cause: GcCause) -> bool {
+        let mut collected = None;
+        let restarted = at_safepoint(&runtime, |threads, timing| {
+            collected = Some(self.collect_at_safepoint(&runtime, threads, timing, collection, cause, None).0);
+        });
+        let event = GcEvent { restarted, ..collected.unwrap() };
+        self.log.log(&event);
+        event.full
+    }
+
+    /// Collect garbage while the threads are stopped, and dump the heap to the given file
+    /// afterwards, returning the collection to log once the threads are restarted, and the
+    /// result of the dump.
+    fn collect_at_safepoint(&mut self, runtime: &Arc<Runtime>, threads: &[Arc<Thread>], timing: SafepointTiming,
+                            collection: Collection, cause: GcCause, dump: Option<&Path>) -> (GcEvent, Option<io::Result<u64>>) {
         let heap = &runtime.heap;
+        let cpu = CpuTime::now();
+        let before = Usage::of(&heap.allocator);
 
-        let mut visited = HashSet::with_capacity_and_hasher(runtime.heap.num_objects(), BuildNoHashHasher::default());
-        visited.insert(0);
-        let mut remaining_to_visit = roots;
-
-        while remaining_to_visit.len() > 0 {
-            let next_object = remaining_to_visit.iter().next().unwrap().clone();
-            remaining_to_visit.remove(&next_object);
-            visited.insert(next_object);
-
-            // Copy object over to new set.
-            let value = heap.get(Reference(next_object));
-            match value {
-                Heaped::Array(mut array) => {
-                    let header = unsafe { array.header.as_ref().unwrap() };
-                    let start = array.header as usize;
-                    let size = size_of::<ArrayHeader>() + header.length;
-
-                    trace!(target: log::GC, gen="gen-1", obj="array", start, size, "mark & copy");
-
-                    let new_start = heap.allocator.gen.copy(start, size);
-
-                    let source = unsafe { slice_from_raw_parts_mut(array.header as *mut u8, size).as_mut().unwrap() };
-                    let dest = unsafe { slice_from_raw_parts_mut(new_start, size).as_mut().unwrap() };
-                    dest.copy_from_slice(source);
-
-                    // Need to update pointers in heap.
-                    array.header = new_start as *mut ArrayHeader;
-                    array.data = unsafe { new_start.add(size_of::<ArrayHeader>()) };
-                    heap.set(Reference(next_object), Heaped::Array(array));
-
-                    // If its an array of references, we want to add all of those to the set.
-                    if header.component.is_reference() {
-                        remaining_to_visit.extend(array.as_ref_slice().iter()
-                            .filter(|reference| !visited.contains(*reference)));
-                    }
-                }
-                Heaped::Object(mut object) => {
-                    let header = unsafe { object.header.as_ref().unwrap() };
-                    let class = unsafe { header.class.as_ref().unwrap() };
-                    let start = object.header as usize;
-                    let size = size_of::<ObjectHeader>() + class.instance_width;
-
-                    trace!(target: log::GC, gen="gen-1", obj="object", start, size, "mark & copy");
-
-                    let new_start = heap.allocator.gen.copy(start, size);
-
-                    let new_header: *const ObjectHeader = new_start.cast();
-                    let new_data: *const u8 = unsafe { new_start.add(size_of::<ObjectHeader>()).cast_const() };
-
-                    unsafe {
-                        // Move the header (careful to move the lock).
-                        let new_header = new_header.cast_mut().as_mut().unwrap();
-                        new_header.class = header.class;
-                        new_header.lock = header.lock.move_me();
-                        new_header.hash_code = header.hash_code;
-                        object.header = new_header as *mut ObjectHeader;
-
-                        // Move the data.
-                        let new_data = slice_from_raw_parts_mut(new_data.cast_mut(), class.instance_width).as_mut().unwrap();
-                        let old_data = slice_from_raw_parts(object.data, class.instance_width).as_ref().unwrap();
-                        new_data.copy_from_slice(old_data);
-                        object.data = new_data.as_mut_ptr();
-                    }
-
-                    heap.set(Reference(next_object), Heaped::Object(object));
-
-                    // For every reference in the objects fields, add to set.
-                    for parent in class.parents() {
-                        for field in &parent.instance_fields {
-                            if field.descriptor.is_reference() {
-                                let reference = object.field_from(field).reference().0;
-                                if !visited.contains(&reference) {
-                                    remaining_to_visit.insert(reference);
-                                }
-                            }
-                        }
-                    }
+        // The roots that are pinned never move, the rest are updated as their objects move.
+        let mut pinned = heap_roots(heap);
+        for thread in threads.iter() {
+            pinned.extend(running_loaders(thread));
+        }
+        let mut out_of_memory_error = heap.out_of_memory_error.write().unwrap();
+        let mut global_refs = heap.global_refs.lock().unwrap();
+        let mut pending_references = heap.pending_references.lock().unwrap();
+        let mut roots: Vec<&mut Reference> = pinned.iter_mut().collect();
+        roots.extend(out_of_memory_error.as_mut());
+        roots.extend(global_refs.iter_mut().flatten());
+        roots.extend(pending_references.iter_mut());
+        for thread in threads.iter() {
+            // The thread is stopped, so the collector has the only access to its stack.
+            let thread = unsafe { Arc::as_ptr(thread).cast_mut().as_mut().unwrap() };
+            thread_roots(thread, &mut roots);
+        }
+
+        let full = match collection {
+            Collection::Minor => {
+                let promotion_failed = self.minor_gc(runtime, &mut roots);
+                if promotion_failed {
+                    debug!(target: log::GC, "Old generation is full, promotion failed");
+                    self.full_gc(runtime, &mut roots, SoftPolicy::LeastRecentlyUsed);
                 }
+                promotion_failed
             }
+            Collection::Full => {
+                self.full_gc(runtime, &mut roots, SoftPolicy::LeastRecentlyUsed);
+                true
+            }
+            Collection::Exhaustive => {
+                self.full_gc(runtime, &mut roots, SoftPolicy::Never);
+                true
+            }
+        };
+        if runtime.options.verify_heap {
+            verify_heap(runtime, &roots);
         }
+        drop(roots);
+        pending_references.append(&mut self.pending);
+        drop(pending_references);
+        drop(global_refs);
+        drop(out_of_memory_error);
+        update_clock(runtime);
+
+        let collected = Instant::now();
+        let cpu = CpuTime::now().since(cpu);
+        let after = Usage::of(&heap.allocator);
+        let id = self.gcs;
+        self.gcs += 1;
+        debug!(target: log::GC, gc=self.gcs, full, used=format!("{}K", heap.allocator.used() / 1024), "Ending collection");
 
-        visited
+        // The heap is dumped while the threads are still stopped.
+        let dumped = dump.map(|path| dump_heap(runtime, threads, path));
+
+        let SafepointTiming { stopping, stopped } = timing;
+        let event = GcEvent { id, cause, full, stopping, stopped, collected, restarted: collected, before, after, cpu };
+        (event, dumped)
     }
 
-    pub fn gc(&mut self, runtime: Arc<Runtime>) {
+    /// Collect the nursery, copying the live objects into the other semispace, or promoting
+    /// them to the old generation, and updating the roots to refer to the copies.
+    ///
+    /// Objects in the old generation are assumed to be alive, and the objects on dirty cards
+    /// are roots, as they may refer to objects in the nursery.
+    ///
+    /// Returns whether an object couldn't be promoted, as the old generation is full.
+    pub fn minor_gc(&mut self, runtime: &Arc<Runtime>, roots: &mut [&mut Reference]) -> bool {
         let heap = &runtime.heap;
+        let young = &heap.allocator.young;
+        let old = &heap.allocator.old;
+        debug!(target: log::GC, used=format!("{}K", young.used() / 1024), "Starting minor collection");
 
-        let used = heap.allocator.gen.used() as f64;
-        let max = HEAP_SIZE as f64;
-        let perc = (100.0 * used) / max;
-        if perc < 25.0 {
-            debug!(target: log::GC, "Skipping GC with perc {:.2}%", perc);
-            return;
+        let mut evacuation = Evacuation { heap, scan: Vec::new(), promoted: Vec::new(), promotion_failed: false };
+        for root in roots.iter_mut() {
+            root.0 = evacuation.evacuate(root.0);
         }
 
-        debug!(target: log::GC, "Starting Gen 1 Copy Garbage Collection");
+        // Only the references to objects in the nursery are discovered, the rest are alive.
+        let mut references = References::new(runtime, SoftPolicy::Always);
+        let remembered = old.dirty_objects();
+        for start in &remembered {
+            references.trace(Heaped::at(*start), |referent| is_young(heap, referent), |slot| *slot = evacuation.evacuate(*slot));
+        }
+        evacuation.scan(&mut references);
+        while references.keep_soft(|slot| *slot = evacuation.evacuate(*slot)) {
+            evacuation.scan(&mut references);
+        }
+        let forwarded = |referent| heap.get(Reference(referent)).forwarded().map(|reference| reference.0);
+        for reference_type in [ReferenceType::Soft, ReferenceType::Weak] {
+            self.pending.extend(references.clear(reference_type, heap, forwarded));
+        }
+        self.pending.extend(references.keep_finalizable(heap, forwarded, |slot| *slot = evacuation.evacuate(*slot)));
+        evacuation.scan(&mut references);
+        self.pending.extend(references.clear(ReferenceType::Phantom, heap, forwarded));
+        young.swap();
+
+        // Only the objects that still refer to the nursery need to be scanned next time.
+        old.cards.clear();
+        for start in remembered.iter().chain(evacuation.promoted.iter()) {
+            remember_if_young(heap, *start);
+        }
+
+        debug!(target: log::GC, promoted=evacuation.promoted.len(), used=format!("{}K", young.used() / 1024), "Ending minor collection");
+        evacuation.promotion_failed
+    }
 
-        // Ensure all threads are ready to start GC.
-        let threads = runtime.threads2.read().unwrap();
-        scope(|scope| {
-            for thread in threads.iter() {
-                Builder::new()
-                    .name(format!("GC-Copy-Pause-{}", thread.name.as_str()))
-                    .spawn_scoped(scope, || thread.safe.start_gc()).unwrap();
+    /// Collect the whole heap, marking the live objects and unloading classes, compacting the
+    /// old generation, and then promoting everything that's still alive in the nursery.
+    ///
+    /// The new addresses of the objects that move are kept to the side, and every reference to
+    /// them is updated once all the objects have moved.
+    ///
+    /// The soft references whose referents are kept alive are chosen by the policy.
+    pub fn full_gc(&mut self, runtime: &Arc<Runtime>, roots: &mut [&mut Reference], soft: SoftPolicy) {
+        let heap = &runtime.heap;
+        let allocator = &heap.allocator;
+        let young = &allocator.young;
+        let old = &allocator.old;
+        debug!(target: log::GC, used=format!("{}K", allocator.used() / 1024), "Starting full collection");
+
+        let visited = self.mark(runtime, roots, soft);
+
+        // Classes whose loader is dead are unloaded before their objects are removed.
+        runtime.method_area.unload(&visited);
+        let is_live = |start: &usize| visited.contains(&allocator.reference(*start).0);
+
+        // Slide the live objects in the old generation down, the objects are visited in
+        // address order, so an object only ever moves into space that has been vacated.
+        let mut forwarding: HashMap<u32, u32, BuildNoHashHasher<u32>> = HashMap::default();
+        let objects = take(&mut *old.objects.lock());
+        let mut compacted = BTreeSet::new();
+        let mut top = old.data.raw as usize;
+        for start in objects.into_iter().filter(is_live) {
+            let heaped = Heaped::at(start);
+            let size = heaped.size();
+
+            // Pinned objects stay where they are, and the objects after them slide down to
+            // their end.
+            let new_start = if heaped.is_pinned() { start } else { top };
+            if new_start != start {
+                trace!(target: log::GC, gen="old", start, size, "compact");
+                unsafe { ptr::copy(start as *const u8, new_start as *mut u8, size) };
+                forwarding.insert(allocator.reference(start).0, allocator.reference(new_start).0);
             }
-        });
-        debug!(target: log::GC, "All threads stopped");
+            compacted.insert(new_start);
+            top = new_start + size;
+        }
+        old.data.truncate(top - old.data.raw as usize);
+        *old.objects.lock() = compacted;
+
+        // Empty the nursery, as far as the old generation has space.
+        let mut survivors = Vec::new();
+        for reference in visited.iter().copied().filter(|reference| *reference != 0) {
+            let start = allocator.start(Reference(reference));
+            if !young.contains(start) {
+                continue;
+            }
+            let size = Heaped::at(start).size();
+            let new_start = if let Some(new_start) = old.allocate(size, false) {
+                unsafe { ptr::copy_nonoverlapping(start as *const u8, new_start, size) };
+                old.insert(new_start as usize);
+                new_start as usize
+            } else {
+                let new_start = young.copy(start, size) as usize;
+                survivors.push(new_start);
+                new_start
+            };
+            forwarding.insert(reference, allocator.reference(new_start).0);
+        }
+        young.swap();
 
+        // Every live object is in its place, so the references to the objects that moved can
+        // be updated.
+        let forward = |slot: &mut u32| {
+            if let Some(to) = forwarding.get(slot) {
+                *slot = *to;
+            }
+        };
+        let objects: Vec<usize> = old.objects.lock().iter().copied().collect();
+        for start in objects.iter().chain(survivors.iter()) {
+            Heaped::at(*start).for_each_slot(forward);
+        }
+        for root in roots.iter_mut() {
+            forward(&mut root.0);
+        }
+        for reference in self.pending.iter_mut() {
+            forward(&mut reference.0);
+        }
 
-        let mut roots: HashSet<u32, BuildNoHashHasher<u32>> = HashSet::with_capacity_and_hasher(runtime.heap.num_objects(), BuildNoHashHasher::default());
-        for thread in threads.iter() {
-            let thread_roots = thread_roots(thread.as_ref());
-            roots.extend(thread_roots.iter());
+        // If the nursery isn't empty, any object in the old generation might refer to it.
+        old.cards.clear();
+        if !survivors.is_empty() {
+            for start in objects {
+                remember_if_young(heap, start);
+            }
         }
-        roots.extend(heap_roots(runtime.heap.as_ref()).iter());
 
-        let used = heap.allocator.gen.used();
-        let percentage = (100.0 * (used as f64)) / HEAP_SIZE as f64;
-        debug!(target: log::GC, gen="gen-1", used=format!("{}mb", used / 1024 / 1024), percentage=format!("{:.2}%", percentage), "Starting Mark&Copy garbage collection");
+        debug!(target: log::GC, moved=forwarding.len(), used=format!("{}K", allocator.used() / 1024), "Ending full collection");
+    }
 
-        let visited = self.visiting(&runtime, roots);
+    /// Find every object reachable from the roots, and the references already cleared by this
+    /// collection, clearing the references whose referents aren't strongly reachable, and
+    /// keeping the objects to finalize alive.
+    pub fn mark(&mut self, runtime: &Arc<Runtime>, roots: &[&mut Reference], soft: SoftPolicy) -> HashSet<u32, BuildNoHashHasher<u32>> {
+        let heap = &runtime.heap;
 
-        // What is dead is dead - remove from heap.
-        heap.retain(&visited);
-        heap.allocator.gen.swap();
+        let mut visited = HashSet::with_hasher(BuildNoHashHasher::default());
+        visited.insert(0);
+        let mut remaining_to_visit: Vec<u32> = roots.iter().map(|root| root.0)
+            .chain(self.pending.iter().map(|reference| reference.0))
+            .collect();
+
+        // A user defined loader keeps the classes it has loaded alive, and every object keeps
+        // the loader of its class alive, so classes are unloaded once their loader is garbage.
+        let loaders = loader_references(runtime);
+
+        let mark = |visited: &mut HashSet<u32, BuildNoHashHasher<u32>>, remaining_to_visit: &mut Vec<u32>, references: &mut References| {
+            while let Some(next_object) = remaining_to_visit.pop() {
+                if !visited.insert(next_object) {
+                    continue;
+                }
 
-        self.gcs += 1;
-        let used = heap.allocator.gen.used();
-        let percentage = (100.0 * (used as f64)) / HEAP_SIZE as f64;
-        debug!(target: log::GC, gen="gen-1", gc=self.gcs, used=format!("{}mb", used / 1024 / 1024), percentage=format!("{:.2}%", percentage), "Ending Mark&Copy collection");
-
-        scope(|scope| {
-            for thread in threads.iter() {
-                Builder::new()
-                    .name(format!("GC-Copy-Restart-{}", thread.name.as_str()))
-                    .spawn_scoped(scope, || thread.safe.end_gc()).unwrap();
+                if let Some(loaded) = loaders.get(&next_object) {
+                    remaining_to_visit.extend(loaded.iter());
+                }
+
+                let heaped = heap.get(Reference(next_object));
+                trace!(target: log::GC, start=heaped.start(), "mark");
+                remaining_to_visit.push(heaped.loader().0);
+                references.trace(heaped, |_| true, |slot| remaining_to_visit.push(*slot));
             }
-        });
-        debug!(target: log::GC, "All threads restarted");
+        };
+
+        let mut references = References::new(runtime, soft);
+        mark(&mut visited, &mut remaining_to_visit, &mut references);
+        while references.keep_soft(|slot| remaining_to_visit.push(*slot)) {
+            mark(&mut visited, &mut remaining_to_visit, &mut references);
+        }
+        let reached = |visited: &HashSet<u32, BuildNoHashHasher<u32>>, referent| visited.contains(&referent).then_some(referent);
+        for reference_type in [ReferenceType::Soft, ReferenceType::Weak] {
+            self.pending.extend(references.clear(reference_type, heap, |referent| reached(&visited, referent)));
+        }
+        let finalizable = references.keep_finalizable(heap, |referent| reached(&visited, referent),
+                                                      |slot| remaining_to_visit.push(*slot));
+        self.pending.extend(finalizable);
+        mark(&mut visited, &mut remaining_to_visit, &mut references);
+        self.pending.extend(references.clear(ReferenceType::Phantom, heap, |referent| reached(&visited, referent)));
+
+        visited
     }
 }
 
-/// Get all the root objects that a thread has access to.
-pub fn thread_roots(thread: &Thread) -> HashSet<u32, BuildNoHashHasher<u32>> {
-    let mut refs = HashSet::with_hasher(BuildNoHashHasher::default());
+/// Copies the live objects out of the nursery in a minor collection.
+///
+/// The old copy of an object is left with its new reference, so every reference to an object
+/// that's already been copied is updated to the same copy.
+struct Evacuation<'a> {
+    heap: &'a Heap,
+    /// The copies whose references haven't been evacuated yet.
+    scan: Vec<Heaped>,
+    /// The addresses of the objects promoted to the old generation.
+    promoted: Vec<usize>,
+    /// Whether an object was old enough to be promoted, but didn't fit in the old generation.
+    promotion_failed: bool,
+}
+
+impl Evacuation<'_> {
+    /// Copy the referenced object out of the nursery, if it's in the nursery, returning its new
+    /// reference.
+    fn evacuate(&mut self, reference: u32) -> u32 {
+        if reference == 0 {
+            return 0;
+        }
+        let allocator = &self.heap.allocator;
+        let start = allocator.start(Reference(reference));
+        if !allocator.young.contains(start) {
+            return reference;
+        }
+        let heaped = Heaped::at(start);
+        if let Some(forwarded) = heaped.forwarded() {
+            return forwarded.0;
+        }
+
+        let size = heaped.size();
+        let age = heaped.age().saturating_add(1);
+        let promote_to = if age >= TENURING_THRESHOLD { allocator.old.allocate(size, false) } else { None };
+        let new_start = if let Some(new_start) = promote_to {
+            trace!(target: log::GC, gen="young", start, size, "promote");
+            unsafe { ptr::copy_nonoverlapping(start as *const u8, new_start, size) };
+            allocator.old.insert(new_start as usize);
+            self.promoted.push(new_start as usize);
+            new_start as usize
+        } else {
+            trace!(target: log::GC, gen="young", start, size, "copy");
+            self.promotion_failed |= age >= TENURING_THRESHOLD;
+            allocator.young.copy(start, size) as usize
+        };
 
+        let copy = Heaped::at(new_start);
+        copy.set_age(age);
+        let new_reference = allocator.reference(new_start);
+        heaped.forward(new_reference);
+        self.scan.push(copy);
+        new_reference.0
+    }
+
+    /// Evacuate everything reachable from the copies made so far, discovering the references
+    /// to objects in the nursery.
+    fn scan(&mut self, references: &mut References) {
+        let heap = self.heap;
+        while let Some(copy) = self.scan.pop() {
+            references.trace(copy, |referent| is_young(heap, referent), |slot| *slot = self.evacuate(*slot));
+        }
+    }
+}
+
+/// Whether the referenced object is in the nursery.
+fn is_young(heap: &Heap, reference: u32) -> bool {
+    heap.allocator.young.contains(heap.allocator.start(Reference(reference)))
+}
+
+/// Dirty the card of an object in the old generation, if it refers to an object in the nursery.
+fn remember_if_young(heap: &Heap, start: usize) {
+    let young = &heap.allocator.young;
+    let mut refers_to_young = false;
+    Heaped::at(start).for_each_slot(|slot| {
+        refers_to_young |= *slot != 0 && young.contains(heap.allocator.start(Reference(*slot)));
+    });
+    if refers_to_young {
+        heap.allocator.old.write_barrier(start);
+    }
+}
+
+/// Add the places that a thread holds references to `roots`, so the collector can update them
+/// when it moves the objects.
+pub fn thread_roots<'a>(thread: &'a mut Thread, roots: &mut Vec<&'a mut Reference>) {
     // The thread instance for this thread itself.
-    thread.reference.map(|r| refs.insert(r.0));
+    roots.extend(thread.reference.as_mut());
 
     // Get all the local vars and operand stack references.
-    for frame in &thread.stack {
-        refs.extend(frame.local_vars.roots().iter());
-        refs.extend(frame.operand_stack.roots().iter());
-        refs.extend(frame.native_roots.iter());
+    for frame in thread.stack.iter_mut() {
+        roots.extend(frame.local_vars.roots());
+        roots.extend(frame.operand_stack.roots());
+        roots.extend(frame.native_args.iter_mut().filter_map(|value| match value {
+            Value::Reference(reference) => Some(reference),
+            _ => None,
+        }));
+        roots.extend(frame.native_roots.iter_mut());
+        roots.extend(frame.native_ex.as_mut());
     }
+}
 
-    refs
+/// The loaders of the classes of the methods a thread is running, as the class of a running
+/// method can't be unloaded.
+fn running_loaders(thread: &Thread) -> Vec<Reference> {
+    thread.stack.iter()
+        .filter(|frame| !frame.method.is_null())
+        .map(|frame| {
+            let method = unsafe { frame.method.as_ref().unwrap() };
+            let class = unsafe { method.class.as_ref().unwrap() };
+            class.loader
+        })
+        .collect()
 }
 
-pub fn heap_roots(heap: &Heap) -> HashSet<u32, BuildNoHashHasher<u32>> {
-    let mut refs = HashSet::with_hasher(BuildNoHashHasher::default());
-    // class objects are roots
-    refs.extend(heap.class_objects.current_values().iter());
+/// The roots held by the heap itself, which are all pinned.
+pub fn heap_roots(heap: &Heap) -> Vec<Reference> {
+    let mut refs = Vec::new();
+    // class objects of the bootstrap loader are roots, its classes are never unloaded
+    refs.extend(heap.class_objects.current_entries().iter()
+        .filter(|((loader, _), _)| loader.0 == 0)
+        .map(|(_, class_object)| *class_object));
 
     // string constants are roots
-    refs.extend(heap.string_constants.current_values().iter());
+    refs.extend(heap.string_constants.current_values().iter().map(|reference| Reference(*reference)));
 
-    // // static fields are roots
-    // refs.extend(heap.static_objects.current_values().iter());
+    // static fields of the bootstrap loader's classes are roots, the static fields of other
+    // classes are alive for as long as their loader is
+    refs.extend(heap.bootstrap_static_objects());
 
     refs
-}
\ No newline at end of file
+}
+
+/// The objects that each user defined loader keeps alive, the class objects and static storage
+/// of the classes it defined, and the loaders it delegated to.
+fn loader_references(runtime: &Runtime) -> HashMap<u32, Vec<u32>, BuildNoHashHasher<u32>> {
+    let mut references: HashMap<u32, Vec<u32>, BuildNoHashHasher<u32>> = HashMap::default();
+    for (loader, class_objects) in runtime.heap.loader_class_objects() {
+        references.entry(loader.0).or_default().extend(class_objects.iter().map(|r| r.0));
+    }
+    for (loader, loaders) in runtime.method_area.loader_references() {
+        references.entry(loader.0).or_default().extend(loaders.iter().map(|r| r.0));
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Deref;
+    use std::thread::scope;
+
+    use crate::collection::classes::ClassRef;
+    use crate::java::{FieldType, Int, Value};
+    use crate::method_area::{Class, ClassFlags, Field, ObjectClass, Primitive};
+    use crate::method_area::const_pool::ConstPool;
+    use crate::runtime::Options;
+
+    use super::*;
+
+    const MB: usize = 1024 * 1024;
+
+    /// A runtime with a 1MB nursery, so that a 1MB array is allocated in the old generation.
+    fn runtime() -> Arc<Runtime> {
+        Runtime::with_options(Options { max_heap_size: Some(6 * MB), ..Options::default() })
+    }
+
+    fn object_class() -> Class {
+        Class::Object(ClassRef::new(Box::leak(Box::new(bootstrap_class("java.lang.Object")))))
+    }
+
+    /// A class of the bootstrap loader without any fields.
+    fn bootstrap_class(name: &str) -> ObjectClass {
+        ObjectClass {
+            name: name.to_string(),
+            flags: ClassFlags { bits: 0 },
+            const_pool: ConstPool {
+                pool: HashMap::with_hasher(BuildNoHashHasher::default()),
+                loader: Reference(0),
+            },
+            super_class: None,
+            interfaces: vec![],
+            instance_fields: vec![],
+            static_fields: vec![],
+            methods: vec![],
+            attributes: vec![],
+            instance_width: 0,
+            static_width: 0,
+            source_file: None,
+            loader: Reference(0),
+            reference_type: None,
+            has_finalizer: false,
+        }
+    }
+
+    /// A class of the bootstrap loader, with a single static reference field.
+    fn class_with_static() -> Class {
+        let Class::Object(class) = object_class() else { unreachable!() };
+        let class = unsafe { (class.deref() as *const ObjectClass).cast_mut().as_mut().unwrap() };
+        class.name = "Statics".to_string();
+        class.static_fields.push(Field {
+            class,
+            flags: 0x0008,
+            is_static: true,
+            name: "value".to_string(),
+            descriptor: FieldType::Reference("java.lang.Object".to_string()),
+            offset: 0,
+            width: 4,
+        });
+        class.static_width = 8;
+        Class::Object(ClassRef::new(class))
+    }
+
+    /// Load `java.lang.ref.Reference` with the fields the collector uses, and the subclass for
+    /// a kind of reference.
+    fn reference_class(runtime: &Runtime, reference_type: ReferenceType) -> Class {
+        let mut reference = bootstrap_class("java.lang.ref.Reference");
+        for (index, name) in ["referent", "next"].into_iter().enumerate() {
+            reference.instance_fields.push(Field {
+                class: ptr::null(),
+                flags: 0,
+                is_static: false,
+                name: name.to_string(),
+                descriptor: FieldType::Reference("java.lang.Object".to_string()),
+                offset: 4 * index,
+                width: 4,
+            });
+        }
+        reference.instance_width = 8;
+        let reference = runtime.method_area.insert_gen_class(reference);
+
+        let mut subclass = bootstrap_class(&format!("java.lang.ref.{:?}Reference", reference_type));
+        subclass.super_class = Some(ClassRef::new(reference));
+        subclass.instance_width = 8;
+        subclass.reference_type = Some(reference_type);
+        if reference_type == ReferenceType::Soft {
+            subclass.instance_fields.push(Field {
+                class: ptr::null(),
+                flags: 0,
+                is_static: false,
+                name: "timestamp".to_string(),
+                descriptor: FieldType::Long,
+                offset: 8,
+                width: 8,
+            });
+            subclass.instance_width = 16;
+        }
+        Class::Object(ClassRef::new(runtime.method_area.insert_gen_class(subclass)))
+    }
+
+    /// The referent of a reference, which is its first field.
+    fn referent(heap: &Heap, reference: Reference) -> Reference {
+        let mut referent = None;
+        heap.get(reference).for_each_slot(|slot| { referent.get_or_insert(Reference(*slot)); });
+        referent.unwrap()
+    }
+
+    /// Whether an object in the old generation survived the last full collection.
+    fn is_compacted(heap: &Heap, start: usize) -> bool {
+        heap.allocator.old.objects.lock().contains(&start)
+    }
+
+    #[test]
+    fn minor_gc_promotes_survivors() {
+        let runtime = runtime();
+        let heap = &runtime.heap;
+        let mut collector = GenerationalCollector::new();
+
+        let mut live = heap.new_array(Class::Primitive(Primitive::Int), Int(16));
+        heap.get_array(live).set_element(Int(3), Value::Int(Int(42)));
+        let hash_code = heap.get_array(live).header().hash_code;
+        heap.new_array(Class::Primitive(Primitive::Int), Int(16));
+        let used = heap.allocator.young.used();
+
+        let before = live;
+        collector.minor_gc(&runtime, &mut [&mut live]);
+
+        // Only the live array is copied, and the root is updated to the copy.
+        assert_ne!(live, before);
+        assert!(heap.allocator.young.contains(heap.get(live).start()));
+        assert_eq!(heap.allocator.young.used(), heap.get(live).size());
+        assert!(heap.allocator.young.used() < used);
+        assert_eq!(heap.get_array(live).get_element(Int(3)).int(), Int(42));
+        assert_eq!(heap.get_array(live).header().hash_code, hash_code);
+        assert_eq!(heap.get(live).age(), 1);
+
+        for _ in 1..TENURING_THRESHOLD {
+            collector.minor_gc(&runtime, &mut [&mut live]);
+        }
+
+        assert!(heap.allocator.old.contains(heap.get(live).start()));
+        assert_eq!(heap.get_array(live).get_element(Int(3)).int(), Int(42));
+        assert_eq!(heap.allocator.young.used(), 0);
+    }
+
+    #[test]
+    fn minor_gc_forwards_shared_references() {
+        let runtime = runtime();
+        let heap = &runtime.heap;
+        let mut collector = GenerationalCollector::new();
+
+        let element = heap.new_array(Class::Primitive(Primitive::Int), Int(16));
+        let mut first = heap.new_array(object_class(), Int(2));
+        let mut second = heap.new_array(object_class(), Int(2));
+        heap.get_array(first).set_element(Int(0), Value::Reference(element));
+        heap.get_array(first).set_element(Int(1), Value::Reference(second));
+        heap.get_array(second).set_element(Int(0), Value::Reference(element));
+
+        collector.minor_gc(&runtime, &mut [&mut first, &mut second]);
+
+        // Every reference to an object is updated to the same copy.
+        let element = heap.get_array(first).get_element(Int(0)).reference();
+        assert!(heap.allocator.young.contains(heap.get(element).start()));
+        assert_eq!(heap.get_array(first).get_element(Int(1)).reference(), second);
+        assert_eq!(heap.get_array(second).get_element(Int(0)).reference(), element);
+    }
+
+    #[test]
+    fn minor_gc_scans_dirty_cards() {
+        let runtime = runtime();
+        let heap = &runtime.heap;
+        let mut collector = GenerationalCollector::new();
+
+        let mut array = heap.new_array(object_class(), Int(MB as i32 / 4));
+        let start = heap.get(array).start();
+        assert!(heap.allocator.old.contains(start));
+
+        // The array doesn't refer to the nursery, so its card is cleaned.
+        collector.minor_gc(&runtime, &mut [&mut array]);
+        assert!(heap.allocator.old.dirty_objects().is_empty());
+
+        let element = heap.new_array(Class::Primitive(Primitive::Int), Int(16));
+        heap.get_array(element).set_element(Int(0), Value::Int(Int(7)));
+        let array_obj = heap.get_array(array);
+        array_obj.set_element(Int(1000), Value::Reference(element));
+        heap.write_barrier(Heaped::Array(array_obj));
+        assert_eq!(heap.allocator.old.dirty_objects(), vec![start]);
+
+        collector.minor_gc(&runtime, &mut []);
+
+        let element = heap.get_array(array).get_element(Int(1000)).reference();
+        assert!(heap.allocator.young.contains(heap.get(element).start()));
+        assert_eq!(heap.get_array(element).get_element(Int(0)).int(), Int(7));
+        assert_eq!(heap.allocator.old.dirty_objects(), vec![start]);
+    }
+
+    #[test]
+    fn full_gc_compacts_old_generation() {
+        let runtime = runtime();
+        let heap = &runtime.heap;
+        let mut collector = GenerationalCollector::new();
+
+        let mut arrays: Vec<Reference> = (0..3)
+            .map(|_| heap.new_array(object_class(), Int(MB as i32 / 4)))
+            .collect();
+        let mut young = heap.new_array(Class::Primitive(Primitive::Int), Int(16));
+        heap.get_array(arrays[2]).set_element(Int(0), Value::Reference(arrays[0]));
+        heap.get_array(arrays[0]).set_element(Int(0), Value::Reference(young));
+        let size = heap.get(arrays[0]).size();
+        let start = heap.get(arrays[0]).start();
+
+        let [first, _, third] = &mut arrays[..] else { unreachable!() };
+        collector.full_gc(&runtime, &mut [first, third, &mut young], SoftPolicy::LeastRecentlyUsed);
+
+        assert_eq!(heap.get(arrays[0]).start(), start);
+        assert_eq!(heap.get(arrays[2]).start(), start + size);
+        assert_eq!(heap.get_array(arrays[2]).get_element(Int(0)).reference(), arrays[0]);
+
+        // The nursery is emptied into the old generation, and the references to it are updated.
+        assert!(heap.allocator.old.contains(heap.get(young).start()));
+        assert_eq!(heap.get_array(arrays[0]).get_element(Int(0)).reference(), young);
+        assert_eq!(heap.allocator.young.used(), 0);
+        assert_eq!(heap.allocator.old.used(), 2 * size + heap.get(young).size());
+    }
+
+    #[test]
+    fn full_gc_leaves_pinned_objects() {
+        let runtime = runtime();
+        let heap = &runtime.heap;
+        let mut collector = GenerationalCollector::new();
+
+        let class = object_class();
+        let first_dead = heap.new_array(Class::Primitive(Primitive::Int), Int(MB as i32 / 4));
+        let mut pinned = heap.new_pinned_object(class.obj().deref());
+        let second_dead = heap.new_array(Class::Primitive(Primitive::Int), Int(MB as i32 / 4));
+        let mut moved = heap.new_array(Class::Primitive(Primitive::Int), Int(MB as i32 / 4));
+        heap.get_array(moved).set_element(Int(7), Value::Int(Int(42)));
+        let pinned_start = heap.get(pinned).start();
+        let first_dead = heap.get(first_dead).start();
+        let second_dead = heap.get(second_dead).start();
+
+        collector.full_gc(&runtime, &mut [&mut pinned, &mut moved], SoftPolicy::LeastRecentlyUsed);
+
+        // The pinned object stays put, so the array after it can only slide down to its end.
+        assert_eq!(heap.get(pinned).start(), pinned_start);
+        assert_eq!(heap.get(moved).start(), second_dead);
+        assert_eq!(heap.get_array(moved).get_element(Int(7)).int(), Int(42));
+        assert!(!is_compacted(heap, first_dead));
+        assert_eq!(heap.allocator.old.used(), second_dead + heap.get(moved).size() - first_dead);
+    }
+
+    #[test]
+    fn gc_roots_static_fields_and_global_refs() {
+        let runtime = runtime();
+        let heap = &runtime.heap;
+        let mut collector = GenerationalCollector::new();
+
+        let statics = heap.get_static(class_with_static().obj().deref());
+        let element = heap.new_array(Class::Primitive(Primitive::Int), Int(16));
+        heap.get_array(element).set_element(Int(3), Value::Int(Int(42)));
+        heap.get(statics).for_each_slot(|slot| *slot = element.0);
+        heap.write_barrier(heap.get(statics));
+
+        let array = heap.new_array(Class::Primitive(Primitive::Int), Int(16));
+        heap.get_array(array).set_element(Int(3), Value::Int(Int(7)));
+        let global = heap.new_global(array);
+        let deleted = heap.new_global(heap.new_array(Class::Primitive(Primitive::Int), Int(16)));
+        heap.delete_global(deleted);
+        let size = heap.get(array).size();
+
+        for collection in [Collection::Minor, Collection::Full] {
+            collector.gc(runtime.clone(), collection, GcCause::AllocationFailure);
+        }
+
+        // The full collection empties the nursery, leaving only the arrays that are rooted.
+        let mut element = Reference(0);
+        heap.get(statics).for_each_slot(|slot| element = Reference(*slot));
+        assert!(heap.allocator.old.contains(heap.get(element).start()));
+        assert_eq!(heap.get_array(element).get_element(Int(3)).int(), Int(42));
+        assert_eq!(heap.get_array(heap.global(global)).get_element(Int(3)).int(), Int(7));
+        assert_eq!(heap.allocator.young.used(), 0);
+        assert_eq!(heap.allocator.old.used(), heap.get(statics).size() + 2 * size);
+
+        // A slot is reused once its global reference is deleted.
+        assert_eq!(heap.new_global(Reference(0)), deleted);
+    }
+
+    #[test]
+    fn gc_clears_references_to_unreachable_objects() {
+        let runtime = runtime();
+        let heap = &runtime.heap;
+        let mut collector = GenerationalCollector::new();
+        let weak_class = reference_class(&runtime, ReferenceType::Weak).obj();
+        let soft_class = reference_class(&runtime, ReferenceType::Soft).obj();
+
+        let new_reference = |class: &ObjectClass, referent: Reference| {
+            let reference = heap.new_object(class);
+            let mut slots = Vec::new();
+            heap.get(reference).for_each_slot(|slot| slots.push(slot as *mut u32));
+            unsafe { *slots[0] = referent.0 };
+            heap.new_global(reference)
+        };
+        let strong = heap.new_array(Class::Primitive(Primitive::Int), Int(16));
+        heap.get_array(strong).set_element(Int(3), Value::Int(Int(7)));
+        let strong_global = heap.new_global(strong);
+        let reachable = new_reference(&weak_class, strong);
+        let unreachable = new_reference(&weak_class, heap.new_array(Class::Primitive(Primitive::Int), Int(16)));
+        let soft = new_reference(&soft_class, heap.new_array(Class::Primitive(Primitive::Int), Int(16)));
+
+        // A minor collection keeps softly reachable objects, and clears the rest.
+        collector.gc(runtime.clone(), Collection::Minor, GcCause::AllocationFailure);
+        assert_eq!(referent(heap, heap.global(reachable)), heap.global(strong_global));
+        assert_eq!(heap.get_array(referent(heap, heap.global(reachable))).get_element(Int(3)).int(), Int(7));
+        assert_eq!(referent(heap, heap.global(unreachable)), Reference(0));
+        assert_ne!(referent(heap, heap.global(soft)), Reference(0));
+        assert_eq!(*heap.pending_references.lock().unwrap(), vec![heap.global(unreachable)]);
+
+        // A cleared reference is no longer active, and stays pending as the heap is compacted.
+        let mut next = Vec::new();
+        heap.get(heap.global(unreachable)).for_each_slot(|slot| next.push(Reference(*slot)));
+        assert_eq!(next, vec![Reference(0), heap.global(unreachable)]);
+        collector.gc(runtime.clone(), Collection::Exhaustive, GcCause::LastDitch);
+        assert_eq!(referent(heap, heap.global(soft)), Reference(0));
+        assert_eq!(*heap.pending_references.lock().unwrap(), vec![heap.global(unreachable), heap.global(soft)]);
+        assert_eq!(heap.get_array(referent(heap, heap.global(reachable))).get_element(Int(3)).int(), Int(7));
+    }
+
+    #[test]
+    fn gc_keeps_objects_to_finalize_alive() {
+        let runtime = runtime();
+        let heap = &runtime.heap;
+        let mut collector = GenerationalCollector::new();
+        let final_class = reference_class(&runtime, ReferenceType::Final).obj();
+        let phantom_class = reference_class(&runtime, ReferenceType::Phantom).obj();
+
+        let finalizee = heap.new_array(Class::Primitive(Primitive::Int), Int(16));
+        heap.get_array(finalizee).set_element(Int(3), Value::Int(Int(7)));
+        let new_reference = |class: &ObjectClass| {
+            let reference = heap.new_object(class);
+            let mut slots = Vec::new();
+            heap.get(reference).for_each_slot(|slot| slots.push(slot as *mut u32));
+            unsafe { *slots[0] = finalizee.0 };
+            heap.new_global(reference)
+        };
+        let finalizer = new_reference(&final_class);
+        let phantom = new_reference(&phantom_class);
+
+        // The object survives to be finalized, so isn't phantom reachable yet.
+        collector.gc(runtime.clone(), Collection::Minor, GcCause::AllocationFailure);
+        let finalizee = referent(heap, heap.global(finalizer));
+        assert_eq!(heap.get_array(finalizee).get_element(Int(3)).int(), Int(7));
+        assert_eq!(referent(heap, heap.global(phantom)), finalizee);
+        assert_eq!(*heap.pending_references.lock().unwrap(), vec![heap.global(finalizer)]);
+
+        // Once it's been finalized, and the final reference cleared, it's collected.
+        heap.get(heap.global(finalizer)).for_each_slot(|slot| *slot = 0);
+        collector.gc(runtime.clone(), Collection::Full, GcCause::AllocationFailure);
+        assert_eq!(referent(heap, heap.global(phantom)), Reference(0));
+        assert_eq!(*heap.pending_references.lock().unwrap(), vec![heap.global(finalizer), heap.global(phantom)]);
+    }
+
+    #[test]
+    fn tlab() {
+        let mut reservation = Reservation::new(2 * MB);
+        let young = CopyGeneration::new(&mut reservation, MB, MB);
+        let tlab_size = young.tlab_size();
+
+        let first = young.allocate(16).unwrap() as usize;
+        let second = young.allocate(16).unwrap() as usize;
+        assert_eq!(second, first + 16);
+        assert_eq!(young.used(), tlab_size);
+
+        // Another thread takes its own TLAB.
+        let other = scope(|s| s.spawn(|| young.allocate(16).unwrap() as usize).join().unwrap());
+        assert_eq!(other, first + tlab_size);
+        assert_eq!(young.used(), 2 * tlab_size);
+
+        // Large objects are allocated outside of the TLAB.
+        let large = young.allocate(tlab_size / 2).unwrap() as usize;
+        assert_eq!(large, first + 2 * tlab_size);
+        assert_eq!(young.allocate(16).unwrap() as usize, second + 16);
+
+        // Swapping the semispaces retires the TLAB.
+        young.swap();
+        let (source, _) = young.source_dest();
+        assert_eq!(young.allocate(16).unwrap(), source.raw);
+        assert_eq!(young.used(), tlab_size);
+    }
+
+    #[test]
+    fn reservation() {
+        let mut reservation = Reservation::new(3 * MB);
+        let first = Data::new(&mut reservation, MB, MB);
+        let second = Data::new(&mut reservation, 2 * MB, MB);
+
+        // Nothing is handed out at the start, where an object would have the null reference.
+        assert!(first.raw as usize > reservation.start());
+        assert_eq!(second.raw as usize, first.raw as usize + MB);
+    }
+
+    #[test]
+    fn card_table() {
+        let cards = CardTable::new(4 * MB);
+        cards.mark(0);
+        cards.mark(3 << CARD_SHIFT);
+        cards.mark((3 << CARD_SHIFT) + 1);
+        cards.mark(MB);
+
+        assert_eq!(cards.dirty(MB).collect::<Vec<_>>(), vec![0, 3]);
+        assert_eq!(cards.dirty(2 * MB).collect::<Vec<_>>(), vec![0, 3, MB >> CARD_SHIFT]);
+
+        cards.clear();
+        assert_eq!(cards.dirty(4 * MB).count(), 0);
+    }
+
+    /// A space of 4MB, which can't outlive its reservation.
+    fn data(reservation: &mut Reservation) -> Data {
+        Data::new(reservation, 4 * MB, MB)
+    }
+
+    #[test]
+    fn data_truncate() {
+        let mut reservation = Reservation::new(4 * MB);
+        let data = data(&mut reservation);
+        let start = data.allocate(2 * MB, 4 * MB).unwrap().cast_mut();
+        unsafe { start.add(MB).write(1) };
+
+        data.truncate(16);
+
+        assert!(data.contains(start as usize));
+        assert!(!data.contains(start as usize + 16));
+        assert_eq!(unsafe { data.allocate(MB, 4 * MB).unwrap().add(MB - 16).read() }, 0);
+    }
+
+    #[test]
+    fn data_grows_committed_space() {
+        let mut reservation = Reservation::new(64 * MB);
+        let data = Data::new(&mut reservation, 64 * MB, MB);
+        assert_eq!(data.committed.load(Ordering::SeqCst), MB);
+
+        let start = data.allocate(3 * MB / 4, 64 * MB).unwrap().cast_mut();
+        let end = data.allocate(MB, 64 * MB).unwrap().cast_mut();
+        unsafe {
+            start.write(1);
+            end.add(MB - 1).write(2);
+        }
+
+        assert_eq!(data.used.load(Ordering::SeqCst), 7 * MB / 4);
+        assert_eq!(data.committed.load(Ordering::SeqCst), 2 * MB);
+    }
+
+    #[test]
+    fn data_clear() {
+        let mut reservation = Reservation::new(4 * MB);
+        let data = data(&mut reservation);
+        let start = data.allocate(16, 4 * MB).unwrap().cast_mut();
+        unsafe { start.write(1) };
+
+        data.clear();
+
+        assert_eq!(data.used.load(Ordering::SeqCst), 0);
+        assert_eq!(unsafe { data.allocate(16, 4 * MB).unwrap().read() }, 0);
+    }
+
+    #[test]
+    fn data_out_of_memory() {
+        let mut reservation = Reservation::new(4 * MB);
+        let data = data(&mut reservation);
+        assert!(data.allocate(3 * MB, 4 * MB).is_some());
+        assert!(data.allocate(2 * MB, 4 * MB).is_none());
+        assert_eq!(data.used.load(Ordering::SeqCst), 3 * MB);
+    }
+
+    #[test]
+    fn data_allocate_within_limit() {
+        let mut reservation = Reservation::new(4 * MB);
+        let data = data(&mut reservation);
+        assert!(data.allocate(3 * MB, 3 * MB).is_some());
+        assert!(data.allocate(MB, 3 * MB).is_none());
+        assert!(data.allocate(MB, 4 * MB).is_some());
+    }
+}
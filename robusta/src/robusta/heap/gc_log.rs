@@ -0,0 +1,356 @@
+//! Logging of each garbage collection, in the formats of HotSpot's `-verbose:gc` and of its
+//! unified `-Xlog:gc` logging, so that the tools that analyse HotSpot's GC logs can read them.
+//!
+//! Unlike the `gcol` tracing, there's a line for each collection, with its cause, the usage of
+//! the heap before and after, and how long the threads took to stop and were stopped for.
+
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::mem::zeroed;
+use std::time::{Duration, Instant};
+
+use libc::{getrusage, RUSAGE_SELF, timeval};
+
+use crate::heap::allocator::Allocator;
+use crate::runtime::{Decorations, GcLogging, LogOutput, Options};
+
+const K: usize = 1024;
+const M: usize = 1024 * 1024;
+
+/// Why a garbage collection was run, named like HotSpot's causes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GcCause {
+    /// An allocation didn't fit in the heap.
+    AllocationFailure,
+    /// `System.gc` or `Runtime.gc` was called.
+    SystemGc,
+    /// The heap is being dumped, and has to be collected so that its objects can be walked.
+    HeapDump,
+    /// A collection of the nursery for `-XX:+GCStress`, like HotSpot's `-XX:+ScavengeALot`.
+    ScavengeAlot,
+    /// A collection of the whole heap for `-XX:+GCStress`, like HotSpot's `-XX:+FullGCALot`.
+    FullGcAlot,
+    /// The heap was still full after a full collection, so soft references are cleared too.
+    LastDitch,
+}
+
+impl GcCause {
+    /// The name of the operation that HotSpot runs at a safepoint for a collection.
+    pub fn operation(&self) -> &'static str {
+        match self {
+            GcCause::AllocationFailure | GcCause::LastDitch | GcCause::ScavengeAlot => "GenCollectForAllocation",
+            GcCause::SystemGc | GcCause::FullGcAlot => "GenCollectFull",
+            GcCause::HeapDump => "HeapDumper",
+        }
+    }
+}
+
+impl Display for GcCause {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let cause = match self {
+            GcCause::AllocationFailure => "Allocation Failure",
+            GcCause::SystemGc => "System.gc()",
+            GcCause::HeapDump => "Heap Dump Initiated GC",
+            GcCause::ScavengeAlot => "ScavengeAlot",
+            GcCause::FullGcAlot => "FullGCAlot",
+            GcCause::LastDitch => "Last ditch collection",
+        };
+        write!(f, "{}", cause)
+    }
+}
+
+/// The space used and committed in each generation, before or after a collection.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Usage {
+    pub young_used: usize,
+    pub young_committed: usize,
+    pub old_used: usize,
+    pub old_committed: usize,
+}
+
+impl Usage {
+    pub fn of(allocator: &Allocator) -> Self {
+        Usage {
+            young_used: allocator.young.used(),
+            young_committed: allocator.young.committed(),
+            old_used: allocator.old.used(),
+            old_committed: allocator.old.committed(),
+        }
+    }
+
+    fn used(&self) -> usize {
+        self.young_used + self.old_used
+    }
+
+    fn committed(&self) -> usize {
+        self.young_committed + self.old_committed
+    }
+}
+
+/// The CPU time used by the process so far, in user and in system mode.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CpuTime {
+    pub user: Duration,
+    pub system: Duration,
+}
+
+impl CpuTime {
+    pub fn now() -> Self {
+        let duration = |time: timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+        unsafe {
+            let mut usage = zeroed();
+            if getrusage(RUSAGE_SELF, &mut usage) != 0 {
+                return CpuTime::default();
+            }
+            CpuTime { user: duration(usage.ru_utime), system: duration(usage.ru_stime) }
+        }
+    }
+
+    /// The CPU time used since an earlier time.
+    pub fn since(&self, earlier: CpuTime) -> CpuTime {
+        CpuTime {
+            user: self.user.saturating_sub(earlier.user),
+            system: self.system.saturating_sub(earlier.system),
+        }
+    }
+}
+
+/// A finished garbage collection, with when each of its phases ended.
+#[derive(Clone, Copy, Debug)]
+pub struct GcEvent {
+    /// The number of collections before this one.
+    pub id: usize,
+    pub cause: GcCause,
+    /// Whether the whole heap was collected, rather than the nursery alone.
+    pub full: bool,
+    /// When the threads were asked to stop.
+    pub stopping: Instant,
+    /// When every thread had stopped, and the collection started.
+    pub stopped: Instant,
+    /// When the collection ended.
+    pub collected: Instant,
+    /// When every thread had been restarted.
+    pub restarted: Instant,
+    pub before: Usage,
+    pub after: Usage,
+    /// The CPU time used by the process during the collection.
+    pub cpu: CpuTime,
+}
+
+/// Writes a line to the enabled logs for each garbage collection.
+pub struct GcLog {
+    /// When the runtime started, that the uptime of unified logs is measured from.
+    start: Instant,
+    /// Whether to print each collection, as with `-verbose:gc`.
+    verbose: bool,
+    /// Whether to print how long the threads were stopped, as with
+    /// `-XX:+PrintGCApplicationStoppedTime`.
+    stopped_time: bool,
+    /// The unified logs, along with their outputs.
+    unified: Vec<(GcLogging, Box<dyn Write + Send>)>,
+    /// When the threads were last restarted after a collection, or the runtime started.
+    last_safepoint: Instant,
+}
+
+impl GcLog {
+    /// A log that doesn't write anything.
+    pub fn disabled() -> Self {
+        GcLog::open(&Options::default())
+    }
+
+    /// Open the logs enabled by the options.
+    ///
+    /// Like `java`, the log files are created up front, and a file that can't be created is
+    /// reported, and logged to stdout instead.
+    pub fn open(options: &Options) -> Self {
+        let unified = options.gc_logs.iter()
+            .map(|logging| {
+                let output: Box<dyn Write + Send> = match &logging.output {
+                    LogOutput::Stdout => Box::new(io::stdout()),
+                    LogOutput::Stderr => Box::new(io::stderr()),
+                    LogOutput::File(path) => match File::create(path) {
+                        Ok(file) => Box::new(BufWriter::new(file)),
+                        Err(error) => {
+                            eprintln!("[error][logging] Error opening log file '{}': {}", path.display(), error);
+                            Box::new(io::stdout())
+                        }
+                    },
+                };
+                (logging.clone(), output)
+            })
+            .collect();
+
+        let start = Instant::now();
+        GcLog {
+            start,
+            verbose: options.verbose_gc,
+            stopped_time: options.print_gc_application_stopped_time,
+            unified,
+            last_safepoint: start,
+        }
+    }
+
+    /// Log a collection once the threads have been restarted.
+    ///
+    /// A log that can't be written to is ignored, as the program can carry on without it.
+    pub fn log(&mut self, event: &GcEvent) {
+        for line in self.verbose_lines(event) {
+            println!("{}", line);
+        }
+        for (logging, output) in self.unified.iter_mut() {
+            let lines = unified_lines(self.start, self.last_safepoint, logging, event);
+            let _ = lines.iter().try_for_each(|line| writeln!(output, "{}", line))
+                .and_then(|_| output.flush());
+        }
+        self.last_safepoint = event.restarted;
+    }
+
+    /// The lines printed for `-verbose:gc` and `-XX:+PrintGCApplicationStoppedTime`, in the
+    /// format of Java 8.
+    fn verbose_lines(&self, event: &GcEvent) -> Vec<String> {
+        let mut lines = vec![];
+        if self.verbose {
+            let gc = if event.full { "Full GC" } else { "GC" };
+            lines.push(format!("[{} ({})  {}K->{}K({}K), {:.7} secs]", gc, event.cause,
+                               event.before.used() / K, event.after.used() / K, event.after.committed() / K,
+                               (event.collected - event.stopped).as_secs_f64()));
+        }
+        if self.stopped_time {
+            lines.push(format!("Total time for which application threads were stopped: {:.7} seconds, Stopping threads took: {:.7} seconds",
+                               (event.restarted - event.stopping).as_secs_f64(),
+                               (event.stopped - event.stopping).as_secs_f64()));
+        }
+        lines
+    }
+}
+
+/// The lines written to a unified log for a collection, in the format of `-Xlog:gc*` and
+/// `-Xlog:safepoint` with the serial collector.
+fn unified_lines(start: Instant, last_safepoint: Instant, logging: &GcLogging, event: &GcEvent) -> Vec<String> {
+    let line = |at: Instant, tags: &str, message: String| {
+        format!("{}{}", decorate(&logging.decorations, at - start, tags), message)
+    };
+    let id = event.id;
+    let pause = if event.full { "Pause Full" } else { "Pause Young" };
+
+    let mut lines = vec![];
+    if logging.details {
+        lines.push(line(event.stopped, "gc,start", format!("GC({}) {} ({})", id, pause, event.cause)));
+        let (before, after) = (event.before, event.after);
+        lines.push(line(event.collected, "gc,heap", format!("GC({}) DefNew: {}K->{}K({}K)",
+                                                            id, before.young_used / K, after.young_used / K, after.young_committed / K)));
+        lines.push(line(event.collected, "gc,heap", format!("GC({}) Tenured: {}K->{}K({}K)",
+                                                            id, before.old_used / K, after.old_used / K, after.old_committed / K)));
+    }
+    if logging.gc {
+        lines.push(line(event.collected, "gc", format!("GC({}) {} ({}) {}M->{}M({}M) {:.3}ms", id, pause, event.cause,
+                                                       event.before.used() / M, event.after.used() / M, event.after.committed() / M,
+                                                       (event.collected - event.stopped).as_secs_f64() * 1000.0)));
+    }
+    if logging.details {
+        lines.push(line(event.collected, "gc,cpu", format!("GC({}) User={:.2}s Sys={:.2}s Real={:.2}s", id,
+                                                           event.cpu.user.as_secs_f64(), event.cpu.system.as_secs_f64(),
+                                                           (event.collected - event.stopped).as_secs_f64())));
+    }
+    if logging.safepoint {
+        lines.push(line(event.restarted, "safepoint", format!(
+            "Safepoint \"{}\", Time since last: {} ns, Reaching safepoint: {} ns, At safepoint: {} ns, Total: {} ns",
+            event.cause.operation(),
+            event.stopping.saturating_duration_since(last_safepoint).as_nanos(),
+            (event.stopped - event.stopping).as_nanos(),
+            (event.restarted - event.stopped).as_nanos(),
+            (event.restarted - event.stopping).as_nanos())));
+    }
+    lines
+}
+
+/// The decorations before a line of a unified log, which are all at the info level.
+fn decorate(decorations: &Decorations, uptime: Duration, tags: &str) -> String {
+    let mut decorated = String::new();
+    if decorations.uptime {
+        decorated.push_str(&format!("[{:.3}s]", uptime.as_secs_f64()));
+    }
+    if decorations.uptime_millis {
+        decorated.push_str(&format!("[{}ms]", uptime.as_millis()));
+    }
+    if decorations.uptime_nanos {
+        decorated.push_str(&format!("[{}ns]", uptime.as_nanos()));
+    }
+    if decorations.level {
+        decorated.push_str("[info]");
+    }
+    if decorations.tags {
+        decorated.push_str(&format!("[{}]", tags));
+    }
+    if !decorated.is_empty() {
+        decorated.push(' ');
+    }
+    decorated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(start: Instant) -> GcEvent {
+        let at = |micros| start + Duration::from_micros(micros);
+        GcEvent {
+            id: 3,
+            cause: GcCause::AllocationFailure,
+            full: false,
+            stopping: at(500_000),
+            stopped: at(500_250),
+            collected: at(501_750),
+            restarted: at(502_000),
+            before: Usage { young_used: 3 * M, young_committed: 3 * M, old_used: 2 * M, old_committed: 8 * M },
+            after: Usage { young_used: 256 * K, young_committed: 3 * M, old_used: 2 * M + 512 * K, old_committed: 8 * M },
+            cpu: CpuTime { user: Duration::from_millis(10), system: Duration::ZERO },
+        }
+    }
+
+    #[test]
+    fn logs_collections_like_java_8() {
+        let options = Options { verbose_gc: true, print_gc_application_stopped_time: true, ..Options::default() };
+        let log = GcLog::open(&options);
+        let event = event(log.start);
+
+        assert_eq!(log.verbose_lines(&event), vec![
+            "[GC (Allocation Failure)  5120K->2816K(11264K), 0.0015000 secs]",
+            "Total time for which application threads were stopped: 0.0020000 seconds, Stopping threads took: 0.0002500 seconds",
+        ]);
+    }
+
+    #[test]
+    fn logs_collections_with_unified_logging() {
+        let start = Instant::now();
+        let event = GcEvent { full: true, cause: GcCause::SystemGc, ..event(start) };
+        let logging = GcLogging {
+            gc: true,
+            details: true,
+            safepoint: true,
+            output: LogOutput::Stdout,
+            decorations: Decorations::default(),
+        };
+
+        assert_eq!(unified_lines(start, start + Duration::from_millis(100), &logging, &event), vec![
+            "[0.500s][info][gc,start] GC(3) Pause Full (System.gc())",
+            "[0.502s][info][gc,heap] GC(3) DefNew: 3072K->256K(3072K)",
+            "[0.502s][info][gc,heap] GC(3) Tenured: 2048K->2560K(8192K)",
+            "[0.502s][info][gc] GC(3) Pause Full (System.gc()) 5M->2M(11M) 1.500ms",
+            "[0.502s][info][gc,cpu] GC(3) User=0.01s Sys=0.00s Real=0.00s",
+            "[0.502s][info][safepoint] Safepoint \"GenCollectFull\", Time since last: 400000000 ns, Reaching safepoint: 250000 ns, At safepoint: 1750000 ns, Total: 2000000 ns",
+        ]);
+
+        let logging = GcLogging {
+            details: false,
+            safepoint: false,
+            decorations: Decorations { uptime: false, uptime_millis: true, level: false, ..Decorations::default() },
+            ..logging
+        };
+        assert_eq!(unified_lines(start, start, &logging, &event), vec![
+            "[501ms][gc] GC(3) Pause Full (System.gc()) 5M->2M(11M) 1.500ms",
+        ]);
+    }
+}
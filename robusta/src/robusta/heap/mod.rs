@@ -1,115 +1,234 @@
-use std::collections::{HashMap, HashSet};
-use std::sync::RwLock;
-use nohash_hasher::BuildNoHashHasher;
-
-use rand::{RngCore, thread_rng};
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::AtomicBool;
 
 use crate::collection::classes::ClassRef;
 use crate::collection::once::OnceMap;
-use crate::heap::allocator::{Allocator, Array, Object};
+use crate::heap::allocator::{Allocator, Array, ArrayHeader, Kind, Object, object_align, ObjectHeader, OutOfMemory, with_reserve};
 use crate::java::{FieldType, Int, Reference, Value};
-use crate::method_area::{Class, ObjectClass};
+use crate::method_area::{Class, Field, ObjectClass};
 use crate::method_area::const_pool::FieldKey;
 use crate::method_area::Primitive::Char;
+use crate::runtime::Options;
+use crate::thread::Thread;
 
 pub mod allocator;
+pub mod dump;
+pub mod finalizer;
+pub mod gc_log;
 mod hash_code;
 pub mod garbage_collector;
+pub mod reference;
 pub mod sync;
+pub mod verify;
 
 pub struct Heap {
     pub allocator: Allocator,
-    references: RwLock<HashMap<u32, Heaped, BuildNoHashHasher<u32>>>,
-    class_objects: OnceMap<String, Reference>,
+    /// The `java.lang.Class` objects, keyed by the defining loader and name of their class.
+    class_objects: OnceMap<(Reference, String), Reference>,
     string_constants: OnceMap<String, Reference>,
-    static_objects: OnceMap<String, Reference>,
+    static_objects: OnceMap<(Reference, String), Reference>,
+    /// The `OutOfMemoryError` thrown when the heap is full, allocated up front as there might
+    /// not be space for it when it's needed.
+    out_of_memory_error: RwLock<Option<Reference>>,
+    /// The global references held by native code, which are roots until they're deleted, the
+    /// slots of deleted references are reused.
+    global_refs: Mutex<Vec<Option<Reference>>>,
+    /// The references cleared by the collector that haven't been moved onto `Reference.pending`
+    /// yet, see [`enqueue_pending`](reference::enqueue_pending).
+    pending_references: Mutex<Vec<Reference>>,
+    /// Whether the heap has been dumped for `-XX:+HeapDumpOnOutOfMemoryError`, which only
+    /// happens the first time the heap is full.
+    dumped_on_out_of_memory: AtomicBool,
 }
 
+/// A global reference, see [`Heap::new_global`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Global(usize);
+
 unsafe impl Send for Heap {}
 
 impl Heap {
-    pub fn num_objects(&self) -> usize {
-        let refs = self.references.read().unwrap();
-        refs.len()
-    }
-
-    pub fn retain(&self, retain: &HashSet<u32, BuildNoHashHasher<u32>>) {
-        let mut references = self.references.write().unwrap();
-
-        let refs_to_remove: Vec<u32> = references.keys()
-            .filter(|key| !retain.contains(key))
-            .map(|key| *key)
-            .collect();
-
-        for reference in &refs_to_remove {
-            references.remove(reference);
-        }
+    /// Record that a reference has been stored in an object, for the garbage collector to keep
+    /// track of references from the old generation into the nursery.
+    pub fn write_barrier(&self, heaped: Heaped) {
+        self.allocator.old.write_barrier(heaped.start());
     }
 
     pub fn clear(&self) {
         self.allocator.swap();
     }
 
-    pub fn new() -> Self {
+    pub fn new(options: &Options) -> Self {
         Heap {
-            allocator: Allocator::new(),
-            references: RwLock::new(HashMap::with_hasher(BuildNoHashHasher::default())),
+            allocator: Allocator::new(options),
             class_objects: OnceMap::new(),
             string_constants: OnceMap::new(),
             static_objects: OnceMap::new(),
+            out_of_memory_error: RwLock::new(None),
+            global_refs: Mutex::new(Vec::new()),
+            pending_references: Mutex::new(Vec::new()),
+            dumped_on_out_of_memory: AtomicBool::new(false),
             // safe_point: AtomicBool::new(false),
         }
     }
 
-    pub fn copy(&self, heaped: Heaped) -> Reference {
-        let new_heaped = match heaped {
+    /// Allocate an empty object of the same class, or an empty array of the same type and
+    /// length, as the referenced object, for Java code to copy it into.
+    ///
+    /// Allocating can move the original, so it must be reachable from a GC root, and read from
+    /// the root again afterwards.
+    pub fn try_new_like(&self, thread: &mut Thread, reference: Reference) -> Result<Reference, OutOfMemory> {
+        match self.get(reference) {
             Heaped::Array(array) => {
-                Heaped::Array(self.allocator.copy_array(array))
+                let new_arr = self.allocator.new_array_like(array)?;
+                Ok(self.insert(Heaped::Array(new_arr)))
             }
-            Heaped::Object(object) => {
-                Heaped::Object(self.allocator.copy_object(object))
-            }
-        };
-        self.insert(new_heaped)
+            Heaped::Object(object) => self.try_new_object(thread, object.class()),
+        }
     }
 
+    /// Copy the fields or elements of an object into an object allocated by
+    /// [`try_new_like`](Heap::try_new_like).
+    pub fn copy(&self, from: Reference, to: Reference) {
+        match (self.get(from), self.get(to)) {
+            (Heaped::Array(from), Heaped::Array(to)) => self.allocator.copy_array(from, to),
+            (Heaped::Object(from), Heaped::Object(to)) => self.allocator.copy_object(from, to),
+            _ => panic!("Cannot copy between an object and an array"),
+        }
+    }
+
+    /// Allocate an object for Java code, failing if the heap is full.
+    ///
+    /// An object whose class has a finalizer is registered for finalization, which runs Java
+    /// code in the allocating thread, so like any allocation, it can collect garbage.
+    pub fn try_new_object(&self, thread: &mut Thread, class: &ObjectClass) -> Result<Reference, OutOfMemory> {
+        let reference = self.allocate_object(class)?;
+        if class.has_finalizer {
+            finalizer::register(thread, reference)
+        } else {
+            Ok(reference)
+        }
+    }
+
+    /// Allocate an object for the JVM itself, which can use the space kept in reserve.
+    ///
+    /// The object isn't registered for finalization, the JVM doesn't make objects that need it.
     pub fn new_object(&self, class: &ObjectClass) -> Reference {
-        let object = self.allocator.new_object(class);
-        self.insert(Heaped::Object(object))
+        with_reserve(|| self.allocate_object(class)).expect("Out of memory")
     }
 
-    pub fn get(&self, reference: Reference) -> Heaped {
-        let mut references = self.references.write().unwrap();
-        let heaped = references.get_mut(&reference.0);
-        heaped.expect(format!("Failed to find reference {}", reference.0).as_str()).clone()
+    /// Allocate an object, class loaders are pinned, as the method area refers to them by their
+    /// reference.
+    fn allocate_object(&self, class: &ObjectClass) -> Result<Reference, OutOfMemory> {
+        let kind = if is_class_loader(class) { Kind::Pinned } else { Kind::Object };
+        let object = self.allocator.new_object(class, kind)?;
+        Ok(self.insert(Heaped::Object(object)))
     }
 
-    pub fn set(&self, reference: Reference, heaped: Heaped) {
-        let mut references = self.references.write().unwrap();
-        references.insert(reference.0, heaped);
+    /// Allocate an object that the JVM keeps by its reference, which is never moved.
+    fn new_pinned_object(&self, class: &ObjectClass) -> Reference {
+        let object = with_reserve(|| self.allocator.new_object(class, Kind::Pinned))
+            .expect("Out of memory");
+        self.insert(Heaped::Object(object))
+    }
+
+    pub fn get(&self, reference: Reference) -> Heaped {
+        Heaped::at(self.allocator.start(reference))
     }
 
     pub fn get_static(&self, class: &ObjectClass) -> Reference {
-        let x = self.static_objects.get_or_init(class.name.clone(), |_| {
+        let x = self.static_objects.get_or_init((class.loader, class.name.clone()), |_| {
             if class.static_width == 0 {
                 return Reference(0);
             }
-            let object = self.allocator.new_static_object(class);
+            let object = with_reserve(|| self.allocator.new_static_object(class))
+                .expect("Out of memory");
+            if class.loader.0 == 0 && class.name == "java.lang.ref.SoftReference" {
+                reference::set_clock(object);
+            }
             self.insert(Heaped::Object(object))
         }).clone();
         x
     }
 
+    /// The static storage of the classes of the bootstrap loader, which are never unloaded.
+    pub fn bootstrap_static_objects(&self) -> Vec<Reference> {
+        self.static_objects.current_entries().into_iter()
+            .filter(|((loader, _), statics)| loader.0 == 0 && statics.0 != 0)
+            .map(|(_, statics)| statics)
+            .collect()
+    }
+
+    /// The class objects and static storage of the classes of each user defined loader, which
+    /// are alive for as long as their loader is.
+    pub fn loader_class_objects(&self) -> HashMap<Reference, Vec<Reference>> {
+        let mut class_objects: HashMap<Reference, Vec<Reference>> = HashMap::new();
+        let entries = self.class_objects.current_entries().into_iter()
+            .chain(self.static_objects.current_entries());
+        for ((loader, _), object) in entries {
+            if loader.0 != 0 && object.0 != 0 {
+                class_objects.entry(loader).or_default().push(object);
+            }
+        }
+        class_objects
+    }
+
+    /// Hold a reference until it's deleted with [`delete_global`](Heap::delete_global), like
+    /// JNI's `NewGlobalRef`, the collector keeps the object alive and the global up to date.
+    pub fn new_global(&self, reference: Reference) -> Global {
+        let mut global_refs = self.global_refs.lock().unwrap();
+        if let Some(index) = global_refs.iter().position(Option::is_none) {
+            global_refs[index] = Some(reference);
+            Global(index)
+        } else {
+            global_refs.push(Some(reference));
+            Global(global_refs.len() - 1)
+        }
+    }
+
+    /// The current value of a global reference.
+    pub fn global(&self, global: Global) -> Reference {
+        self.global_refs.lock().unwrap()[global.0].expect("Global reference has been deleted")
+    }
+
+    /// Release a global reference, so it no longer keeps its object alive.
+    pub fn delete_global(&self, global: Global) {
+        self.global_refs.lock().unwrap()[global.0] = None;
+    }
+
+    /// Forget the class objects and static storage of the classes of an unloaded loader, the
+    /// objects themselves are collected with the rest of the garbage.
+    pub fn unload(&self, loader: Reference) {
+        self.class_objects.retain(|(other, _)| *other != loader);
+        self.static_objects.retain(|(other, _)| *other != loader);
+    }
+
+    /// Allocate an array for Java code, failing if the heap is full.
+    pub fn try_new_array(&self, arr_type: Class, length: Int) -> Result<Reference, OutOfMemory> {
+        let array = self.allocator.new_array(arr_type, length)?;
+        Ok(self.insert(Heaped::Array(array)))
+    }
+
+    /// Allocate an array for the JVM itself, which can use the space kept in reserve.
     pub fn new_array(&self, arr_type: Class, length: Int) -> Reference {
-        let array = self.allocator.new_array(arr_type, length);
-        self.insert(Heaped::Array(array))
+        with_reserve(|| self.try_new_array(arr_type, length)).expect("Out of memory")
+    }
+
+    pub fn set_out_of_memory_error(&self, error: Reference) {
+        self.out_of_memory_error.write().unwrap().get_or_insert(error);
+    }
+
+    /// The preallocated `OutOfMemoryError`, once the JVM has started.
+    pub fn out_of_memory_error(&self) -> Option<Reference> {
+        *self.out_of_memory_error.read().unwrap()
     }
 
     pub fn get_object(&self, reference: Reference) -> Object {
-        let references = self.references.read().unwrap();
-        match references.get(&reference.0).unwrap() {
-            Heaped::Object(object) => object.clone(),
-            _ => panic!("")
+        match self.get(reference) {
+            Heaped::Object(object) => object,
+            Heaped::Array(_) => panic!("Reference {} is an array, not an object", reference.0)
         }
     }
 
@@ -126,10 +245,9 @@ impl Heap {
     }
 
     pub fn get_array(&self, reference: Reference) -> Array {
-        let references = self.references.read().unwrap();
-        match references.get(&reference.0).unwrap() {
-            Heaped::Array(array) => array.clone(),
-            _ => panic!("")
+        match self.get(reference) {
+            Heaped::Array(array) => array,
+            Heaped::Object(_) => panic!("Reference {} is an object, not an array", reference.0)
         }
     }
 
@@ -143,7 +261,7 @@ impl Heap {
             char_array.copy_from_slice(&chars);
 
             // String
-            let object_ref = self.new_object(class);
+            let object_ref = self.new_pinned_object(class);
             let object = self.get_object(object_ref);
 
             object.set_field(&FieldKey {
@@ -157,12 +275,12 @@ impl Heap {
     }
 
     pub fn insert_class_object(&self, class: Class, class_class: &ObjectClass, string_class: &ObjectClass) -> Reference {
-        self.class_objects.get_or_init(class.name(), |_| {
+        self.class_objects.get_or_init((class.loader(), class.name()), |_| {
             // Name
             let name_ref = self.insert_string_const(&class.name(), string_class);
 
             // Class
-            let object_ref = self.new_object(class_class);
+            let object_ref = self.new_pinned_object(class_class);
             let object = self.get_object(object_ref);
 
             object.set_field(&FieldKey {
@@ -171,25 +289,25 @@ impl Heap {
                 descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
             }, Value::Reference(name_ref));
 
+            object.set_field(&FieldKey {
+                class: "java.lang.Class".to_string(),
+                name: "classLoader".to_string(),
+                descriptor: FieldType::from_descriptor("Ljava/lang/ClassLoader;").unwrap(),
+            }, Value::Reference(class.loader()));
+
             object_ref
         }).clone()
     }
 
     fn insert(&self, heaped: Heaped) -> Reference {
-        let mut rng = thread_rng();
-        let mut references = self.references.write().unwrap();
-
-        // TODO: This is probably an awful way to allocate references!
-        let reference = {
-            let mut next_ref = Reference(rng.next_u32());
-            while references.contains_key(&next_ref.0) {
-                next_ref = Reference(rng.next_u32());
-            }
-            next_ref
-        };
-
-        references.insert(reference.0, heaped);
-        reference
+        let start = heaped.start();
+        if !self.allocator.young.contains(start) {
+            // An object allocated straight into the old generation may be given references to
+            // the nursery before any write barrier sees it.
+            self.allocator.old.insert(start);
+            self.allocator.old.write_barrier(start);
+        }
+        self.allocator.reference(start)
     }
 
     pub fn get_thread_alive(&self, thread: Reference) -> bool {
@@ -223,6 +341,11 @@ impl Heap {
     }
 }
 
+/// Whether instances of the class are class loaders.
+fn is_class_loader(class: &ObjectClass) -> bool {
+    class.parents().any(|parent| parent.name == "java.lang.ClassLoader" && parent.loader.0 == 0)
+}
+
 #[derive(Clone, Copy)]
 pub enum Heaped {
     Object(Object),
@@ -230,6 +353,124 @@ pub enum Heaped {
 }
 
 impl Heaped {
+    /// The object that starts at the given address in the heap, an array or an object depending
+    /// on the kind in its header.
+    pub fn at(start: usize) -> Heaped {
+        let kind = unsafe { (start as *const Kind).read() };
+        match kind {
+            Kind::Array => Heaped::Array(Array {
+                header: start as *mut ArrayHeader,
+                data: (start + size_of::<ArrayHeader>()) as *mut u8,
+            }),
+            _ => Heaped::Object(Object {
+                header: start as *mut ObjectHeader,
+                data: (start + size_of::<ObjectHeader>()) as *mut u8,
+            }),
+        }
+    }
+
+    /// The address of the start of the object in the heap.
+    pub fn start(&self) -> usize {
+        match self {
+            Heaped::Object(object) => object.header as usize,
+            Heaped::Array(array) => array.header as usize,
+        }
+    }
+
+    pub fn kind(&self) -> Kind {
+        unsafe { (self.start() as *const Kind).read() }
+    }
+
+    /// Whether the garbage collector has to leave the object where it is.
+    pub fn is_pinned(&self) -> bool {
+        matches!(self.kind(), Kind::Pinned | Kind::Static)
+    }
+
+    /// The size of the object in the heap, including its header and any padding after it.
+    pub fn size(&self) -> usize {
+        let size = match self {
+            Heaped::Object(object) if self.kind() == Kind::Static => size_of::<ObjectHeader>() + object.class().static_width,
+            Heaped::Object(object) => size_of::<ObjectHeader>() + object.class().instance_width,
+            Heaped::Array(array) => size_of::<ArrayHeader>() + array.header().length,
+        };
+        object_align(size)
+    }
+
+    /// The number of minor collections that the object has survived.
+    pub fn age(&self) -> u8 {
+        match self {
+            Heaped::Object(object) => object.header().age,
+            Heaped::Array(array) => array.header().age,
+        }
+    }
+
+    pub fn set_age(&self, age: u8) {
+        match self {
+            Heaped::Object(object) => unsafe { (*object.header).age = age },
+            Heaped::Array(array) => unsafe { (*array.header).age = age },
+        }
+    }
+
+    /// The new reference of the object, if the garbage collector has moved it.
+    pub fn forwarded(&self) -> Option<Reference> {
+        let header = self.start() as *const ObjectHeader;
+        unsafe {
+            match (*header).kind {
+                Kind::Forwarded => Some(Reference((*header).hash_code.0 as u32)),
+                _ => None,
+            }
+        }
+    }
+
+    /// Leave the new reference of the object behind, once the garbage collector has copied
+    /// it, in place of the hash code, which the copy has.
+    pub fn forward(&self, to: Reference) {
+        // The headers of objects and arrays start the same way.
+        let header = self.start() as *mut ObjectHeader;
+        unsafe {
+            (*header).kind = Kind::Forwarded;
+            (*header).hash_code = Int(to.0 as i32);
+        }
+    }
+
+    /// The loader of the class of the object, which the object keeps alive.
+    pub fn loader(&self) -> Reference {
+        match self {
+            Heaped::Object(object) => object.class().loader,
+            Heaped::Array(array) => array.header().component.loader(),
+        }
+    }
+
+    /// Call `f` on each field or element of the object that holds a reference, so that the
+    /// garbage collector can follow the reference, or update it.
+    pub fn for_each_slot(&self, mut f: impl FnMut(&mut u32)) {
+        match self {
+            Heaped::Array(array) => {
+                if array.header().component.is_reference() {
+                    let slots: *mut u32 = array.data.cast();
+                    for idx in 0..array.header().length / 4 {
+                        f(unsafe { slots.add(idx).as_mut().unwrap() });
+                    }
+                }
+            }
+            Heaped::Object(object) => {
+                let class = object.class();
+                let mut field_slots = |fields: &[Field]| {
+                    for field in fields.iter().filter(|field| field.descriptor.is_reference()) {
+                        f(unsafe { object.data.add(field.offset).cast::<u32>().as_mut().unwrap() });
+                    }
+                };
+                if self.kind() == Kind::Static {
+                    field_slots(&class.static_fields);
+                } else {
+                    for parent in class.parents() {
+                        field_slots(&parent.instance_fields);
+                    }
+                }
+            }
+        }
+    }
+
     pub fn class(&self, object: Class) -> Class {
         match self {
             Heaped::Object(object) => Class::Object(ClassRef::new(object.class() as *const ObjectClass)),
@@ -242,4 +483,4 @@ impl Heaped {
             }
         }
     }
-}
\ No newline at end of file
+}
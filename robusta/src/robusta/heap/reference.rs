@@ -0,0 +1,331 @@
+//! The collector's handling of `java.lang.ref.Reference`, whose referent doesn't keep its object
+//! alive.
+//!
+//! While tracing, the collector skips the referent of an active reference, and discovers the
+//! reference instead. Once everything strongly reachable has been found, the references are
+//! processed from the strongest to the weakest: soft references keep their referents alive
+//! unless memory is short and they haven't been used for a while, then the soft and weak
+//! references whose referents weren't reached are cleared. Final references keep their
+//! referents alive for them to be finalized, and then the phantom references whose referents
+//! still weren't reached are cleared. The references go on the pending list, for the
+//! `Reference Handler` thread to add them to their queues.
+
+use std::mem::take;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing::debug;
+
+use crate::heap::allocator::{Kind, Object};
+use crate::heap::{Heap, Heaped};
+use crate::java::{FieldType, Long, Reference, Value};
+use crate::log;
+use crate::method_area::const_pool::FieldKey;
+use crate::method_area::ReferenceType;
+use crate::runtime::Runtime;
+use crate::thread::Thread;
+
+/// How many milliseconds a soft reference is kept after it was last used, for each megabyte that
+/// is free in the heap, like HotSpot's `-XX:SoftRefLRUPolicyMSPerMB`.
+const SOFT_REF_LRU_POLICY_MS_PER_MB: i64 = 1000;
+
+const MB: usize = 1024 * 1024;
+
+/// Which soft references a collection keeps the referents of.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SoftPolicy {
+    /// Keep every softly reachable object, a minor collection doesn't clear soft references.
+    Always,
+    /// Keep the softly reachable objects that have been used recently, the more free memory
+    /// there is, the longer ago.
+    LeastRecentlyUsed,
+    /// Clear every soft reference, as the heap is about to run out of memory.
+    Never,
+}
+
+/// The offsets of the fields of the reference classes that the collector uses.
+struct Offsets {
+    referent: usize,
+    next: usize,
+    /// `SoftReference.timestamp`, the value of the clock when `get` was last called.
+    timestamp: Option<usize>,
+}
+
+/// The references discovered by a collection, which are processed once tracing is done.
+pub struct References {
+    /// The offsets of the fields, or `None` if `java.lang.ref.Reference` hasn't been loaded, so
+    /// there can't be any references.
+    offsets: Option<Offsets>,
+    policy: SoftPolicy,
+    /// The value of `SoftReference.clock`, the time of the last collection.
+    clock: i64,
+    /// How long before the last collection a soft reference must have been used to be kept.
+    max_interval: i64,
+    /// Whether references are still being discovered, once the finalizable objects are being
+    /// kept alive, the references found are treated like any other object.
+    discovering: bool,
+    soft: Vec<Object>,
+    /// The soft references that the policy didn't keep, to be cleared if their referents weren't
+    /// reached some other way.
+    expired: Vec<Object>,
+    weak: Vec<Object>,
+    finalizable: Vec<Object>,
+    phantom: Vec<Object>,
+}
+
+impl References {
+    pub fn new(runtime: &Runtime, policy: SoftPolicy) -> Self {
+        let heap = &runtime.heap;
+        let method_area = &runtime.method_area;
+        let offset = |class: &str, name: &str| {
+            let class = method_area.find_loaded_class(Reference(0), class)?;
+            class.instance_fields.iter().find(|field| field.name == name).map(|field| field.offset)
+        };
+        let offsets = offset("java.lang.ref.Reference", "referent").map(|referent| Offsets {
+            referent,
+            next: offset("java.lang.ref.Reference", "next").unwrap(),
+            timestamp: offset("java.lang.ref.SoftReference", "timestamp"),
+        });
+
+        let clock = soft_statics(runtime).map_or(0, |statics| statics.get_static(&clock_key()).long().0);
+        let free = heap.allocator.capacity().saturating_sub(heap.allocator.used()) / MB;
+
+        References {
+            offsets,
+            policy,
+            clock,
+            max_interval: free as i64 * SOFT_REF_LRU_POLICY_MS_PER_MB,
+            discovering: true,
+            soft: Vec::new(),
+            expired: Vec::new(),
+            weak: Vec::new(),
+            finalizable: Vec::new(),
+            phantom: Vec::new(),
+        }
+    }
+
+    /// Call `f` on each slot of the object that the collector traces.
+    ///
+    /// If the object is an active reference to an object that `discover` accepts, the reference
+    /// is discovered, and its referent is left to be processed later.
+    pub fn trace(&mut self, heaped: Heaped, discover: impl Fn(u32) -> bool, mut f: impl FnMut(&mut u32)) {
+        match self.discover(heaped, discover) {
+            Some(referent) => heaped.for_each_slot(|slot| {
+                if !std::ptr::eq(slot, referent) {
+                    f(slot)
+                }
+            }),
+            None => heaped.for_each_slot(f),
+        }
+    }
+
+    /// Discover the object if it's a reference that the collector handles specially, returning
+    /// the slot of its referent.
+    fn discover(&mut self, heaped: Heaped, discover: impl Fn(u32) -> bool) -> Option<*const u32> {
+        let offsets = self.offsets.as_ref().filter(|_| self.discovering)?;
+        let Heaped::Object(object) = heaped else {
+            return None;
+        };
+        // The static fields of `SoftReference` are stored in an object of its class.
+        if heaped.kind() == Kind::Static {
+            return None;
+        }
+        let discovered = match object.class().reference_type? {
+            ReferenceType::Soft => &mut self.soft,
+            ReferenceType::Weak => &mut self.weak,
+            ReferenceType::Final => &mut self.finalizable,
+            ReferenceType::Phantom => &mut self.phantom,
+        };
+
+        // A reference that has been cleared or enqueued is inactive, its `next` isn't null.
+        let referent = *slot(object, offsets.referent);
+        if *slot(object, offsets.next) != 0 || referent == 0 || !discover(referent) {
+            return None;
+        }
+        discovered.push(object);
+        Some(slot(object, offsets.referent))
+    }
+
+    /// Call `keep` on the referent slots of the discovered soft references that the policy
+    /// keeps, returning whether there were any.
+    ///
+    /// The collector has to trace from the referents it keeps, which can discover more soft
+    /// references, so this is repeated until there are none left to keep.
+    pub fn keep_soft(&mut self, mut keep: impl FnMut(&mut u32)) -> bool {
+        let Some(offsets) = &self.offsets else {
+            return false;
+        };
+        let mut kept = false;
+        for object in take(&mut self.soft) {
+            let keeps = match (self.policy, offsets.timestamp) {
+                (SoftPolicy::Always, _) => true,
+                (SoftPolicy::LeastRecentlyUsed, Some(timestamp)) => {
+                    let timestamp = unsafe { object.data.add(timestamp).cast::<i64>().read() };
+                    self.clock - timestamp <= self.max_interval
+                }
+                _ => false,
+            };
+            if keeps {
+                keep(slot(object, offsets.referent));
+                kept = true;
+            } else {
+                self.expired.push(object);
+            }
+        }
+        kept
+    }
+
+    /// Call `keep` on the referent slots of the discovered final references whose referents
+    /// weren't reached, returning the references to go on the pending list, so the referents
+    /// are finalized. `reached` gives the new reference of a referent that was reached.
+    ///
+    /// The collector has to trace from the referents it keeps, everything they refer to must
+    /// survive for the finalizers, so no more references are discovered.
+    pub fn keep_finalizable(&mut self, heap: &Heap, reached: impl Fn(u32) -> Option<u32>, mut keep: impl FnMut(&mut u32)) -> Vec<Reference> {
+        self.discovering = false;
+        let Some(offsets) = &self.offsets else {
+            return Vec::new();
+        };
+
+        let mut finalizable = Vec::new();
+        for object in take(&mut self.finalizable) {
+            let referent = slot(object, offsets.referent);
+            if let Some(reached) = reached(*referent) {
+                *referent = reached;
+                continue;
+            }
+            // The referent is kept until the finalizer thread clears the reference, but the
+            // reference is no longer active.
+            let reference = heap.allocator.reference(object.header as usize);
+            keep(referent);
+            *slot(object, offsets.next) = reference.0;
+            finalizable.push(reference);
+        }
+        if !finalizable.is_empty() {
+            debug!(target: log::GC, finalizable=finalizable.len(), "Found objects to finalize");
+        }
+        finalizable
+    }
+
+    /// Clear the discovered soft, weak or phantom references whose referents weren't reached,
+    /// returning them to go on the pending list.
+    ///
+    /// `reached` gives the new reference of a referent that was reached, which the reference is
+    /// updated to.
+    pub fn clear(&mut self, reference_type: ReferenceType, heap: &Heap, reached: impl Fn(u32) -> Option<u32>) -> Vec<Reference> {
+        let discovered = match reference_type {
+            ReferenceType::Soft => take(&mut self.expired),
+            ReferenceType::Weak => take(&mut self.weak),
+            ReferenceType::Phantom => take(&mut self.phantom),
+            ReferenceType::Final => panic!("Final references are never cleared by the collector"),
+        };
+        let Some(offsets) = &self.offsets else {
+            return Vec::new();
+        };
+
+        let mut cleared = Vec::new();
+        for object in discovered {
+            let referent = slot(object, offsets.referent);
+            if let Some(reached) = reached(*referent) {
+                *referent = reached;
+                continue;
+            }
+            // A pending reference refers to itself, so it's no longer active.
+            let reference = heap.allocator.reference(object.header as usize);
+            *referent = 0;
+            *slot(object, offsets.next) = reference.0;
+            cleared.push(reference);
+        }
+        if !cleared.is_empty() {
+            debug!(target: log::GC, ?reference_type, cleared=cleared.len(), "Cleared references");
+        }
+        cleared
+    }
+}
+
+/// Set `SoftReference.clock` to the time of the collection that's ending, which a soft reference
+/// copies to its timestamp when it's used.
+pub fn update_clock(runtime: &Runtime) {
+    if let Some(statics) = soft_statics(runtime) {
+        set_clock(statics);
+    }
+}
+
+/// Set `SoftReference.clock` in its static fields to the current time, which is done when the
+/// class is loaded too, so that the soft references made before the first collection aren't
+/// out of date.
+pub fn set_clock(statics: Object) {
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as i64;
+    statics.set_static(&clock_key(), Value::Long(Long(millis)));
+}
+
+/// The static fields of `SoftReference`, once it has been loaded.
+fn soft_statics(runtime: &Runtime) -> Option<Object> {
+    let heap = &runtime.heap;
+    let class = runtime.method_area.find_loaded_class(Reference(0), "java.lang.ref.SoftReference")?;
+    let statics = heap.get_static(&class);
+    (statics.0 != 0).then(|| heap.get_object(statics))
+}
+
+/// Move the references cleared by the collector onto `Reference.pending`, and wake the
+/// `Reference Handler` thread to enqueue them.
+///
+/// The pending list is guarded by `Reference.lock`, so until a thread can take the lock after a
+/// collection, the references are kept on the heap's list, which the collector treats as roots.
+/// A thread that already holds the lock, the `Reference Handler` itself, leaves them for the
+/// next thread.
+pub fn enqueue_pending(thread: &mut Thread) {
+    let runtime = thread.runtime.clone();
+    let heap = &runtime.heap;
+    if heap.pending_references.lock().unwrap().is_empty() {
+        return;
+    }
+    let Some(class) = runtime.method_area.find_loaded_class(Reference(0), "java.lang.ref.Reference") else {
+        return;
+    };
+    // The static fields of a class never move.
+    let statics = heap.get_object(heap.get_static(&class));
+    let lock = statics.get_static(&reference_key("lock", "Ljava/lang/ref/Reference$Lock;")).reference();
+    if lock.0 == 0 || thread.locks.contains_key(&heap.get_object(lock).header().lock.id()) {
+        return;
+    }
+
+    thread.enter_monitor(lock);
+    let pending_key = reference_key("pending", "Ljava/lang/ref/Reference;");
+    let discovered_key = reference_key("discovered", "Ljava/lang/ref/Reference;");
+    let mut pending = statics.get_static(&pending_key).reference();
+    let cleared = take(&mut *heap.pending_references.lock().unwrap());
+    for reference in cleared.into_iter().rev() {
+        let object = heap.get_object(reference);
+        object.set_field(&discovered_key, Value::Reference(pending));
+        heap.write_barrier(Heaped::Object(object));
+        pending = reference;
+    }
+    statics.set_static(&pending_key, Value::Reference(pending));
+    heap.write_barrier(Heaped::Object(statics));
+
+    // The lock can have moved while this thread was waiting for it.
+    let lock = statics.get_static(&reference_key("lock", "Ljava/lang/ref/Reference$Lock;")).reference();
+    heap.get_object(lock).header().lock.notify_all();
+    thread.exit_monitor(lock);
+}
+
+/// The slot of a reference field of an object.
+fn slot<'a>(object: Object, offset: usize) -> &'a mut u32 {
+    unsafe { object.data.add(offset).cast::<u32>().as_mut().unwrap() }
+}
+
+fn reference_key(name: &str, descriptor: &str) -> FieldKey {
+    FieldKey {
+        class: "java.lang.ref.Reference".to_string(),
+        name: name.to_string(),
+        descriptor: FieldType::from_descriptor(descriptor).unwrap(),
+    }
+}
+
+fn clock_key() -> FieldKey {
+    FieldKey {
+        class: "java.lang.ref.SoftReference".to_string(),
+        name: "clock".to_string(),
+        descriptor: FieldType::Long,
+    }
+}
@@ -19,6 +19,11 @@ impl ObjectLock {
         }
     }
 
+    /// Identifies the lock, which stays the same when the garbage collector moves the object.
+    pub fn id(&self) -> usize {
+        Arc::as_ptr(&self.mutex) as usize
+    }
+
     pub fn lock(&self) -> Synchronized {
         let guard = self.mutex.lock_arc();
         Synchronized {
@@ -27,14 +32,17 @@ impl ObjectLock {
         }
     }
 
-    /// Blocking!
-    /// - We don't seem to need to pass re-entry all the way through here?
-    /// Only the thread handle actually seems to need to exist here.
-    pub fn wait(&self, duration: Option<Duration>) {
+    /// Release the monitor, and block until notified, returning the number of times the monitor
+    /// had been entered.
+    ///
+    /// The thread joins the wait set before it releases the monitor, so a notification sent as
+    /// soon as another thread can enter the monitor isn't missed.
+    pub fn wait(&self, sync: Synchronized, duration: Option<Duration>) -> usize {
         {
             let mut waiting = self.waiting.write().unwrap();
             waiting.push(current());
         }
+        let reentry = sync.drop_all();
 
         // Blocking wait here.
         if let Some(duration) = duration {
@@ -42,6 +50,7 @@ impl ObjectLock {
         } else {
             park();
         }
+        reentry
     }
 
     pub fn notify(&self) {
@@ -0,0 +1,258 @@
+//! Checking the heap is consistent after each garbage collection, with `-XX:+VerifyHeap`.
+
+use std::collections::HashSet;
+
+use nohash_hasher::BuildNoHashHasher;
+use tracing::debug;
+
+use crate::collection::classes::ClassRef;
+use crate::heap::allocator::Kind;
+use crate::heap::{Heap, Heaped};
+use crate::java::Reference;
+use crate::log;
+use crate::method_area::Class;
+use crate::runtime::Runtime;
+
+/// Walk every object in the heap, panicking with the first problem found.
+///
+/// Every object must have a valid header and a loaded class, and fit in its generation without
+/// overlapping the next object. Every reference, in an object or a root, must be null or refer
+/// to the start of an object. An object in the old generation that refers to the nursery must
+/// be on a dirty card, or the next minor collection won't find the reference.
+pub fn verify_heap(runtime: &Runtime, roots: &[&mut Reference]) {
+    match verify(runtime, roots) {
+        Ok(objects) => debug!(target: log::GC, objects, "Verified heap"),
+        Err(problem) => panic!("Heap verification failed: {}", problem),
+    }
+}
+
+/// Check the heap, returning the number of objects in it, or the first problem found.
+fn verify(runtime: &Runtime, roots: &[&mut Reference]) -> Result<usize, String> {
+    let heap = &runtime.heap;
+    let allocator = &heap.allocator;
+    let classes = runtime.method_area.loaded_classes();
+
+    // The survivors of the collection are packed together at the start of the nursery.
+    let mut objects = Vec::new();
+    let mut start = allocator.young.start();
+    while allocator.young.contains(start) {
+        let size = verify_header(start, &classes)?;
+        if Heaped::at(start).is_pinned() {
+            return Err(format!("pinned object at {:#x} is in the nursery", start));
+        }
+        if !allocator.young.contains(start + size - 1) {
+            return Err(format!("object at {:#x} of {} bytes runs past the end of the nursery", start, size));
+        }
+        objects.push(start);
+        start += size;
+    }
+
+    let mut end = 0;
+    for start in allocator.old.objects() {
+        if start < end {
+            return Err(format!("object at {:#x} overlaps the object before it", start));
+        }
+        let size = verify_header(start, &classes)?;
+        if !allocator.old.contains(start + size - 1) {
+            return Err(format!("object at {:#x} of {} bytes runs past the end of the old generation", start, size));
+        }
+        objects.push(start);
+        end = start + size;
+    }
+
+    let references: HashSet<u32, BuildNoHashHasher<u32>> = objects.iter()
+        .map(|start| allocator.reference(*start).0)
+        .collect();
+    for start in &objects {
+        let mut problem = None;
+        let mut refers_to_young = false;
+        Heaped::at(*start).for_each_slot(|slot| {
+            if *slot == 0 || problem.is_some() {
+                return;
+            }
+            if !references.contains(slot) {
+                problem = Some(format!("object at {:#x} refers to {}, which isn't an object", start, describe(heap, *slot)));
+                return;
+            }
+            refers_to_young |= allocator.young.contains(allocator.start(Reference(*slot)));
+        });
+        if let Some(problem) = problem {
+            return Err(problem);
+        }
+        if refers_to_young && allocator.old.contains(*start) && !allocator.old.is_remembered(*start) {
+            return Err(format!("object at {:#x} refers to the nursery, but isn't on a dirty card", start));
+        }
+    }
+
+    for root in roots {
+        if root.0 != 0 && !references.contains(&root.0) {
+            return Err(format!("a root refers to {}, which isn't an object", describe(heap, root.0)));
+        }
+    }
+
+    Ok(objects.len())
+}
+
+/// Check the header of the object starting at the given address, returning the size of the
+/// object.
+fn verify_header(start: usize, classes: &HashSet<ClassRef>) -> Result<usize, String> {
+    let kind = unsafe { (start as *const u8).read() };
+    if !(Kind::Object as u8..=Kind::Array as u8).contains(&kind) {
+        return Err(format!("object at {:#x} has the invalid kind {}", start, kind));
+    }
+
+    match Heaped::at(start) {
+        Heaped::Object(object) => {
+            let class = object.header().class;
+            if !classes.contains(&ClassRef::new(class)) {
+                return Err(format!("object at {:#x} has the class {:p}, which isn't loaded", start, class));
+            }
+            if kind == Kind::Static as u8 && object.class().static_width == 0 {
+                return Err(format!("static object at {:#x} is for {}, which has no static fields", start, object.class().name));
+            }
+        }
+        Heaped::Array(array) => {
+            let header = array.header();
+            if !is_loaded(&header.component, classes) {
+                return Err(format!("array at {:#x} has a component class that isn't loaded", start));
+            }
+            if !header.length.is_multiple_of(header.component.component_width()) {
+                return Err(format!("array at {:#x} has a length of {} bytes, which isn't a whole number of {}",
+                                   start, header.length, header.component.name()));
+            }
+        }
+    }
+
+    Ok(Heaped::at(start).size())
+}
+
+/// Whether a class, and for an array class each of the classes it's made up of, is loaded.
+fn is_loaded(class: &Class, classes: &HashSet<ClassRef>) -> bool {
+    match class {
+        Class::Primitive(_) => true,
+        Class::Object(class) => classes.contains(class),
+        Class::Array { object, component } => is_loaded(object, classes) && is_loaded(component, classes),
+    }
+}
+
+/// Describe a reference that doesn't refer to an object, and where it points.
+fn describe(heap: &Heap, reference: u32) -> String {
+    format!("reference {} ({:#x})", reference, heap.allocator.start(Reference(reference)))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use nohash_hasher::BuildNoHashHasher;
+
+    use crate::heap::garbage_collector::GenerationalCollector;
+    use crate::java::{Int, Value};
+    use crate::method_area::{ClassFlags, ObjectClass};
+    use crate::method_area::const_pool::ConstPool;
+    use crate::runtime::Options;
+
+    use super::*;
+
+    const MB: usize = 1024 * 1024;
+
+    /// A runtime with a 1MB nursery, and `java.lang.Object` loaded.
+    fn runtime() -> (Arc<Runtime>, Class) {
+        let runtime = Runtime::with_options(Options { max_heap_size: Some(6 * MB), ..Options::default() });
+        let class = runtime.method_area.insert_gen_class(ObjectClass {
+            name: "java.lang.Object".to_string(),
+            flags: ClassFlags { bits: 0 },
+            const_pool: ConstPool {
+                pool: HashMap::with_hasher(BuildNoHashHasher::default()),
+                loader: Reference(0),
+            },
+            super_class: None,
+            interfaces: vec![],
+            instance_fields: vec![],
+            static_fields: vec![],
+            methods: vec![],
+            attributes: vec![],
+            instance_width: 0,
+            static_width: 0,
+            source_file: None,
+            loader: Reference(0),
+            reference_type: None,
+            has_finalizer: false,
+        });
+        (runtime, Class::Object(ClassRef::new(class)))
+    }
+
+    /// Allocate an object referred to by an array in the nursery, which is referred to by an
+    /// array in the old generation, and collect the nursery, returning the arrays.
+    fn collected_heap(runtime: &Arc<Runtime>, object_class: Class) -> (Reference, Reference) {
+        let heap = &runtime.heap;
+        let mut collector = GenerationalCollector::new();
+
+        let object = heap.new_object(&object_class.obj());
+        heap.new_object(&object_class.obj());
+        let mut young = heap.new_array(object_class.clone(), Int(2));
+        let mut old = heap.new_array(object_class, Int(MB as i32 / 4));
+        heap.get_array(young).set_element(Int(0), Value::Reference(object));
+        heap.get_array(old).set_element(Int(0), Value::Reference(young));
+        heap.write_barrier(heap.get(old));
+
+        collector.minor_gc(runtime, &mut [&mut young, &mut old]);
+        (young, old)
+    }
+
+    #[test]
+    fn verifies_consistent_heap() {
+        let (runtime, object_class) = runtime();
+        let (mut young, _) = collected_heap(&runtime, object_class);
+
+        assert_eq!(verify(&runtime, &[&mut young]), Ok(3));
+    }
+
+    #[test]
+    fn finds_dangling_references() {
+        let (runtime, object_class) = runtime();
+        let heap = &runtime.heap;
+        let (young, _) = collected_heap(&runtime, object_class);
+
+        let mut dangling = Reference(young.0 + 1);
+        heap.get_array(young).set_element(Int(1), Value::Reference(dangling));
+        assert_eq!(verify(&runtime, &[]).unwrap_err(), format!("object at {:#x} refers to {}, which isn't an object",
+                                                               heap.get(young).start(), describe(heap, dangling.0)));
+
+        heap.get_array(young).set_element(Int(1), Value::Reference(Reference(0)));
+        assert_eq!(verify(&runtime, &[&mut dangling]).unwrap_err(),
+                   format!("a root refers to {}, which isn't an object", describe(heap, dangling.0)));
+    }
+
+    #[test]
+    fn finds_missing_write_barriers() {
+        let (runtime, object_class) = runtime();
+        let heap = &runtime.heap;
+        let (mut young, mut old) = collected_heap(&runtime, object_class.clone());
+
+        // The card of an array that doesn't refer to the nursery is cleaned by a collection.
+        let mut other = heap.new_array(object_class, Int(MB as i32 / 4));
+        GenerationalCollector::new().minor_gc(&runtime, &mut [&mut young, &mut old, &mut other]);
+        assert_eq!(verify(&runtime, &[]), Ok(4));
+
+        heap.get_array(other).set_element(Int(0), Value::Reference(young));
+        assert_eq!(verify(&runtime, &[]).unwrap_err(),
+                   format!("object at {:#x} refers to the nursery, but isn't on a dirty card", heap.get(other).start()));
+    }
+
+    #[test]
+    fn finds_corrupt_headers() {
+        let (runtime, object_class) = runtime();
+        let heap = &runtime.heap;
+        let (young, _) = collected_heap(&runtime, object_class);
+
+        let object = heap.get_array(young).get_element(Int(0)).reference();
+        let start = heap.get(object).start();
+        unsafe { (start as *mut u8).write(Kind::Forwarded as u8) };
+        assert_eq!(verify(&runtime, &[]).unwrap_err(), format!("object at {:#x} has the invalid kind 5", start));
+
+        unsafe { (start as *mut u8).write(Kind::Pinned as u8) };
+        assert_eq!(verify(&runtime, &[]).unwrap_err(), format!("pinned object at {:#x} is in the nursery", start));
+    }
+}
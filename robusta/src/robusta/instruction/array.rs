@@ -1,3 +1,5 @@
+use crate::heap::allocator::Array;
+use crate::heap::Heaped;
 use crate::java::{ Int, Value};
 use crate::thread::Thread;
 
@@ -5,10 +7,22 @@ pub fn a_new_array(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
     let const_pool = frame.const_pool;
     let class_idx = frame.read_u16();
-    let class = thread.runtime.method_area.resolve_class(const_pool, class_idx);
+    let runtime = thread.runtime.clone();
+    let class = match runtime.method_area.resolve_class(thread, const_pool, class_idx) {
+        Ok(class) => class,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
     let frame = thread.stack.last_mut().unwrap();
     let count = frame.operand_stack.pop().int();
-    let array_ref = thread.runtime.heap.new_array(class, count);
+    let Ok(array_ref) = thread.runtime.heap.try_new_array(class, count) else {
+        let ex = thread.out_of_memory_error();
+        thread.throw(ex);
+        return;
+    };
     let frame = thread.stack.last_mut().unwrap();
     frame.operand_stack.push(Value::Reference(array_ref));
 }
@@ -37,92 +51,59 @@ pub fn char_array_load(thread: &mut Thread) {
 }
 
 pub fn byte_array_store(thread: &mut Thread) {
-    let frame = thread.stack.last_mut().unwrap();
-
-    let value = frame.operand_stack.pop();
-    let index = frame.operand_stack.pop().int();
-    let arr_ref = frame.operand_stack.pop().reference();
-
-    let arr = thread.runtime.heap.get_array(arr_ref);
-
-    arr.set_element(index, value);
+    array_store(thread);
 }
 
 pub fn long_array_store(thread: &mut Thread) {
-    let frame = thread.stack.last_mut().unwrap();
-
-    let value = frame.operand_stack.pop();
-    let index = frame.operand_stack.pop().int();
-    let arr_ref = frame.operand_stack.pop().reference();
-
-    let arr = thread.runtime.heap.get_array(arr_ref);
-
-    arr.set_element(index, value);
+    array_store(thread);
 }
 
 pub fn char_array_store(thread: &mut Thread) {
-    let frame = thread.stack.last_mut().unwrap();
-
-    let value = frame.operand_stack.pop();
-    let index = frame.operand_stack.pop().int();
-    let arr_ref = frame.operand_stack.pop().reference();
-
-    let arr = thread.runtime.heap.get_array(arr_ref);
-
-    arr.set_element(index, value);
+    array_store(thread);
 }
 
 pub fn int_array_store(thread: &mut Thread) {
-    let frame = thread.stack.last_mut().unwrap();
-
-    let value = frame.operand_stack.pop();
-    let index = frame.operand_stack.pop().int();
-    let arr_ref = frame.operand_stack.pop().reference();
-
-    let arr = thread.runtime.heap.get_array(arr_ref);
-
-    arr.set_element(index, value);
+    array_store(thread);
 }
 
 pub fn a_array_store(thread: &mut Thread) {
-    let frame = thread.stack.last_mut().unwrap();
-
-    let value = frame.operand_stack.pop();
-    let index = frame.operand_stack.pop().int();
-    let arr_ref = frame.operand_stack.pop().reference();
-
-    let arr = thread.runtime.heap.get_array(arr_ref);
-
-    arr.set_element(index, value);
+    let arr = array_store(thread);
+    thread.runtime.heap.write_barrier(Heaped::Array(arr));
 }
 
 pub fn a_array_load(thread: &mut Thread) {
-    let frame = thread.stack.last_mut().unwrap();
-
-    let index = frame.operand_stack.pop().int();
-    let arr_ref = frame.operand_stack.pop().reference();
-
-    let arr = thread.runtime.heap.get_array(arr_ref);
+    array_load(thread);
+}
 
-    let elem = arr.get_element(index);
+pub fn l_array_load(thread: &mut Thread) {
+    array_load(thread);
+}
 
-    frame.operand_stack.push(elem);
+pub fn int_array_load(thread: &mut Thread) {
+    array_load(thread);
 }
 
-pub fn l_array_load(thread: &mut Thread) {
-    let frame = thread.stack.last_mut().unwrap();
+pub fn byte_array_load(thread: &mut Thread) {
+    array_load(thread);
+}
 
-    let index = frame.operand_stack.pop().int();
-    let arr_ref = frame.operand_stack.pop().reference();
+pub fn float_array_load(thread: &mut Thread) {
+    array_load(thread);
+}
 
-    let arr = thread.runtime.heap.get_array(arr_ref);
+pub fn double_array_load(thread: &mut Thread) {
+    array_load(thread);
+}
 
-    let elem = arr.get_element(index);
+pub fn float_array_store(thread: &mut Thread) {
+    array_store(thread);
+}
 
-    frame.operand_stack.push(elem);
+pub fn double_array_store(thread: &mut Thread) {
+    array_store(thread);
 }
 
-pub fn int_array_load(thread: &mut Thread) {
+fn array_load(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
 
     let index = frame.operand_stack.pop().int();
@@ -135,15 +116,16 @@ pub fn int_array_load(thread: &mut Thread) {
     frame.operand_stack.push(elem);
 }
 
-pub fn byte_array_load(thread: &mut Thread) {
+/// Store a value in an array, returning the array for the write barrier.
+fn array_store(thread: &mut Thread) -> Array {
     let frame = thread.stack.last_mut().unwrap();
 
+    let value = frame.operand_stack.pop();
     let index = frame.operand_stack.pop().int();
     let arr_ref = frame.operand_stack.pop().reference();
 
     let arr = thread.runtime.heap.get_array(arr_ref);
 
-    let elem = arr.get_element(index);
-
-    frame.operand_stack.push(elem);
-}
\ No newline at end of file
+    arr.set_element(index, value);
+    arr
+}
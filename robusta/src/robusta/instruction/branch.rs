@@ -10,10 +10,8 @@ pub fn if_int_cmp_ge(thread: &mut Thread) {
     let value1 = frame.operand_stack.pop().int();
 
     if value1.0 >= value2.0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -26,10 +24,8 @@ pub fn if_int_cmp_eq(thread: &mut Thread) {
     let value1 = frame.operand_stack.pop().int();
 
     if value1.0 == value2.0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -42,10 +38,8 @@ pub fn if_int_cmp_le(thread: &mut Thread) {
     let value1 = frame.operand_stack.pop().int();
 
     if value1.0 <= value2.0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -58,10 +52,8 @@ pub fn if_int_cmp_lt(thread: &mut Thread) {
     let value1 = frame.operand_stack.pop().int();
 
     if value1.0 < value2.0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -74,10 +66,8 @@ pub fn if_int_cmp_ne(thread: &mut Thread) {
     let value1 = frame.operand_stack.pop().int();
 
     if value1.0 != value2.0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -90,10 +80,8 @@ pub fn if_int_cmp_gt(thread: &mut Thread) {
     let value1 = frame.operand_stack.pop().int();
 
     if value1.0 > value2.0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -106,10 +94,8 @@ pub fn if_ref_cmp_ne(thread: &mut Thread) {
     let value1 = frame.operand_stack.pop().reference();
 
     if value1.0 != value2.0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -122,10 +108,8 @@ pub fn if_ref_cmp_eq(thread: &mut Thread) {
     let value1 = frame.operand_stack.pop().reference();
 
     if value1.0 == value2.0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -133,10 +117,8 @@ pub fn goto(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
     let offset = frame.read_i16();
 
-    let mut pc = frame.pc as i64;
-    pc -= 3;
-    pc += offset as i64;
-    frame.pc = pc as usize;
+    let start_pc = frame.pc - 3;
+    jump(thread, start_pc, offset as i64);
 }
 
 pub fn if_eq(thread: &mut Thread) {
@@ -147,10 +129,8 @@ pub fn if_eq(thread: &mut Thread) {
     let value = frame.operand_stack.pop().int();
 
     if value.0 == 0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -161,10 +141,8 @@ pub fn if_lt(thread: &mut Thread) {
     let value = frame.operand_stack.pop().int();
 
     if value.0 < 0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -175,10 +153,8 @@ pub fn if_le(thread: &mut Thread) {
     let value = frame.operand_stack.pop().int();
 
     if value.0 <= 0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -189,10 +165,8 @@ pub fn if_gt(thread: &mut Thread) {
     let value = frame.operand_stack.pop().int();
 
     if value.0 > 0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -203,10 +177,8 @@ pub fn if_ge(thread: &mut Thread) {
     let value = frame.operand_stack.pop().int();
 
     if value.0 >= 0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -218,10 +190,8 @@ pub fn if_ne(thread: &mut Thread) {
     let value = frame.operand_stack.pop().int();
 
     if value.0 != 0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -233,10 +203,8 @@ pub fn if_null(thread: &mut Thread) {
     let value = frame.operand_stack.pop().reference();
 
     if value.0 == 0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -248,10 +216,8 @@ pub fn if_non_null(thread: &mut Thread) {
     let value = frame.operand_stack.pop().reference();
 
     if value.0 != 0 {
-        let mut pc = frame.pc as i64;
-        pc -= 3;
-        pc += offset as i64;
-        frame.pc = pc as usize;
+        let start_pc = frame.pc - 3;
+        jump(thread, start_pc, offset as i64);
     }
 }
 
@@ -273,6 +239,24 @@ pub fn fcmp(thread: &mut Thread, nan: i32) {
     frame.operand_stack.push(Value::Int(Int(result)));
 }
 
+pub fn dcmp(thread: &mut Thread, nan: i32) {
+    let frame = thread.stack.last_mut().unwrap();
+    let value2 = frame.operand_stack.pop().double().0;
+    let value1 = frame.operand_stack.pop().double().0;
+
+    let result = if value1 > value2 {
+        1
+    } else if value1 == value2 {
+        0
+    } else if value1 < value2 {
+        -1
+    } else {
+        nan
+    };
+
+    frame.operand_stack.push(Value::Int(Int(result)));
+}
+
 pub fn lcmp(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
     let value2 = frame.operand_stack.pop().long().0;
@@ -309,8 +293,40 @@ pub fn lookup_switch(thread: &mut Thread) {
     let key = frame.operand_stack.pop().int().0;
     let offset = pairs.iter().find(|(k,_)| *k == key).map(|(_,off)| *off).unwrap_or_else(|| default);
 
-    let mut pc = start_pc as i64;
-    pc += offset as i64;
-    let pc = pc as usize;
-    frame.pc = pc;
-}
\ No newline at end of file
+    jump(thread, start_pc, offset as i64);
+}
+
+pub fn table_switch(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let start_pc = frame.pc - 1;
+
+    let offset = frame.pc % 4;
+    let padding_required = (4 - offset) % 4;
+    for _ in 0..padding_required {
+        frame.read_u8();
+    }
+
+    let default = frame.read_i32();
+    let low = frame.read_i32();
+    let high = frame.read_i32();
+
+    let index = frame.operand_stack.pop().int().0;
+    let offset = if index < low || index > high {
+        default
+    } else {
+        frame.pc += (index - low) as usize * 4;
+        frame.read_i32()
+    };
+
+    jump(thread, start_pc, offset as i64);
+}
+
+/// Jump by the offset of a branch instruction, from the start of the instruction, polling for a
+/// safepoint when jumping backward, so that a loop can't hold up a safepoint.
+fn jump(thread: &mut Thread, start_pc: usize, offset: i64) {
+    let frame = thread.stack.last_mut().unwrap();
+    frame.pc = (start_pc as i64 + offset) as usize;
+    if offset <= 0 {
+        thread.poll_safepoint();
+    }
+}
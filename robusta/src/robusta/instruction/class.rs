@@ -5,7 +5,17 @@ pub fn check_cast(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
     let index = frame.read_u16();
 
-    let class = thread.runtime.method_area.resolve_class(frame.const_pool, index);
+    let const_pool = frame.const_pool;
+    let runtime = thread.runtime.clone();
+    let class = match runtime.method_area.resolve_class(thread, const_pool, index) {
+        Ok(class) => class,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
+    let frame = thread.stack.last_mut().unwrap();
     let reference = frame.operand_stack.pop().reference();
 
     if reference.0 == 0 {
@@ -26,7 +36,17 @@ pub fn instance_of(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
     let index = frame.read_u16();
 
-    let class = thread.runtime.method_area.resolve_class(frame.const_pool, index);
+    let const_pool = frame.const_pool;
+    let runtime = thread.runtime.clone();
+    let class = match runtime.method_area.resolve_class(thread, const_pool, index) {
+        Ok(class) => class,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
+    let frame = thread.stack.last_mut().unwrap();
     let reference = frame.operand_stack.pop().reference();
 
     if reference.0 == 0 {
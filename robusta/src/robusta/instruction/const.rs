@@ -8,8 +8,16 @@ pub fn load_constant(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
     let index = frame.read_u8() as u16;
 
-    let frame = thread.stack.last_mut().unwrap();
-    let value = thread.runtime.method_area.resolve_category_one(frame.const_pool, index);
+    let const_pool = frame.const_pool;
+    let runtime = thread.runtime.clone();
+    let value = match runtime.method_area.resolve_category_one(thread, const_pool, index) {
+        Ok(value) => value,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
 
     let frame = thread.stack.last_mut().unwrap();
     frame.operand_stack.push(value);
@@ -19,8 +27,16 @@ pub fn load_constant_wide(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
     let const_idx = frame.read_u16();
 
-    let frame = thread.stack.last_mut().unwrap();
-    let const_value = thread.runtime.method_area.resolve_category_one(frame.const_pool, const_idx);
+    let const_pool = frame.const_pool;
+    let runtime = thread.runtime.clone();
+    let const_value = match runtime.method_area.resolve_category_one(thread, const_pool, const_idx) {
+        Ok(const_value) => const_value,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
 
     let frame = thread.stack.last_mut().unwrap();
     frame.operand_stack.push(const_value);
@@ -1,3 +1,10 @@
+//! Conversions between primitive types.
+//!
+//! Rust's `as` saturates out of range floating point values and converts NaN to zero when
+//! casting to an integer, as `f2i`, `f2l`, `d2i` and `d2l` require, so they are plain casts.
+//!
+//! For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-2.html#jvms-2.11.4).
+
 use crate::java::{Double, Float, Int, Long, Value};
 use crate::thread::Thread;
 
@@ -65,6 +72,55 @@ pub fn float_to_int(thread: &mut Thread) {
     frame.operand_stack.push(Value::Int(Int(int)));
 }
 
+pub fn float_to_long(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let float = frame.operand_stack.pop().float();
+    let long = float.0 as i64;
+    frame.operand_stack.push(Value::Long(Long(long)));
+}
+
+pub fn float_to_double(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let float = frame.operand_stack.pop().float();
+    let double = float.0 as f64;
+    frame.operand_stack.push(Value::Double(Double(double)));
+}
+
+pub fn double_to_int(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let double = frame.operand_stack.pop().double();
+    let int = double.0 as i32;
+    frame.operand_stack.push(Value::Int(Int(int)));
+}
+
+pub fn double_to_long(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let double = frame.operand_stack.pop().double();
+    let long = double.0 as i64;
+    frame.operand_stack.push(Value::Long(Long(long)));
+}
+
+pub fn double_to_float(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let double = frame.operand_stack.pop().double();
+    let float = double.0 as f32;
+    frame.operand_stack.push(Value::Float(Float(float)));
+}
+
+pub fn long_to_float(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let long = frame.operand_stack.pop().long();
+    let float = long.0 as f32;
+    frame.operand_stack.push(Value::Float(Float(float)));
+}
+
+pub fn long_to_double(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let long = frame.operand_stack.pop().long();
+    let double = long.0 as f64;
+    frame.operand_stack.push(Value::Double(Double(double)));
+}
+
 pub fn int_to_long(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
     let int = frame.operand_stack.pop().int();
@@ -35,4 +35,34 @@ pub fn dup_x1(thread: &mut Thread) {
     frame.operand_stack.push(value1);
     frame.operand_stack.push(value2);
     frame.operand_stack.push(value1);
-}
\ No newline at end of file
+}
+
+pub fn dup_x2(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let value1 = frame.operand_stack.pop();
+    let values = frame.operand_stack.pop_units(2);
+
+    frame.operand_stack.push(value1);
+    frame.operand_stack.push_all(&values);
+    frame.operand_stack.push(value1);
+}
+
+pub fn dup2_x1(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let values = frame.operand_stack.pop_units(2);
+    let value3 = frame.operand_stack.pop();
+
+    frame.operand_stack.push_all(&values);
+    frame.operand_stack.push(value3);
+    frame.operand_stack.push_all(&values);
+}
+
+pub fn dup2_x2(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let values = frame.operand_stack.pop_units(2);
+    let under = frame.operand_stack.pop_units(2);
+
+    frame.operand_stack.push_all(&values);
+    frame.operand_stack.push_all(&under);
+    frame.operand_stack.push_all(&values);
+}
@@ -1,3 +1,5 @@
+use crate::heap::Heaped;
+use crate::java::Value;
 use crate::method_area::const_pool::FieldKey;
 use crate::thread::Thread;
 
@@ -6,7 +8,15 @@ pub fn get_field(thread: &mut Thread) {
     let const_pool = curr_frame.const_pool;
 
     let field_idx = curr_frame.read_u16();
-    let field = thread.runtime.method_area.resolve_field(thread.runtime.clone(), const_pool, field_idx);
+    let runtime = thread.runtime.clone();
+    let field = match runtime.method_area.resolve_field(thread, const_pool, field_idx) {
+        Ok(field) => field,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
     let field = unsafe { field.as_ref().unwrap() };
     let class = unsafe { field.class.as_ref().unwrap() };
 
@@ -31,11 +41,22 @@ pub fn get_static(thread: &mut Thread) {
     }
     let field_idx = curr_frame.read_u16();
 
-    let field = thread.runtime.method_area.resolve_static(thread.runtime.clone(), const_pool, field_idx);
+    let runtime = thread.runtime.clone();
+    let field = match runtime.method_area.resolve_static(thread, const_pool, field_idx) {
+        Ok(field) => field,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
     let field = unsafe { field.as_ref().unwrap() };
     let class = unsafe { field.class.as_ref().unwrap() };
     let rt = thread.runtime.clone();
-    rt.method_area.initialize(thread, class);
+    if let Err(ex) = rt.method_area.initialize(thread, class) {
+        thread.throw(ex);
+        return;
+    }
 
     let static_ref = thread.runtime.heap.get_static(class);
     let static_obj = thread.runtime.heap.get_object(static_ref);
@@ -56,7 +77,15 @@ pub fn put_static(thread: &mut Thread) {
 
     let field_idx = curr_frame.read_u16();
 
-    let field = thread.runtime.method_area.resolve_static(thread.runtime.clone(), const_pool, field_idx);
+    let runtime = thread.runtime.clone();
+    let field = match runtime.method_area.resolve_static(thread, const_pool, field_idx) {
+        Ok(field) => field,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
     let field = unsafe { field.as_ref().unwrap() };
     let class = unsafe { field.class.as_ref().unwrap() };
 
@@ -65,7 +94,10 @@ pub fn put_static(thread: &mut Thread) {
     }
 
     let rt = thread.runtime.clone();
-    rt.method_area.initialize(thread, class);
+    if let Err(ex) = rt.method_area.initialize(thread, class) {
+        thread.throw(ex);
+        return;
+    }
 
     let static_ref = thread.runtime.heap.get_static(class);
     let static_obj = thread.runtime.heap.get_object(static_ref);
@@ -78,6 +110,9 @@ pub fn put_static(thread: &mut Thread) {
         name: field.name.clone(),
         descriptor: field.descriptor.clone(),
     }, value);
+    if matches!(value, Value::Reference(_)) {
+        thread.runtime.heap.write_barrier(Heaped::Object(static_obj));
+    }
 }
 
 pub fn put_field(thread: &mut Thread) {
@@ -86,7 +121,15 @@ pub fn put_field(thread: &mut Thread) {
 
     let field_idx = curr_frame.read_u16();
 
-    let field = thread.runtime.method_area.resolve_field(thread.runtime.clone(), const_pool, field_idx);
+    let runtime = thread.runtime.clone();
+    let field = match runtime.method_area.resolve_field(thread, const_pool, field_idx) {
+        Ok(field) => field,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
     let field = unsafe { field.as_ref().unwrap() };
     let class = unsafe { field.class.as_ref().unwrap() };
 
@@ -100,5 +143,8 @@ pub fn put_field(thread: &mut Thread) {
         class: class.name.clone(),
         name: field.name.clone(),
         descriptor: field.descriptor.clone(),
-    }, value)
+    }, value);
+    if matches!(value, Value::Reference(_)) {
+        thread.runtime.heap.write_barrier(Heaped::Object(object));
+    }
 }
\ No newline at end of file
@@ -1,7 +1,8 @@
 use tracing::debug;
 
 use crate::log;
-use crate::method_area::const_pool::{ConstPool, MethodKey};
+use crate::java::Value;
+use crate::method_area::const_pool::{ConstPool, FieldKey, MethodKey};
 use crate::method_area::Method;
 use crate::thread::Thread;
 
@@ -29,9 +30,19 @@ pub fn invoke_interface(thread: &mut Thread) {
     let _count = frame.read_u8();
     let _ = frame.read_u8();
 
-    let method = thread.runtime.method_area.resolve_method(frame.const_pool, index);
+    let const_pool = frame.const_pool;
+    let runtime = thread.runtime.clone();
+    let method = match runtime.method_area.resolve_method(thread, const_pool, index) {
+        Ok(method) => method,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
     let method = unsafe { method.as_ref().unwrap() };
 
+    let frame = thread.stack.last_mut().unwrap();
     let args = frame.pop_args(false, &method.descriptor);
     let this_ref = args[0].reference();
     let this_obj = thread.runtime.heap.get_object(this_ref);
@@ -44,10 +55,6 @@ pub fn invoke_interface(thread: &mut Thread) {
     }).unwrap();
     let class = unsafe { this_method.class.as_ref().unwrap() };
 
-    if this_method.is_synchronized {
-        thread.enter_monitor(this_ref);
-    }
-
     if this_method.is_native {
         debug!(target: log::INSTR, method=format!("{}.{}{}", class.name.as_str(), method.name.as_str(), method.descriptor.descriptor()), "Invoking native method");
         let native_method = thread.find_native(this_method).unwrap();
@@ -58,16 +65,80 @@ pub fn invoke_interface(thread: &mut Thread) {
     }
 }
 
+/// Instruction `invokedynamic` invokes a dynamically-computed call site.
+///
+/// The call site is linked once, to a class whose instances capture the popped arguments.
+///
+/// See [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.invokedynamic).
+pub fn invoke_dynamic(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+    let index = frame.read_u16();
+    let _ = frame.read_u16();
+
+    let const_pool = unsafe { frame.const_pool.as_ref().unwrap() };
+    let caller = frame.class.clone();
+    let runtime = thread.runtime.clone();
+    let class = match runtime.method_area.resolve_call_site(thread, &caller, const_pool, index) {
+        Ok(class) => unsafe { class.as_ref().unwrap() },
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
+
+    let descriptor = &const_pool.get_call_site(index).const_key.descriptor;
+
+    // Allocate before popping the captured arguments, they must stay rooted if we GC.
+    let Ok(object_ref) = runtime.heap.try_new_object(thread, class) else {
+        let ex = thread.out_of_memory_error();
+        thread.throw(ex);
+        return;
+    };
+    let object = thread.runtime.heap.get_object(object_ref);
+
+    let frame = thread.stack.last_mut().unwrap();
+    let args = frame.pop_args(true, descriptor);
+    for (i, (arg, field_type)) in args.into_iter().zip(&descriptor.parameters).enumerate() {
+        object.set_field(&FieldKey {
+            class: class.name.clone(),
+            name: format!("arg${}", i + 1),
+            descriptor: field_type.clone(),
+        }, arg);
+    }
+
+    let frame = thread.stack.last_mut().unwrap();
+    frame.operand_stack.push(Value::Reference(object_ref));
+}
+
 fn invoke(thread: &mut Thread, _: &str, is_static: bool, is_virtual: bool) {
     let cur_frame = thread.stack.last_mut().unwrap();
     if cur_frame.class.eq("PrintArgs") {
         let _b = 2;
     }
     let method_idx = cur_frame.read_u16();
-    let cur_frame = thread.stack.last_mut().unwrap();
-    let method = thread.runtime.method_area.resolve_method(cur_frame.const_pool, method_idx);
+    let const_pool = cur_frame.const_pool;
+    let runtime = thread.runtime.clone();
+    let method = match runtime.method_area.resolve_method(thread, const_pool, method_idx) {
+        Ok(method) => method,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
     let method = unsafe { method.as_ref().unwrap() };
 
+    // Initialize before popping the arguments, they must stay rooted if we GC.
+    if is_static {
+        let class = unsafe { method.class.as_ref().unwrap() };
+        let rt = thread.runtime.clone();
+        if let Err(ex) = rt.method_area.initialize(thread, class) {
+            thread.throw(ex);
+            return;
+        }
+    }
+
     let cur_frame = thread.stack.last_mut().unwrap();
     let args = cur_frame.pop_args(is_static, &method.descriptor);
 
@@ -87,19 +158,6 @@ fn invoke(thread: &mut Thread, _: &str, is_static: bool, is_virtual: bool) {
 
     let method = unsafe { method.as_ref().unwrap() };
     let class = unsafe { method.class.as_ref().unwrap() };
-    if is_static {
-        let rt = thread.runtime.clone();
-        rt.method_area.initialize(thread, class);
-    }
-
-    if method.is_synchronized {
-        let this_ref = if is_static {
-            thread.runtime.heap.get_static(class)
-        } else {
-            args[0].reference()
-        };
-        thread.enter_monitor(this_ref);
-    }
 
     if method.is_native {
         debug!(target: log::INSTR, method=format!("{}.{}{}", class.name.as_str(), method.name.as_str(), method.descriptor.descriptor()), "Invoking native method");
@@ -28,6 +28,13 @@ pub fn dstore_n(thread: &mut Thread, n: u16) {
     cur_frame.local_vars.store_value(n, value);
 }
 
+pub fn fstore_n(thread: &mut Thread, n: u16) {
+    let cur_frame = thread.stack.last_mut().unwrap();
+    let value = cur_frame.operand_stack.pop();
+
+    cur_frame.local_vars.store_value(n, Value::Float(value.float()));
+}
+
 pub fn lstore_n(thread: &mut Thread, n: u16) {
     let cur_frame = thread.stack.last_mut().unwrap();
     let value = cur_frame.operand_stack.pop();
@@ -89,6 +89,43 @@ pub fn d_add(thread: &mut Thread) {
     frame.operand_stack.push(Value::Double(Double(result)));
 }
 
+pub fn d_div(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    let value2 = frame.operand_stack.pop().double();
+    let value1 = frame.operand_stack.pop().double();
+
+    let result = value1.0 / value2.0;
+
+    frame.operand_stack.push(Value::Double(Double(result)));
+}
+
+/// Instruction `drem`
+///
+/// The result is truncating, like C's `fmod`, rather than the IEEE 754 remainder.
+///
+/// See [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.drem).
+pub fn d_rem(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    let value2 = frame.operand_stack.pop().double();
+    let value1 = frame.operand_stack.pop().double();
+
+    let result = value1.0 % value2.0;
+
+    frame.operand_stack.push(Value::Double(Double(result)));
+}
+
+pub fn d_neg(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    let value = frame.operand_stack.pop().double();
+
+    let result = -value.0;
+
+    frame.operand_stack.push(Value::Double(Double(result)));
+}
+
 pub fn d_mul(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
 
@@ -133,6 +170,65 @@ pub fn l_mul(thread: &mut Thread) {
     frame.operand_stack.push(Value::Long(Long(result)));
 }
 
+pub fn f_add(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    let value2 = frame.operand_stack.pop().float();
+    let value1 = frame.operand_stack.pop().float();
+
+    let result = value1.0 + value2.0;
+
+    frame.operand_stack.push(Value::Float(Float(result)));
+}
+
+pub fn f_sub(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    let value2 = frame.operand_stack.pop().float();
+    let value1 = frame.operand_stack.pop().float();
+
+    let result = value1.0 - value2.0;
+
+    frame.operand_stack.push(Value::Float(Float(result)));
+}
+
+pub fn f_div(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    let value2 = frame.operand_stack.pop().float();
+    let value1 = frame.operand_stack.pop().float();
+
+    let result = value1.0 / value2.0;
+
+    frame.operand_stack.push(Value::Float(Float(result)));
+}
+
+/// Instruction `frem`
+///
+/// The result is truncating, like C's `fmod`, rather than the IEEE 754 remainder.
+///
+/// See [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-6.html#jvms-6.5.frem).
+pub fn f_rem(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    let value2 = frame.operand_stack.pop().float();
+    let value1 = frame.operand_stack.pop().float();
+
+    let result = value1.0 % value2.0;
+
+    frame.operand_stack.push(Value::Float(Float(result)));
+}
+
+pub fn f_neg(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    let value = frame.operand_stack.pop().float();
+
+    let result = -value.0;
+
+    frame.operand_stack.push(Value::Float(Float(result)));
+}
+
 pub fn f_mul(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
 
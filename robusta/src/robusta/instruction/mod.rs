@@ -1,18 +1,18 @@
 use tracing::trace;
 pub use new::new;
-use crate::instruction::array::{a_array_load, a_array_store, a_new_array, array_length, byte_array_load, byte_array_store, char_array_load, char_array_store, int_array_load, int_array_store, l_array_load, long_array_store};
-use crate::instruction::branch::{fcmp, goto, if_eq, if_ge, if_gt, if_int_cmp_eq, if_int_cmp_ge, if_int_cmp_gt, if_int_cmp_le, if_int_cmp_lt, if_int_cmp_ne, if_le, if_lt, if_ne, if_non_null, if_null, if_ref_cmp_eq, if_ref_cmp_ne, lcmp, lookup_switch};
+use crate::instruction::array::{a_array_load, a_array_store, a_new_array, array_length, byte_array_load, byte_array_store, char_array_load, char_array_store, double_array_load, double_array_store, float_array_load, float_array_store, int_array_load, int_array_store, l_array_load, long_array_store};
+use crate::instruction::branch::{dcmp, fcmp, goto, if_eq, if_ge, if_gt, if_int_cmp_eq, if_int_cmp_ge, if_int_cmp_gt, if_int_cmp_le, if_int_cmp_lt, if_int_cmp_ne, if_le, if_lt, if_ne, if_non_null, if_null, if_ref_cmp_eq, if_ref_cmp_ne, lcmp, lookup_switch, table_switch};
 use crate::instruction::class::{check_cast, instance_of};
-use crate::instruction::conv::{float_to_int, int_to_byte, int_to_char, int_to_double, int_to_float, int_to_long, int_to_short, long_to_int};
-use crate::instruction::dup::{dup, dup2, dup_x1};
+use crate::instruction::conv::{double_to_float, double_to_int, double_to_long, float_to_double, float_to_int, float_to_long, int_to_byte, int_to_char, int_to_double, int_to_float, int_to_long, int_to_short, long_to_double, long_to_float, long_to_int};
+use crate::instruction::dup::{dup, dup2, dup2_x1, dup2_x2, dup_x1, dup_x2};
 use crate::instruction::field::{get_field, get_static, put_field, put_static};
-use crate::instruction::invoke::{invoke_interface, invoke_special, invoke_static, invoke_virtual};
-use crate::instruction::locals::{aload, aload_n, astore, astore_n, dload, dload_n, dstore, dstore_n, fload, fload_n, fstore, iload, iload_n, istore, istore_n, lload, lload_n, lstore, lstore_n};
-use crate::instruction::math::{d_add, d_mul, d_sub, f_mul, i_add, i_div, i_inc, i_mul, i_neg, i_sub, iand, ior, irem, ishl, ishr, iushr, ixor, l_add, l_div, l_mul, l_sub, land, lor, lrem, lshl, lshr, lushr};
+use crate::instruction::invoke::{invoke_dynamic, invoke_interface, invoke_special, invoke_static, invoke_virtual};
+use crate::instruction::locals::{aload, aload_n, astore, astore_n, dload, dload_n, dstore, dstore_n, fload, fload_n, fstore, fstore_n, iload, iload_n, istore, istore_n, lload, lload_n, lstore, lstore_n};
+use crate::instruction::math::{d_add, d_div, d_mul, d_neg, d_rem, d_sub, f_add, f_div, f_mul, f_neg, f_rem, f_sub, i_add, i_div, i_inc, i_mul, i_neg, i_sub, iand, ior, irem, ishl, ishr, iushr, ixor, l_add, l_div, l_mul, l_sub, land, lor, lrem, lshl, lshr, lushr};
 use crate::instruction::new::new_array;
 use crate::instruction::r#const::{aconst_null, dconst_n, fconst_n, iconst_n, lconst_n, load_constant, load_constant_cat_2_wide, load_constant_wide};
 use crate::instruction::r#return::{a_return, a_throw, d_return, f_return, i_return, l_return, r#return};
-use crate::instruction::stack::{bipush, pop, sipush};
+use crate::instruction::stack::{bipush, pop, pop2, sipush, swap};
 use crate::instruction::sync::{monitor_enter, monitor_exit};
 use crate::log;
 
@@ -98,6 +98,8 @@ pub fn instruction(thread: &mut Thread) {
         0x2D => aload_n(thread, 3),
         0x2E => int_array_load(thread),
         0x2F => l_array_load(thread),
+        0x30 => float_array_load(thread),
+        0x31 => double_array_load(thread),
         0x32 => a_array_load(thread),
         0x33 => byte_array_load(thread),
         0x34 => char_array_load(thread),
@@ -114,6 +116,10 @@ pub fn instruction(thread: &mut Thread) {
         0x40 => lstore_n(thread, 1),
         0x41 => lstore_n(thread, 2),
         0x42 => lstore_n(thread, 3),
+        0x43 => fstore_n(thread, 0),
+        0x44 => fstore_n(thread, 1),
+        0x45 => fstore_n(thread, 2),
+        0x46 => fstore_n(thread, 3),
         0x47 => dstore_n(thread, 0),
         0x48 => dstore_n(thread, 1),
         0x49 => dstore_n(thread, 2),
@@ -124,18 +130,27 @@ pub fn instruction(thread: &mut Thread) {
         0x4E => astore_n(thread, 3),
         0x4F => int_array_store(thread),
         0x50 => long_array_store(thread),
+        0x51 => float_array_store(thread),
+        0x52 => double_array_store(thread),
         0x53 => a_array_store(thread),
         0x54 => byte_array_store(thread),
         0x55 => char_array_store(thread),
         0x57 => pop(thread),
+        0x58 => pop2(thread),
         0x59 => dup(thread),
         0x5A => dup_x1(thread),
+        0x5B => dup_x2(thread),
         0x5C => dup2(thread),
+        0x5D => dup2_x1(thread),
+        0x5E => dup2_x2(thread),
+        0x5F => swap(thread),
         0x60 => i_add(thread),
         0x61 => l_add(thread),
+        0x62 => f_add(thread),
         0x63 => d_add(thread),
         0x64 => i_sub(thread),
         0x65 => l_sub(thread),
+        0x66 => f_sub(thread),
         0x67 => d_sub(thread),
         0x68 => i_mul(thread),
         0x69 => l_mul(thread),
@@ -143,9 +158,15 @@ pub fn instruction(thread: &mut Thread) {
         0x6B => d_mul(thread),
         0x6C => i_div(thread),
         0x6D => l_div(thread),
+        0x6E => f_div(thread),
+        0x6F => d_div(thread),
         0x70 => irem(thread),
         0x71 => lrem(thread),
+        0x72 => f_rem(thread),
+        0x73 => d_rem(thread),
         0x74 => i_neg(thread),
+        0x76 => f_neg(thread),
+        0x77 => d_neg(thread),
         0x78 => ishl(thread),
         0x79 => lshl(thread),
         0x7A => ishr(thread),
@@ -162,13 +183,22 @@ pub fn instruction(thread: &mut Thread) {
         0x86 => int_to_float(thread),
         0x87 => int_to_double(thread),
         0x88 => long_to_int(thread),
+        0x89 => long_to_float(thread),
+        0x8A => long_to_double(thread),
         0x8B => float_to_int(thread),
+        0x8C => float_to_long(thread),
+        0x8D => float_to_double(thread),
+        0x8E => double_to_int(thread),
+        0x8F => double_to_long(thread),
+        0x90 => double_to_float(thread),
         0x91 => int_to_byte(thread),
         0x92 => int_to_char(thread),
         0x93 => int_to_short(thread),
         0x94 => lcmp(thread),
         0x95 => fcmp(thread, -1),
         0x96 => fcmp(thread, 1),
+        0x97 => dcmp(thread, -1),
+        0x98 => dcmp(thread, 1),
         0x99 => if_eq(thread),
         0x9A => if_ne(thread),
         0x9B => if_lt(thread),
@@ -184,6 +214,7 @@ pub fn instruction(thread: &mut Thread) {
         0xA5 => if_ref_cmp_eq(thread),
         0xA6 => if_ref_cmp_ne(thread),
         0xA7 => goto(thread),
+        0xAA => table_switch(thread),
         0xAB => lookup_switch(thread),
         0xAC => i_return(thread),
         0xAD => l_return(thread),
@@ -199,6 +230,7 @@ pub fn instruction(thread: &mut Thread) {
         0xB7 => invoke_special(thread),
         0xB8 => invoke_static(thread),
         0xB9 => invoke_interface(thread),
+        0xBA => invoke_dynamic(thread),
         0xBB => new(thread),
         0xBC => new_array(thread),
         0xBD => a_new_array(thread),
@@ -264,6 +296,8 @@ fn op_name(code: u8) -> &'static str {
         0x2D => "aload_3",
         0x2E => "iaload",
         0x2F => "laload",
+        0x30 => "faload",
+        0x31 => "daload",
         0x32 => "aaload",
         0x33 => "baload",
         0x34 => "caload",
@@ -280,6 +314,10 @@ fn op_name(code: u8) -> &'static str {
         0x40 => "lstore_1",
         0x41 => "lstore_2",
         0x42 => "lstore_3",
+        0x43 => "fstore_0",
+        0x44 => "fstore_1",
+        0x45 => "fstore_2",
+        0x46 => "fstore_3",
         0x47 => "dstore_0",
         0x48 => "dstore_1",
         0x49 => "dstore_2",
@@ -290,18 +328,27 @@ fn op_name(code: u8) -> &'static str {
         0x4E => "astore_3",
         0x4F => "iastore",
         0x50 => "lastore",
+        0x51 => "fastore",
+        0x52 => "dastore",
         0x53 => "aastore",
         0x54 => "bastore",
         0x55 => "castore",
         0x57 => "pop",
+        0x58 => "pop2",
         0x59 => "dup",
         0x5A => "dup_x1",
+        0x5B => "dup_x2",
         0x5C => "dup2",
+        0x5D => "dup2_x1",
+        0x5E => "dup2_x2",
+        0x5F => "swap",
         0x60 => "iadd",
         0x61 => "ladd",
+        0x62 => "fadd",
         0x63 => "dadd",
         0x64 => "isub",
         0x65 => "lsub",
+        0x66 => "fsub",
         0x67 => "dsub",
         0x68 => "imul",
         0x69 => "lmul",
@@ -309,9 +356,15 @@ fn op_name(code: u8) -> &'static str {
         0x6B => "dmul",
         0x6C => "idiv",
         0x6D => "ldiv",
+        0x6E => "fdiv",
+        0x6F => "ddiv",
         0x70 => "irem",
         0x71 => "lrem",
+        0x72 => "frem",
+        0x73 => "drem",
         0x74 => "ineg",
+        0x76 => "fneg",
+        0x77 => "dneg",
         0x78 => "ishl",
         0x79 => "lshl",
         0x7A => "ishr",
@@ -328,13 +381,22 @@ fn op_name(code: u8) -> &'static str {
         0x86 => "i2f",
         0x87 => "i2d",
         0x88 => "l2i",
+        0x89 => "l2f",
+        0x8A => "l2d",
         0x8B => "f2i",
+        0x8C => "f2l",
+        0x8D => "f2d",
+        0x8E => "d2i",
+        0x8F => "d2l",
+        0x90 => "d2f",
         0x91 => "i2b",
         0x92 => "i2c",
         0x93 => "i2s",
         0x94 => "lcmp",
         0x95 => "fcmpl",
         0x96 => "fcmpg",
+        0x97 => "dcmpl",
+        0x98 => "dcmpg",
         0x99 => "ifeq",
         0x9A => "ifne",
         0x9B => "iflt",
@@ -350,6 +412,7 @@ fn op_name(code: u8) -> &'static str {
         0xA5 => "if_acmpeq",
         0xA6 => "if_acmpne",
         0xA7 => "goto",
+        0xAA => "tableswitch",
         0xAB => "lookupswitch",
         0xAC => "ireturn",
         0xAD => "lreturn",
@@ -365,6 +428,7 @@ fn op_name(code: u8) -> &'static str {
         0xB7 => "invokespecial",
         0xB8 => "invokestatic",
         0xB9 => "invokeinterface",
+        0xBA => "invokedynamic",
         0xBB => "new",
         0xBC => "newarray",
         0xBD => "anewarray",
@@ -379,4 +443,139 @@ fn op_name(code: u8) -> &'static str {
         0xC7 => "ifnonnull",
         _ => panic!("not implemented opcode 0x{:0x?}", code)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::ptr::null;
+
+    use nohash_hasher::BuildNoHashHasher;
+
+    use crate::class_file::Code;
+    use crate::java::{Double, Int, Long, MethodType, Reference, Value};
+    use crate::method_area::{ClassFlags, Method, ObjectClass};
+    use crate::method_area::const_pool::ConstPool;
+    use crate::runtime::Runtime;
+    use crate::thread::Thread;
+
+    /// Run the given bytecode in a generated class, returning the final operand stack.
+    fn run(name: &str, code: Vec<u8>) -> Vec<Value> {
+        let runtime = Runtime::new();
+        let length = code.len();
+        let class = runtime.method_area.insert_gen_class(ObjectClass {
+            name: format!("<{}>", name),
+            flags: ClassFlags { bits: 0 },
+            const_pool: ConstPool { pool: HashMap::with_hasher(BuildNoHashHasher::default()), loader: Reference(0) },
+            super_class: None,
+            interfaces: vec![],
+            instance_fields: vec![],
+            static_fields: vec![],
+            methods: vec![
+                Method {
+                    flags: 0,
+                    class: null(),
+                    is_static: true,
+                    is_native: false,
+                    is_synchronized: false,
+                    name: name.to_string(),
+                    descriptor: MethodType::from_descriptor("()V").unwrap(),
+                    code: Some(Code { max_stack: 0, max_locals: 0, code, ex_table: vec![], attributes: vec![] }),
+                }
+            ],
+            attributes: vec![],
+            instance_width: 0,
+            static_width: 0,
+            source_file: None,
+            loader: Reference(0),
+            reference_type: None,
+            has_finalizer: false,
+        });
+        let class = unsafe { class.as_ref().unwrap() };
+
+        let thread = Thread::new(name.to_string(), None, runtime.clone(), class.name.clone(),
+                                 &class.const_pool as *const ConstPool, &class.methods[0] as *const Method, vec![]);
+        let thread = thread.as_mut();
+        while thread.stack.last().unwrap().pc < length {
+            thread.next();
+        }
+
+        let frame = thread.stack.last_mut().unwrap();
+        let mut values = vec![];
+        while !frame.operand_stack.is_empty() {
+            values.push(frame.operand_stack.pop());
+        }
+        values.reverse();
+        values
+    }
+
+    fn int(int: i32) -> Value {
+        Value::Int(Int(int))
+    }
+
+    fn long(long: i64) -> Value {
+        Value::Long(Long(long))
+    }
+
+    fn double(double: f64) -> Value {
+        Value::Double(Double(double))
+    }
+
+    #[test]
+    fn pop2() {
+        // iconst_1, iconst_2, iconst_3, pop2
+        assert_eq!(run("pop2_cat_1", vec![0x04, 0x05, 0x06, 0x58]), vec![int(1)]);
+        // iconst_1, lconst_1, pop2
+        assert_eq!(run("pop2_cat_2", vec![0x04, 0x0A, 0x58]), vec![int(1)]);
+    }
+
+    #[test]
+    fn swap() {
+        // iconst_1, iconst_2, swap
+        assert_eq!(run("swap", vec![0x04, 0x05, 0x5F]), vec![int(2), int(1)]);
+    }
+
+    #[test]
+    fn dup_x2() {
+        // iconst_1, iconst_2, iconst_3, dup_x2
+        assert_eq!(run("dup_x2_form_1", vec![0x04, 0x05, 0x06, 0x5B]),
+                   vec![int(3), int(1), int(2), int(3)]);
+        // lconst_1, iconst_2, dup_x2
+        assert_eq!(run("dup_x2_form_2", vec![0x0A, 0x05, 0x5B]),
+                   vec![int(2), long(1), int(2)]);
+    }
+
+    #[test]
+    fn dup2_x1() {
+        // iconst_1, iconst_2, iconst_3, dup2_x1
+        assert_eq!(run("dup2_x1_form_1", vec![0x04, 0x05, 0x06, 0x5D]),
+                   vec![int(2), int(3), int(1), int(2), int(3)]);
+        // iconst_1, lconst_1, dup2_x1
+        assert_eq!(run("dup2_x1_form_2", vec![0x04, 0x0A, 0x5D]),
+                   vec![long(1), int(1), long(1)]);
+    }
+
+    #[test]
+    fn dup2_x2() {
+        // iconst_1, iconst_2, iconst_3, iconst_4, dup2_x2
+        assert_eq!(run("dup2_x2_form_1", vec![0x04, 0x05, 0x06, 0x07, 0x5E]),
+                   vec![int(3), int(4), int(1), int(2), int(3), int(4)]);
+        // iconst_1, iconst_2, lconst_1, dup2_x2
+        assert_eq!(run("dup2_x2_form_2", vec![0x04, 0x05, 0x0A, 0x5E]),
+                   vec![long(1), int(1), int(2), long(1)]);
+        // lconst_1, iconst_2, iconst_3, dup2_x2
+        assert_eq!(run("dup2_x2_form_3", vec![0x0A, 0x05, 0x06, 0x5E]),
+                   vec![int(2), int(3), long(1), int(2), int(3)]);
+        // lconst_1, dconst_1, dup2_x2
+        assert_eq!(run("dup2_x2_form_4", vec![0x0A, 0x0F, 0x5E]),
+                   vec![double(1.0), long(1), double(1.0)]);
+    }
+
+    #[test]
+    fn wide() {
+        // bipush 7, wide istore 300, lconst_1, wide lstore 400, wide lload 400, wide iload 300
+        assert_eq!(run("wide", vec![0x10, 0x07, 0xC4, 0x36, 0x01, 0x2C, 0x0A, 0xC4, 0x37, 0x01, 0x90,
+                                    0xC4, 0x16, 0x01, 0x90, 0xC4, 0x15, 0x01, 0x2C]),
+                   vec![long(1), int(7)]);
+    }
+}
@@ -13,11 +13,25 @@ pub fn new(thread: &mut Thread) {
 
     let class_idx = cur_frame.read_u16();
 
-    let cur_frame = thread.stack.last_mut().unwrap();
-    let class = thread.runtime.method_area.resolve_class(cur_frame.const_pool, class_idx);
-    rt.method_area.initialize(thread, &class.obj());
+    let const_pool = cur_frame.const_pool;
+    let class = match rt.method_area.resolve_class(thread, const_pool, class_idx) {
+        Ok(class) => class,
+        Err(error) => {
+            let ex = error.to_throwable(thread);
+            thread.throw(ex);
+            return;
+        }
+    };
+    if let Err(ex) = rt.method_area.initialize(thread, &class.obj()) {
+        thread.throw(ex);
+        return;
+    }
 
-    let new_ref = thread.runtime.heap.new_object(&class.obj());
+    let Ok(new_ref) = rt.heap.try_new_object(thread, &class.obj()) else {
+        let ex = thread.out_of_memory_error();
+        thread.throw(ex);
+        return;
+    };
 
     let cur_frame = thread.stack.last_mut().unwrap();
     cur_frame.operand_stack.push(Value::Reference(new_ref));
@@ -29,18 +43,24 @@ pub fn new_array(thread: &mut Thread) {
 
     let count = cur_frame.operand_stack.pop().int();
 
-    let arr_ref = match array_type {
-        4 => thread.runtime.heap.new_array(Class::Primitive(Primitive::Boolean), count),
-        5 => thread.runtime.heap.new_array(Class::Primitive(Primitive::Char), count),
-        6 => thread.runtime.heap.new_array(Class::Primitive(Primitive::Float), count),
-        7 => thread.runtime.heap.new_array(Class::Primitive(Primitive::Double), count),
-        8 => thread.runtime.heap.new_array(Class::Primitive(Primitive::Byte), count),
-        9 => thread.runtime.heap.new_array(Class::Primitive(Primitive::Short), count),
-        10 => thread.runtime.heap.new_array(Class::Primitive(Primitive::Int), count),
-        11 => thread.runtime.heap.new_array(Class::Primitive(Primitive::Long), count),
+    let component = match array_type {
+        4 => Primitive::Boolean,
+        5 => Primitive::Char,
+        6 => Primitive::Float,
+        7 => Primitive::Double,
+        8 => Primitive::Byte,
+        9 => Primitive::Short,
+        10 => Primitive::Int,
+        11 => Primitive::Long,
         _ => panic!("newarray has not been implemented for array type {}", array_type)
     };
 
+    let Ok(arr_ref) = thread.runtime.heap.try_new_array(Class::Primitive(component), count) else {
+        let ex = thread.out_of_memory_error();
+        thread.throw(ex);
+        return;
+    };
+
     let cur_frame = thread.stack.last_mut().unwrap();
     cur_frame.operand_stack.push(Value::Reference(arr_ref));
 }
\ No newline at end of file
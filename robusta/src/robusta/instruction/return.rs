@@ -12,6 +12,9 @@ pub fn r#return(thread: &mut Thread) {
     exit_monitor(thread);
 
     thread.stack.pop();
+    // Returns are polled, as well as backward branches, so that recursion can't hold up a
+    // safepoint either.
+    thread.poll_safepoint();
 }
 
 pub fn a_return(thread: &mut Thread) {
@@ -25,6 +28,7 @@ pub fn a_return(thread: &mut Thread) {
     let cur_frame = thread.stack.last_mut().unwrap();
 
     cur_frame.operand_stack.push(reference);
+    thread.poll_safepoint();
 }
 
 pub fn i_return(thread: &mut Thread) {
@@ -38,6 +42,7 @@ pub fn i_return(thread: &mut Thread) {
     let cur_frame = thread.stack.last_mut().unwrap();
 
     cur_frame.operand_stack.push(int);
+    thread.poll_safepoint();
 }
 
 pub fn f_return(thread: &mut Thread) {
@@ -51,6 +56,7 @@ pub fn f_return(thread: &mut Thread) {
     let cur_frame = thread.stack.last_mut().unwrap();
 
     cur_frame.operand_stack.push(float);
+    thread.poll_safepoint();
 }
 
 pub fn d_return(thread: &mut Thread) {
@@ -64,6 +70,7 @@ pub fn d_return(thread: &mut Thread) {
     let cur_frame = thread.stack.last_mut().unwrap();
 
     cur_frame.operand_stack.push(double);
+    thread.poll_safepoint();
 }
 
 pub fn l_return(thread: &mut Thread) {
@@ -77,12 +84,13 @@ pub fn l_return(thread: &mut Thread) {
     let cur_frame = thread.stack.last_mut().unwrap();
 
     cur_frame.operand_stack.push(long);
+    thread.poll_safepoint();
 }
 
 #[allow(unreachable_code)]
 pub fn a_throw(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
-    let throwable_ref = frame.operand_stack.pop().reference();
+    let mut throwable_ref = frame.operand_stack.pop().reference();
     let throwable = thread.runtime.heap.get_object(throwable_ref);
     let throw_class = throwable.class();
 
@@ -101,17 +109,24 @@ pub fn a_throw(thread: &mut Thread) {
         let method = unsafe { current_frame.method.as_ref().unwrap() };
         let code = method.code.as_ref().unwrap();
         let ex_table = &code.ex_table;
+        let pc = current_frame.pc;
+        let pool = current_frame.const_pool;
         for handler in ex_table {
-            let in_range = (handler.start_pc as usize) < current_frame.pc && current_frame.pc <= handler.end_pc as usize;
+            let in_range = (handler.start_pc as usize) < pc && pc <= handler.end_pc as usize;
             if !in_range {
                 continue;
             }
             let is_handler = handler.catch_type == 0 || {
-                let pool = current_frame.const_pool;
-                let catch_class = thread.runtime.method_area.resolve_class(pool, handler.catch_type);
-                throw_class.is_instance_of(&catch_class.obj())
+                // A catch type that can't be loaded can't be a super class of the throwable.
+                // Loading it can run Java code, so the throwable is rooted in case of a GC.
+                let runtime = thread.runtime.clone();
+                thread.push_root(throwable_ref);
+                let catch_class = runtime.method_area.resolve_class(thread, pool, handler.catch_type);
+                throwable_ref = thread.pop_root();
+                catch_class.is_ok_and(|catch_class| throw_class.is_instance_of(&catch_class.obj()))
             };
             if is_handler {
+                let current_frame = thread.stack.last_mut().unwrap();
                 current_frame.pc = handler.handler_pc as usize;
                 current_frame.operand_stack.push(Value::Reference(throwable_ref));
                 return;
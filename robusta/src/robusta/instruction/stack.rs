@@ -7,6 +7,22 @@ pub fn pop(thread: &mut Thread) {
     frame.operand_stack.pop();
 }
 
+pub fn pop2(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    frame.operand_stack.pop_units(2);
+}
+
+pub fn swap(thread: &mut Thread) {
+    let frame = thread.stack.last_mut().unwrap();
+
+    let value1 = frame.operand_stack.pop();
+    let value2 = frame.operand_stack.pop();
+
+    frame.operand_stack.push(value1);
+    frame.operand_stack.push(value2);
+}
+
 pub fn sipush(thread: &mut Thread) {
     let frame = thread.stack.last_mut().unwrap();
     let short = frame.read_i16() as i32;
@@ -6,6 +6,18 @@ pub fn wide(thread: &mut Thread) {
     let opcode = frame.read_u8();
 
     match opcode {
+        // iload, lload, fload, dload & aload
+        0x15..=0x19 => {
+            let index = frame.read_u16();
+            let value = frame.local_vars.load_value(index);
+            frame.operand_stack.push(value);
+        }
+        // istore, lstore, fstore, dstore & astore
+        0x36..=0x3A => {
+            let index = frame.read_u16();
+            let value = frame.operand_stack.pop();
+            frame.local_vars.store_value(index, value);
+        }
         0x84 => {
             let index = frame.read_u16();
             let value2 = frame.read_i16() as i32;
@@ -19,8 +19,9 @@ pub struct Float(pub f32);
 pub struct Double(pub f64);
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-/// Reference is the reference type in the JVM.  References are 32-bit unsigned integers
-/// that key into the heap.
+/// Reference is the reference type in the JVM.  References are 32-bit compressed pointers,
+/// the offset of the object from the start of the heap in 8-byte units, so a 32-bit reference
+/// can address up to 32GB of heap.  The zero reference is null.
 ///
 /// We are using 32 bit integers to match the "category-1" size in the specification, this
 /// might be something we decide to change later.
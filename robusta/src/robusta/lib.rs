@@ -6,7 +6,7 @@
 
 extern crate core;
 
-use std::env::args;
+use std::fmt::{Display, Formatter};
 use std::sync::Arc;
 
 use tracing::debug;
@@ -17,9 +17,10 @@ use tracing_subscriber::fmt::format::FmtSpan;
 
 use crate::java::{Int, MethodType, Reference, Value};
 use crate::method_area::const_pool::{ConstPool, MethodKey};
-use crate::method_area::Method;
-use crate::runtime::Runtime;
+use crate::method_area::{LoadClassError, Method, VerifyError};
+use crate::runtime::{Options, Runtime};
 use crate::thread::Thread;
+use crate::thread::dump::dump_threads_on_quit;
 
 pub mod java;
 pub mod class_file;
@@ -41,22 +42,67 @@ pub struct VirtualMachine {
     main_thread: Arc<Thread>,
 }
 
-impl VirtualMachine {
-    pub fn new() -> Self {
-        let is_debug = args().any(|arg| arg.eq("-d"));
-        let is_trace = args().any(|arg| arg.eq("-t"));
-
-        let mut filter = EnvFilter::builder()
-            .with_default_directive(LevelFilter::OFF.into());
+/// The reasons a virtual machine can fail to start its main class.
+#[derive(Debug)]
+pub enum StartError {
+    /// The main class doesn't exist.
+    MainClassNotFound(String),
+    /// The main class, or one of its dependencies, couldn't be loaded.
+    Load(LoadClassError),
+    /// The main class failed verification.
+    Verify(VerifyError),
+    /// The main class has no `public static void main(String[] args)` method.
+    MainMethodNotFound(String),
+}
 
-        if is_debug {
-            filter = filter.with_default_directive(LevelFilter::DEBUG.into());
-        }
-        if is_trace {
-            filter = filter.with_default_directive(LevelFilter::TRACE.into());
+impl Display for StartError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StartError::MainClassNotFound(name) =>
+                write!(f, "Error: Could not find or load main class {}", name),
+            StartError::Load(error) =>
+                write!(f, "Exception in thread \"main\" {}: {}", error.class_name(), error),
+            StartError::Verify(error) =>
+                write!(f, "Exception in thread \"main\" java.lang.VerifyError: {}", error),
+            StartError::MainMethodNotFound(name) =>
+                write!(f, "Error: Main method not found in class {}, please define the main method as:\n   public static void main(String[] args)", name),
         }
+    }
+}
 
-        let filter: EnvFilter = filter.parse("").unwrap();
+/// Configures a virtual machine before it is created, see [`VirtualMachine::builder`].
+pub struct VirtualMachineBuilder {
+    main_class: String,
+    options: Options,
+    args: Vec<String>,
+    log_level: LevelFilter,
+}
+
+impl VirtualMachineBuilder {
+    /// The options used to configure the runtime.
+    pub fn options(mut self, options: Options) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// The arguments passed to the main method.
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// The level of the virtual machine's own logging, off by default.
+    pub fn log_level(mut self, log_level: LevelFilter) -> Self {
+        self.log_level = log_level;
+        self
+    }
+
+    /// Initialize the runtime and load the main class, ready to be started.
+    pub fn build(self) -> Result<VirtualMachine, StartError> {
+        let filter = EnvFilter::builder()
+            .with_default_directive(self.log_level.into())
+            .parse("")
+            .unwrap();
 
         let subscriber = fmt()
             .without_time()
@@ -71,11 +117,12 @@ impl VirtualMachine {
 
         debug!(target: log::JVM, "Starting Robusta");
 
-        let runtime = Runtime::new();
+        let runtime = Runtime::with_options(self.options);
+        dump_threads_on_quit(runtime.clone());
         runtime.method_area.load_class("sun.misc.Launcher");
 
         // Required Initialization
-        {
+        let jvm_init_thread = {
             let create_thread_class = runtime.method_area.insert_gen_class(shim::create_main_thread());
             let class_ref = unsafe { create_thread_class.as_ref().unwrap() };
             let method = &class_ref.methods[0];
@@ -86,19 +133,22 @@ impl VirtualMachine {
 
             let jvm_init_t = jvm_init_thread.as_mut();
 
+            jvm_init_t.exit_safe();
             while jvm_init_t.stack.len() > 0 {
                 jvm_init_t.next();
             }
+            jvm_init_thread
         };
 
         // Let's remove the JVM init thread.
         runtime.threads2.write().unwrap().clear();
 
-        let string_args: Vec<Reference> = args()
-            .skip(1)
-            .skip_while(|arg| arg.starts_with('-'))
-            .skip(1) // main class
-            .map(|arg| runtime.method_area.load_string(&arg))
+        // There might not be space to allocate an out of memory error when it's needed.
+        let out_of_memory_error = jvm_init_thread.as_mut().new_throwable("java.lang.OutOfMemoryError", "Java heap space");
+        runtime.heap.set_out_of_memory_error(out_of_memory_error.reference());
+
+        let string_args: Vec<Reference> = self.args.iter()
+            .map(|arg| runtime.method_area.load_string(arg))
             .collect();
 
         let args_arr_ref = runtime.heap.new_array(
@@ -109,18 +159,20 @@ impl VirtualMachine {
             args_arr.set_element(Int(idx as i32), Value::Reference(arg.clone()));
         }
 
-        let main_class = args()
-            .skip(1)
-            .skip_while(|arg| arg.starts_with('-'))
-            .next().unwrap();
-
-        let main_class = runtime.method_area.load_class(&main_class);
+        let main_class = match runtime.method_area.try_load_class(&self.main_class) {
+            Ok(main_class) => main_class,
+            Err(LoadClassError::NotFound(name)) if name == self.main_class =>
+                return Err(StartError::MainClassNotFound(self.main_class)),
+            Err(error) => return Err(StartError::Load(error)),
+        };
+        // Verifying the main class may load other classes, which happens in the init thread.
+        runtime.method_area.link(jvm_init_thread.as_mut(), &main_class).map_err(StartError::Verify)?;
         let method = main_class.find_method(&MethodKey {
             class: main_class.name.clone(),
             name: "main".to_string(),
             descriptor: MethodType::from_descriptor("([Ljava/lang/String;)V").unwrap(),
-        }).unwrap();
-
+        }).filter(|method| method.is_static)
+            .ok_or_else(|| StartError::MainMethodNotFound(self.main_class.clone()))?;
 
         let main_thread = Thread::new(
             "main".to_string(),
@@ -131,10 +183,22 @@ impl VirtualMachine {
             method as *const Method,
             vec![Value::Reference(args_arr_ref)]);
 
-        VirtualMachine { runtime, main_thread }
+        Ok(VirtualMachine { runtime, main_thread })
+    }
+}
+
+impl VirtualMachine {
+    /// Start configuring a virtual machine that runs the given main class.
+    pub fn builder(main_class: &str) -> VirtualMachineBuilder {
+        VirtualMachineBuilder {
+            main_class: main_class.to_string(),
+            options: Options::default(),
+            args: vec![],
+            log_level: LevelFilter::OFF,
+        }
     }
 
     pub fn start(&mut self) {
-        self.main_thread.as_mut().run()
+        self.main_thread.run()
     }
 }
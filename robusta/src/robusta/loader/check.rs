@@ -0,0 +1,200 @@
+use crate::class_file::{ClassAttribute, ClassFile, CodeAttribute, MethodAttribute, StackMapFrame, VerificationType};
+use crate::class_file::const_pool::Const;
+use crate::java::{FieldType, MethodType};
+use crate::loader::parser::LoadError;
+
+/// Check that the constants, and the references to them, in a class file are well formed, so
+/// that creating a class from it can't fail.
+///
+/// This doesn't check the code of the methods, which the verifier does.
+///
+/// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.8).
+pub fn check(file: &ClassFile) -> Result<(), LoadError> {
+    let mut indices: Vec<&u16> = file.const_pool.keys().collect();
+    indices.sort();
+    for index in indices {
+        check_const(file, file.const_pool.get(index).unwrap())?;
+    }
+
+    class(file, file.this_class)?;
+    if file.super_class != 0 {
+        class(file, file.super_class)?;
+    }
+    for interface in &file.interfaces {
+        class(file, *interface)?;
+    }
+
+    for field in &file.fields {
+        utf8(file, field.name)?;
+        field_descriptor(file, field.descriptor)?;
+    }
+
+    for method in &file.methods {
+        let name = utf8(file, method.name)?;
+        let descriptor = utf8(file, method.descriptor)?;
+        if MethodType::from_descriptor(descriptor).is_err() {
+            return Err(LoadError::simple(&format!("Method \"{}\" has illegal signature \"{}\"", name, descriptor)));
+        }
+        for attribute in &method.attributes {
+            if let MethodAttribute::Code(code) = attribute {
+                for handler in code.ex_table.iter().filter(|handler| handler.catch_type != 0) {
+                    class(file, handler.catch_type)?;
+                }
+                for attribute in &code.attributes {
+                    if let CodeAttribute::StackMapTable(table) = attribute {
+                        for frame in &table.entries {
+                            check_stack_map_frame(file, frame)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for attribute in &file.attributes {
+        match attribute {
+            ClassAttribute::SourceFile(source_file) => {
+                utf8(file, source_file.source_file)?;
+            }
+            ClassAttribute::BootstrapMethods(bootstrap_methods) => {
+                for method in &bootstrap_methods.methods {
+                    match get(file, method.method_ref)? {
+                        Const::MethodHandle(_) => {}
+                        _ => return Err(invalid_index(method.method_ref)),
+                    }
+                    for argument in &method.arguments {
+                        match get(file, *argument)? {
+                            Const::Integer(_) | Const::Float(_) | Const::Long(_) | Const::Double(_) |
+                            Const::String(_) | Const::Class(_) | Const::MethodType(_) | Const::MethodHandle(_) => {}
+                            _ => return Err(invalid_index(*argument)),
+                        }
+                    }
+                }
+            }
+            ClassAttribute::Unknown(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn check_const(file: &ClassFile, con: &Const) -> Result<(), LoadError> {
+    match con {
+        Const::Utf8(_) | Const::Integer(_) | Const::Float(_) | Const::Long(_) | Const::Double(_) => {}
+        Const::Class(class) => {
+            // Array classes are named by their descriptors.
+            let name = utf8(file, class.name)?;
+            if name.starts_with('[') && FieldType::from_descriptor(name).is_err() {
+                return Err(LoadError::simple(&format!("Illegal class name \"{}\"", name)));
+            }
+        }
+        Const::String(string) => {
+            utf8(file, string.string)?;
+        }
+        Const::FieldRef(field) => {
+            self::class(file, field.class)?;
+            let (_, descriptor) = name_and_type(file, field.name_and_type)?;
+            field_descriptor(file, descriptor)?;
+        }
+        Const::MethodRef(method) => {
+            self::class(file, method.class)?;
+            method_name_and_type(file, method.name_and_type)?;
+        }
+        Const::InterfaceMethodRef(method) => {
+            self::class(file, method.class)?;
+            method_name_and_type(file, method.name_and_type)?;
+        }
+        Const::NameAndType(name_and_type) => {
+            utf8(file, name_and_type.name)?;
+            utf8(file, name_and_type.descriptor)?;
+        }
+        Const::MethodHandle(handle) => {
+            let reference = get(file, handle.reference_idx)?;
+            let valid = match handle.reference_kind {
+                1..=4 => matches!(reference, Const::FieldRef(_)),
+                5 | 8 => matches!(reference, Const::MethodRef(_)),
+                6 | 7 => matches!(reference, Const::MethodRef(_) | Const::InterfaceMethodRef(_)),
+                9 => matches!(reference, Const::InterfaceMethodRef(_)),
+                kind => return Err(LoadError::simple(&format!("Bad method handle kind {}", kind))),
+            };
+            if !valid {
+                return Err(invalid_index(handle.reference_idx));
+            }
+        }
+        Const::MethodType(method_type) => {
+            method_descriptor(file, method_type.descriptor)?;
+        }
+        Const::InvokeDynamic(invoke_dynamic) => {
+            let bootstrap_methods = file.bootstrap_methods().map_or(0, |methods| methods.methods.len());
+            if invoke_dynamic.bootstrap_method_attr as usize >= bootstrap_methods {
+                return Err(LoadError::simple(&format!("Invalid bootstrap method index {}", invoke_dynamic.bootstrap_method_attr)));
+            }
+            method_name_and_type(file, invoke_dynamic.name_and_type)?;
+        }
+    }
+    Ok(())
+}
+
+fn check_stack_map_frame(file: &ClassFile, frame: &StackMapFrame) -> Result<(), LoadError> {
+    let types: Vec<&VerificationType> = match frame {
+        StackMapFrame::Same { .. } | StackMapFrame::Chop { .. } => vec![],
+        StackMapFrame::SameLocals1StackItem { stack, .. } => vec![stack],
+        StackMapFrame::Append { locals, .. } => locals.iter().collect(),
+        StackMapFrame::Full { locals, stack, .. } => locals.iter().chain(stack).collect(),
+    };
+    for verification_type in types {
+        if let VerificationType::Object(index) = verification_type {
+            class(file, *index)?;
+        }
+    }
+    Ok(())
+}
+
+fn get(file: &ClassFile, index: u16) -> Result<&Const, LoadError> {
+    file.const_pool.get(&index).ok_or_else(|| invalid_index(index))
+}
+
+fn utf8(file: &ClassFile, index: u16) -> Result<&str, LoadError> {
+    match get(file, index)? {
+        // The parser has already decoded the string, so it's valid.
+        Const::Utf8(utf8) => std::str::from_utf8(&utf8.bytes).map_err(|_| invalid_index(index)),
+        _ => Err(invalid_index(index)),
+    }
+}
+
+fn class(file: &ClassFile, index: u16) -> Result<(), LoadError> {
+    match get(file, index)? {
+        Const::Class(_) => Ok(()),
+        _ => Err(invalid_index(index)),
+    }
+}
+
+fn name_and_type(file: &ClassFile, index: u16) -> Result<(u16, u16), LoadError> {
+    match get(file, index)? {
+        Const::NameAndType(name_and_type) => Ok((name_and_type.name, name_and_type.descriptor)),
+        _ => Err(invalid_index(index)),
+    }
+}
+
+fn method_name_and_type(file: &ClassFile, index: u16) -> Result<(), LoadError> {
+    let (_, descriptor) = name_and_type(file, index)?;
+    method_descriptor(file, descriptor)
+}
+
+fn field_descriptor(file: &ClassFile, index: u16) -> Result<(), LoadError> {
+    let descriptor = utf8(file, index)?;
+    FieldType::from_descriptor(descriptor)
+        .map(|_| ())
+        .map_err(|_| LoadError::simple(&format!("Illegal field signature \"{}\"", descriptor)))
+}
+
+fn method_descriptor(file: &ClassFile, index: u16) -> Result<(), LoadError> {
+    let descriptor = utf8(file, index)?;
+    MethodType::from_descriptor(descriptor)
+        .map(|_| ())
+        .map_err(|_| LoadError::simple(&format!("Illegal method signature \"{}\"", descriptor)))
+}
+
+fn invalid_index(index: u16) -> LoadError {
+    LoadError::simple(&format!("Invalid constant pool index {}", index))
+}
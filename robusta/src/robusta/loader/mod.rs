@@ -1,19 +1,60 @@
 use std::collections::HashMap;
-use std::fs::File;
+use std::env::split_paths;
+use std::fs::{File, read_dir};
 use std::io::{BufReader, Read};
 use std::path::{Path, PathBuf};
 
 use zip::ZipArchive;
+use zip::result::ZipResult;
 
 use crate::class_file::ClassFile;
-use crate::loader::parser::parse;
+pub use crate::loader::parser::{LoadError, parse};
 
+mod check;
 mod parser;
 
+/// Parse a class path, a list of paths separated by the platform's path separator, as given
+/// to `-cp` or in `CLASSPATH`.
+///
+/// Like `java`, an empty entry is the current directory, and an entry with a base name of `*`
+/// is expanded to the jar files in that directory.
+pub fn parse_class_path(class_path: &str) -> Vec<PathBuf> {
+    split_paths(class_path).flat_map(|path| {
+        if path.as_os_str().is_empty() {
+            vec![PathBuf::from(".")]
+        } else if path.file_name().is_some_and(|name| name == "*") {
+            let dir = path.parent()
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            expand_wildcard(dir)
+        } else {
+            vec![path]
+        }
+    }).collect()
+}
+
+/// Find all the jar files in a directory, in name order so the class path is stable.
+fn expand_wildcard(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(dir) else {
+        return vec![];
+    };
+    let mut jars: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("jar")))
+        .collect();
+    jars.sort();
+    jars
+}
+
 /// A class file loader.
 pub trait Loader: Send + Sync {
     /// Find the class file matching the given name and return it.
-    fn find(&self, class_name: &str) -> Option<ClassFile>;
+    ///
+    /// Returns `None` if there is no class file for the class, or an error if the class file
+    /// is found but can't be parsed.
+    fn find(&self, class_name: &str) -> Result<Option<ClassFile>, LoadError>;
 }
 
 /// A directory loader, looking for class files in a given directory.
@@ -23,16 +64,17 @@ struct DirLoader {
 }
 
 impl Loader for DirLoader {
-    fn find(&self, class_name: &str) -> Option<ClassFile> {
+    fn find(&self, class_name: &str) -> Result<Option<ClassFile>, LoadError> {
         let file_path = self.root_dir
             .join(class_name.replace(".", "/"))
             .with_extension("class");
 
-        let mut file = File::open(file_path).ok();
+        let mut file = match File::open(file_path) {
+            Ok(file) => BufReader::new(file),
+            Err(_) => return Ok(None),
+        };
 
-        file.as_mut().map(|file| {
-            parse(file)
-        })
+        parse(&mut file).map(Some)
     }
 }
 
@@ -42,70 +84,275 @@ struct JarLoader {
 }
 
 impl JarLoader {
-    pub fn new(path: &Path) -> Self {
-        let file = File::open(path).unwrap();
-        let mut archive = ZipArchive::new(BufReader::new(file)).unwrap();
+    /// Read all the files in a jar or zip archive, failing if the archive is corrupt.
+    pub fn new(path: &Path) -> ZipResult<Self> {
+        let file = File::open(path)?;
+        let mut archive = ZipArchive::new(BufReader::new(file))?;
 
         let size = archive.len();
         let mut files = HashMap::with_capacity(size);
 
         for idx in 0..size {
-            let mut file = archive.by_index(idx).unwrap();
+            let mut file = archive.by_index(idx)?;
 
-            let file_name = file.enclosed_name().unwrap().to_owned();
+            // A file outside of the archive's root can't be a class file.
+            let Some(file_name) = file.enclosed_name().map(Path::to_path_buf) else {
+                continue;
+            };
 
             let mut data = Vec::with_capacity(file.size() as usize);
-            file.read_to_end(&mut data).unwrap();
+            file.read_to_end(&mut data)?;
 
             files.insert(file_name, data);
         }
 
-        JarLoader { files }
+        Ok(JarLoader { files })
     }
 }
 
 impl Loader for JarLoader {
-    fn find(&self, class_name: &str) -> Option<ClassFile> {
+    fn find(&self, class_name: &str) -> Result<Option<ClassFile>, LoadError> {
         let file_name = PathBuf::from(class_name.replace(".", "/"))
             .with_extension("class");
 
         let data = self.files.get(&file_name);
         if data.is_none() {
-            return None;
+            return Ok(None);
         }
 
         let mut bytes = data.unwrap().as_slice();
-        Some(parse(&mut bytes))
+        parse(&mut bytes).map(Some)
+    }
+}
+
+/// The main attributes of a jar file's manifest, `META-INF/MANIFEST.MF`.
+///
+/// For further information, see [the spec](https://docs.oracle.com/javase/8/docs/technotes/guides/jar/jar.html#JAR_Manifest).
+pub struct Manifest {
+    attributes: HashMap<String, String>,
+}
+
+impl Manifest {
+    /// Read the manifest of a jar file, a jar without a manifest has no attributes.
+    pub fn read(jar: &Path) -> ZipResult<Self> {
+        let file = File::open(jar)?;
+        let mut archive = ZipArchive::new(BufReader::new(file))?;
+
+        let mut contents = String::new();
+        if let Ok(mut manifest) = archive.by_name("META-INF/MANIFEST.MF") {
+            manifest.read_to_string(&mut contents)?;
+        }
+
+        Ok(Manifest::parse(&contents))
+    }
+
+    fn parse(contents: &str) -> Self {
+        // Long values are continued on the next line, after a single space.
+        let mut lines: Vec<String> = Vec::new();
+        for line in contents.lines() {
+            if line.is_empty() {
+                // The main section ends at the first blank line.
+                break;
+            }
+            match (line.strip_prefix(' '), lines.last_mut()) {
+                (Some(continued), Some(last)) => last.push_str(continued),
+                _ => lines.push(line.to_string()),
+            }
+        }
+
+        let attributes = lines.iter()
+            .filter_map(|line| line.split_once(": "))
+            .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+            .collect();
+
+        Manifest { attributes }
+    }
+
+    /// The `Main-Class` attribute, the binary name of the class to run.
+    pub fn main_class(&self) -> Option<String> {
+        self.attributes.get("Main-Class").map(|name| name.replace('/', "."))
+    }
+
+    /// The `Class-Path` attribute, the paths of other jars and directories, relative to the jar.
+    pub fn class_path(&self, jar: &Path) -> Vec<PathBuf> {
+        let dir = jar.parent().unwrap_or(Path::new(""));
+        self.attributes.get("Class-Path")
+            .map(|class_path| class_path.split_whitespace().map(|path| dir.join(path)).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// A loader for the Java classes that Robusta itself depends on, these are built into the
+/// binary so they are found whatever the class path is.
+struct RobustaLoader;
+
+const ROBUSTA_CLASS: &[u8] = include_bytes!("../../../../classes/com/jkitch/robusta/Robusta.class");
+
+impl Loader for RobustaLoader {
+    fn find(&self, class_name: &str) -> Result<Option<ClassFile>, LoadError> {
+        match class_name {
+            "com.jkitch.robusta.Robusta" => parse(&mut &ROBUSTA_CLASS[..]).map(Some),
+            _ => Ok(None),
+        }
     }
 }
 
 /// The class file loader delegates to each internal loader, looking for a matching
 /// class file.
 pub struct ClassFileLoader {
-    loaders: Vec<Box<dyn Loader>>,
+    /// Each loader, with a description of where it loads classes from.
+    loaders: Vec<(String, Box<dyn Loader>)>,
 }
 
 impl ClassFileLoader {
+    /// Construct a new class file loader from the boot class path, that also finds Robusta's
+    /// own classes.
+    pub fn new_boot(boot_class_path: Vec<PathBuf>) -> Self {
+        let mut loader = ClassFileLoader::new(boot_class_path);
+        loader.loaders.push(("robusta".to_string(), Box::new(RobustaLoader)));
+        loader
+    }
+
     /// Construct a new class file loader from the class path.
+    ///
+    /// Like `java`, entries in the class path that don't exist are ignored, and any file is
+    /// read as a jar or zip archive, with a warning if it can't be.
     pub fn new(class_path: Vec<PathBuf>) -> Self {
         ClassFileLoader {
-            loaders: class_path.iter().map(|path| {
+            loaders: class_path.iter().filter(|path| path.exists()).filter_map(|path| {
+                let full_path = path.canonicalize().unwrap_or(path.clone());
                 if path.is_dir() {
-                    Box::new(DirLoader { root_dir: path.clone() }) as _
-                } else if path.extension().unwrap().eq(&PathBuf::from("jar")) {
-                    Box::new(JarLoader::new(path)) as _
-                } else {
-                    panic!("Unknown type of path {}", path.to_str().unwrap())
+                    let source = format!("file:{}/", full_path.display());
+                    return Some((source, Box::new(DirLoader { root_dir: path.clone() }) as _));
+                }
+                match JarLoader::new(path) {
+                    Ok(loader) => Some((full_path.display().to_string(), Box::new(loader) as _)),
+                    Err(error) => {
+                        eprintln!("Warning: Ignoring class path entry {}: {}", path.display(), error);
+                        None
+                    }
                 }
             }).collect()
         }
     }
+
+    /// Find the class file matching the given name, and return it with a description of
+    /// where it was found.
+    pub fn find_with_source(&self, class_name: &str) -> Result<Option<(ClassFile, &str)>, LoadError> {
+        for (source, loader) in &self.loaders {
+            if let Some(class_file) = loader.find(class_name)? {
+                return Ok(Some((class_file, source.as_str())));
+            }
+        }
+        Ok(None)
+    }
 }
 
 impl Loader for ClassFileLoader {
-    fn find(&self, class_name: &str) -> Option<ClassFile> {
-        self.loaders.iter()
-            .flat_map(|source| source.find(class_name))
-            .next()
+    fn find(&self, class_name: &str) -> Result<Option<ClassFile>, LoadError> {
+        let class_file = self.find_with_source(class_name)?;
+        Ok(class_file.map(|(class_file, _)| class_file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env::{join_paths, temp_dir};
+    use std::fs::{create_dir_all, remove_dir_all, write};
+    use std::io::Write;
+    use std::process::id;
+
+    use zip::write::{FileOptions, ZipWriter};
+
+    use super::*;
+
+    fn class_path(paths: &[&str]) -> String {
+        join_paths(paths).unwrap().into_string().unwrap()
+    }
+
+    #[test]
+    fn parse_class_path_entries() {
+        assert_eq!(parse_class_path(&class_path(&["classes", "lib/a.jar", "", "/b"])), vec![
+            PathBuf::from("classes"),
+            PathBuf::from("lib/a.jar"),
+            PathBuf::from("."),
+            PathBuf::from("/b"),
+        ]);
+    }
+
+    #[test]
+    fn parse_class_path_wildcard() {
+        let dir = temp_dir().join(format!("robusta-class-path-{}", id()));
+        create_dir_all(dir.join("dir.jar")).unwrap();
+        for file in ["b.jar", "a.JAR", "c.class", "d.txt"] {
+            File::create(dir.join(file)).unwrap();
+        }
+
+        let wildcard = dir.join("*");
+        let class_path = parse_class_path(&class_path(&["classes", wildcard.to_str().unwrap()]));
+
+        remove_dir_all(&dir).unwrap();
+        assert_eq!(class_path, vec![
+            PathBuf::from("classes"),
+            dir.join("a.JAR"),
+            dir.join("b.jar"),
+        ]);
+    }
+
+    #[test]
+    fn manifest() {
+        let manifest = Manifest::parse("Manifest-Version: 1.0\r
+Main-Class: com/example/Main\r
+Class-Path: lib/a.jar lib/b\r
+ .jar classes/\r
+\r
+Name: com/example/\r
+Sealed: true\r
+");
+
+        assert_eq!(manifest.main_class(), Some("com.example.Main".to_string()));
+        assert_eq!(manifest.class_path(Path::new("app/app.jar")), vec![
+            PathBuf::from("app/lib/a.jar"),
+            PathBuf::from("app/lib/b.jar"),
+            PathBuf::from("app/classes/"),
+        ]);
+        assert!(!manifest.attributes.contains_key("Sealed"));
+    }
+
+    #[test]
+    fn manifest_empty() {
+        let manifest = Manifest::parse("Manifest-Version: 1.0\n");
+
+        assert_eq!(manifest.main_class(), None);
+        assert!(manifest.class_path(Path::new("app.jar")).is_empty());
+    }
+
+    #[test]
+    fn parse_class_path_missing_wildcard() {
+        let dir = temp_dir().join(format!("robusta-class-path-missing-{}", id()));
+        let wildcard = dir.join("*");
+
+        assert!(parse_class_path(wildcard.to_str().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn class_file_loader_entries() {
+        let dir = temp_dir().join(format!("robusta-class-file-loader-{}", id()));
+        create_dir_all(&dir).unwrap();
+        let mut zip = ZipWriter::new(File::create(dir.join("classes.zip")).unwrap());
+        zip.start_file("com/jkitch/robusta/Robusta.class", FileOptions::default()).unwrap();
+        zip.write_all(ROBUSTA_CLASS).unwrap();
+        zip.finish().unwrap();
+        write(dir.join("corrupt.jar"), b"not a jar").unwrap();
+
+        // The missing and corrupt entries are skipped.
+        let loader = ClassFileLoader::new(vec![dir.join("missing"), dir.join("corrupt.jar"), dir.join("classes.zip")]);
+        let found = loader.find_with_source("com.jkitch.robusta.Robusta").unwrap()
+            .map(|(_, source)| source.to_string());
+        let zip = dir.join("classes.zip").canonicalize().unwrap();
+
+        remove_dir_all(&dir).unwrap();
+        assert_eq!(loader.loaders.len(), 1);
+        assert_eq!(found, Some(zip.display().to_string()));
     }
 }
@@ -1,16 +1,19 @@
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt::{Display, Formatter};
-use std::io::Read;
+use std::io::{ErrorKind, Read};
 use nohash_hasher::BuildNoHashHasher;
 
-use crate::class_file::{ClassAttribute, ClassFile, Code, CodeAttribute, const_pool, ExHandler, Field, LineNumber, LineNumberTable, MAGIC, Method, MethodAttribute, SourceFile, UnknownAttribute};
+use crate::class_file::{BootstrapMethod, BootstrapMethods, ClassAttribute, ClassFile, Code, CodeAttribute, const_pool, ExHandler, Field, LineNumber, LineNumberTable, MAGIC, MAX_MAJOR_VERSION, Method, MIN_MAJOR_VERSION, MethodAttribute, SourceFile, StackMapFrame, StackMapTable, UnknownAttribute, VerificationType};
 use crate::class_file::const_pool::{Class, Const, Double, FieldRef, Float, Integer, InterfaceMethodRef, InvokeDynamic, Long, MethodHandle, MethodRef, MethodType, NameAndType, Utf8};
+use crate::loader::check::check;
 
-/// Parse a class file structure from a reader.
-pub fn parse(reader: &mut dyn Read) -> ClassFile {
+/// Parse a class file structure from a reader, checking that it's well formed.
+pub fn parse(reader: &mut dyn Read) -> Result<ClassFile, LoadError> {
     let mut parser = Parser { reader, buffer: [0; 8] };
-    parser.read_class_file().unwrap()
+    let class_file = parser.read_class_file()?;
+    check(&class_file)?;
+    Ok(class_file)
 }
 
 /// The internal representation of a parser.
@@ -26,7 +29,7 @@ impl<'a> Parser<'a> {
     fn read_class_file(&mut self) -> Result<ClassFile, LoadError> {
         let magic = self.read_u32()?;
         if magic != MAGIC {
-            return Err(LoadError::simple("Expected magic constant"));
+            return Err(LoadError::simple(&format!("Incompatible magic value {}", magic)));
         }
 
         let mut class_file = ClassFile {
@@ -45,6 +48,12 @@ impl<'a> Parser<'a> {
 
         class_file.minor_version = self.read_u16()?;
         class_file.major_version = self.read_u16()?;
+        if class_file.major_version < MIN_MAJOR_VERSION || class_file.major_version > MAX_MAJOR_VERSION {
+            return Err(LoadError::UnsupportedVersion {
+                major: class_file.major_version,
+                minor: class_file.minor_version,
+            });
+        }
 
         let const_pool_count = self.read_u16()?;
         let mut idx = 1;
@@ -54,6 +63,9 @@ impl<'a> Parser<'a> {
             class_file.const_pool.insert(idx, const_item);
             idx += size;
         }
+        if idx > const_pool_count {
+            return Err(LoadError::simple("Invalid constant pool count"));
+        }
 
         class_file.access_flags = self.read_u16()?;
         class_file.this_class = self.read_u16()?;
@@ -81,21 +93,41 @@ impl<'a> Parser<'a> {
             class_file.attributes.push(self.read_class_attribute(&class_file)?);
         }
 
+        if self.reader.read(&mut self.buffer[0..1]).map_err(LoadError::io)? != 0 {
+            return Err(LoadError::simple("Extra bytes at the end of class file"));
+        }
+
         Ok(class_file)
     }
 
     fn read_class_attribute(&mut self, file: &ClassFile) -> Result<ClassAttribute, LoadError> {
-        let name_idx = self.read_u16()?;
-        let name = String::from_utf8(file.get_const_utf8(name_idx).bytes.clone()).unwrap();
+        let (name_idx, name) = self.read_attribute_name(file)?;
 
         match name.as_str() {
             "SourceFile" => {
                 let length = self.read_u32()?;
-                assert_eq!(length, 2);
+                if length != 2 {
+                    return Err(LoadError::simple(&format!("Wrong SourceFile attribute length {}", length)));
+                }
                 Ok(ClassAttribute::SourceFile(SourceFile {
                     source_file: self.read_u16()?
                 }))
             }
+            "BootstrapMethods" => {
+                let _ = self.read_u32()?;
+                let num_bootstrap_methods = self.read_u16()?;
+                let mut methods = Vec::new();
+                for _ in 0..num_bootstrap_methods {
+                    let method_ref = self.read_u16()?;
+                    let num_arguments = self.read_u16()?;
+                    let mut arguments = Vec::new();
+                    for _ in 0..num_arguments {
+                        arguments.push(self.read_u16()?);
+                    }
+                    methods.push(BootstrapMethod { method_ref, arguments });
+                }
+                Ok(ClassAttribute::BootstrapMethods(BootstrapMethods { methods }))
+            }
             _ => {
                 let length = self.read_u32()?;
                 let bytes = self.read_length(length as usize)?;
@@ -108,8 +140,7 @@ impl<'a> Parser<'a> {
     }
 
     fn read_method_attribute(&mut self, file: &ClassFile) -> Result<MethodAttribute, LoadError> {
-        let name_idx = self.read_u16()?;
-        let name = String::from_utf8(file.get_const_utf8(name_idx).bytes.clone()).unwrap();
+        let (name_idx, name) = self.read_attribute_name(file)?;
 
         match name.as_str() {
             "Code" => {
@@ -128,8 +159,7 @@ impl<'a> Parser<'a> {
     }
 
     fn read_code_attribute(&mut self, file: &ClassFile) -> Result<CodeAttribute, LoadError> {
-        let name_idx = self.read_u16()?;
-        let name = String::from_utf8(file.get_const_utf8(name_idx).bytes.clone()).unwrap();
+        let (name_idx, name) = self.read_attribute_name(file)?;
 
         match name.as_str() {
             "LineNumberTable" => {
@@ -146,6 +176,15 @@ impl<'a> Parser<'a> {
                     table: line_number_table,
                 }))
             }
+            "StackMapTable" => {
+                let _ = self.read_u32()?;
+                let number_of_entries = self.read_u16()?;
+                let mut entries = Vec::new();
+                for _ in 0..number_of_entries {
+                    entries.push(self.read_stack_map_frame()?);
+                }
+                Ok(CodeAttribute::StackMapTable(StackMapTable { entries }))
+            }
             _ => {
                 let length = self.read_u32()?;
                 let bytes = self.read_length(length as usize)?;
@@ -157,13 +196,88 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Read the index of an attribute's name, and the name it refers to.
+    fn read_attribute_name(&mut self, file: &ClassFile) -> Result<(u16, String), LoadError> {
+        let name_idx = self.read_u16()?;
+        match file.const_pool.get(&name_idx) {
+            Some(const_pool::Const::Utf8(utf8)) => {
+                let name = String::from_utf8(utf8.bytes.clone()).map_err(LoadError::new)?;
+                Ok((name_idx, name))
+            }
+            _ => Err(LoadError::simple(&format!("Invalid attribute name index {}", name_idx))),
+        }
+    }
+
+    fn read_stack_map_frame(&mut self) -> Result<StackMapFrame, LoadError> {
+        let frame_type = self.read_u8()?;
+        match frame_type {
+            0..=63 => Ok(StackMapFrame::Same {
+                offset_delta: frame_type as u16,
+            }),
+            64..=127 => Ok(StackMapFrame::SameLocals1StackItem {
+                offset_delta: frame_type as u16 - 64,
+                stack: self.read_verification_type()?,
+            }),
+            247 => Ok(StackMapFrame::SameLocals1StackItem {
+                offset_delta: self.read_u16()?,
+                stack: self.read_verification_type()?,
+            }),
+            248..=250 => Ok(StackMapFrame::Chop {
+                offset_delta: self.read_u16()?,
+                k: 251 - frame_type,
+            }),
+            251 => Ok(StackMapFrame::Same {
+                offset_delta: self.read_u16()?,
+            }),
+            252..=254 => {
+                let offset_delta = self.read_u16()?;
+                let mut locals = Vec::new();
+                for _ in 0..(frame_type - 251) {
+                    locals.push(self.read_verification_type()?);
+                }
+                Ok(StackMapFrame::Append { offset_delta, locals })
+            }
+            255 => {
+                let offset_delta = self.read_u16()?;
+                let number_of_locals = self.read_u16()?;
+                let mut locals = Vec::new();
+                for _ in 0..number_of_locals {
+                    locals.push(self.read_verification_type()?);
+                }
+                let number_of_stack_items = self.read_u16()?;
+                let mut stack = Vec::new();
+                for _ in 0..number_of_stack_items {
+                    stack.push(self.read_verification_type()?);
+                }
+                Ok(StackMapFrame::Full { offset_delta, locals, stack })
+            }
+            _ => Err(LoadError::simple(&format!("Unknown stack map frame type {}", frame_type)))
+        }
+    }
+
+    fn read_verification_type(&mut self) -> Result<VerificationType, LoadError> {
+        let tag = self.read_u8()?;
+        match tag {
+            0 => Ok(VerificationType::Top),
+            1 => Ok(VerificationType::Integer),
+            2 => Ok(VerificationType::Float),
+            3 => Ok(VerificationType::Double),
+            4 => Ok(VerificationType::Long),
+            5 => Ok(VerificationType::Null),
+            6 => Ok(VerificationType::UninitializedThis),
+            7 => Ok(VerificationType::Object(self.read_u16()?)),
+            8 => Ok(VerificationType::Uninitialized(self.read_u16()?)),
+            _ => Err(LoadError::simple(&format!("Unknown verification type tag {}", tag)))
+        }
+    }
+
     fn read_const(&mut self) -> Result<Const, LoadError> {
         let tag = self.read_u8()?;
         match tag {
             1 => {
                 let length = self.read_u16()?;
                 let bytes = self.read_length(length as usize)?;
-                Ok(Const::Utf8(Utf8 { bytes }))
+                Ok(Const::Utf8(Utf8 { bytes: decode_modified_utf8(bytes)? }))
             }
             3 => Ok(Const::Integer(Integer {
                 int: self.read_i32()?,
@@ -210,7 +324,7 @@ impl<'a> Parser<'a> {
                 bootstrap_method_attr: self.read_u16()?,
                 name_and_type: self.read_u16()?,
             })),
-            _ => Err(LoadError::simple(format!("unknown tag {}", tag).as_str()))
+            _ => Err(LoadError::simple(&format!("Unknown constant tag {}", tag)))
         }
     }
 
@@ -291,13 +405,13 @@ impl<'a> Parser<'a> {
 
     fn read_u8(&mut self) -> Result<u8, LoadError> {
         self.reader.read_exact(&mut self.buffer[0..1])
-            .map_err(LoadError::new)?;
+            .map_err(LoadError::io)?;
         Ok(self.buffer[0])
     }
 
     fn read_u16(&mut self) -> Result<u16, LoadError> {
         self.reader.read_exact(&mut self.buffer[0..2])
-            .map_err(LoadError::new)?;
+            .map_err(LoadError::io)?;
         let u16_slice: &[u8; 2] = &self.buffer[0..2].try_into()
             .map_err(LoadError::new)?;
         Ok(u16::from_be_bytes(*u16_slice))
@@ -305,7 +419,7 @@ impl<'a> Parser<'a> {
 
     fn read_u32(&mut self) -> Result<u32, LoadError> {
         self.reader.read_exact(&mut self.buffer[0..4])
-            .map_err(LoadError::new)?;
+            .map_err(LoadError::io)?;
         let u32_slice: &[u8; 4] = &self.buffer[0..4].try_into()
             .map_err(LoadError::new)?;
         Ok(u32::from_be_bytes(*u32_slice))
@@ -313,7 +427,7 @@ impl<'a> Parser<'a> {
 
     fn read_i32(&mut self) -> Result<i32, LoadError> {
         self.reader.read_exact(&mut self.buffer[0..4])
-            .map_err(LoadError::new)?;
+            .map_err(LoadError::io)?;
         let i32_slice: &[u8; 4] = &self.buffer[0..4].try_into()
             .map_err(LoadError::new)?;
         Ok(i32::from_be_bytes(*i32_slice))
@@ -321,7 +435,7 @@ impl<'a> Parser<'a> {
 
     fn read_f32(&mut self) -> Result<f32, LoadError> {
         self.reader.read_exact(&mut self.buffer[0..4])
-            .map_err(LoadError::new)?;
+            .map_err(LoadError::io)?;
         let f32_slice: &[u8; 4] = &self.buffer[0..4].try_into()
             .map_err(LoadError::new)?;
         Ok(f32::from_be_bytes(*f32_slice))
@@ -329,7 +443,7 @@ impl<'a> Parser<'a> {
 
     fn read_i64(&mut self) -> Result<i64, LoadError> {
         self.reader.read_exact(&mut self.buffer[0..8])
-            .map_err(LoadError::new)?;
+            .map_err(LoadError::io)?;
         let i64_slice: &[u8; 8] = &self.buffer[0..8].try_into()
             .map_err(LoadError::new)?;
         Ok(i64::from_be_bytes(*i64_slice))
@@ -337,7 +451,7 @@ impl<'a> Parser<'a> {
 
     fn read_f64(&mut self) -> Result<f64, LoadError> {
         self.reader.read_exact(&mut self.buffer[0..8])
-            .map_err(LoadError::new)?;
+            .map_err(LoadError::io)?;
         let f64_slice: &[u8; 8] = &self.buffer[0..8].try_into()
             .map_err(LoadError::new)?;
         Ok(f64::from_be_bytes(*f64_slice))
@@ -345,29 +459,77 @@ impl<'a> Parser<'a> {
 
     fn read_length(&mut self, length: usize) -> Result<Vec<u8>, LoadError> {
         let mut vec = vec![0; length];
-        self.reader.read_exact(&mut vec).map_err(LoadError::new)?;
+        self.reader.read_exact(&mut vec).map_err(LoadError::io)?;
         Ok(vec)
     }
 }
 
+/// Decode a modified UTF-8 string into standard UTF-8.
+///
+/// The two differ in how the null character is encoded, in two bytes rather than one, and
+/// supplementary characters, which are encoded as a pair of surrogates of three bytes each.
+fn decode_modified_utf8(bytes: Vec<u8>) -> Result<Vec<u8>, LoadError> {
+    if bytes.iter().all(|byte| (0x01..0x80).contains(byte)) {
+        return Ok(bytes);
+    }
+
+    let continuation = |idx: usize| {
+        bytes.get(idx)
+            .filter(|byte| *byte & 0xC0 == 0x80)
+            .map(|byte| (byte & 0x3F) as u16)
+            .ok_or_else(|| LoadError::simple("Illegal UTF8 string in constant pool"))
+    };
+    let mut chars = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let x = bytes[idx] as u16;
+        let (char, width) = match x {
+            0x01..=0x7F => (x, 1),
+            0xC0..=0xDF => ((x & 0x1F) << 6 | continuation(idx + 1)?, 2),
+            0xE0..=0xEF => ((x & 0x0F) << 12 | continuation(idx + 1)? << 6 | continuation(idx + 2)?, 3),
+            _ => return Err(LoadError::simple("Illegal UTF8 string in constant pool")),
+        };
+        chars.push(char);
+        idx += width;
+    }
+    // An unpaired surrogate can't be represented in a Rust string.
+    Ok(String::from_utf16_lossy(&chars).into_bytes())
+}
 
 /// An error occurring during class file loading.
 #[derive(Debug)]
-pub struct LoadError(Box<dyn Error>);
+pub enum LoadError {
+    /// The class file is malformed or truncated.
+    Format(Box<dyn Error>),
+    /// The class file's version is not supported.
+    UnsupportedVersion { major: u16, minor: u16 },
+}
 
 impl LoadError {
     fn new<E: Error + Into<Box<dyn Error>>>(error: E) -> Self {
-        LoadError(error.into())
+        LoadError::Format(error.into())
+    }
+
+    fn io(error: std::io::Error) -> Self {
+        if error.kind() == ErrorKind::UnexpectedEof {
+            return LoadError::simple("Truncated class file");
+        }
+        LoadError::new(error)
     }
 
-    fn simple(error: &str) -> Self {
-        LoadError(Box::new(SimpleError(error.to_string())))
+    pub(super) fn simple(error: &str) -> Self {
+        LoadError::Format(Box::new(SimpleError(error.to_string())))
     }
 }
 
 impl Display for LoadError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        self.0.fmt(f)
+        match self {
+            LoadError::Format(error) => error.fmt(f),
+            LoadError::UnsupportedVersion { major, minor } => {
+                write!(f, "Unsupported major.minor version {}.{}", major, minor)
+            }
+        }
     }
 }
 
@@ -383,3 +545,125 @@ impl Display for SimpleError {
 }
 
 impl Error for SimpleError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty class file, with the given version and constant pool entries.
+    fn class_file(major_version: u16, const_pool: &[u8], const_pool_count: u16) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&MAGIC.to_be_bytes());
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        bytes.extend_from_slice(&major_version.to_be_bytes());
+        bytes.extend_from_slice(&(const_pool_count + 1).to_be_bytes());
+        bytes.extend_from_slice(const_pool);
+        // Access flags, this class (the last constant), super class, interfaces, fields,
+        // methods & attributes.
+        bytes.extend_from_slice(&[0; 2]);
+        bytes.extend_from_slice(&const_pool_count.to_be_bytes());
+        bytes.extend_from_slice(&[0; 10]);
+        bytes
+    }
+
+    fn parse_bytes(bytes: &[u8]) -> Result<ClassFile, LoadError> {
+        let mut reader = bytes;
+        parse(&mut reader)
+    }
+
+    #[test]
+    fn parse_empty() {
+        let class_file = parse_bytes(&class_file(52, &[1, 0, 1, b'A', 7, 0, 1], 2)).unwrap();
+
+        assert_eq!(class_file.major_version, 52);
+        assert_eq!(class_file.const_pool.len(), 2);
+    }
+
+    #[test]
+    fn parse_bad_magic() {
+        let mut bytes = class_file(52, &[], 0);
+        bytes[3] = 0xBF;
+
+        let error = parse_bytes(&bytes).err().unwrap();
+        assert_eq!(error.to_string(), "Incompatible magic value 3405691583");
+    }
+
+    #[test]
+    fn parse_unsupported_version() {
+        let error = parse_bytes(&class_file(53, &[], 0)).err().unwrap();
+        assert!(matches!(error, LoadError::UnsupportedVersion { major: 53, minor: 0 }));
+
+        let error = parse_bytes(&class_file(44, &[], 0)).err().unwrap();
+        assert!(matches!(error, LoadError::UnsupportedVersion { major: 44, minor: 0 }));
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let bytes = class_file(52, &[1, 0, 1, b'A'], 1);
+
+        for length in 0..bytes.len() {
+            let error = parse_bytes(&bytes[..length]).err().unwrap();
+            assert_eq!(error.to_string(), "Truncated class file");
+        }
+    }
+
+    #[test]
+    fn parse_extra_bytes() {
+        let mut bytes = class_file(52, &[], 0);
+        bytes.push(0);
+
+        let error = parse_bytes(&bytes).err().unwrap();
+        assert_eq!(error.to_string(), "Extra bytes at the end of class file");
+    }
+
+    #[test]
+    fn parse_unknown_const_tag() {
+        let error = parse_bytes(&class_file(52, &[2, 0, 0], 1)).err().unwrap();
+        assert_eq!(error.to_string(), "Unknown constant tag 2");
+    }
+
+    #[test]
+    fn parse_invalid_const_index() {
+        // A class that names itself with the wrong kind of constant.
+        let error = parse_bytes(&class_file(52, &[1, 0, 1, b'A', 8, 0, 1], 2)).err().unwrap();
+        assert_eq!(error.to_string(), "Invalid constant pool index 2");
+
+        // A class whose name is out of range of the constant pool.
+        let error = parse_bytes(&class_file(52, &[1, 0, 1, b'A', 7, 0, 9], 2)).err().unwrap();
+        assert_eq!(error.to_string(), "Invalid constant pool index 9");
+    }
+
+    #[test]
+    fn parse_modified_utf8() {
+        // A null character, and a supplementary character as a pair of surrogates.
+        let bytes = class_file(52, &[1, 0, 9, b'A', 0xC0, 0x80, 0xED, 0xA0, 0xBD, 0xED, 0xB8, 0x80, 7, 0, 1], 2);
+        let parsed = parse_bytes(&bytes).unwrap();
+        match parsed.const_pool.get(&1) {
+            Some(Const::Utf8(utf8)) => assert_eq!(utf8.bytes, "A\0\u{1F600}".as_bytes()),
+            other => panic!("Expected const utf8, got {:?}", other),
+        }
+
+        let error = parse_bytes(&class_file(52, &[1, 0, 2, b'A', 0xF0, 7, 0, 1], 2)).err().unwrap();
+        assert_eq!(error.to_string(), "Illegal UTF8 string in constant pool");
+    }
+
+    #[test]
+    fn parse_illegal_descriptor() {
+        // A field whose descriptor isn't a type.
+        let mut bytes = class_file(52, &[1, 0, 1, b'A', 1, 0, 1, b'Q', 7, 0, 1], 3);
+        let fields = bytes.len() - 6;
+        bytes.splice(fields..fields + 2, [0, 1, 0, 0, 0, 1, 0, 2, 0, 0]);
+
+        let error = parse_bytes(&bytes).err().unwrap();
+        assert_eq!(error.to_string(), "Illegal field signature \"Q\"");
+    }
+
+    #[test]
+    fn parse_invalid_bootstrap_method_index() {
+        // An invokedynamic call site in a class without any bootstrap methods.
+        let bytes = class_file(52, &[1, 0, 1, b'A', 1, 0, 3, b'(', b')', b'V', 12, 0, 1, 0, 2, 18, 0, 0, 0, 3, 7, 0, 1], 5);
+
+        let error = parse_bytes(&bytes).err().unwrap();
+        assert_eq!(error.to_string(), "Invalid bootstrap method index 0");
+    }
+}
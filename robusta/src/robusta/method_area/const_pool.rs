@@ -5,20 +5,22 @@ use crate::class_file::ClassFile;
 use crate::class_file::const_pool as cp;
 use crate::collection::once::Once;
 use crate::java::{FieldType, MethodType, Reference};
-use crate::method_area::{Class, Field, Method};
+use crate::method_area::{Class, Field, Method, ObjectClass};
 
 /// The run-time constant pool of a class is a collection of constants and symbolic references to
 /// other data in the JVM.
 pub struct ConstPool {
     pub pool: HashMap<u16, Const, BuildNoHashHasher<u16>>,
+    /// The loader that defined the class, symbolic references are resolved using this loader.
+    pub loader: Reference,
 }
 
 unsafe impl Send for ConstPool {}
 
 impl ConstPool {
-    pub fn new(file: &ClassFile) -> Self {
+    pub fn new(file: &ClassFile, loader: Reference) -> Self {
         let pool: HashMap<u16, Const, BuildNoHashHasher<u16>> = HashMap::with_hasher(BuildNoHashHasher::default());
-        let mut pool = ConstPool { pool };
+        let mut pool = ConstPool { pool, loader };
 
         // Want to descend keys to ensure that when we visit references to other constants,
         // that those constants have already been added.
@@ -108,6 +110,27 @@ impl ConstPool {
                         resolved: Once::new(),
                     }));
                 }
+                cp::Const::InvokeDynamic(invoke_dynamic) => {
+                    // The parser has checked that the bootstrap method exists.
+                    let bootstrap_methods = file.bootstrap_methods().unwrap();
+                    let bootstrap_method = &bootstrap_methods.methods[invoke_dynamic.bootstrap_method_attr as usize];
+                    let name_and_type = file.get_const_name_and_type(invoke_dynamic.name_and_type);
+                    let name = file.get_const_utf8(name_and_type.name);
+                    let name = String::from_utf8(name.bytes.clone()).unwrap();
+                    let descriptor = file.get_const_utf8(name_and_type.descriptor);
+                    let descriptor = MethodType::from_descriptor(String::from_utf8(descriptor.bytes.clone()).unwrap().as_str()).unwrap();
+                    pool.pool.insert(*key, Const::CallSite(SymbolicReference {
+                        const_key: CallSiteKey {
+                            bootstrap: method_handle_key(file, bootstrap_method.method_ref),
+                            arguments: bootstrap_method.arguments.iter()
+                                .map(|index| bootstrap_argument(file, *index))
+                                .collect(),
+                            name,
+                            descriptor,
+                        },
+                        resolved: Once::new(),
+                    }));
+                }
                 _ => {}
             }
         }
@@ -132,6 +155,13 @@ impl ConstPool {
         }
     }
 
+    pub fn get_call_site(&self, index: u16) -> &SymbolicReference<CallSiteKey, *const ObjectClass> {
+        match self.pool.get(&index).unwrap() {
+            Const::CallSite(reference) => reference,
+            _ => panic!("Expected to find a call site at index {} in the constant pool", index)
+        }
+    }
+
     pub fn get_field(&self, index: u16) -> &SymbolicReference<FieldKey, *const Field> {
         match self.pool.get(&index).unwrap() {
             Const::Field(reference) => reference,
@@ -144,6 +174,8 @@ pub enum Const {
     Class(SymbolicReference<ClassKey, Class>),
     Field(SymbolicReference<FieldKey, *const Field>),
     Method(SymbolicReference<MethodKey, *const Method>),
+    /// A dynamic call site, resolved to the class that implements it.
+    CallSite(SymbolicReference<CallSiteKey, *const ObjectClass>),
     String(SymbolicReference<String, Reference>),
     Integer(i32),
     Float(f32),
@@ -151,22 +183,62 @@ pub enum Const {
     Double(f64),
 }
 
+#[derive(Clone)]
 pub struct ClassKey {
     pub name: String,
 }
 
+#[derive(Clone)]
 pub struct FieldKey {
     pub class: String,
     pub name: String,
     pub descriptor: FieldType,
 }
 
+#[derive(Clone)]
 pub struct MethodKey {
     pub class: String,
     pub name: String,
     pub descriptor: MethodType,
 }
 
+/// The symbolic reference to a method handle, as named by `reference_kind` in
+/// [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.4.3.5).
+#[derive(Clone)]
+pub enum MethodHandleKey {
+    GetField(FieldKey),
+    GetStatic(FieldKey),
+    PutField(FieldKey),
+    PutStatic(FieldKey),
+    InvokeVirtual(MethodKey),
+    InvokeStatic(MethodKey),
+    InvokeSpecial(MethodKey),
+    NewInvokeSpecial(MethodKey),
+    InvokeInterface(MethodKey),
+}
+
+/// A static argument supplied to a bootstrap method.
+#[derive(Clone)]
+pub enum BootstrapArgument {
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    String(String),
+    Class(ClassKey),
+    MethodType(MethodType),
+    MethodHandle(MethodHandleKey),
+}
+
+/// The symbolic reference to a dynamic call site, from a `CONSTANT_InvokeDynamic_info`
+/// and its entry in the `BootstrapMethods` attribute.
+pub struct CallSiteKey {
+    pub bootstrap: MethodHandleKey,
+    pub arguments: Vec<BootstrapArgument>,
+    pub name: String,
+    pub descriptor: MethodType,
+}
+
 /// A symbolic reference is a resolvable reference to another object in the method area, or to a
 /// java value.
 pub struct SymbolicReference<K, V: Unpin> {
@@ -180,5 +252,75 @@ impl<K, V: Unpin> SymbolicReference<K, V> {
     {
         self.resolved.get_or_init(|| f(&self.const_key))
     }
+
+    /// Resolve the reference, unless resolution fails, in which case it can be attempted again.
+    pub fn try_resolve<F, E>(&self, f: F) -> Result<&V, E>
+        where F: FnOnce(&K) -> Result<V, E>
+    {
+        self.resolved.try_get_or_init(|| f(&self.const_key))
+    }
 }
 
+fn method_handle_key(file: &ClassFile, index: u16) -> MethodHandleKey {
+    let handle = file.get_const_method_handle(index);
+    let (class, name_and_type) = match file.const_pool.get(&handle.reference_idx).unwrap() {
+        cp::Const::FieldRef(field) => (field.class, field.name_and_type),
+        cp::Const::MethodRef(method) => (method.class, method.name_and_type),
+        cp::Const::InterfaceMethodRef(method) => (method.class, method.name_and_type),
+        other => panic!("Expected a member reference for method handle, got {:?}", other)
+    };
+    let class = file.get_const_class(class);
+    let class = String::from_utf8(file.get_const_utf8(class.name).bytes.clone()).unwrap().replace("/", ".");
+    let name_and_type = file.get_const_name_and_type(name_and_type);
+    let name = String::from_utf8(file.get_const_utf8(name_and_type.name).bytes.clone()).unwrap();
+    let descriptor = String::from_utf8(file.get_const_utf8(name_and_type.descriptor).bytes.clone()).unwrap();
+
+    let field = || FieldKey {
+        class: class.clone(),
+        name: name.clone(),
+        descriptor: FieldType::from_descriptor(&descriptor).unwrap(),
+    };
+    let method = || MethodKey {
+        class: class.clone(),
+        name: name.clone(),
+        descriptor: MethodType::from_descriptor(&descriptor).unwrap(),
+    };
+
+    match handle.reference_kind {
+        1 => MethodHandleKey::GetField(field()),
+        2 => MethodHandleKey::GetStatic(field()),
+        3 => MethodHandleKey::PutField(field()),
+        4 => MethodHandleKey::PutStatic(field()),
+        5 => MethodHandleKey::InvokeVirtual(method()),
+        6 => MethodHandleKey::InvokeStatic(method()),
+        7 => MethodHandleKey::InvokeSpecial(method()),
+        8 => MethodHandleKey::NewInvokeSpecial(method()),
+        9 => MethodHandleKey::InvokeInterface(method()),
+        kind => panic!("Unknown method handle reference kind {}", kind)
+    }
+}
+
+fn bootstrap_argument(file: &ClassFile, index: u16) -> BootstrapArgument {
+    match file.const_pool.get(&index).unwrap() {
+        cp::Const::Integer(integer) => BootstrapArgument::Integer(integer.int),
+        cp::Const::Float(float) => BootstrapArgument::Float(float.float),
+        cp::Const::Long(long) => BootstrapArgument::Long(long.long),
+        cp::Const::Double(double) => BootstrapArgument::Double(double.double),
+        cp::Const::String(string) => {
+            let string = file.get_const_utf8(string.string);
+            BootstrapArgument::String(String::from_utf8(string.bytes.clone()).unwrap())
+        }
+        cp::Const::Class(class) => {
+            let name = file.get_const_utf8(class.name);
+            let name = String::from_utf8(name.bytes.clone()).unwrap().replace("/", ".");
+            BootstrapArgument::Class(ClassKey { name })
+        }
+        cp::Const::MethodType(method_type) => {
+            let descriptor = file.get_const_utf8(method_type.descriptor);
+            let descriptor = String::from_utf8(descriptor.bytes.clone()).unwrap();
+            BootstrapArgument::MethodType(MethodType::from_descriptor(&descriptor).unwrap())
+        }
+        cp::Const::MethodHandle(_) => BootstrapArgument::MethodHandle(method_handle_key(file, index)),
+        other => panic!("Unexpected bootstrap method argument {:?}", other)
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+use std::ptr::null;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use nohash_hasher::BuildNoHashHasher;
+
+use crate::class_file::Code;
+use crate::collection::classes::ClassRef;
+use crate::collection::once::Once;
+use crate::java::{FieldType, MethodType, Reference};
+use crate::method_area::{ClassFlags, Field, Method, ObjectClass};
+use crate::method_area::const_pool::{BootstrapArgument, CallSiteKey, ClassKey, Const, ConstPool, FieldKey, MethodHandleKey, MethodKey, SymbolicReference};
+
+const LAMBDA_METAFACTORY: &str = "java.lang.invoke.LambdaMetafactory";
+
+const FLAG_SERIALIZABLE: i32 = 1;
+const FLAG_MARKERS: i32 = 2;
+const FLAG_BRIDGES: i32 = 4;
+
+static LAMBDA_COUNT: AtomicUsize = AtomicUsize::new(1);
+
+/// A lambda or method reference call site, bootstrapped by `LambdaMetafactory`.
+///
+/// Rather than running the metafactory, and all of `java.lang.invoke` with it, the VM spins the
+/// class that the metafactory would have created itself. The class stores the captured
+/// arguments in fields, and implements each functional method by invoking the implementation
+/// method with the captured arguments followed by the method's own arguments.
+///
+/// Other bootstrap methods would need method handles to be invoked, which isn't supported.
+///
+/// For further information, see [the docs](https://docs.oracle.com/javase/8/docs/api/java/lang/invoke/LambdaMetafactory.html).
+pub struct Lambda {
+    name: String,
+    captured: Vec<FieldType>,
+    implementation: MethodHandleKey,
+    method_types: Vec<MethodType>,
+    interfaces: Vec<String>,
+}
+
+impl Lambda {
+    /// Read the lambda from the call site, failing with a message if it isn't bootstrapped by
+    /// `LambdaMetafactory`, or the metafactory's arguments are wrong.
+    pub fn from_call_site(call_site: &CallSiteKey) -> Result<Self, String> {
+        let is_alt = match &call_site.bootstrap {
+            MethodHandleKey::InvokeStatic(method) if method.class == LAMBDA_METAFACTORY && method.name == "metafactory" => false,
+            MethodHandleKey::InvokeStatic(method) if method.class == LAMBDA_METAFACTORY && method.name == "altMetafactory" => true,
+            MethodHandleKey::InvokeStatic(method) => {
+                return Err(format!("Unsupported bootstrap method {}.{}", method.class, method.name));
+            }
+            _ => return Err("Unsupported bootstrap method handle".to_string()),
+        };
+
+        let mut arguments = call_site.arguments.iter();
+        let sam_method_type = match arguments.next() {
+            Some(BootstrapArgument::MethodType(method_type)) => method_type.clone(),
+            _ => return Err("Expected LambdaMetafactory samMethodType argument".to_string()),
+        };
+        let implementation = match arguments.next() {
+            Some(BootstrapArgument::MethodHandle(handle @ (MethodHandleKey::InvokeVirtual(_) |
+                                                           MethodHandleKey::InvokeStatic(_) |
+                                                           MethodHandleKey::InvokeSpecial(_) |
+                                                           MethodHandleKey::NewInvokeSpecial(_) |
+                                                           MethodHandleKey::InvokeInterface(_)))) => handle.clone(),
+            _ => return Err("Expected LambdaMetafactory implMethod argument".to_string()),
+        };
+        // The instantiated method type only narrows the erased types, which we don't check.
+        arguments.next();
+
+        let interface = match &call_site.descriptor.returns {
+            Some(FieldType::Reference(name)) => name.clone(),
+            _ => return Err("Expected LambdaMetafactory invokedType to return an interface".to_string()),
+        };
+
+        let mut lambda = Lambda {
+            name: call_site.name.clone(),
+            captured: call_site.descriptor.parameters.clone(),
+            implementation,
+            method_types: vec![sam_method_type],
+            interfaces: vec![interface],
+        };
+
+        if is_alt {
+            let flags = match arguments.next() {
+                Some(BootstrapArgument::Integer(flags)) => *flags,
+                _ => return Err("Expected LambdaMetafactory flags argument".to_string()),
+            };
+            if flags & FLAG_SERIALIZABLE != 0 {
+                lambda.interfaces.push("java.io.Serializable".to_string());
+            }
+            if flags & FLAG_MARKERS != 0 {
+                let count = match arguments.next() {
+                    Some(BootstrapArgument::Integer(count)) => *count,
+                    _ => return Err("Expected LambdaMetafactory marker count argument".to_string()),
+                };
+                for _ in 0..count {
+                    match arguments.next() {
+                        Some(BootstrapArgument::Class(class)) => lambda.interfaces.push(class.name.clone()),
+                        _ => return Err("Expected LambdaMetafactory marker interface argument".to_string()),
+                    }
+                }
+            }
+            if flags & FLAG_BRIDGES != 0 {
+                let count = match arguments.next() {
+                    Some(BootstrapArgument::Integer(count)) => *count,
+                    _ => return Err("Expected LambdaMetafactory bridge count argument".to_string()),
+                };
+                for _ in 0..count {
+                    match arguments.next() {
+                        Some(BootstrapArgument::MethodType(method_type)) => lambda.method_types.push(method_type.clone()),
+                        _ => return Err("Expected LambdaMetafactory bridge argument".to_string()),
+                    }
+                }
+            }
+        }
+
+        Ok(lambda)
+    }
+
+    /// The names of the interfaces implemented by the lambda class.
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    /// Create the class implementing the lambda, named after the calling class and defined by
+    /// the calling class's loader.
+    pub fn spin(&self, caller: &str, loader: Reference, object: ClassRef, interfaces: Vec<ClassRef>) -> ObjectClass {
+        let name = format!("{}$$Lambda${}", caller, LAMBDA_COUNT.fetch_add(1, Ordering::Relaxed));
+        let mut pool = PoolBuilder { pool: HashMap::with_hasher(BuildNoHashHasher::default()), next: 1 };
+
+        let mut instance_fields: Vec<Field> = self.captured.iter().enumerate()
+            .map(|(i, descriptor)| Field {
+                class: null(),
+                flags: 0x0012, // private final
+                is_static: false,
+                name: format!("arg${}", i + 1),
+                width: descriptor.width(),
+                descriptor: descriptor.clone(),
+                offset: 0,
+            }).collect();
+
+        let field_consts: Vec<u16> = instance_fields.iter()
+            .map(|field| pool.add(Const::Field(SymbolicReference {
+                const_key: FieldKey {
+                    class: name.clone(),
+                    name: field.name.clone(),
+                    descriptor: field.descriptor.clone(),
+                },
+                resolved: Once::new(),
+            })))
+            .collect();
+
+        // Sort to get a better order for object packing.
+        instance_fields.sort_by(|a, b| a.width.cmp(&b.width).reverse());
+        let mut instance_offset = object.instance_width;
+        for field in &mut instance_fields {
+            field.offset = instance_offset;
+            instance_offset += field.width;
+        }
+
+        const ALIGN: usize = 4;
+        let instance_pad = ALIGN - (instance_offset % ALIGN);
+        let instance_width = instance_offset + instance_pad;
+
+        let methods = self.method_types.iter()
+            .map(|method_type| Method {
+                class: null(),
+                flags: 0x0001, // public
+                is_static: false,
+                is_native: false,
+                is_synchronized: false,
+                name: self.name.clone(),
+                descriptor: method_type.clone(),
+                code: Some(self.code(method_type, &field_consts, &mut pool)),
+            })
+            .collect();
+
+        ObjectClass {
+            name,
+            flags: ClassFlags { bits: 0x1010 }, // final synthetic
+            const_pool: ConstPool { pool: pool.pool, loader },
+            super_class: Some(object),
+            interfaces,
+            instance_fields,
+            static_fields: vec![],
+            methods,
+            attributes: vec![],
+            instance_width,
+            static_width: 0,
+            source_file: None,
+            loader,
+            reference_type: None,
+            has_finalizer: false,
+        }
+    }
+
+    /// Generate the code for a functional method of the given type.
+    fn code(&self, method_type: &MethodType, field_consts: &[u16], pool: &mut PoolBuilder) -> Code {
+        let mut code = vec![];
+
+        let (opcode, method) = match &self.implementation {
+            MethodHandleKey::InvokeVirtual(method) => (0xB6, method),
+            MethodHandleKey::InvokeStatic(method) => (0xB8, method),
+            MethodHandleKey::InvokeSpecial(method) => (0xB7, method),
+            MethodHandleKey::NewInvokeSpecial(method) => (0xB7, method),
+            MethodHandleKey::InvokeInterface(method) => (0xB9, method),
+            _ => unreachable!("The implementation method handle is checked by from_call_site"),
+        };
+
+        let mut parameters = vec![];
+        let returns = match &self.implementation {
+            MethodHandleKey::NewInvokeSpecial(method) => {
+                let class_const = pool.add(Const::Class(SymbolicReference {
+                    const_key: ClassKey { name: method.class.clone() },
+                    resolved: Once::new(),
+                }));
+                code.push(0xBB); // new
+                code.extend_from_slice(&class_const.to_be_bytes());
+                code.push(0x59); // dup
+                Some(FieldType::Reference(method.class.clone()))
+            }
+            MethodHandleKey::InvokeStatic(method) => method.descriptor.returns.clone(),
+            _ => {
+                parameters.push(FieldType::Reference(method.class.clone()));
+                method.descriptor.returns.clone()
+            }
+        };
+        parameters.extend(method.descriptor.parameters.iter().cloned());
+        let mut parameters = parameters.iter();
+
+        for (captured, field_const) in self.captured.iter().zip(field_consts) {
+            code.push(0x2A); // aload_0
+            code.push(0xB4); // getfield
+            code.extend_from_slice(&field_const.to_be_bytes());
+            adapt(&mut code, pool, Some(captured), parameters.next());
+        }
+
+        let mut slot: u16 = 1;
+        for parameter in &method_type.parameters {
+            code.push(match parameter {
+                FieldType::Long => 0x16,
+                FieldType::Float => 0x17,
+                FieldType::Double => 0x18,
+                FieldType::Reference(_) | FieldType::Array(_) => 0x19,
+                _ => 0x15,
+            });
+            // A slot past 255 needs a wide load.
+            match u8::try_from(slot) {
+                Ok(slot) => code.push(slot),
+                Err(_) => {
+                    let opcode = code.pop().unwrap();
+                    code.extend_from_slice(&[0xC4, opcode]); // wide
+                    code.extend_from_slice(&slot.to_be_bytes());
+                }
+            }
+            slot += if parameter.width() == 8 { 2 } else { 1 };
+            adapt(&mut code, pool, Some(parameter), parameters.next());
+        }
+
+        let method_const = pool.add(Const::Method(SymbolicReference {
+            const_key: method.clone(),
+            resolved: Once::new(),
+        }));
+        code.push(opcode);
+        code.extend_from_slice(&method_const.to_be_bytes());
+        if opcode == 0xB9 {
+            let count = 1 + method.descriptor.parameters.iter()
+                .map(|p| if p.width() == 8 { 2 } else { 1 })
+                .sum::<u8>();
+            code.push(count);
+            code.push(0);
+        }
+
+        match &method_type.returns {
+            None => match &returns {
+                Some(FieldType::Long) | Some(FieldType::Double) => code.push(0x58), // pop2
+                Some(_) => code.push(0x57), // pop
+                None => {}
+            },
+            Some(_) => adapt(&mut code, pool, returns.as_ref(), method_type.returns.as_ref()),
+        }
+
+        code.push(match &method_type.returns {
+            None => 0xB1,
+            Some(FieldType::Long) => 0xAD,
+            Some(FieldType::Float) => 0xAE,
+            Some(FieldType::Double) => 0xAF,
+            Some(FieldType::Reference(_)) | Some(FieldType::Array(_)) => 0xB0,
+            Some(_) => 0xAC,
+        });
+
+        Code {
+            max_stack: 2 * (slot + self.captured.len() as u16) + 2,
+            max_locals: slot,
+            code,
+            ex_table: vec![],
+            attributes: vec![],
+        }
+    }
+}
+
+/// Convert the value on top of the stack, casting references and boxing, unboxing or widening
+/// primitives where the functional method and the implementation method disagree.
+///
+/// The lambda class isn't verified, so the casts are what throw `ClassCastException` when the
+/// lambda is called with the wrong type of argument.
+fn adapt(code: &mut Vec<u8>, pool: &mut PoolBuilder, from: Option<&FieldType>, to: Option<&FieldType>) {
+    let (from, to) = match (from, to) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return,
+    };
+    match (from.is_reference(), to.is_reference()) {
+        (true, true) => {
+            match to {
+                FieldType::Reference(name) if from != to && name != "java.lang.Object" => check_cast(code, pool, name),
+                _ => {}
+            }
+        }
+        (false, true) => {
+            let wrapper = wrapper(from);
+            let method = pool.add(Const::Method(SymbolicReference {
+                const_key: MethodKey {
+                    class: wrapper.to_string(),
+                    name: "valueOf".to_string(),
+                    descriptor: MethodType { parameters: vec![from.clone()], returns: Some(FieldType::Reference(wrapper.to_string())) },
+                },
+                resolved: Once::new(),
+            }));
+            code.push(0xB8); // invokestatic
+            code.extend_from_slice(&method.to_be_bytes());
+        }
+        (true, false) => {
+            let wrapper = wrapper(to);
+            check_cast(code, pool, wrapper);
+            let method = pool.add(Const::Method(SymbolicReference {
+                const_key: MethodKey {
+                    class: wrapper.to_string(),
+                    name: format!("{}Value", to.as_class()),
+                    descriptor: MethodType { parameters: vec![], returns: Some(to.clone()) },
+                },
+                resolved: Once::new(),
+            }));
+            code.push(0xB6); // invokevirtual
+            code.extend_from_slice(&method.to_be_bytes());
+        }
+        (false, false) => {
+            let is_int = |t: &FieldType| matches!(t, FieldType::Boolean | FieldType::Byte | FieldType::Char | FieldType::Short | FieldType::Int);
+            let conversion = match (from, to) {
+                (f, FieldType::Long) if is_int(f) => Some(0x85), // i2l
+                (f, FieldType::Float) if is_int(f) => Some(0x86), // i2f
+                (f, FieldType::Double) if is_int(f) => Some(0x87), // i2d
+                (FieldType::Long, FieldType::Float) => Some(0x89), // l2f
+                (FieldType::Long, FieldType::Double) => Some(0x8A), // l2d
+                (FieldType::Float, FieldType::Double) => Some(0x8D), // f2d
+                _ => None,
+            };
+            if let Some(conversion) = conversion {
+                code.push(conversion);
+            }
+        }
+    }
+}
+
+fn check_cast(code: &mut Vec<u8>, pool: &mut PoolBuilder, class: &str) {
+    let class_const = pool.add(Const::Class(SymbolicReference {
+        const_key: ClassKey { name: class.to_string() },
+        resolved: Once::new(),
+    }));
+    code.push(0xC0); // checkcast
+    code.extend_from_slice(&class_const.to_be_bytes());
+}
+
+fn wrapper(primitive: &FieldType) -> &'static str {
+    match primitive {
+        FieldType::Boolean => "java.lang.Boolean",
+        FieldType::Byte => "java.lang.Byte",
+        FieldType::Char => "java.lang.Character",
+        FieldType::Short => "java.lang.Short",
+        FieldType::Int => "java.lang.Integer",
+        FieldType::Long => "java.lang.Long",
+        FieldType::Float => "java.lang.Float",
+        FieldType::Double => "java.lang.Double",
+        _ => panic!("Expected a primitive type"),
+    }
+}
+
+struct PoolBuilder {
+    pool: HashMap<u16, Const, BuildNoHashHasher<u16>>,
+    next: u16,
+}
+
+impl PoolBuilder {
+    fn add(&mut self, con: Const) -> u16 {
+        let index = self.next;
+        self.pool.insert(index, con);
+        self.next += 1;
+        index
+    }
+}
@@ -1,27 +1,48 @@
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use std::io::Cursor;
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::ptr;
 use std::sync::Arc;
 
 use maplit::hashset;
+use nohash_hasher::BuildNoHashHasher;
+use parking_lot::RwLock;
 use tracing::debug;
 
-use crate::class_file::{ACCESS_FLAG_NATIVE, ACCESS_FLAG_STATIC, ClassAttribute, Code, METHOD_ACC_SYNC};
+use crate::class_file::{ACCESS_FLAG_NATIVE, ACCESS_FLAG_STATIC, ClassAttribute, ClassFile, Code, METHOD_ACC_SYNC};
 use crate::collection::classes::{Classes, ClassRef};
 use crate::heap::Heap;
 use crate::java::{Double, FieldType, Float, Int, Long, MethodType, Reference, Value};
-use crate::loader::{ClassFileLoader, Loader};
+use crate::loader::{ClassFileLoader, LoadError, parse};
 use crate::log;
 use crate::method_area::const_pool::{Const, ConstPool, FieldKey, MethodKey};
-use crate::runtime::Runtime;
+use crate::method_area::lambda::Lambda;
+use crate::runtime::{Options, Verify};
 use crate::thread::Thread;
+pub use crate::method_area::verifier::VerifyError;
 
 pub mod const_pool;
+mod lambda;
+mod verifier;
 
 pub struct MethodArea {
+    /// The loader for the boot class path, the classes of the Java platform.
+    boot_loader: ClassFileLoader,
+    /// The loader for the application class path.
     loader: ClassFileLoader,
     heap: *const Heap,
     classes: Classes,
+    verify: Verify,
+    /// Print each class as it is loaded.
+    verbose: bool,
+    /// The names of the classes loaded from the boot class path.
+    boot_classes: RwLock<HashSet<String>>,
+    /// The result of linking each class, before it is initialized.
+    linked: RwLock<HashMap<(Reference, String), Result<(), VerifyError>>>,
+    /// The loading constraints between loaders, added as classes are resolved.
+    constraints: RwLock<Vec<LoaderConstraint>>,
 }
 
 unsafe impl Send for MethodArea {}
@@ -79,6 +100,15 @@ impl Class {
         }
     }
 
+    /// The loader that defined the class, array classes belong to the loader of their component.
+    pub fn loader(&self) -> Reference {
+        match self {
+            Class::Primitive(_) => Reference(0),
+            Class::Array { component, .. } => component.loader(),
+            Class::Object(class) => class.loader,
+        }
+    }
+
     pub fn find_method(&self, key: &MethodKey) -> Option<&Method> {
         match self {
             Class::Object(class_ref) => class_ref.find_method(key),
@@ -172,28 +202,38 @@ impl MethodArea {
             let ptr = ptr.cast_mut();
             ptr.write(Classes::new());
         }
+        self.linked.write().clear();
+        self.boot_classes.write().clear();
+        self.constraints.write().clear();
     }
 
-    pub fn new(heap: *const Heap) -> Self {
+    pub fn new(heap: *const Heap, options: &Options) -> Self {
         MethodArea {
-            loader: ClassFileLoader::new(vec![
-                PathBuf::from("./classes"),
-                PathBuf::from("./classes/rt.jar"),
-            ]),
+            boot_loader: ClassFileLoader::new_boot(options.boot_class_path.clone()),
+            loader: ClassFileLoader::new(options.class_path.clone()),
             heap,
             classes: Classes::new(),
+            verify: options.verify,
+            verbose: options.verbose_class,
+            boot_classes: RwLock::new(HashSet::new()),
+            linked: RwLock::new(HashMap::new()),
+            constraints: RwLock::new(vec![]),
         }
     }
 
+    /// Insert a class generated by the JVM, which is trusted and not verified.
     pub fn insert_gen_class(&self, class: ObjectClass) -> *const ObjectClass {
-        let class = self.classes.load_class(&class.name.clone(), |_| class);
+        let loader = class.loader;
+        let name = class.name.clone();
+        self.linked.write().insert((loader, name.clone()), Ok(()));
+        let class = self.classes.load_class(loader, &name, |_| Ok(class)).unwrap();
         class.self_referential();
         &*class as *const ObjectClass
     }
 
-    pub fn resolve_category_one(&self, pool: *const ConstPool, index: u16) -> Value {
+    pub fn resolve_category_one(&self, thread: &mut Thread, pool: *const ConstPool, index: u16) -> Result<Value, LoadClassError> {
         let pool = unsafe { pool.as_ref().unwrap() };
-        match pool.get_const(index) {
+        let value = match pool.get_const(index) {
             Const::Integer(int) => Value::Int(Int(*int)),
             Const::Float(float) => Value::Float(Float(*float)),
             Const::String(reference) => {
@@ -201,12 +241,13 @@ impl MethodArea {
                 Value::Reference(*reference)
             }
             Const::Class(reference) => {
-                let class = reference.resolve(|key| self.load_outer_class(&key.name));
+                let class = reference.try_resolve(|key| self.try_load_outer_class_in(thread, pool.loader, &key.name))?;
                 let class_object = self.load_class_object(class.clone());
                 Value::Reference(class_object)
             }
             _ => panic!("Expected to load a category 1 const, but not found")
-        }
+        };
+        Ok(value)
     }
 
     pub fn resolve_category_two(&self, pool: *const ConstPool, index: u16) -> Value {
@@ -220,17 +261,37 @@ impl MethodArea {
 
     /// Resolve a class symbolic reference in the constant pool, and return a reference to the
     /// class.
-    pub fn resolve_class(&self, pool: *const ConstPool, index: u16) -> Class {
+    pub fn resolve_class(&self, thread: &mut Thread, pool: *const ConstPool, index: u16) -> Result<Class, LoadClassError> {
         let pool = unsafe { pool.as_ref().unwrap() };
         let class_const = pool.get_class(index);
-        let class = class_const.resolve(|class_key| {
-            self.load_outer_class(&class_key.name)
-        });
-        class.clone()
+        let class = class_const.try_resolve(|class_key| {
+            self.try_load_outer_class_in(thread, pool.loader, &class_key.name)
+        })?;
+        Ok(class.clone())
     }
 
+    /// Load a class that the JVM requires, panicking if it can't be loaded.
     pub fn load_outer_class(&self, name: &str) -> Class {
-        match name {
+        self.try_load_outer_class(name)
+            .unwrap_or_else(|error| panic!("{}: {}", error.class_name(), error))
+    }
+
+    /// Load a primitive, array or object class using the bootstrap loader.
+    pub fn try_load_outer_class(&self, name: &str) -> Result<Class, LoadClassError> {
+        self.outer_class(name, &mut |name| self.try_load_class(name))
+    }
+
+    /// Load a primitive, array or object class using the given loader.
+    pub fn try_load_outer_class_in(&self, thread: &mut Thread, loader: Reference, name: &str) -> Result<Class, LoadClassError> {
+        self.outer_class(name, &mut |name| self.try_load_class_in(thread, loader, name))
+    }
+
+    /// Create a primitive or array class, or load an object class with the given function.
+    ///
+    /// Array classes are created by the JVM, rather than loaded, and belong to the loader of their
+    /// component.
+    fn outer_class(&self, name: &str, load: &mut dyn FnMut(&str) -> Result<ClassRef, LoadClassError>) -> Result<Class, LoadClassError> {
+        let class = match name {
             "boolean" => Class::Primitive(Primitive::Boolean),
             "byte" => Class::Primitive(Primitive::Byte),
             "char" => Class::Primitive(Primitive::Char),
@@ -243,189 +304,387 @@ impl MethodArea {
                 if name.starts_with('[') {
                     let field_type = FieldType::from_descriptor(name).unwrap();
                     Class::Array {
-                        component: Box::new(self.load_outer_class(&field_type.component_type())),
-                        object: Box::new(self.load_outer_class("java.lang.Object")),
+                        component: Box::new(self.outer_class(&field_type.component_type(), load)?),
+                        object: Box::new(Class::Object(self.try_load_class("java.lang.Object")?)),
                     }
                 } else {
-                    Class::Object(self.load_class(name))
+                    Class::Object(load(name)?)
                 }
             }
-        }
+        };
+        Ok(class)
     }
 
-    pub fn resolve_method(&self, pool: *const ConstPool, index: u16) -> *const Method {
+    pub fn resolve_method(&self, thread: &mut Thread, pool: *const ConstPool, index: u16) -> Result<*const Method, LoadClassError> {
         let pool = unsafe { pool.as_ref().unwrap() };
         let method_const = pool.get_method(index);
-        let method = method_const.resolve(|method_key| {
-            let class = self.load_outer_class(&method_key.class);
+        let method = method_const.try_resolve(|method_key| {
+            let class = self.try_load_outer_class_in(thread, pool.loader, &method_key.class)?;
             let method = class.find_method(method_key).unwrap();
-            method as *const Method
-        });
-        *method
+            let declaring_class = unsafe { method.class.as_ref().unwrap() };
+            // The method may be invoked without its class being initialized, such as a default
+            // method, so it must be verified before then.
+            self.link(thread, declaring_class).map_err(LoadClassError::Verify)?;
+            for name in method_key.descriptor.class_names() {
+                self.add_constraint(&name, pool.loader, declaring_class.loader).map_err(|_| {
+                    LoadClassError::LoaderConstraint {
+                        member: format!("method {}.{}{}", declaring_class.name, method.name, method.descriptor.descriptor()),
+                        name,
+                    }
+                })?;
+            }
+            Ok(method as *const Method)
+        })?;
+        Ok(*method)
     }
 
-    pub fn resolve_field(&self, _: Arc<Runtime>, pool: *const ConstPool, index: u16) -> *const Field {
+    /// Resolve a dynamic call site in the constant pool, and return the class whose instances
+    /// the call site produces.
+    ///
+    /// Only call sites bootstrapped by `LambdaMetafactory` are supported, any other bootstrap
+    /// method fails with a `BootstrapMethodError`.
+    pub fn resolve_call_site(&self, thread: &mut Thread, caller: &str, pool: &ConstPool, index: u16) -> Result<*const ObjectClass, LoadClassError> {
+        let call_site_const = pool.get_call_site(index);
+        let class = call_site_const.try_resolve(|call_site_key| {
+            let lambda = Lambda::from_call_site(call_site_key).map_err(|message| LoadClassError::Bootstrap {
+                call_site: format!("{}.{}{}", caller, call_site_key.name, call_site_key.descriptor.descriptor()),
+                message,
+            })?;
+            let object = self.load_class("java.lang.Object");
+            let interfaces = lambda.interfaces().iter()
+                .map(|name| self.try_load_class_in(thread, pool.loader, name))
+                .collect::<Result<Vec<ClassRef>, LoadClassError>>()?;
+            let class = lambda.spin(caller, pool.loader, object, interfaces);
+            debug!(target: log::LOADER, class=class.name.as_str(), "Spun lambda class");
+            Ok(self.insert_gen_class(class))
+        })?;
+        Ok(*class)
+    }
+
+    pub fn resolve_field(&self, thread: &mut Thread, pool: *const ConstPool, index: u16) -> Result<*const Field, LoadClassError> {
         let pool = unsafe { pool.as_ref().unwrap() };
         let field_const = pool.get_field(index);
-        let field = field_const.resolve(|field_key| {
-            let class = self.load_class(&field_key.class);
+        let field = field_const.try_resolve(|field_key| {
+            let class = self.try_load_class_in(thread, pool.loader, &field_key.class)?;
             let field = class.find_field(field_key);
-            field as *const Field
-        });
-        *field
+            self.add_field_constraint(pool.loader, field)?;
+            Ok(field as *const Field)
+        })?;
+        Ok(*field)
     }
 
-    pub fn resolve_static(&self, _: Arc<Runtime>, pool: *const ConstPool, index: u16) -> *const Field {
+    pub fn resolve_static(&self, thread: &mut Thread, pool: *const ConstPool, index: u16) -> Result<*const Field, LoadClassError> {
         let pool = unsafe { pool.as_ref().unwrap() };
         let field_const = pool.get_field(index);
-        let field = field_const.resolve(|field_key| {
-            let class = self.load_class(&field_key.class);
+        let field = field_const.try_resolve(|field_key| {
+            let class = self.try_load_class_in(thread, pool.loader, &field_key.class)?;
             let field = class.find_static(field_key);
-            field as *const Field
-        });
-        *field
+            self.add_field_constraint(pool.loader, field)?;
+            Ok(field as *const Field)
+        })?;
+        Ok(*field)
+    }
+
+    /// The class of a resolved field's type must be the same for the referencing loader and the
+    /// loader of the class declaring the field.
+    fn add_field_constraint(&self, loader: Reference, field: &Field) -> Result<(), LoadClassError> {
+        let declaring_class = unsafe { field.class.as_ref().unwrap() };
+        let Some(name) = field.descriptor.class_name() else {
+            return Ok(());
+        };
+        self.add_constraint(&name, loader, declaring_class.loader).map_err(|_| {
+            LoadClassError::LoaderConstraint {
+                member: format!("field {}.{}", declaring_class.name, field.name),
+                name,
+            }
+        })
     }
 
+    /// Load a class that the JVM requires, panicking if it can't be loaded.
     pub fn load_class(&self, class_name: &str) -> ClassRef {
-        if class_name.eq("[Ljava.lang.StackTraceElement;") {
-            let _b = 2;
-        }
-        let class = self.classes.load_class(class_name, |name| {
-            let class_file = self.loader.find(name).expect(class_name);
-            let pool = ConstPool::new(&class_file);
+        self.try_load_class(class_name)
+            .unwrap_or_else(|error| panic!("{}: {}", error.class_name(), error))
+    }
 
-            let super_class = if class_file.super_class == 0 { None } else {
-                let super_class = pool.get_class(class_file.super_class);
-                let super_class = super_class.resolve(|key| self.load_outer_class(&key.name));
-                Some(super_class.obj())
+    /// Load an object class, and its super classes and interfaces, using the bootstrap loader.
+    ///
+    /// The bootstrap loader loads the Java platform from the boot class path, and the
+    /// application from the class path.
+    ///
+    /// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.3.1).
+    pub fn try_load_class(&self, class_name: &str) -> Result<ClassRef, LoadClassError> {
+        let class = self.classes.load_class(Reference(0), class_name, |name| {
+            let error = |error| LoadClassError::new(name, error);
+            let (class_file, source, is_boot) = match self.boot_loader.find_with_source(name).map_err(error)? {
+                Some((class_file, source)) => (class_file, source, true),
+                None => match self.loader.find_with_source(name).map_err(error)? {
+                    Some((class_file, source)) => (class_file, source, false),
+                    None => return Err(LoadClassError::NotFound(name.to_string())),
+                },
             };
 
-            let interfaces: Vec<ClassRef> = class_file.interfaces.iter().map(|index| {
-                let name = pool.get_class(*index);
-                let class = name.resolve(|key| self.load_outer_class(&key.name));
-                class.obj()
-            }).collect();
+            let class = self.create_class(name, &class_file, Reference(0), &mut |name| self.try_load_outer_class(name))?;
 
-            let mut instance_fields: Vec<Field> = class_file.fields.iter()
-                .filter(|f| (f.access_flags & ACCESS_FLAG_STATIC) == 0)
-                .map(|f| {
-                    let is_static = false;
-                    let name = class_file.get_const_utf8(f.name);
-                    let name = String::from_utf8(name.bytes.clone()).unwrap();
-                    let descriptor = class_file.get_const_utf8(f.descriptor);
-                    let descriptor = FieldType::from_descriptor(String::from_utf8(descriptor.bytes.clone()).unwrap().as_str()).unwrap();
-                    Field {
-                        class: 0 as *const ObjectClass,
-                        flags: f.access_flags,
-                        is_static,
-                        name,
-                        width: descriptor.width(),
-                        descriptor,
-                        offset: 0,
-                    }
-                }).collect();
-
-            // Sort to get a better order for object packing.
-            instance_fields.sort_by(|a, b| a.width.cmp(&b.width).reverse());
-            let mut instance_offset = super_class.map_or(0, |c| (*c).instance_width);
-            for field in &mut instance_fields {
-                field.offset = instance_offset;
-                instance_offset += field.width;
+            // Only the platform's classes are trusted, unless every class is to be verified.
+            if is_boot && self.verify != Verify::All {
+                self.linked.write().insert((Reference(0), name.to_string()), Ok(()));
+            } else if let Err(error) = verifier::check_version(name, &class_file) {
+                self.linked.write().insert((Reference(0), name.to_string()), Err(error));
             }
 
-            let mut static_fields: Vec<Field> = class_file.fields.iter()
-                .filter(|f| (f.access_flags & ACCESS_FLAG_STATIC) != 0)
-                .map(|f| {
-                    let is_static = true;
-                    let name = class_file.get_const_utf8(f.name);
-                    let name = String::from_utf8(name.bytes.clone()).unwrap();
-                    let descriptor = class_file.get_const_utf8(f.descriptor);
-                    let descriptor = FieldType::from_descriptor(String::from_utf8(descriptor.bytes.clone()).unwrap().as_str()).unwrap();
-                    Field {
-                        class: 0 as *const ObjectClass,
-                        flags: f.access_flags,
-                        is_static,
-                        name,
-                        width: descriptor.width(),
-                        descriptor,
-                        offset: 0,
-                    }
-                }).collect();
-
-            // Sort to get a better order for object packing.
-            static_fields.sort_by(|a, b| a.width.cmp(&b.width).reverse());
-            let mut static_offset = 0; // parent fields arent included.
-            for field in &mut static_fields {
-                field.offset = static_offset;
-                static_offset += field.width;
+            if self.verbose {
+                println!("[Loaded {} from {}]", name, source);
+            }
+            if is_boot {
+                self.boot_classes.write().insert(name.to_string());
             }
+            Ok(class)
+        })?;
+        class.self_referential();
+        if !class.static_fields.is_empty() {
+            let heap = unsafe { self.heap.as_ref().unwrap() };
+            heap.get_static(&*class);
+        }
+        Ok(class)
+    }
 
-            const ALIGN: usize = 4;
-
-            // Get our final padded width.
-            let instance_pad = ALIGN - (instance_offset % ALIGN);
-            let instance_width = instance_offset + instance_pad;
-
-            let static_pad = ALIGN - (static_offset % ALIGN);
-            let static_width = static_offset + static_pad;
-
-            let methods: Vec<Method> = class_file.methods.iter()
-                .map(|m| {
-                    let is_static = (m.access_flags & ACCESS_FLAG_STATIC) != 0;
-                    let is_native = (m.access_flags & ACCESS_FLAG_NATIVE) != 0;
-                    let is_synchronized = (m.access_flags & METHOD_ACC_SYNC) != 0;
-                    let name = class_file.get_const_utf8(m.name);
-                    let name = String::from_utf8(name.bytes.clone()).unwrap();
-
-                    let descriptor = class_file.get_const_utf8(m.descriptor);
-                    let descriptor = MethodType::from_descriptor(String::from_utf8(descriptor.bytes.clone()).unwrap().as_str()).unwrap();
-                    Method {
-                        class: 0 as *const ObjectClass,
-                        flags: m.access_flags,
-                        is_static,
-                        is_native,
-                        is_synchronized,
-                        name,
-                        descriptor,
-                        code: m.code().map(|c| c.clone()),
-                    }
-                }).collect();
-
-            let source_file = class_file.attributes.iter()
-                .find_map(|attr| {
-                    match attr {
-                        ClassAttribute::SourceFile(source_file) => {
-                            let file_name = class_file.get_const_utf8(source_file.source_file);
-                            let file_name = String::from_utf8(file_name.bytes.clone()).unwrap();
-                            Some(file_name)
-                        }
-                        _ => None
-                    }
-                });
+    /// Load an object class using the given loader, a user defined loader is asked to load the
+    /// class, unless it has already loaded it.
+    ///
+    /// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.3.2).
+    pub fn try_load_class_in(&self, thread: &mut Thread, loader: Reference, class_name: &str) -> Result<ClassRef, LoadClassError> {
+        if loader.0 == 0 {
+            return self.try_load_class(class_name);
+        }
+        if let Some(class) = self.classes.find(loader, class_name) {
+            return Ok(class);
+        }
+
+        let heap = unsafe { self.heap.as_ref().unwrap() };
+        let loader_object = heap.get_object(loader);
+        let loader_class = loader_object.class();
+
+        let load_class = loader_class.find_method(&MethodKey {
+            class: loader_class.name.clone(),
+            name: "loadClass".to_string(),
+            descriptor: MethodType::from_descriptor("(Ljava/lang/String;)Ljava/lang/Class;").unwrap(),
+        }).unwrap();
+        let name = self.load_string(class_name);
+        let (class_object, ex) = thread.native_invoke(load_class.class, load_class as *const Method,
+                                                      vec![Value::Reference(loader), Value::Reference(name)]);
+
+        // Like HotSpot, an exception thrown by the loader is reported as the class not being found.
+        let class_object = match (class_object, ex) {
+            (Some(Value::Reference(class_object)), None) if class_object.0 != 0 => class_object,
+            _ => return Err(LoadClassError::NotFound(class_name.to_string())),
+        };
+        let class = match self.class_of(class_object) {
+            Class::Object(class) if class.name == class_name => class,
+            class => return Err(LoadClassError::WrongName {
+                name: class_name.to_string(),
+                actual: class.name().replace('.', "/"),
+            }),
+        };
+
+        self.check_constraints(class_name, loader, Some(class))?;
+        Ok(self.classes.initiate(loader, class))
+    }
+
+    /// Define a class from the bytes of a class file, for a user defined loader.
+    ///
+    /// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.3.5).
+    pub fn define_class(&self, thread: &mut Thread, loader: Reference, name: Option<&str>, bytes: &[u8], source: Option<&str>) -> Result<ClassRef, LoadClassError> {
+        let class_file = parse(&mut Cursor::new(bytes))
+            .map_err(|error| LoadClassError::new(name.unwrap_or("<Unknown>"), error))?;
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => {
+                let this_class = class_file.get_const_class(class_file.this_class);
+                String::from_utf8(class_file.get_const_utf8(this_class.name).bytes.clone()).unwrap().replace('/', ".")
+            }
+        };
+
+        if self.classes.find(loader, &name).is_some() {
+            return Err(LoadClassError::DuplicateDefinition { name });
+        }
+        self.check_constraints(&name, loader, None)?;
+
+        let class = self.create_class(&name, &class_file, loader, &mut |name| self.try_load_outer_class_in(thread, loader, name))?;
+        let class = self.classes.define(loader, class)
+            .ok_or_else(|| LoadClassError::DuplicateDefinition { name: name.clone() })?;
+
+        if let Err(error) = verifier::check_version(&name, &class_file) {
+            self.linked.write().insert((loader, name.clone()), Err(error));
+        }
+        if self.verbose {
+            println!("[Loaded {} from {}]", name, source.unwrap_or("__JVM_DefineClass__"));
+        }
 
-            let class = ObjectClass {
-                name: name.to_string(),
-                flags: ClassFlags { bits: class_file.access_flags },
-                const_pool: pool,
-                super_class,
-                interfaces,
-                instance_fields,
-                static_fields,
-                methods,
-                attributes: vec![], // TODO: Implement,
-                instance_width,
-                static_width,
-                source_file,
-            };
-            debug!(target: log::LOADER, class=name, "Loaded class");
-            class
-        });
         class.self_referential();
         if !class.static_fields.is_empty() {
             let heap = unsafe { self.heap.as_ref().unwrap() };
-            heap.get_static(&*class);
+            heap.get_static(&class);
         }
-        class
+        Ok(class)
+    }
+
+    /// Find a class that the loader has either defined, or loaded by delegating to another loader.
+    pub fn find_loaded_class(&self, loader: Reference, class_name: &str) -> Option<ClassRef> {
+        self.classes.find(loader, class_name)
+    }
+
+    /// Create a class from its class file, using the given function to load its super class and
+    /// interfaces.
+    fn create_class(&self, name: &str, class_file: &ClassFile, loader: Reference, load: &mut dyn FnMut(&str) -> Result<Class, LoadClassError>) -> Result<ObjectClass, LoadClassError> {
+        let this_class = class_file.get_const_class(class_file.this_class);
+        let this_class = String::from_utf8(class_file.get_const_utf8(this_class.name).bytes.clone()).unwrap();
+        if this_class.replace('/', ".") != name {
+            return Err(LoadClassError::WrongName { name: name.to_string(), actual: this_class });
+        }
+
+        let pool = ConstPool::new(class_file, loader);
+
+        let super_class = if class_file.super_class == 0 { None } else {
+            let super_class = pool.get_class(class_file.super_class);
+            let super_class = super_class.try_resolve(|key| load(&key.name))?;
+            Some(super_class.obj())
+        };
+
+        let interfaces = class_file.interfaces.iter().map(|index| {
+            let name = pool.get_class(*index);
+            let class = name.try_resolve(|key| load(&key.name))?;
+            Ok(class.obj())
+        }).collect::<Result<Vec<ClassRef>, LoadClassError>>()?;
+        let mut instance_fields: Vec<Field> = class_file.fields.iter()
+            .filter(|f| (f.access_flags & ACCESS_FLAG_STATIC) == 0)
+            .map(|f| {
+                let is_static = false;
+                let name = class_file.get_const_utf8(f.name);
+                let name = String::from_utf8(name.bytes.clone()).unwrap();
+                let descriptor = class_file.get_const_utf8(f.descriptor);
+                let descriptor = FieldType::from_descriptor(String::from_utf8(descriptor.bytes.clone()).unwrap().as_str()).unwrap();
+                Field {
+                    class: 0 as *const ObjectClass,
+                    flags: f.access_flags,
+                    is_static,
+                    name,
+                    width: descriptor.width(),
+                    descriptor,
+                    offset: 0,
+                }
+            }).collect();
+
+        // Sort to get a better order for object packing.
+        instance_fields.sort_by(|a, b| a.width.cmp(&b.width).reverse());
+        let mut instance_offset = super_class.map_or(0, |c| (*c).instance_width);
+        for field in &mut instance_fields {
+            field.offset = instance_offset;
+            instance_offset += field.width;
+        }
+
+        let mut static_fields: Vec<Field> = class_file.fields.iter()
+            .filter(|f| (f.access_flags & ACCESS_FLAG_STATIC) != 0)
+            .map(|f| {
+                let is_static = true;
+                let name = class_file.get_const_utf8(f.name);
+                let name = String::from_utf8(name.bytes.clone()).unwrap();
+                let descriptor = class_file.get_const_utf8(f.descriptor);
+                let descriptor = FieldType::from_descriptor(String::from_utf8(descriptor.bytes.clone()).unwrap().as_str()).unwrap();
+                Field {
+                    class: 0 as *const ObjectClass,
+                    flags: f.access_flags,
+                    is_static,
+                    name,
+                    width: descriptor.width(),
+                    descriptor,
+                    offset: 0,
+                }
+            }).collect();
+
+        // Sort to get a better order for object packing.
+        static_fields.sort_by(|a, b| a.width.cmp(&b.width).reverse());
+        let mut static_offset = 0; // parent fields arent included.
+        for field in &mut static_fields {
+            field.offset = static_offset;
+            static_offset += field.width;
+        }
+
+        const ALIGN: usize = 4;
+
+        // Get our final padded width.
+        let instance_pad = ALIGN - (instance_offset % ALIGN);
+        let instance_width = instance_offset + instance_pad;
+
+        let static_pad = ALIGN - (static_offset % ALIGN);
+        let static_width = static_offset + static_pad;
+
+        let methods: Vec<Method> = class_file.methods.iter()
+            .map(|m| {
+                let is_static = (m.access_flags & ACCESS_FLAG_STATIC) != 0;
+                let is_native = (m.access_flags & ACCESS_FLAG_NATIVE) != 0;
+                let is_synchronized = (m.access_flags & METHOD_ACC_SYNC) != 0;
+                let name = class_file.get_const_utf8(m.name);
+                let name = String::from_utf8(name.bytes.clone()).unwrap();
+
+                let descriptor = class_file.get_const_utf8(m.descriptor);
+                let descriptor = MethodType::from_descriptor(String::from_utf8(descriptor.bytes.clone()).unwrap().as_str()).unwrap();
+                Method {
+                    class: 0 as *const ObjectClass,
+                    flags: m.access_flags,
+                    is_static,
+                    is_native,
+                    is_synchronized,
+                    name,
+                    descriptor,
+                    code: m.code().map(|c| c.clone()),
+                }
+            }).collect();
+
+        // The finalizer of `java.lang.Object` does nothing, and neither does any other that just
+        // returns, so the objects of the classes with those needn't be finalized.
+        let finalize = methods.iter()
+            .find(|method| !method.is_static && method.name == "finalize" && method.descriptor.descriptor() == "()V");
+        let has_finalizer = match finalize {
+            Some(finalize) => finalize.code.as_ref().is_none_or(|code| code.code != [0xB1]),
+            None => super_class.as_ref().is_some_and(|class| class.has_finalizer),
+        };
+
+        let source_file = class_file.attributes.iter()
+            .find_map(|attr| {
+                match attr {
+                    ClassAttribute::SourceFile(source_file) => {
+                        let file_name = class_file.get_const_utf8(source_file.source_file);
+                        let file_name = String::from_utf8(file_name.bytes.clone()).unwrap();
+                        Some(file_name)
+                    }
+                    _ => None
+                }
+            });
+
+        let class = ObjectClass {
+            name: name.to_string(),
+            flags: ClassFlags { bits: class_file.access_flags },
+            const_pool: pool,
+            super_class,
+            interfaces,
+            instance_fields,
+            static_fields,
+            methods,
+            attributes: vec![], // TODO: Implement,
+            instance_width,
+            static_width,
+            source_file,
+            loader,
+            reference_type: ReferenceType::of(name, loader, super_class.as_ref()),
+            has_finalizer,
+        };
+        debug!(target: log::LOADER, class=name, "Loaded class");
+        Ok(class)
+    }
+
+    /// Whether the class was loaded from the boot class path, and is part of the Java platform.
+    pub fn is_boot_class(&self, class_name: &str) -> bool {
+        self.boot_classes.read().contains(class_name)
     }
 
     pub fn load_string(&self, string: &str) -> Reference {
@@ -441,21 +700,179 @@ impl MethodArea {
         heap.insert_class_object(class, &*class_class, &*string_class)
     }
 
-    pub fn initialize(&self, thread: &mut Thread, class: &ObjectClass) {
+    /// The class represented by a `java.lang.Class` object, which has already been loaded.
+    pub fn class_of(&self, class_object: Reference) -> Class {
+        let heap = unsafe { self.heap.as_ref().unwrap() };
+        let class_object = heap.get_object(class_object);
+        let name = class_object.get_string("name", heap);
+        let loader = class_object.get_field(&FieldKey {
+            class: "java.lang.Class".to_string(),
+            name: "classLoader".to_string(),
+            descriptor: FieldType::from_descriptor("Ljava/lang/ClassLoader;").unwrap(),
+        }).reference();
+
+        self.outer_class(&name, &mut |name| match loader.0 {
+            0 => self.try_load_class(name),
+            _ => self.classes.find(loader, name).ok_or_else(|| LoadClassError::NotFound(name.to_string())),
+        }).unwrap_or_else(|error| panic!("{}: {}", error.class_name(), error))
+    }
+
+    /// Every class that is loaded, by any loader.
+    pub fn loaded_classes(&self) -> HashSet<ClassRef> {
+        self.classes.all()
+    }
+
+    /// The loaders that each user defined loader keeps alive, the defining loaders of the classes
+    /// it has loaded by delegating to them.
+    pub fn loader_references(&self) -> HashMap<Reference, Vec<Reference>> {
+        self.classes.user_loaders().into_iter()
+            .map(|(loader, classes)| {
+                let references = classes.iter()
+                    .map(|class| class.loader)
+                    .filter(|other| other.0 != 0 && *other != loader)
+                    .collect();
+                (loader, references)
+            })
+            .collect()
+    }
+
+    /// Unload the classes of every user defined loader that is no longer alive.
+    ///
+    /// For further information, see [the spec](https://docs.oracle.com/javase/specs/jls/se8/html/jls-12.html#jls-12.7).
+    pub fn unload(&self, live: &HashSet<u32, BuildNoHashHasher<u32>>) {
+        let heap = unsafe { self.heap.as_ref().unwrap() };
+        let dead: HashSet<Reference> = self.classes.user_loaders().into_keys()
+            .filter(|loader| !live.contains(&loader.0))
+            .collect();
+        if dead.is_empty() {
+            return;
+        }
+
+        self.linked.write().retain(|(loader, _), _| !dead.contains(loader));
+        self.constraints.write().retain_mut(|constraint| {
+            constraint.loaders.retain(|loader| !dead.contains(loader));
+            constraint.loaders.len() > 1
+        });
+
+        for loader in &dead {
+            heap.unload(*loader);
+            for class in self.classes.unload(*loader) {
+                debug!(target: log::LOADER, class=class.name.as_str(), loader=loader.0, "Unloading class");
+                if self.verbose {
+                    println!("[Unloading class {} {:#018x}]", class.name, Arc::as_ptr(&class) as usize);
+                }
+            }
+        }
+    }
+
+    /// Add the loading constraint that both loaders load the same class for the name, failing if
+    /// they have already loaded different classes.
+    ///
+    /// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.3.4).
+    fn add_constraint(&self, name: &str, first: Reference, second: Reference) -> Result<(), ()> {
+        if first == second {
+            return Ok(());
+        }
+        let mut constraints = self.constraints.write();
+
+        // Constraints are transitive, so any existing constraints on either loader are merged.
+        let mut loaders = HashSet::from([first, second]);
+        constraints.retain(|constraint| {
+            let is_related = constraint.name == name &&
+                (constraint.loaders.contains(&first) || constraint.loaders.contains(&second));
+            if is_related {
+                loaders.extend(constraint.loaders.iter());
+            }
+            !is_related
+        });
+
+        let classes: HashSet<ClassRef> = loaders.iter()
+            .filter_map(|loader| self.classes.find(*loader, name))
+            .collect();
+        let is_violated = classes.len() > 1;
+        if is_violated {
+            // Keep the original constraints, the new constraint is never added.
+            loaders.remove(&first);
+            loaders.remove(&second);
+        }
+        constraints.push(LoaderConstraint { name: name.to_string(), loaders });
+        if is_violated { Err(()) } else { Ok(()) }
+    }
+
+    /// Check that the loader loading the class, or defining a new class if `None`, doesn't
+    /// violate any loading constraints.
+    fn check_constraints(&self, name: &str, loader: Reference, class: Option<ClassRef>) -> Result<(), LoadClassError> {
+        let constraints = self.constraints.read();
+        let Some(constraint) = constraints.iter()
+            .find(|constraint| constraint.name == name && constraint.loaders.contains(&loader)) else {
+            return Ok(());
+        };
+        let is_violated = constraint.loaders.iter()
+            .filter_map(|other| self.classes.find(*other, name))
+            .any(|other| Some(other) != class);
+        if is_violated {
+            return Err(LoadClassError::LoaderConstraint {
+                member: format!("class {}", name),
+                name: name.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Link a loaded class, its super classes and its interfaces, verifying their bytecode unless
+    /// they are trusted.
+    ///
+    /// Verifying a class may load other classes using its loader, in the given thread.
+    ///
+    /// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.4).
+    pub fn link(&self, thread: &mut Thread, class: &ObjectClass) -> Result<(), VerifyError> {
+        if self.verify == Verify::None {
+            return Ok(());
+        }
+        if let Some(parent) = &class.super_class {
+            self.link(thread, parent)?;
+        }
+        for interface in &class.interfaces {
+            self.link(thread, interface)?;
+        }
+        let key = (class.loader, class.name.clone());
+        if let Some(result) = self.linked.read().get(&key) {
+            return result.clone();
+        }
+
+        // Verifying may load other classes, so we can't hold the lock, another thread might
+        // verify the same class at the same time, but the result is the same.
+        let thread = RefCell::new(thread);
+        let result = verifier::verify(class, &|name| {
+            self.try_load_class_in(&mut thread.borrow_mut(), class.loader, name)
+        });
+        debug!(target: log::LOADER, class=class.name.as_str(), verified=result.is_ok(), "Verified class");
+        self.linked.write().entry(key).or_insert(result).clone()
+    }
+
+    /// Initialize the class, linking it first if required.
+    ///
+    /// If the class fails verification, the `java.lang.VerifyError` to throw is returned.
+    pub fn initialize(&self, thread: &mut Thread, class: &ObjectClass) -> Result<(), Value> {
+        if let Err(error) = self.link(thread, class) {
+            return Err(thread.new_throwable("java.lang.VerifyError", &error.to_string()));
+        }
+
         let already_init = thread.stack.iter().any(|f| {
             if f.method == 0 as *const Method {
                 return false;
             }
             let method = unsafe { f.method.as_ref().unwrap() };
-            method.name.eq("<clinit>") && f.class.eq(&class.name)
+            method.name.eq("<clinit>") && ptr::eq(method.class, class)
         });
         if already_init {
-            return;
+            return Ok(());
         }
 
-        self.classes.initialize(&class.name, |_| {
+        self.classes.initialize(class.loader, &class.name, |_| {
             if let Some(parent) = &class.super_class {
-                self.initialize(thread, &parent);
+                // Our super classes have already been linked.
+                let _ = self.initialize(thread, parent);
             }
             if let Some(clinit) = class.methods.iter().find(|m| m.name.eq("<clinit>")) {
                 let depth = thread.stack.len();
@@ -470,6 +887,102 @@ impl MethodArea {
                 }
             }
         });
+        Ok(())
+    }
+}
+
+/// A loading constraint, that every loader loads the same class for the name.
+///
+/// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.3.4).
+struct LoaderConstraint {
+    name: String,
+    loaders: HashSet<Reference>,
+}
+
+/// An error loading a class, thrown as a subclass of `java.lang.LinkageError`.
+///
+/// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-5.html#jvms-5.3.5).
+#[derive(Clone, Debug)]
+pub enum LoadClassError {
+    /// No class file could be found for the class.
+    NotFound(String),
+    /// The class file found doesn't declare the class.
+    WrongName { name: String, actual: String },
+    /// The class file is malformed.
+    Format { name: String, message: String },
+    /// The class file's version isn't supported.
+    UnsupportedVersion { name: String, major: u16, minor: u16 },
+    /// The loader has already defined or loaded a class with the same name.
+    DuplicateDefinition { name: String },
+    /// Resolving the member would let two loaders use different classes for the same name.
+    LoaderConstraint { member: String, name: String },
+    /// The bootstrap method of a dynamic call site can't link it.
+    Bootstrap { call_site: String, message: String },
+    /// The class declaring the member failed verification.
+    Verify(VerifyError),
+}
+
+impl LoadClassError {
+    fn new(name: &str, error: LoadError) -> Self {
+        match error {
+            LoadError::Format(error) => LoadClassError::Format {
+                name: name.to_string(),
+                message: error.to_string(),
+            },
+            LoadError::UnsupportedVersion { major, minor } => LoadClassError::UnsupportedVersion {
+                name: name.to_string(),
+                major,
+                minor,
+            },
+        }
+    }
+
+    /// The name of the error class to throw.
+    pub fn class_name(&self) -> &'static str {
+        match self {
+            LoadClassError::NotFound(_) | LoadClassError::WrongName { .. } => "java.lang.NoClassDefFoundError",
+            LoadClassError::Format { .. } => "java.lang.ClassFormatError",
+            LoadClassError::UnsupportedVersion { .. } => "java.lang.UnsupportedClassVersionError",
+            LoadClassError::DuplicateDefinition { .. } | LoadClassError::LoaderConstraint { .. } => "java.lang.LinkageError",
+            LoadClassError::Bootstrap { .. } => "java.lang.BootstrapMethodError",
+            LoadClassError::Verify(_) => "java.lang.VerifyError",
+        }
+    }
+
+    /// Create the error to throw in the given thread.
+    pub fn to_throwable(&self, thread: &mut Thread) -> Value {
+        thread.new_throwable(self.class_name(), &self.to_string())
+    }
+}
+
+impl Display for LoadClassError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadClassError::NotFound(name) => {
+                write!(f, "{}", name.replace('.', "/"))
+            }
+            LoadClassError::WrongName { name, actual } => {
+                write!(f, "{} (wrong name: {})", name.replace('.', "/"), actual)
+            }
+            LoadClassError::Format { name, message } => {
+                write!(f, "{} in class file {}", message, name.replace('.', "/"))
+            }
+            LoadClassError::UnsupportedVersion { name, major, minor } => {
+                write!(f, "{} : Unsupported major.minor version {}.{}", name.replace('.', "/"), major, minor)
+            }
+            LoadClassError::DuplicateDefinition { name } => {
+                write!(f, "attempted duplicate class definition for name: \"{}\"", name.replace('.', "/"))
+            }
+            LoadClassError::LoaderConstraint { member, name } => {
+                write!(f, "loader constraint violation: when resolving {} the class loaders have different Class objects for the type {}", member, name.replace('.', "/"))
+            }
+            LoadClassError::Bootstrap { call_site, message } => {
+                write!(f, "{} for call site {}", message, call_site)
+            }
+            LoadClassError::Verify(error) => {
+                write!(f, "{}", error)
+            }
+        }
     }
 }
 
@@ -486,6 +999,38 @@ pub struct ObjectClass {
     pub instance_width: usize,
     pub static_width: usize,
     pub source_file: Option<String>,
+    /// The loader that defined the class, `null` for the bootstrap loader.
+    pub loader: Reference,
+    /// The strength of the reference, if the class is a subclass of `java.lang.ref.Reference`
+    /// whose referent the garbage collector treats specially.
+    pub reference_type: Option<ReferenceType>,
+    /// Whether instances of the class have a finalizer to run before they're collected.
+    pub has_finalizer: bool,
+}
+
+/// The kinds of `java.lang.ref.Reference`, from the strongest to the weakest.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReferenceType {
+    Soft,
+    Weak,
+    Final,
+    Phantom,
+}
+
+impl ReferenceType {
+    /// The kind of reference that instances of a class are, given its name and loader, and the
+    /// kind of its super class.
+    fn of(name: &str, loader: Reference, super_class: Option<&ClassRef>) -> Option<ReferenceType> {
+        let reference_type = match name {
+            _ if loader.0 != 0 => None,
+            "java.lang.ref.SoftReference" => Some(ReferenceType::Soft),
+            "java.lang.ref.WeakReference" => Some(ReferenceType::Weak),
+            "java.lang.ref.FinalReference" => Some(ReferenceType::Final),
+            "java.lang.ref.PhantomReference" => Some(ReferenceType::Phantom),
+            _ => None,
+        };
+        reference_type.or_else(|| super_class.and_then(|class| class.reference_type))
+    }
 }
 
 pub struct Hierarchy {
@@ -572,11 +1117,24 @@ impl ObjectClass {
             .map(|class| class.deref() as *const ObjectClass)
             .flat_map(|class| unsafe { (*class).methods.iter() })
             .find(|mthd| mthd.name.eq(&key.name) && mthd.descriptor.eq(&key.descriptor))
+            .or_else(|| self.find_default_method(key))
+    }
+
+    /// Find a default method, a non abstract method declared in one of our interfaces.
+    fn find_default_method(&self, key: &MethodKey) -> Option<&Method> {
+        self.parents_and_interfaces().iter()
+            .filter(|class| (class.flags.bits & 0x0200) != 0)
+            .map(|class| class.deref() as *const ObjectClass)
+            .flat_map(|class| unsafe { (*class).methods.iter() })
+            .find(|mthd| {
+                (mthd.flags & 0x0400) == 0 &&
+                    mthd.name.eq(&key.name) && mthd.descriptor.eq(&key.descriptor)
+            })
     }
 
     pub fn is_instance_of(&self, other: &ObjectClass) -> bool {
         self.parents_and_interfaces().iter().any(|c| {
-            c.name.eq(&other.name)
+            c.name.eq(&other.name) && c.loader == other.loader
         })
     }
 }
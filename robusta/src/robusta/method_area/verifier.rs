@@ -0,0 +1,1274 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::class_file::{ClassFile, Code, StackMapFrame, VerificationType};
+use crate::collection::classes::ClassRef;
+use crate::java::{FieldType, MethodType};
+use crate::method_area::{LoadClassError, Method, ObjectClass};
+use crate::method_area::const_pool::{CallSiteKey, Const, FieldKey, MethodKey};
+
+/// Verify the bytecode of every method in a class by type checking, using the `StackMapTable`
+/// attribute of each method.
+///
+/// Verifying a class may load other classes with `load`, to check that one reference type is
+/// assignable to another, but never links or initializes them.
+///
+/// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.10.1).
+pub fn verify(class: &ObjectClass, load: &Load) -> Result<()> {
+    for method in &class.methods {
+        if let Some(code) = &method.code {
+            MethodVerifier::new(load, class, method, code).verify()?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that a class file is new enough to have the stack map frames that type checking needs.
+///
+/// Class files older than Java 6 would need verifying by type inference, which isn't supported.
+pub fn check_version(class_name: &str, class_file: &ClassFile) -> Result<()> {
+    if class_file.major_version < 50 {
+        return Err(VerifyError {
+            class: class_name.to_string(),
+            location: None,
+            reason: format!("Verifying class file version {}.{} by type inference is not supported",
+                            class_file.major_version, class_file.minor_version),
+        });
+    }
+    Ok(())
+}
+
+/// The reason a class failed verification, thrown as a `java.lang.VerifyError`.
+#[derive(Clone, Debug)]
+pub struct VerifyError {
+    class: String,
+    /// The method and offset of the instruction that failed verification, unless the class as a
+    /// whole did.
+    location: Option<(String, usize)>,
+    reason: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some((method, pc)) => write!(f, "{} in method {}.{} at offset {}", self.reason, self.class, method, pc),
+            None => write!(f, "{} in class {}", self.reason, self.class),
+        }
+    }
+}
+
+/// The verification type of a local variable or operand stack value.
+///
+/// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-4.html#jvms-4.10.1.2).
+#[derive(Clone, Debug, PartialEq)]
+enum Type {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    UninitializedThis,
+    /// An object created by the `new` instruction at the given offset, that is not yet initialized.
+    Uninitialized(usize),
+    /// An initialized class or array, named as in the constant pool.
+    Reference(String),
+}
+
+impl Type {
+    fn from_field_type(field_type: &FieldType) -> Type {
+        match field_type {
+            FieldType::Boolean | FieldType::Byte | FieldType::Char | FieldType::Short | FieldType::Int => Type::Integer,
+            FieldType::Float => Type::Float,
+            FieldType::Long => Type::Long,
+            FieldType::Double => Type::Double,
+            FieldType::Reference(name) => Type::Reference(name.clone()),
+            FieldType::Array(_) => Type::Reference(field_type.descriptor().replace('/', ".")),
+        }
+    }
+
+    fn object() -> Type {
+        Type::Reference("java.lang.Object".to_string())
+    }
+
+    fn category(&self) -> usize {
+        match self {
+            Type::Long | Type::Double => 2,
+            _ => 1,
+        }
+    }
+
+    fn is_reference(&self) -> bool {
+        matches!(self, Type::Null | Type::UninitializedThis | Type::Uninitialized(_) | Type::Reference(_))
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Top => f.write_str("top"),
+            Type::Integer => f.write_str("integer"),
+            Type::Float => f.write_str("float"),
+            Type::Long => f.write_str("long"),
+            Type::Double => f.write_str("double"),
+            Type::Null => f.write_str("null"),
+            Type::UninitializedThis => f.write_str("uninitializedThis"),
+            Type::Uninitialized(offset) => write!(f, "uninitialized({})", offset),
+            Type::Reference(name) => write!(f, "'{}'", name),
+        }
+    }
+}
+
+/// The types of the local variables and operand stack at an instruction.
+///
+/// Like the operand stack of a running frame, a `long` or `double` is a single entry on the
+/// stack, but takes up two local variables, the second of which is `Top`.
+#[derive(Clone, Debug)]
+struct Frame {
+    locals: Vec<Type>,
+    stack: Vec<Type>,
+}
+
+impl Frame {
+    /// An instance initializer has not yet called `super()` or `this()`.
+    fn is_this_uninit(&self) -> bool {
+        self.locals.contains(&Type::UninitializedThis)
+    }
+
+    /// Replace every occurrence of an uninitialized type, once it has been initialized.
+    fn initialize(&mut self, uninit: &Type, init: &Type) {
+        for value in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if value == uninit {
+                *value = init.clone();
+            }
+        }
+    }
+}
+
+/// The type checker for a single method.
+struct MethodVerifier<'a> {
+    /// Loads classes using the loader of the class being verified.
+    load: &'a Load<'a>,
+    class: &'a ObjectClass,
+    method: &'a Method,
+    code: &'a Code,
+    /// The offsets of every instruction in the method, in order.
+    instructions: Vec<usize>,
+    /// The frames given by the `StackMapTable`, keyed by offset.
+    stack_map: HashMap<usize, Frame>,
+    /// The current frame, at the instruction being verified.
+    frame: Frame,
+    pc: usize,
+}
+
+type Result<T> = std::result::Result<T, VerifyError>;
+
+/// Load a class by name, for the verifier.
+pub type Load<'a> = dyn Fn(&str) -> std::result::Result<ClassRef, LoadClassError> + 'a;
+
+impl<'a> MethodVerifier<'a> {
+    fn new(load: &'a Load<'a>, class: &'a ObjectClass, method: &'a Method, code: &'a Code) -> Self {
+        MethodVerifier {
+            load,
+            class,
+            method,
+            code,
+            instructions: vec![],
+            stack_map: HashMap::new(),
+            frame: Frame { locals: vec![], stack: vec![] },
+            pc: 0,
+        }
+    }
+
+    fn error<T>(&self, reason: impl Into<String>) -> Result<T> {
+        Err(VerifyError {
+            class: self.class.name.clone(),
+            location: Some((format!("{}{}", self.method.name, self.method.descriptor.descriptor()), self.pc)),
+            reason: reason.into(),
+        })
+    }
+
+    fn verify(mut self) -> Result<()> {
+        if self.code.code.is_empty() {
+            return self.error("Code attribute has no instructions");
+        }
+
+        let mut pc = 0;
+        while pc < self.code.code.len() {
+            self.pc = pc;
+            match instruction_length(&self.code.code, pc) {
+                Some(length) => {
+                    self.instructions.push(pc);
+                    pc += length;
+                }
+                None => return self.error(format!("Illegal instruction 0x{:02X}", self.code.code[pc])),
+            }
+        }
+
+        let initial = self.initial_frame()?;
+        self.stack_map = self.stack_map()?;
+        self.verify_handlers()?;
+
+        let mut next = Some(initial);
+        for idx in 0..self.instructions.len() {
+            self.pc = self.instructions[idx];
+            let frame = match (next, self.stack_map.get(&self.pc)) {
+                (Some(frame), Some(stack_map)) => {
+                    if !self.is_frame_assignable(&frame, stack_map) {
+                        return self.error("Instruction type does not match stack map");
+                    }
+                    stack_map.clone()
+                }
+                (None, Some(stack_map)) => stack_map.clone(),
+                (Some(frame), None) => frame,
+                (None, None) => return self.error("Expecting a stack map frame"),
+            };
+            self.check_handlers(&frame)?;
+            self.frame = frame;
+            next = if self.execute()? { Some(self.frame.clone()) } else { None };
+        }
+
+        if next.is_some() {
+            return self.error("Falling off the end of the code");
+        }
+        Ok(())
+    }
+
+    /// The frame at the start of the method, with the method's arguments in its locals.
+    fn initial_frame(&self) -> Result<Frame> {
+        Ok(Frame { locals: self.expand_locals(&self.initial_locals())?, stack: vec![] })
+    }
+
+    /// The method's arguments, where `long` and `double` are a single entry.
+    fn initial_locals(&self) -> Vec<Type> {
+        let mut locals = vec![];
+        if !self.method.is_static {
+            let is_init = self.method.name.eq("<init>") && !self.class.name.eq("java.lang.Object");
+            locals.push(if is_init { Type::UninitializedThis } else { Type::Reference(self.class.name.clone()) });
+        }
+        locals.extend(self.method.descriptor.parameters.iter().map(Type::from_field_type));
+        locals
+    }
+
+    /// Decode the `StackMapTable` into complete frames.
+    fn stack_map(&self) -> Result<HashMap<usize, Frame>> {
+        let mut frames = HashMap::new();
+        let table = match self.code.stack_map_table() {
+            Some(table) => table,
+            None => return Ok(frames),
+        };
+
+        let mut locals = self.initial_locals();
+        let mut previous: Option<usize> = None;
+        for entry in &table.entries {
+            let delta = entry.offset_delta() as usize;
+            let offset = previous.map_or(delta, |previous| previous + delta + 1);
+            let stack = match entry {
+                StackMapFrame::Same { .. } => vec![],
+                StackMapFrame::SameLocals1StackItem { stack, .. } => vec![self.verification_type(stack)?],
+                StackMapFrame::Chop { k, .. } => {
+                    let k = *k as usize;
+                    if k > locals.len() {
+                        return self.error(format!("Stack map frame at offset {} removes too many locals", offset));
+                    }
+                    locals.truncate(locals.len() - k);
+                    vec![]
+                }
+                StackMapFrame::Append { locals: appended, .. } => {
+                    for local in appended {
+                        locals.push(self.verification_type(local)?);
+                    }
+                    vec![]
+                }
+                StackMapFrame::Full { locals: full, stack, .. } => {
+                    locals = full.iter()
+                        .map(|local| self.verification_type(local))
+                        .collect::<Result<Vec<Type>>>()?;
+                    stack.iter()
+                        .map(|value| self.verification_type(value))
+                        .collect::<Result<Vec<Type>>>()?
+                }
+            };
+
+            if self.instructions.binary_search(&offset).is_err() {
+                return self.error(format!("Stack map frame at offset {} is not at an instruction", offset));
+            }
+            let frame = Frame { locals: self.expand_locals(&locals)?, stack };
+            if stack_size(&frame.stack) > self.code.max_stack as usize {
+                return self.error(format!("Stack map frame at offset {} exceeds the max stack size", offset));
+            }
+            frames.insert(offset, frame);
+            previous = Some(offset);
+        }
+
+        Ok(frames)
+    }
+
+    /// Expand a list of locals, where `long` and `double` are a single entry, into local variables.
+    fn expand_locals(&self, types: &[Type]) -> Result<Vec<Type>> {
+        let mut locals = vec![];
+        for local in types {
+            locals.push(local.clone());
+            if local.category() == 2 {
+                locals.push(Type::Top);
+            }
+        }
+        let max_locals = self.code.max_locals as usize;
+        if locals.len() > max_locals {
+            return self.error("Local variables exceed the max locals size");
+        }
+        locals.resize(max_locals, Type::Top);
+        Ok(locals)
+    }
+
+    fn verification_type(&self, verification_type: &VerificationType) -> Result<Type> {
+        Ok(match verification_type {
+            VerificationType::Top => Type::Top,
+            VerificationType::Integer => Type::Integer,
+            VerificationType::Float => Type::Float,
+            VerificationType::Long => Type::Long,
+            VerificationType::Double => Type::Double,
+            VerificationType::Null => Type::Null,
+            VerificationType::UninitializedThis => Type::UninitializedThis,
+            VerificationType::Object(index) => Type::Reference(self.class_name(*index)?),
+            VerificationType::Uninitialized(offset) => Type::Uninitialized(*offset as usize),
+        })
+    }
+
+    /// Check that each exception handler covers a valid range of instructions, and catches a
+    /// throwable class.
+    fn verify_handlers(&mut self) -> Result<()> {
+        let code = self.code;
+        for handler in &code.ex_table {
+            self.pc = handler.handler_pc as usize;
+            let start_pc = handler.start_pc as usize;
+            let end_pc = handler.end_pc as usize;
+            let is_instruction = |pc: usize| self.instructions.binary_search(&pc).is_ok();
+            let valid_end = end_pc == code.code.len() || is_instruction(end_pc);
+            if start_pc >= end_pc || !is_instruction(start_pc) || !valid_end {
+                return self.error("Illegal exception table range");
+            }
+            if !self.stack_map.contains_key(&self.pc) {
+                return self.error("Expecting a stack map frame at exception handler");
+            }
+            let catch_type = self.catch_type(handler.catch_type)?;
+            if !self.is_assignable(&catch_type, &Type::Reference("java.lang.Throwable".to_string())) {
+                return self.error(format!("Catch type {} is not a subclass of Throwable", catch_type));
+            }
+        }
+        Ok(())
+    }
+
+    fn catch_type(&self, index: u16) -> Result<Type> {
+        if index == 0 {
+            Ok(Type::Reference("java.lang.Throwable".to_string()))
+        } else {
+            Ok(Type::Reference(self.class_name(index)?))
+        }
+    }
+
+    /// Check that every exception handler covering the current instruction can accept the
+    /// current locals.
+    fn check_handlers(&self, frame: &Frame) -> Result<()> {
+        for handler in &self.code.ex_table {
+            if self.pc < handler.start_pc as usize || self.pc >= handler.end_pc as usize {
+                continue;
+            }
+            let handler_frame = Frame {
+                locals: frame.locals.clone(),
+                stack: vec![self.catch_type(handler.catch_type)?],
+            };
+            let stack_map = self.stack_map.get(&(handler.handler_pc as usize)).unwrap();
+            if !self.is_frame_assignable(&handler_frame, stack_map) {
+                return self.error(format!("Stack map does not match the one at exception handler {}", handler.handler_pc));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_frame_assignable(&self, from: &Frame, to: &Frame) -> bool {
+        from.stack.len() == to.stack.len() &&
+            (!from.is_this_uninit() || to.is_this_uninit()) &&
+            from.locals.iter().zip(to.locals.iter()).all(|(from, to)| self.is_assignable(from, to)) &&
+            from.stack.iter().zip(to.stack.iter()).all(|(from, to)| self.is_assignable(from, to))
+    }
+
+    fn is_assignable(&self, from: &Type, to: &Type) -> bool {
+        if from == to {
+            return true;
+        }
+        match (from, to) {
+            (_, Type::Top) => true,
+            (Type::Null, Type::Reference(_)) => true,
+            (Type::Reference(from), Type::Reference(to)) => self.is_class_assignable(from, to),
+            _ => false,
+        }
+    }
+
+    /// Interfaces are treated like `java.lang.Object`, any reference is assignable to them, and
+    /// checked when the interface method is invoked instead.
+    fn is_class_assignable(&self, from: &str, to: &str) -> bool {
+        if from.eq(to) || to.eq("java.lang.Object") {
+            return true;
+        }
+        match (from.starts_with('['), to.starts_with('[')) {
+            (true, true) => {
+                let from = FieldType::from_descriptor(from);
+                let to = FieldType::from_descriptor(to);
+                match (from, to) {
+                    (Ok(FieldType::Array(from)), Ok(FieldType::Array(to))) => {
+                        if from.is_reference() && to.is_reference() {
+                            self.is_assignable(&Type::from_field_type(&from), &Type::from_field_type(&to))
+                        } else {
+                            from == to
+                        }
+                    }
+                    _ => false,
+                }
+            }
+            (true, false) => to.eq("java.lang.Cloneable") || to.eq("java.io.Serializable"),
+            (false, true) => false,
+            (false, false) => {
+                // Classes that can't be loaded aren't assignable, failing verification.
+                let Ok(target) = (self.load)(to) else {
+                    return false;
+                };
+                if (target.flags.bits & 0x0200) != 0 {
+                    return true;
+                }
+                let Ok(source) = (self.load)(from) else {
+                    return false;
+                };
+                source.parents().any(|class| class.name.eq(to))
+            }
+        }
+    }
+
+    /// Type check the current instruction, updating the current frame, and checking the frames
+    /// at any branch targets.
+    ///
+    /// Returns whether execution can continue to the next instruction.
+    fn execute(&mut self) -> Result<bool> {
+        let pc = self.pc;
+        let opcode = self.code.code[pc];
+        match opcode {
+            0x00 => {}
+            0x01 => self.push(Type::Null)?,
+            0x02..=0x08 | 0x10 | 0x11 => self.push(Type::Integer)?,
+            0x09 | 0x0A => self.push(Type::Long)?,
+            0x0B..=0x0D => self.push(Type::Float)?,
+            0x0E | 0x0F => self.push(Type::Double)?,
+            0x12 => self.load_constant(self.read_u8(pc + 1) as u16, 1)?,
+            0x13 => self.load_constant(self.read_u16(pc + 1), 1)?,
+            0x14 => self.load_constant(self.read_u16(pc + 1), 2)?,
+            0x15..=0x19 => self.load(opcode - 0x15, self.read_u8(pc + 1) as usize)?,
+            0x1A..=0x2D => self.load((opcode - 0x1A) / 4, ((opcode - 0x1A) % 4) as usize)?,
+            0x2E => self.array_load(&["[I"], Type::Integer)?,
+            0x2F => self.array_load(&["[J"], Type::Long)?,
+            0x30 => self.array_load(&["[F"], Type::Float)?,
+            0x31 => self.array_load(&["[D"], Type::Double)?,
+            0x32 => {
+                self.pop_type(&Type::Integer)?;
+                let component = self.pop_reference_array()?;
+                self.push(component)?;
+            }
+            0x33 => self.array_load(&["[B", "[Z"], Type::Integer)?,
+            0x34 => self.array_load(&["[C"], Type::Integer)?,
+            0x35 => self.array_load(&["[S"], Type::Integer)?,
+            0x36..=0x3A => self.store(opcode - 0x36, self.read_u8(pc + 1) as usize)?,
+            0x3B..=0x4E => self.store((opcode - 0x3B) / 4, ((opcode - 0x3B) % 4) as usize)?,
+            0x4F => self.array_store(&["[I"], Type::Integer)?,
+            0x50 => self.array_store(&["[J"], Type::Long)?,
+            0x51 => self.array_store(&["[F"], Type::Float)?,
+            0x52 => self.array_store(&["[D"], Type::Double)?,
+            0x53 => {
+                self.pop_type(&Type::object())?;
+                self.pop_type(&Type::Integer)?;
+                self.pop_reference_array()?;
+            }
+            0x54 => self.array_store(&["[B", "[Z"], Type::Integer)?,
+            0x55 => self.array_store(&["[C"], Type::Integer)?,
+            0x56 => self.array_store(&["[S"], Type::Integer)?,
+            0x57 => {
+                self.pop_category_one()?;
+            }
+            0x58 => {
+                self.pop_units(2)?;
+            }
+            0x59 => {
+                let value = self.pop_category_one()?;
+                self.push(value.clone())?;
+                self.push(value)?;
+            }
+            0x5A => {
+                let value1 = self.pop_category_one()?;
+                let value2 = self.pop_category_one()?;
+                self.push_all(&[value1.clone(), value2, value1])?;
+            }
+            0x5B => {
+                let value1 = self.pop_category_one()?;
+                let under = self.pop_units(2)?;
+                self.push(value1.clone())?;
+                self.push_all(&under)?;
+                self.push(value1)?;
+            }
+            0x5C => {
+                let values = self.pop_units(2)?;
+                self.push_all(&values)?;
+                self.push_all(&values)?;
+            }
+            0x5D => {
+                let values = self.pop_units(2)?;
+                let value3 = self.pop_category_one()?;
+                self.push_all(&values)?;
+                self.push(value3)?;
+                self.push_all(&values)?;
+            }
+            0x5E => {
+                let values = self.pop_units(2)?;
+                let under = self.pop_units(2)?;
+                self.push_all(&values)?;
+                self.push_all(&under)?;
+                self.push_all(&values)?;
+            }
+            0x5F => {
+                let value1 = self.pop_category_one()?;
+                let value2 = self.pop_category_one()?;
+                self.push_all(&[value1, value2])?;
+            }
+            0x60..=0x73 => {
+                let value = numeric_type(opcode - 0x60);
+                self.pop_type(&value)?;
+                self.pop_type(&value)?;
+                self.push(value)?;
+            }
+            0x74..=0x77 => {
+                let value = numeric_type(opcode - 0x74);
+                self.pop_type(&value)?;
+                self.push(value)?;
+            }
+            0x78..=0x7D => {
+                let value = match opcode {
+                    0x78 | 0x7A | 0x7C => Type::Integer,
+                    _ => Type::Long,
+                };
+                self.pop_type(&Type::Integer)?;
+                self.pop_type(&value)?;
+                self.push(value)?;
+            }
+            0x7E..=0x83 => {
+                let value = match opcode {
+                    0x7E | 0x80 | 0x82 => Type::Integer,
+                    _ => Type::Long,
+                };
+                self.pop_type(&value)?;
+                self.pop_type(&value)?;
+                self.push(value)?;
+            }
+            0x84 => self.increment(self.read_u8(pc + 1) as usize)?,
+            0x85..=0x90 => {
+                let from = numeric_type((opcode - 0x85) / 3);
+                let to = [0, 1, 2, 3].iter()
+                    .map(|idx| numeric_type(*idx))
+                    .filter(|to| to != &from)
+                    .nth(((opcode - 0x85) % 3) as usize)
+                    .unwrap();
+                self.pop_type(&from)?;
+                self.push(to)?;
+            }
+            0x91..=0x93 => {
+                self.pop_type(&Type::Integer)?;
+                self.push(Type::Integer)?;
+            }
+            0x94..=0x98 => {
+                let value = match opcode {
+                    0x94 => Type::Long,
+                    0x95 | 0x96 => Type::Float,
+                    _ => Type::Double,
+                };
+                self.pop_type(&value)?;
+                self.pop_type(&value)?;
+                self.push(Type::Integer)?;
+            }
+            0x99..=0x9E => {
+                self.pop_type(&Type::Integer)?;
+                self.branch(self.read_i16(pc + 1) as i32)?;
+            }
+            0x9F..=0xA4 => {
+                self.pop_type(&Type::Integer)?;
+                self.pop_type(&Type::Integer)?;
+                self.branch(self.read_i16(pc + 1) as i32)?;
+            }
+            0xA5 | 0xA6 => {
+                self.pop_reference()?;
+                self.pop_reference()?;
+                self.branch(self.read_i16(pc + 1) as i32)?;
+            }
+            0xA7 => {
+                self.branch(self.read_i16(pc + 1) as i32)?;
+                return Ok(false);
+            }
+            0xA8 | 0xA9 | 0xC9 => return self.error("Subroutines are not allowed in class files verified by type checking"),
+            0xAA => {
+                self.pop_type(&Type::Integer)?;
+                let start = pc + 1 + (3 - pc % 4);
+                self.branch(self.read_i32(start))?;
+                let low = self.read_i32(start + 4) as i64;
+                let high = self.read_i32(start + 8) as i64;
+                for idx in 0..=(high - low) as usize {
+                    self.branch(self.read_i32(start + 12 + 4 * idx))?;
+                }
+                return Ok(false);
+            }
+            0xAB => {
+                self.pop_type(&Type::Integer)?;
+                let start = pc + 1 + (3 - pc % 4);
+                self.branch(self.read_i32(start))?;
+                let pairs = self.read_i32(start + 4) as usize;
+                let mut previous = None;
+                for idx in 0..pairs {
+                    let key = self.read_i32(start + 8 + 8 * idx);
+                    if previous.is_some_and(|previous| previous >= key) {
+                        return self.error("Bad lookupswitch instruction, keys are not sorted");
+                    }
+                    previous = Some(key);
+                    self.branch(self.read_i32(start + 12 + 8 * idx))?;
+                }
+                return Ok(false);
+            }
+            0xAC..=0xB0 => {
+                let returns = self.method.descriptor.returns.as_ref().map(Type::from_field_type);
+                let expected = match (opcode, returns) {
+                    (0xAC, Some(Type::Integer)) => Type::Integer,
+                    (0xAD, Some(Type::Long)) => Type::Long,
+                    (0xAE, Some(Type::Float)) => Type::Float,
+                    (0xAF, Some(Type::Double)) => Type::Double,
+                    (0xB0, Some(Type::Reference(name))) => Type::Reference(name),
+                    (_, None) => return self.error("Method does not return a value"),
+                    _ => return self.error("Wrong return type in method"),
+                };
+                self.pop_type(&expected)?;
+                return Ok(false);
+            }
+            0xB1 => {
+                if self.method.descriptor.returns.is_some() {
+                    return self.error("Method expects a return value");
+                }
+                if self.frame.is_this_uninit() {
+                    return self.error("Constructor must call super() or this() before return");
+                }
+                return Ok(false);
+            }
+            0xB2 => {
+                let field = self.field_key(self.read_u16(pc + 1))?;
+                self.push(Type::from_field_type(&field.descriptor))?;
+            }
+            0xB3 => {
+                let field = self.field_key(self.read_u16(pc + 1))?;
+                self.pop_type(&Type::from_field_type(&field.descriptor))?;
+            }
+            0xB4 => {
+                let field = self.field_key(self.read_u16(pc + 1))?;
+                self.pop_type(&Type::Reference(field.class.clone()))?;
+                self.push(Type::from_field_type(&field.descriptor))?;
+            }
+            0xB5 => {
+                let field = self.field_key(self.read_u16(pc + 1))?;
+                self.pop_type(&Type::from_field_type(&field.descriptor))?;
+                let object = self.pop()?;
+                // An instance initializer may set its own fields before calling `super()`.
+                let is_own_field = object == Type::UninitializedThis && field.class.eq(&self.class.name);
+                if !is_own_field && !self.is_assignable(&object, &Type::Reference(field.class.clone())) {
+                    return self.error(format!("Bad type on operand stack, expected '{}' but found {}", field.class, object));
+                }
+            }
+            0xB6..=0xB9 => self.invoke(opcode)?,
+            0xBA => {
+                if self.read_u16(pc + 3) != 0 {
+                    return self.error("Bad invokedynamic instruction");
+                }
+                let call_site = self.call_site_key(self.read_u16(pc + 1))?;
+                self.pop_args(&call_site.descriptor)?;
+                if let Some(returns) = &call_site.descriptor.returns {
+                    self.push(Type::from_field_type(returns))?;
+                }
+            }
+            0xBB => {
+                let name = self.class_name(self.read_u16(pc + 1))?;
+                if name.starts_with('[') {
+                    return self.error(format!("Illegal use of new with array class {}", name));
+                }
+                let uninit = Type::Uninitialized(pc);
+                if self.frame.stack.contains(&uninit) {
+                    return self.error("Uninitialized object already exists on the operand stack");
+                }
+                self.frame.initialize(&uninit, &Type::Top);
+                self.push(uninit)?;
+            }
+            0xBC => {
+                let array = match self.read_u8(pc + 1) {
+                    4 => "[Z",
+                    5 => "[C",
+                    6 => "[F",
+                    7 => "[D",
+                    8 => "[B",
+                    9 => "[S",
+                    10 => "[I",
+                    11 => "[J",
+                    _ => return self.error("Illegal newarray instruction"),
+                };
+                self.pop_type(&Type::Integer)?;
+                self.push(Type::Reference(array.to_string()))?;
+            }
+            0xBD => {
+                let name = self.class_name(self.read_u16(pc + 1))?;
+                self.pop_type(&Type::Integer)?;
+                let array = if name.starts_with('[') { format!("[{}", name) } else { format!("[L{};", name) };
+                self.push(Type::Reference(array))?;
+            }
+            0xBE => {
+                let array = self.pop()?;
+                let is_array = match &array {
+                    Type::Null => true,
+                    Type::Reference(name) => name.starts_with('['),
+                    _ => false,
+                };
+                if !is_array {
+                    return self.error(format!("Bad type on operand stack, expected an array but found {}", array));
+                }
+                self.push(Type::Integer)?;
+            }
+            0xBF => {
+                self.pop_type(&Type::Reference("java.lang.Throwable".to_string()))?;
+                return Ok(false);
+            }
+            0xC0 => {
+                let name = self.class_name(self.read_u16(pc + 1))?;
+                self.pop_type(&Type::object())?;
+                self.push(Type::Reference(name))?;
+            }
+            0xC1 => {
+                self.class_name(self.read_u16(pc + 1))?;
+                self.pop_type(&Type::object())?;
+                self.push(Type::Integer)?;
+            }
+            0xC2 | 0xC3 => {
+                self.pop_type(&Type::object())?;
+            }
+            0xC4 => {
+                let opcode = self.read_u8(pc + 1);
+                let index = self.read_u16(pc + 2) as usize;
+                match opcode {
+                    0x15..=0x19 => self.load(opcode - 0x15, index)?,
+                    0x36..=0x3A => self.store(opcode - 0x36, index)?,
+                    0x84 => self.increment(index)?,
+                    0xA9 => return self.error("Subroutines are not allowed in class files verified by type checking"),
+                    _ => return self.error(format!("Illegal wide instruction 0x{:02X}", opcode)),
+                }
+            }
+            0xC5 => {
+                let name = self.class_name(self.read_u16(pc + 1))?;
+                let dimensions = self.read_u8(pc + 3) as usize;
+                if dimensions == 0 || name.chars().take_while(|c| *c == '[').count() < dimensions {
+                    return self.error("Illegal dimensions in multianewarray instruction");
+                }
+                for _ in 0..dimensions {
+                    self.pop_type(&Type::Integer)?;
+                }
+                self.push(Type::Reference(name))?;
+            }
+            0xC6 | 0xC7 => {
+                self.pop_reference()?;
+                self.branch(self.read_i16(pc + 1) as i32)?;
+            }
+            0xC8 => {
+                self.branch(self.read_i32(pc + 1))?;
+                return Ok(false);
+            }
+            _ => return self.error(format!("Illegal instruction 0x{:02X}", opcode)),
+        }
+        Ok(true)
+    }
+
+    /// Type check `invokevirtual`, `invokespecial`, `invokestatic` and `invokeinterface`.
+    fn invoke(&mut self, opcode: u8) -> Result<()> {
+        let method = self.method_key(self.read_u16(self.pc + 1))?;
+        let is_init = method.name.eq("<init>");
+        if method.name.starts_with('<') && !(is_init && opcode == 0xB7) {
+            return self.error(format!("Illegal call to method {}", method.name));
+        }
+        if opcode == 0xB9 {
+            let count = self.read_u8(self.pc + 3) as usize;
+            let units = 1 + method.descriptor.parameters.iter()
+                .map(|param| Type::from_field_type(param).category())
+                .sum::<usize>();
+            if count != units || self.read_u8(self.pc + 4) != 0 {
+                return self.error("Inconsistent args count operand in invokeinterface");
+            }
+        }
+
+        self.pop_args(&method.descriptor)?;
+
+        match opcode {
+            0xB8 => {}
+            0xB7 if is_init => {
+                if method.descriptor.returns.is_some() {
+                    return self.error("Instance initializer must return void");
+                }
+                let object = self.pop()?;
+                let initialized = match &object {
+                    Type::UninitializedThis => Type::Reference(self.class.name.clone()),
+                    Type::Uninitialized(offset) => {
+                        let offset = *offset;
+                        let is_new = self.instructions.binary_search(&offset).is_ok() && self.code.code[offset] == 0xBB;
+                        if !is_new {
+                            return self.error(format!("Expected a new instruction at offset {}", offset));
+                        }
+                        Type::Reference(self.class_name(self.read_u16(offset + 1))?)
+                    }
+                    _ => return self.error(format!("Bad type on operand stack, expected an uninitialized object but found {}", object)),
+                };
+                self.frame.initialize(&object, &initialized);
+            }
+            0xB7 => {
+                self.pop_type(&Type::Reference(self.class.name.clone()))?;
+            }
+            _ => {
+                self.pop_type(&Type::Reference(method.class.clone()))?;
+            }
+        }
+
+        if let Some(returns) = &method.descriptor.returns {
+            self.push(Type::from_field_type(returns))?;
+        }
+        Ok(())
+    }
+
+    fn pop_args(&mut self, descriptor: &MethodType) -> Result<()> {
+        for param in descriptor.parameters.iter().rev() {
+            self.pop_type(&Type::from_field_type(param))?;
+        }
+        Ok(())
+    }
+
+    fn load_constant(&mut self, index: u16, category: usize) -> Result<()> {
+        let value = match self.class.const_pool.pool.get(&index) {
+            Some(Const::Integer(_)) => Type::Integer,
+            Some(Const::Float(_)) => Type::Float,
+            Some(Const::Long(_)) => Type::Long,
+            Some(Const::Double(_)) => Type::Double,
+            Some(Const::String(_)) => Type::Reference("java.lang.String".to_string()),
+            Some(Const::Class(_)) => Type::Reference("java.lang.Class".to_string()),
+            _ => return self.error(format!("Illegal constant at index {} in the constant pool", index)),
+        };
+        if value.category() != category {
+            return self.error(format!("Illegal constant at index {} in the constant pool", index));
+        }
+        self.push(value)
+    }
+
+    /// Load a local variable of the given kind, `0` to `4` for `int`, `long`, `float`, `double`
+    /// and reference, in the order of the load instructions.
+    fn load(&mut self, kind: u8, index: usize) -> Result<()> {
+        let value = match self.frame.locals.get(index) {
+            Some(value) => value.clone(),
+            None => return self.error(format!("Illegal local variable number {}", index)),
+        };
+        let is_valid = match kind {
+            0 => value == Type::Integer,
+            1 => value == Type::Long,
+            2 => value == Type::Float,
+            3 => value == Type::Double,
+            _ => value.is_reference(),
+        };
+        if !is_valid {
+            return self.error(format!("Bad local variable type, found {} in local {}", value, index));
+        }
+        self.push(value)
+    }
+
+    /// Store into a local variable of the given kind, as with `load`.
+    fn store(&mut self, kind: u8, index: usize) -> Result<()> {
+        let value = match kind {
+            0..=3 => self.pop_type(&numeric_type(kind))?,
+            _ => {
+                let value = self.pop()?;
+                if !value.is_reference() {
+                    return self.error(format!("Bad type on operand stack, expected a reference but found {}", value));
+                }
+                value
+            }
+        };
+        if index + value.category() > self.frame.locals.len() {
+            return self.error(format!("Illegal local variable number {}", index));
+        }
+        if index > 0 && self.frame.locals[index - 1].category() == 2 {
+            self.frame.locals[index - 1] = Type::Top;
+        }
+        if value.category() == 2 {
+            self.frame.locals[index + 1] = Type::Top;
+        }
+        self.frame.locals[index] = value;
+        Ok(())
+    }
+
+    fn increment(&mut self, index: usize) -> Result<()> {
+        match self.frame.locals.get(index) {
+            Some(Type::Integer) => Ok(()),
+            Some(value) => self.error(format!("Bad local variable type, found {} in local {}", value, index)),
+            None => self.error(format!("Illegal local variable number {}", index)),
+        }
+    }
+
+    fn array_load(&mut self, arrays: &[&str], value: Type) -> Result<()> {
+        self.pop_type(&Type::Integer)?;
+        self.pop_array(arrays)?;
+        self.push(value)
+    }
+
+    fn array_store(&mut self, arrays: &[&str], value: Type) -> Result<()> {
+        self.pop_type(&value)?;
+        self.pop_type(&Type::Integer)?;
+        self.pop_array(arrays)
+    }
+
+    fn pop_array(&mut self, arrays: &[&str]) -> Result<()> {
+        let array = self.pop()?;
+        match &array {
+            Type::Null => Ok(()),
+            Type::Reference(name) if arrays.contains(&name.as_str()) => Ok(()),
+            _ => self.error(format!("Bad type on operand stack, expected '{}' but found {}", arrays[0], array)),
+        }
+    }
+
+    /// Pop an array of references, returning the type of its components.
+    fn pop_reference_array(&mut self) -> Result<Type> {
+        let array = self.pop()?;
+        if array == Type::Null {
+            return Ok(Type::Null);
+        }
+        if let Type::Reference(name) = &array {
+            if let Ok(FieldType::Array(component)) = FieldType::from_descriptor(name) {
+                if component.is_reference() {
+                    return Ok(Type::from_field_type(&component));
+                }
+            }
+        }
+        self.error(format!("Bad type on operand stack, expected an array of references but found {}", array))
+    }
+
+    fn branch(&self, offset: i32) -> Result<()> {
+        let target = self.pc as i64 + offset as i64;
+        let stack_map = usize::try_from(target).ok().and_then(|target| self.stack_map.get(&target));
+        match stack_map {
+            Some(stack_map) if self.is_frame_assignable(&self.frame, stack_map) => Ok(()),
+            Some(_) => self.error(format!("Stack map does not match the one at branch target {}", target)),
+            None => self.error(format!("Expecting a stack map frame at branch target {}", target)),
+        }
+    }
+
+    fn push(&mut self, value: Type) -> Result<()> {
+        self.frame.stack.push(value);
+        if stack_size(&self.frame.stack) > self.code.max_stack as usize {
+            return self.error("Exceeded the max stack size");
+        }
+        Ok(())
+    }
+
+    fn push_all(&mut self, values: &[Type]) -> Result<()> {
+        for value in values {
+            self.push(value.clone())?;
+        }
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Type> {
+        match self.frame.stack.pop() {
+            Some(value) => Ok(value),
+            None => self.error("Attempt to pop empty stack"),
+        }
+    }
+
+    fn pop_type(&mut self, expected: &Type) -> Result<Type> {
+        let value = self.pop()?;
+        if !self.is_assignable(&value, expected) {
+            return self.error(format!("Bad type on operand stack, expected {} but found {}", expected, value));
+        }
+        Ok(value)
+    }
+
+    fn pop_reference(&mut self) -> Result<Type> {
+        let value = self.pop()?;
+        if !value.is_reference() {
+            return self.error(format!("Bad type on operand stack, expected a reference but found {}", value));
+        }
+        Ok(value)
+    }
+
+    fn pop_category_one(&mut self) -> Result<Type> {
+        let value = self.pop()?;
+        if value.category() != 1 {
+            return self.error(format!("Bad type on operand stack, expected a category 1 value but found {}", value));
+        }
+        Ok(value)
+    }
+
+    /// Pop values totalling the given units, without splitting a category 2 value, as with
+    /// `OperandStack::pop_units`.
+    fn pop_units(&mut self, units: usize) -> Result<Vec<Type>> {
+        let mut values = vec![];
+        let mut popped = 0;
+        while popped < units {
+            let value = self.pop()?;
+            popped += value.category();
+            values.push(value);
+        }
+        if popped != units {
+            return self.error("Bad type on operand stack, cannot split a category 2 value");
+        }
+        values.reverse();
+        Ok(values)
+    }
+
+    fn class_name(&self, index: u16) -> Result<String> {
+        match self.class.const_pool.pool.get(&index) {
+            Some(Const::Class(class)) => Ok(class.const_key.name.clone()),
+            _ => self.error(format!("Expected a class at index {} in the constant pool", index)),
+        }
+    }
+
+    fn field_key(&self, index: u16) -> Result<&'a FieldKey> {
+        match self.class.const_pool.pool.get(&index) {
+            Some(Const::Field(field)) => Ok(&field.const_key),
+            _ => self.error(format!("Expected a field at index {} in the constant pool", index)),
+        }
+    }
+
+    fn method_key(&self, index: u16) -> Result<&'a MethodKey> {
+        match self.class.const_pool.pool.get(&index) {
+            Some(Const::Method(method)) => Ok(&method.const_key),
+            _ => self.error(format!("Expected a method at index {} in the constant pool", index)),
+        }
+    }
+
+    fn call_site_key(&self, index: u16) -> Result<&'a CallSiteKey> {
+        match self.class.const_pool.pool.get(&index) {
+            Some(Const::CallSite(call_site)) => Ok(&call_site.const_key),
+            _ => self.error(format!("Expected a call site at index {} in the constant pool", index)),
+        }
+    }
+
+    fn read_u8(&self, at: usize) -> u8 {
+        self.code.code[at]
+    }
+
+    fn read_u16(&self, at: usize) -> u16 {
+        u16::from_be_bytes([self.code.code[at], self.code.code[at + 1]])
+    }
+
+    fn read_i16(&self, at: usize) -> i16 {
+        self.read_u16(at) as i16
+    }
+
+    fn read_i32(&self, at: usize) -> i32 {
+        i32::from_be_bytes([self.code.code[at], self.code.code[at + 1], self.code.code[at + 2], self.code.code[at + 3]])
+    }
+}
+
+/// The numeric type of typed instructions, in the order `int`, `long`, `float`, `double`.
+fn numeric_type(idx: u8) -> Type {
+    match idx % 4 {
+        0 => Type::Integer,
+        1 => Type::Long,
+        2 => Type::Float,
+        _ => Type::Double,
+    }
+}
+
+/// The size of the operand stack, where category 2 values take up two units.
+fn stack_size(stack: &[Type]) -> usize {
+    stack.iter().map(|value| value.category()).sum()
+}
+
+/// The length of the instruction at `pc`, or `None` if it is not a valid instruction.
+fn instruction_length(code: &[u8], pc: usize) -> Option<usize> {
+    let read_i32 = |at: usize| -> Option<i32> {
+        let bytes = code.get(at..at + 4)?;
+        Some(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+    let padding = 3 - pc % 4;
+
+    let length = match code[pc] {
+        0x10 | 0x12 | 0x15..=0x19 | 0x36..=0x3A | 0xA9 | 0xBC => 2,
+        0x11 | 0x13 | 0x14 | 0x84 | 0x99..=0xA8 | 0xB2..=0xB8 | 0xBB | 0xBD | 0xC0 | 0xC1 | 0xC6 | 0xC7 => 3,
+        0xC5 => 4,
+        0xB9 | 0xBA | 0xC8 | 0xC9 => 5,
+        0xAA => {
+            let low = read_i32(pc + 1 + padding + 4)? as i64;
+            let high = read_i32(pc + 1 + padding + 8)? as i64;
+            if high < low {
+                return None;
+            }
+            1 + padding + 12 + 4 * (high - low + 1) as usize
+        }
+        0xAB => {
+            let pairs = read_i32(pc + 1 + padding + 4)?;
+            if pairs < 0 {
+                return None;
+            }
+            1 + padding + 8 + 8 * pairs as usize
+        }
+        // The modified instruction is checked when the wide instruction is verified.
+        0xC4 => match code.get(pc + 1)? {
+            0x84 => 6,
+            _ => 4,
+        },
+        0x00..=0xC9 => 1,
+        _ => return None,
+    };
+
+    if pc + length > code.len() {
+        return None;
+    }
+    Some(length)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::ptr::null;
+
+    use nohash_hasher::BuildNoHashHasher;
+
+    use crate::class_file::{CodeAttribute, StackMapTable};
+    use crate::java::Reference;
+    use crate::method_area::ClassFlags;
+    use crate::method_area::const_pool::ConstPool;
+    use crate::runtime::Runtime;
+
+    use super::*;
+
+    /// Verify a generated class with a single static method.
+    fn verify_method(descriptor: &str, max_stack: u16, max_locals: u16, code: Vec<u8>, stack_map: Vec<StackMapFrame>) -> Result<()> {
+        let runtime = Runtime::new();
+        let attributes = if stack_map.is_empty() {
+            vec![]
+        } else {
+            vec![CodeAttribute::StackMapTable(StackMapTable { entries: stack_map })]
+        };
+        let class = ObjectClass {
+            name: "<verify>".to_string(),
+            flags: ClassFlags { bits: 0 },
+            const_pool: ConstPool { pool: HashMap::with_hasher(BuildNoHashHasher::default()), loader: Reference(0) },
+            super_class: None,
+            interfaces: vec![],
+            instance_fields: vec![],
+            static_fields: vec![],
+            methods: vec![
+                Method {
+                    flags: 0,
+                    class: null(),
+                    is_static: true,
+                    is_native: false,
+                    is_synchronized: false,
+                    name: "test".to_string(),
+                    descriptor: MethodType::from_descriptor(descriptor).unwrap(),
+                    code: Some(Code { max_stack, max_locals, code, ex_table: vec![], attributes }),
+                }
+            ],
+            attributes: vec![],
+            instance_width: 0,
+            static_width: 0,
+            source_file: None,
+            loader: Reference(0),
+            reference_type: None,
+            has_finalizer: false,
+        };
+        verify(&class, &|name| runtime.method_area.try_load_class(name))
+    }
+
+    fn reason(result: Result<()>) -> String {
+        result.unwrap_err().reason
+    }
+
+    #[test]
+    fn valid_code() {
+        // iconst_1, ireturn
+        assert!(verify_method("()I", 1, 0, vec![0x04, 0xAC], vec![]).is_ok());
+        // lload_0, lconst_1, ladd, l2i, ireturn
+        assert!(verify_method("(J)I", 4, 2, vec![0x1E, 0x0A, 0x61, 0x88, 0xAC], vec![]).is_ok());
+        // iload_0, ifeq +4, return, return
+        assert!(verify_method("(I)V", 1, 1, vec![0x1A, 0x99, 0, 4, 0xB1, 0xB1], vec![
+            StackMapFrame::Same { offset_delta: 5 },
+        ]).is_ok());
+    }
+
+    #[test]
+    fn bad_types() {
+        // aconst_null, ireturn
+        assert_eq!(reason(verify_method("()I", 1, 0, vec![0x01, 0xAC], vec![])),
+                   "Bad type on operand stack, expected integer but found null");
+        // lconst_1, ireturn
+        assert_eq!(reason(verify_method("()I", 2, 0, vec![0x0A, 0xAC], vec![])),
+                   "Bad type on operand stack, expected integer but found long");
+        // iconst_1, lreturn
+        assert_eq!(reason(verify_method("()I", 1, 0, vec![0x04, 0xAD], vec![])),
+                   "Wrong return type in method");
+        // lconst_1, pop
+        assert_eq!(reason(verify_method("()V", 2, 0, vec![0x0A, 0x57, 0xB1], vec![])),
+                   "Bad type on operand stack, expected a category 1 value but found long");
+    }
+
+    #[test]
+    fn bad_locals() {
+        // iload_0, ireturn
+        assert_eq!(reason(verify_method("()I", 1, 1, vec![0x1A, 0xAC], vec![])),
+                   "Bad local variable type, found top in local 0");
+        // iload_1, ireturn
+        assert_eq!(reason(verify_method("(I)I", 1, 1, vec![0x1B, 0xAC], vec![])),
+                   "Illegal local variable number 1");
+        // dconst_0, dstore_0, iload_1, ireturn
+        assert_eq!(reason(verify_method("()I", 2, 2, vec![0x0E, 0x47, 0x1B, 0xAC], vec![])),
+                   "Bad local variable type, found top in local 1");
+    }
+
+    #[test]
+    fn bad_stack() {
+        // iconst_1, ireturn
+        assert_eq!(reason(verify_method("()I", 0, 0, vec![0x04, 0xAC], vec![])),
+                   "Exceeded the max stack size");
+        // ireturn
+        assert_eq!(reason(verify_method("()I", 1, 0, vec![0xAC], vec![])),
+                   "Attempt to pop empty stack");
+    }
+
+    #[test]
+    fn bad_control_flow() {
+        // iconst_1
+        assert_eq!(reason(verify_method("()V", 1, 0, vec![0x04], vec![])),
+                   "Falling off the end of the code");
+        // iload_0, ifeq +4, return, return
+        assert_eq!(reason(verify_method("(I)V", 1, 1, vec![0x1A, 0x99, 0, 4, 0xB1, 0xB1], vec![])),
+                   "Expecting a stack map frame at branch target 5");
+        // iload_0, ifeq +4, return, return, with a frame that expects an int on the stack
+        assert_eq!(reason(verify_method("(I)V", 1, 1, vec![0x1A, 0x99, 0, 4, 0xB1, 0xB1], vec![
+            StackMapFrame::SameLocals1StackItem { offset_delta: 5, stack: VerificationType::Integer },
+        ])), "Stack map does not match the one at branch target 5");
+        // goto +1, into the middle of the goto instruction
+        assert_eq!(reason(verify_method("()V", 1, 0, vec![0xA7, 0, 1, 0xB1], vec![])),
+                   "Expecting a stack map frame at branch target 1");
+        // An unknown opcode
+        assert_eq!(reason(verify_method("()V", 0, 0, vec![0xFF], vec![])),
+                   "Illegal instruction 0xFF");
+    }
+
+    #[test]
+    fn bad_wide() {
+        // wide ret 0
+        assert_eq!(reason(verify_method("()V", 0, 1, vec![0xC4, 0xA9, 0, 0, 0xB1], vec![])),
+                   "Subroutines are not allowed in class files verified by type checking");
+        // wide iconst_0
+        assert_eq!(reason(verify_method("()V", 1, 1, vec![0xC4, 0x03, 0, 0, 0xB1], vec![])),
+                   "Illegal wide instruction 0x03");
+    }
+}
@@ -11,15 +11,21 @@ use nohash_hasher::BuildNoHashHasher;
 use rand::{RngCore, thread_rng};
 
 use crate::class_file::Code;
+use crate::collection::classes::ClassRef;
 use crate::collection::once::Once;
 use crate::heap::allocator::ArrayHeader;
+use crate::heap::finalizer;
+use crate::heap::garbage_collector::Collection;
+use crate::heap::gc_log::GcCause;
+use crate::heap::Heaped;
 use crate::java::{Double, FieldType, Int, Long, MethodType, Reference, Value};
 use crate::method_area;
-use crate::method_area::{Class, ClassFlags, ObjectClass};
+use crate::method_area::{Class, ClassFlags, LoadClassError, ObjectClass};
 use crate::method_area::const_pool::{ClassKey, Const, ConstPool, FieldKey, MethodKey, SymbolicReference};
 use crate::native::{Args, Plugin};
 use crate::native::stateless::{Method, stateless};
 use crate::thread::Thread;
+use crate::thread::safepoint::ThreadState;
 
 pub fn java_lang_plugins() -> Vec<Arc<dyn Plugin>> {
     vec![
@@ -79,6 +85,46 @@ pub fn java_lang_plugins() -> Vec<Arc<dyn Plugin>> {
             },
             Arc::new(available_processors),
         ),
+        stateless(
+            Method {
+                class: "java.lang.Runtime".to_string(),
+                name: "runFinalization0".to_string(),
+                descriptor: MethodType::from_descriptor("()V").unwrap(),
+            },
+            Arc::new(run_finalization),
+        ),
+        stateless(
+            Method {
+                class: "java.lang.Runtime".to_string(),
+                name: "gc".to_string(),
+                descriptor: MethodType::from_descriptor("()V").unwrap(),
+            },
+            Arc::new(runtime_gc),
+        ),
+        stateless(
+            Method {
+                class: "java.lang.Runtime".to_string(),
+                name: "totalMemory".to_string(),
+                descriptor: MethodType::from_descriptor("()J").unwrap(),
+            },
+            Arc::new(total_memory),
+        ),
+        stateless(
+            Method {
+                class: "java.lang.Runtime".to_string(),
+                name: "freeMemory".to_string(),
+                descriptor: MethodType::from_descriptor("()J").unwrap(),
+            },
+            Arc::new(free_memory),
+        ),
+        stateless(
+            Method {
+                class: "java.lang.Runtime".to_string(),
+                name: "maxMemory".to_string(),
+                descriptor: MethodType::from_descriptor("()J").unwrap(),
+            },
+            Arc::new(max_memory),
+        ),
         stateless(
             Method {
                 class: "sun.misc.VM".to_string(),
@@ -231,6 +277,30 @@ pub fn java_lang_plugins() -> Vec<Arc<dyn Plugin>> {
             },
             Arc::new(no_op),
         ),
+        stateless(
+            Method {
+                class: "java.lang.ClassLoader".to_string(),
+                name: "defineClass1".to_string(),
+                descriptor: MethodType::from_descriptor("(Ljava/lang/String;[BIILjava/security/ProtectionDomain;Ljava/lang/String;)Ljava/lang/Class;").unwrap(),
+            },
+            Arc::new(class_loader_define_class),
+        ),
+        stateless(
+            Method {
+                class: "java.lang.ClassLoader".to_string(),
+                name: "findLoadedClass0".to_string(),
+                descriptor: MethodType::from_descriptor("(Ljava/lang/String;)Ljava/lang/Class;").unwrap(),
+            },
+            Arc::new(class_loader_find_loaded_class),
+        ),
+        stateless(
+            Method {
+                class: "java.lang.ClassLoader".to_string(),
+                name: "findBootstrapClass".to_string(),
+                descriptor: MethodType::from_descriptor("(Ljava/lang/String;)Ljava/lang/Class;").unwrap(),
+            },
+            Arc::new(class_loader_find_bootstrap_class),
+        ),
         stateless(
             Method {
                 class: "java.lang.Object".to_string(),
@@ -439,6 +509,14 @@ pub fn java_lang_plugins() -> Vec<Arc<dyn Plugin>> {
             },
             Arc::new(is_assignable_from),
         ),
+        stateless(
+            Method {
+                class: "java.lang.Class".to_string(),
+                name: "isInstance".to_string(),
+                descriptor: MethodType::from_descriptor("(Ljava/lang/Object;)Z").unwrap(),
+            },
+            Arc::new(is_instance),
+        ),
         stateless(
             Method {
                 class: "java.lang.Class".to_string(),
@@ -471,10 +549,7 @@ fn class_is_array(args: &Args) -> (Option<Value>, Option<Value>) {
 
 fn class_get_component_type(args: &Args) -> (Option<Value>, Option<Value>) {
     let class = args.params[0].reference();
-    let class_object = args.runtime.heap.get_object(class);
-    let name = class_object.get_string("name", &args.runtime.heap);
-
-    let class_class = args.runtime.method_area.load_outer_class(&name);
+    let class_class = args.runtime.method_area.class_of(class);
 
     let comp = match class_class {
         Class::Array { component, .. } => component.as_ref().clone(),
@@ -491,6 +566,32 @@ fn available_processors(_: &Args) -> (Option<Value>, Option<Value>) {
     (Some(Value::Int(Int(cores as i32))), None)
 }
 
+fn run_finalization(args: &Args) -> (Option<Value>, Option<Value>) {
+    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+    (None, finalizer::run_finalization(thread))
+}
+
+fn runtime_gc(args: &Args) -> (Option<Value>, Option<Value>) {
+    args.runtime.heap.allocator.collector.collect(args.runtime.clone(), Collection::Full, GcCause::SystemGc);
+    (None, None)
+}
+
+fn total_memory(args: &Args) -> (Option<Value>, Option<Value>) {
+    let committed = args.runtime.heap.allocator.committed();
+    (Some(Value::Long(Long(committed as i64))), None)
+}
+
+fn free_memory(args: &Args) -> (Option<Value>, Option<Value>) {
+    let allocator = &args.runtime.heap.allocator;
+    let free = allocator.committed().saturating_sub(allocator.used());
+    (Some(Value::Long(Long(free as i64))), None)
+}
+
+fn max_memory(args: &Args) -> (Option<Value>, Option<Value>) {
+    let capacity = args.runtime.heap.allocator.capacity();
+    (Some(Value::Long(Long(capacity as i64))), None)
+}
+
 fn unix_canonicalize(args: &Args) -> (Option<Value>, Option<Value>) {
     let path = args.params[1].reference();
     let path = args.runtime.heap.get_string(path);
@@ -509,14 +610,7 @@ fn get_declared_fields(args: &Args) -> (Option<Value>, Option<Value>) {
     let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
 
     let class_ref = args.params[0].reference();
-    let class_obj = args.runtime.heap.get_object(class_ref);
-    let class_name_ref = class_obj.get_field(&FieldKey {
-        class: "java.lang.String".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let class_name = args.runtime.heap.get_string(class_name_ref);
-    let class = args.runtime.method_area.load_outer_class(&class_name);
+    let class = args.runtime.method_area.class_of(class_ref);
 
     let field_class = args.runtime.method_area.load_outer_class("java.lang.reflect.Field");
     let field_class_obj = field_class.obj();
@@ -544,6 +638,8 @@ fn get_declared_fields(args: &Args) -> (Option<Value>, Option<Value>) {
             let annotations = Reference(0);
 
             let field = args.runtime.heap.new_object(&field_class_obj);
+            // The fields are rooted, they can move while the next ones are initialized.
+            all_fields.push(args.new_local(field));
 
             let (_, ex) = thread.native_invoke(
                 field_class_obj.deref() as *const ObjectClass,
@@ -562,7 +658,6 @@ fn get_declared_fields(args: &Args) -> (Option<Value>, Option<Value>) {
             if ex.is_some() {
                 return (None, ex);
             }
-            all_fields.push(field);
         }
     }
 
@@ -570,7 +665,7 @@ fn get_declared_fields(args: &Args) -> (Option<Value>, Option<Value>) {
     let fields_array = args.runtime.heap.get_array(fields_array_ref);
 
     for (idx, field) in all_fields.iter().enumerate() {
-        fields_array.set_element(Int(idx as i32), Value::Reference(*field));
+        fields_array.set_element(Int(idx as i32), Value::Reference(args.local(*field)));
     }
 
     (Some(Value::Reference(fields_array_ref)), None)
@@ -582,14 +677,7 @@ fn get_declared_constructors(args: &Args) -> (Option<Value>, Option<Value>) {
     let class_class = args.runtime.method_area.load_outer_class("java.lang.Class");
 
     let class_ref = args.params[0].reference();
-    let class_obj = args.runtime.heap.get_object(class_ref);
-    let class_name_ref = class_obj.get_field(&FieldKey {
-        class: "java.lang.String".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let class_name = args.runtime.heap.get_string(class_name_ref);
-    let class = args.runtime.method_area.load_outer_class(&class_name);
+    let class = args.runtime.method_area.class_of(class_ref);
 
     let constr_class = args.runtime.method_area.load_outer_class("java.lang.reflect.Constructor");
     let constr_class_obj = constr_class.obj();
@@ -625,6 +713,8 @@ fn get_declared_constructors(args: &Args) -> (Option<Value>, Option<Value>) {
             let param_annotations = Reference(0);
 
             let constr = args.runtime.heap.new_object(&constr_class_obj);
+            // The constructors are rooted, they can move while the next ones are initialized.
+            all_constructors.push(args.new_local(constr));
 
             let (_, ex) = thread.native_invoke(
                 constr_class_obj.deref() as *const ObjectClass,
@@ -644,7 +734,6 @@ fn get_declared_constructors(args: &Args) -> (Option<Value>, Option<Value>) {
             if ex.is_some() {
                 return (None, ex);
             }
-            all_constructors.push(constr);
         }
     }
 
@@ -652,7 +741,7 @@ fn get_declared_constructors(args: &Args) -> (Option<Value>, Option<Value>) {
     let constr_array = args.runtime.heap.get_array(constr_array_ref);
 
     for (idx, constr) in all_constructors.iter().enumerate() {
-        constr_array.set_element(Int(idx as i32), Value::Reference(*constr));
+        constr_array.set_element(Int(idx as i32), Value::Reference(args.local(*constr)));
     }
 
     (Some(Value::Reference(constr_array_ref)), None)
@@ -674,15 +763,8 @@ fn field_offset(args: &Args) -> (Option<Value>, Option<Value>) {
         name: "clazz".to_string(),
         descriptor: FieldType::from_descriptor("Ljava/lang/Class;").unwrap(),
     }).reference();
-    let class_obj = args.runtime.heap.get_object(class_ref);
-    let class_name_ref = class_obj.get_field(&FieldKey {
-        class: "java.lang.Class".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let class_name = args.runtime.heap.get_string(class_name_ref);
-
-    let class = args.runtime.method_area.load_class(&class_name);
+    let class = args.runtime.method_area.class_of(class_ref);
+    let class = class.obj();
     let field = class.instance_fields.iter().find(|f| f.name.eq(&name)).unwrap();
     let offset = field.offset as i64;
 
@@ -691,15 +773,8 @@ fn field_offset(args: &Args) -> (Option<Value>, Option<Value>) {
 
 fn get_class_access_flags(args: &Args) -> (Option<Value>, Option<Value>) {
     let class_ref = args.params[0].reference();
-    let class_obj = args.runtime.heap.get_object(class_ref);
-
-    let name_ref = class_obj.get_field(&FieldKey {
-        class: "java.lang.Class".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let name = args.runtime.heap.get_string(name_ref);
-    let class = args.runtime.method_area.load_class(&name);
+    let class = args.runtime.method_area.class_of(class_ref);
+    let class = class.obj();
 
     let flags = class.flags.bits as i32;
 
@@ -708,15 +783,8 @@ fn get_class_access_flags(args: &Args) -> (Option<Value>, Option<Value>) {
 
 fn get_modifiers(args: &Args) -> (Option<Value>, Option<Value>) {
     let class_ref = args.params[0].reference();
-    let class_obj = args.runtime.heap.get_object(class_ref);
-
-    let name_ref = class_obj.get_field(&FieldKey {
-        class: "java.lang.Class".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let name = args.runtime.heap.get_string(name_ref);
-    let class = args.runtime.method_area.load_class(&name);
+    let class = args.runtime.method_area.class_of(class_ref);
+    let class = class.obj();
 
     let flags = class.flags.bits as i32;
 
@@ -741,6 +809,7 @@ fn compare_and_swap(args: &Args) -> (Option<Value>, Option<Value>) {
             0
         }
     };
+    args.runtime.heap.write_barrier(Heaped::Object(object));
 
     (Some(Value::Int(Int(result))), None)
 }
@@ -808,28 +877,29 @@ fn object_get_class(args: &Args) -> (Option<Value>, Option<Value>) {
 
 fn array_new_array(args: &Args) -> (Option<Value>, Option<Value>) {
     let component_ref = args.params[0].reference();
-    let component_obj = args.runtime.heap.get_object(component_ref);
-
-    let name_ref = component_obj.get_field(&FieldKey {
-        class: "java.lang.String".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let name = args.runtime.heap.get_string(name_ref);
-    let class = args.runtime.method_area.load_outer_class(&name);
+    let class = args.runtime.method_area.class_of(component_ref);
 
     let length = args.params[1].int();
 
-    let array = args.runtime.heap.new_array(class, length);
+    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+    let Ok(array) = args.runtime.heap.try_new_array(class, length) else {
+        return (None, Some(thread.out_of_memory_error()));
+    };
 
     (Some(Value::Reference(array)), None)
 }
 
 fn object_clone(args: &Args) -> (Option<Value>, Option<Value>) {
     let object_ref = args.params[0].reference();
-    let object_obj = args.runtime.heap.get(object_ref);
 
-    let copied = args.runtime.heap.copy(object_obj);
+    // The object must stay rooted, in case the heap is full and we need to GC, which can move
+    // it.
+    let object = args.new_local(object_ref);
+    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+    let Ok(copied) = args.runtime.heap.try_new_like(thread, object_ref) else {
+        return (None, Some(thread.out_of_memory_error()));
+    };
+    args.runtime.heap.copy(args.local(object), copied);
 
     (Some(Value::Reference(copied)), None)
 }
@@ -841,30 +911,23 @@ fn object_wait(args: &Args) -> (Option<Value>, Option<Value>) {
     let object_ref = args.params[0].reference();
     let millis = args.params[1].long();
 
-    let sync = thread.locks.remove(&object_ref.0).expect("Do not hold the lock on this object");
-    let reentry = sync.drop_all();
-
-    let object_obj = args.runtime.heap.get_object(object_ref);
+    // The object may move while we wait, but its lock doesn't.
+    let lock = args.runtime.heap.get_object(object_ref).header().lock.move_me();
 
-    let lock = &object_obj.header().lock;
+    let sync = thread.locks.remove(&lock.id()).expect("Do not hold the lock on this object");
 
-    // Entering safe region!
-    thread.safe.enter();
+    // The thread stays safe while it waits to be notified, and to enter the monitor again.
+    thread.enter_safe(ThreadState::Blocked);
 
     let timeout = if millis.0 == 0 { None } else { Some(Duration::from_millis(millis.0 as u64)) };
 
-    lock.wait(timeout);
-
-    thread.safe.exit();
+    let reentry = lock.wait(sync, timeout);
 
-    let object_obj = args.runtime.heap.get_object(object_ref);
-    let lock = &object_obj.header().lock;
-    thread.safe.enter();
     let mut sync = lock.lock();
     sync.reentry = reentry;
-    thread.locks.insert(object_ref.0, sync);
+    thread.locks.insert(lock.id(), sync);
 
-    thread.safe.exit();
+    thread.exit_safe();
 
     (None, None)
 }
@@ -875,12 +938,12 @@ fn object_notify_all(args: &Args) -> (Option<Value>, Option<Value>) {
 
     let object_ref = args.params[0].reference();
 
-    if !thread.locks.contains_key(&object_ref.0) {
+    let object_obj = args.runtime.heap.get_object(object_ref);
+    let lock = &object_obj.header().lock;
+    if !thread.locks.contains_key(&lock.id()) {
         panic!("Should have ownership of the sync!");
     }
 
-    let object_obj = args.runtime.heap.get_object(object_ref);
-    let lock = &object_obj.header().lock;
     lock.notify_all();
 
     (None, None)
@@ -923,17 +986,7 @@ fn fill_in_stack_trace(args: &Args) -> (Option<Value>, Option<Value>) {
             method: method.name.clone(),
             file: class.source_file.clone(),
             line: {
-                let line_numbers = method.code.as_ref().and_then(|code| code.line_number_table())
-                    .map(|table| &table.table);
-                if let Some(table) = line_numbers {
-                    table.iter()
-                        .filter(|ln| ln.start_pc as usize <= frame.pc)
-                        .last()
-                        .map(|ln| ln.line_number)
-                        .unwrap() as i32
-                } else {
-                    -2
-                }
+                frame.line_number().map_or(-2, |line| line as i32)
             },
         }
     }).collect();
@@ -946,6 +999,7 @@ fn fill_in_stack_trace(args: &Args) -> (Option<Value>, Option<Value>) {
         flags: ClassFlags { bits: 0 },
         const_pool: ConstPool {
             pool: HashMap::with_hasher(BuildNoHashHasher::default()),
+            loader: Reference(0),
         },
         super_class: None,
         interfaces: vec![],
@@ -956,6 +1010,9 @@ fn fill_in_stack_trace(args: &Args) -> (Option<Value>, Option<Value>) {
         instance_width: 0,
         static_width: 0,
         source_file: None,
+        loader: Reference(0),
+        reference_type: None,
+        has_finalizer: false,
     };
 
     let mut method = method_area::Method {
@@ -1110,10 +1167,13 @@ fn fill_in_stack_trace(args: &Args) -> (Option<Value>, Option<Value>) {
     let class = args.runtime.method_area.insert_gen_class(class);
     let method = &unsafe { class.as_ref().unwrap() }.methods[0] as *const method_area::Method;
 
+    // The throwable can move while the stack trace is built.
+    let throwable = args.new_local(throwable_ref);
     let (array_reference, ex) = thread.native_invoke(class, method, vec![]);
     if ex.is_some() {
         return (None, ex);
     }
+    let throwable_ref = args.local(throwable);
 
     // Store array reference in field
     let throwable = args.runtime.heap.get_object(throwable_ref);
@@ -1122,6 +1182,7 @@ fn fill_in_stack_trace(args: &Args) -> (Option<Value>, Option<Value>) {
         name: "backtrace".to_string(),
         descriptor: FieldType::from_descriptor("Ljava/lang/Object;").unwrap(),
     }, Value::Reference(array_reference.unwrap().reference()));
+    args.runtime.heap.write_barrier(Heaped::Object(throwable));
 
     (Some(Value::Reference(throwable_ref)), None)
 }
@@ -1144,34 +1205,19 @@ fn thread_start(args: &Args) -> (Option<Value>, Option<Value>) {
     }).reference();
     let name = args.runtime.heap.get_string(name_ref);
 
-    if name.eq("Reference Handler") {
-        // TODO: Handle this thread!
-        return (None, None);
-    }
+    let class = thread_obj.class();
+    let method = class.find_method(&MethodKey {
+        class: class.name.clone(),
+        name: "run".to_string(),
+        descriptor: MethodType::from_descriptor("()V").unwrap(),
+    }).unwrap() as *const method_area::Method;
 
-    let runtime = args.runtime.clone();
-    let class = thread_obj.class().name.clone();
+    // The thread is registered before it starts, so the collector keeps its reference to the
+    // thread object up to date from the start.
+    let thread = Thread::new(name.clone(), Some(thread_ref), args.runtime.clone(), class.name.clone(),
+                             &class.const_pool, method, vec![Value::Reference(thread_ref)]);
 
-    Builder::new().name(name.clone()).spawn(move || {
-        let const_pool = &thread_obj.class().const_pool as *const ConstPool;
-        let method = thread_obj.class().find_method(&MethodKey {
-            class: class.clone(),
-            name: "run".to_string(),
-            descriptor: MethodType::from_descriptor("()V").unwrap(),
-        }).unwrap() as *const method_area::Method;
-
-        let thread = Thread::new(name, Some(thread_ref.clone()), runtime, class, const_pool, method, vec![
-            Value::Reference(thread_ref)
-        ]);
-
-        // hack
-        unsafe {
-            let t = thread.as_ref() as *const Thread;
-            let t = t as *mut Thread;
-            let t = t.as_mut().unwrap();
-            t.run();
-        }
-    }).unwrap();
+    Builder::new().name(name).spawn(move || thread.run()).unwrap();
 
     (None, None)
 }
@@ -1212,6 +1258,10 @@ pub fn current_thread(args: &Args) -> (Option<Value>, Option<Value>) {
             "main",
             &args.runtime.method_area.load_class("java.lang.String"));
 
+        // The thread groups can move while they're initialized.
+        let system_group = args.new_local(system_thread_group);
+        let main_group = args.new_local(main_thread_group);
+
         // Init System Thread Group
         let (_, ex) = thread.native_invoke(
             thread_group_class.deref() as *const ObjectClass,
@@ -1226,15 +1276,16 @@ pub fn current_thread(args: &Args) -> (Option<Value>, Option<Value>) {
             thread_group_class.deref() as *const ObjectClass,
             thread_group_init_main as *const method_area::Method,
             vec![
-                Value::Reference(main_thread_group),
+                Value::Reference(args.local(main_group)),
                 Value::Reference(Reference(0)),
-                Value::Reference(system_thread_group),
+                Value::Reference(args.local(system_group)),
                 Value::Reference(main_string),
             ],
         );
         if ex.is_some() {
             return (None, ex);
         }
+        let main_thread_group = args.local(main_group);
 
         // Create our main thread.
         let thread_class = args.runtime.method_area.load_class("java.lang.Thread");
@@ -1289,8 +1340,21 @@ pub fn get_primitive_class(args: &Args) -> (Option<Value>, Option<Value>) {
     (Some(Value::Reference(primitive_object)), None)
 }
 
-fn assertion_status(_: &Args) -> (Option<Value>, Option<Value>) {
-    (Some(Value::Int(Int(0))), None)
+fn assertion_status(args: &Args) -> (Option<Value>, Option<Value>) {
+    let class_ref = args.params[0].reference();
+    let class_inst = args.runtime.heap.get_object(class_ref);
+
+    let name_ref = class_inst.get_field(&FieldKey {
+        class: "java.lang.Class".to_string(),
+        name: "name".to_string(),
+        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
+    }).reference();
+    let name = args.runtime.heap.get_string(name_ref);
+
+    let is_system = args.runtime.method_area.is_boot_class(&name);
+    let enabled = args.runtime.options.assertions.enabled(&name, is_system);
+
+    (Some(Value::Int(Int(enabled as i32))), None)
 }
 
 fn float_to_int_bits(args: &Args) -> (Option<Value>, Option<Value>) {
@@ -1346,14 +1410,14 @@ fn address_size(_: &Args) -> (Option<Value>, Option<Value>) {
 
 fn get_caller_class(args: &Args) -> (Option<Value>, Option<Value>) {
     let thread = unsafe { args.thread.as_ref().unwrap() };
-    let class_name = thread.stack.iter().rev()
+    let method = thread.stack.iter().rev()
         .skip(2) // skip this frame
         .skip_while(|f| f.class.starts_with('<')) // skip internal frames
         .next()
-        .map(|f| &f.class)
+        .map(|f| unsafe { f.method.as_ref().unwrap() })
         .unwrap();
 
-    let class = args.runtime.method_area.load_outer_class(class_name);
+    let class = Class::Object(ClassRef::new(method.class));
     let class_ref = args.runtime.method_area.load_class_object(class);
 
     (Some(Value::Reference(class_ref)), None)
@@ -1365,16 +1429,87 @@ fn for_name_0(args: &Args) -> (Option<Value>, Option<Value>) {
     let name_ref = args.params[0].reference();
     let name = args.runtime.heap.get_string(name_ref);
     let initialize = args.params[1].int().0 != 0;
+    let loader = args.params[2].reference();
 
-    let class = args.runtime.method_area.load_outer_class(&name);
+    let class = match args.runtime.method_area.try_load_outer_class_in(thread, loader, &name) {
+        Ok(class) => class,
+        Err(LoadClassError::NotFound(not_found)) if not_found == name => {
+            let ex = thread.new_throwable("java.lang.ClassNotFoundException", &name);
+            return (None, Some(ex));
+        }
+        Err(error) => return (None, Some(error.to_throwable(thread))),
+    };
     if initialize {
-        args.runtime.method_area.initialize(thread, class.obj().deref());
+        if let Err(ex) = args.runtime.method_area.initialize(thread, class.obj().deref()) {
+            return (None, Some(ex));
+        }
     }
 
     let class_obj = args.runtime.method_area.load_class_object(class);
     (Some(Value::Reference(class_obj)), None)
 }
 
+fn class_loader_define_class(args: &Args) -> (Option<Value>, Option<Value>) {
+    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+
+    let loader = args.params[0].reference();
+    let name = match args.params[1].reference() {
+        Reference(0) => None,
+        name => Some(args.runtime.heap.get_string(name)),
+    };
+    let offset = args.params[3].int().0;
+    let length = args.params[4].int().0;
+    let array = args.runtime.heap.get_array(args.params[2].reference());
+    let array_length = array.as_bytes_slice().len();
+    if offset < 0 || length < 0 || offset as usize + length as usize > array_length {
+        let message = format!("offset {}, length {}, array length {}", offset, length, array_length);
+        return (None, Some(thread.new_throwable("java.lang.IndexOutOfBoundsException", &message)));
+    }
+    let bytes: Vec<u8> = array.as_bytes_slice()[offset as usize..(offset + length) as usize]
+        .iter()
+        .map(|byte| *byte as u8)
+        .collect();
+    let source = match args.params[6].reference() {
+        Reference(0) => None,
+        source => Some(args.runtime.heap.get_string(source)),
+    };
+
+    let class = args.runtime.method_area.define_class(thread, loader, name.as_deref(), &bytes, source.as_deref());
+    match class {
+        Ok(class) => {
+            let class_obj = args.runtime.method_area.load_class_object(Class::Object(class));
+            (Some(Value::Reference(class_obj)), None)
+        }
+        Err(error) => (None, Some(error.to_throwable(thread))),
+    }
+}
+
+fn class_loader_find_loaded_class(args: &Args) -> (Option<Value>, Option<Value>) {
+    let loader = args.params[0].reference();
+    let name = args.runtime.heap.get_string(args.params[1].reference());
+
+    let class_obj = args.runtime.method_area.find_loaded_class(loader, &name)
+        .map(|class| args.runtime.method_area.load_class_object(Class::Object(class)))
+        .unwrap_or(Reference(0));
+
+    (Some(Value::Reference(class_obj)), None)
+}
+
+fn class_loader_find_bootstrap_class(args: &Args) -> (Option<Value>, Option<Value>) {
+    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+
+    let name = args.runtime.heap.get_string(args.params[1].reference());
+
+    match args.runtime.method_area.try_load_class(&name) {
+        Ok(class) => {
+            let class_obj = args.runtime.method_area.load_class_object(Class::Object(class));
+            (Some(Value::Reference(class_obj)), None)
+        }
+        Err(LoadClassError::NotFound(_)) => (Some(Value::Reference(Reference(0))), None),
+        Err(error) => (None, Some(error.to_throwable(thread))),
+    }
+}
+
 fn get_control_context(args: &Args) -> (Option<Value>, Option<Value>) {
     let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
     let acc_class = args.runtime.method_area.load_outer_class("java.security.AccessControlContext");
@@ -1390,6 +1525,8 @@ fn get_control_context(args: &Args) -> (Option<Value>, Option<Value>) {
     let acc_ref = args.runtime.heap.new_object(&acc_class);
     let domains_ref = args.runtime.heap.new_array(pro_dom_class, Int(0));
 
+    // The context can move while it's initialized.
+    let acc = args.new_local(acc_ref);
     let (_, ex) = thread.native_invoke(
         acc_class.deref() as *const ObjectClass,
         acc_init as *const method_area::Method,
@@ -1401,6 +1538,7 @@ fn get_control_context(args: &Args) -> (Option<Value>, Option<Value>) {
     if ex.is_some() {
         return (None, ex);
     }
+    let acc_ref = args.local(acc);
 
     (Some(Value::Reference(acc_ref)), None)
 }
@@ -1459,26 +1597,8 @@ fn stack_trace_elem(args: &Args) -> (Option<Value>, Option<Value>) {
 }
 
 fn is_assignable_from(args: &Args) -> (Option<Value>, Option<Value>) {
-    let class_ref = args.params[0].reference();
-    let class_inst = args.runtime.heap.get_object(class_ref);
-
-    let name_ref = class_inst.get_field(&FieldKey {
-        class: "java.lang.Class".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let name = args.runtime.heap.get_string(name_ref);
-
-    let other_inst = args.runtime.heap.get_object(args.params[1].reference());
-    let other_name_ref = other_inst.get_field(&FieldKey {
-        class: "java.lang.Class".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let other_name = args.runtime.heap.get_string(other_name_ref);
-
-    let this_class = args.runtime.method_area.load_outer_class(&name);
-    let other_class = args.runtime.method_area.load_outer_class(&other_name);
+    let this_class = args.runtime.method_area.class_of(args.params[0].reference());
+    let other_class = args.runtime.method_area.class_of(args.params[1].reference());
 
     let is_assignable = other_class.is_instance_of(&this_class);
     let is_assignable = if is_assignable { 1 } else { 0 };
@@ -1486,6 +1606,20 @@ fn is_assignable_from(args: &Args) -> (Option<Value>, Option<Value>) {
     (Some(Value::Int(Int(is_assignable))), None)
 }
 
+fn is_instance(args: &Args) -> (Option<Value>, Option<Value>) {
+    let class = args.runtime.method_area.class_of(args.params[0].reference());
+    let object_ref = args.params[1].reference();
+    if object_ref.0 == 0 {
+        return (Some(Value::Int(Int(0))), None);
+    }
+
+    let object_class = args.runtime.heap.get(object_ref)
+        .class(args.runtime.method_area.load_outer_class("java.lang.Object"));
+    let is_instance = if object_class.is_instance_of(&class) { 1 } else { 0 };
+
+    (Some(Value::Int(Int(is_instance))), None)
+}
+
 fn is_primitive(args: &Args) -> (Option<Value>, Option<Value>) {
     let class_ref = args.params[0].reference();
     let class_inst = args.runtime.heap.get_object(class_ref);
@@ -1508,16 +1642,7 @@ fn is_primitive(args: &Args) -> (Option<Value>, Option<Value>) {
 
 fn is_interface(args: &Args) -> (Option<Value>, Option<Value>) {
     let class_ref = args.params[0].reference();
-    let class_inst = args.runtime.heap.get_object(class_ref);
-
-    let name_ref = class_inst.get_field(&FieldKey {
-        class: "java.lang.Class".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let name = args.runtime.heap.get_string(name_ref);
-
-    let class = args.runtime.method_area.load_outer_class(&name);
+    let class = args.runtime.method_area.class_of(class_ref);
     let is_interface = class.is_interface();
     let is_interface = if is_interface { 1 } else { 0 };
 
@@ -1526,16 +1651,7 @@ fn is_interface(args: &Args) -> (Option<Value>, Option<Value>) {
 
 fn get_super_class(args: &Args) -> (Option<Value>, Option<Value>) {
     let class_ref = args.params[0].reference();
-    let class_inst = args.runtime.heap.get_object(class_ref);
-
-    let name_ref = class_inst.get_field(&FieldKey {
-        class: "java.lang.Class".to_string(),
-        name: "name".to_string(),
-        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
-    }).reference();
-    let name = args.runtime.heap.get_string(name_ref);
-
-    let this_class = args.runtime.method_area.load_outer_class(&name);
+    let this_class = args.runtime.method_area.class_of(class_ref);
 
     let parent_ref = match this_class {
         Class::Primitive(_) => Reference(0),
@@ -1545,8 +1661,7 @@ fn get_super_class(args: &Args) -> (Option<Value>, Option<Value>) {
         }
         Class::Object(obj) => {
             if let Some(parent) = &obj.super_class {
-                let parent_class = args.runtime.method_area.load_outer_class(&parent.name);
-                args.runtime.method_area.load_class_object(parent_class)
+                args.runtime.method_area.load_class_object(Class::Object(*parent))
             } else {
                 Reference(0)
             }
@@ -1587,6 +1702,9 @@ fn array_copy(args: &Args) -> (Option<Value>, Option<Value>) {
 
         ptr::copy(src_start.cast_const(), dest_start, bytes);
     }
+    if dest_comp.is_reference() {
+        args.runtime.heap.write_barrier(Heaped::Array(dest_array));
+    }
 
     (None, None)
 }
\ No newline at end of file
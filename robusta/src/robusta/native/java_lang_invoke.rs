@@ -0,0 +1,52 @@
+use std::sync::Arc;
+
+use crate::java::{Int, MethodType, Value};
+use crate::native::{Args, Plugin};
+use crate::native::stateless::{Method, stateless};
+
+pub fn java_lang_invoke_plugins() -> Vec<Arc<dyn Plugin>> {
+    vec![
+        stateless(
+            Method {
+                class: "java.lang.invoke.MethodHandleNatives".to_string(),
+                name: "getConstant".to_string(),
+                descriptor: MethodType::from_descriptor("(I)I").unwrap(),
+            },
+            Arc::new(get_constant),
+        ),
+        stateless(
+            Method {
+                class: "java.lang.invoke.MethodHandleNatives".to_string(),
+                name: "getNamedCon".to_string(),
+                descriptor: MethodType::from_descriptor("(I[Ljava/lang/Object;)I").unwrap(),
+            },
+            Arc::new(get_named_con),
+        ),
+    ]
+}
+
+/// `MethodHandleNatives.Constants.GC_COUNT_GWT`, whether guardWithTest method handles count
+/// their branches for a JIT to profile.
+const GC_COUNT_GWT: i32 = 4;
+
+/// Get one of the VM's method handle options, asked for by `MethodHandleNatives.<clinit>`.
+fn get_constant(args: &Args) -> (Option<Value>, Option<Value>) {
+    let which = args.params[0].int().0;
+    let value = match which {
+        // Like HotSpot without a JIT, there's nothing to profile, so branches aren't counted.
+        GC_COUNT_GWT => 0,
+        // HotSpot answers zero for any option it doesn't know.
+        _ => 0,
+    };
+    (Some(Value::Int(Int(value))), None)
+}
+
+/// Get the name and value of the VM's `which`th method handle constant, for
+/// `MethodHandleNatives.verifyConstants` to compare with its own.
+///
+/// This is a stub that has no constants, leaving the name unset so the Java side stops at the
+/// first. That's safe as the constants are only verified when assertions are enabled for the
+/// platform classes, and the comparison only reports mismatches, it doesn't use the values.
+fn get_named_con(_: &Args) -> (Option<Value>, Option<Value>) {
+    (Some(Value::Int(Int(0))), None)
+}
@@ -0,0 +1,243 @@
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::heap::Heaped;
+use crate::java::{FieldType, Int, Long, MethodType, Reference, Value};
+use crate::method_area;
+use crate::method_area::const_pool::{FieldKey, MethodKey};
+use crate::method_area::ObjectClass;
+use crate::native::{Args, Plugin};
+use crate::native::stateless::{Method, stateless};
+
+/// The version of the management interface that `sun.management` checks for.
+const MANAGEMENT_VERSION: &str = "1.2";
+
+/// The name of the garbage collector bean for collections of the nursery alone.
+const COPY: &str = "Copy";
+
+/// The name of the garbage collector bean for collections of the whole heap.
+const MARK_SWEEP_COMPACT: &str = "MarkSweepCompact";
+
+pub fn management_plugins() -> Vec<Arc<dyn Plugin>> {
+    vec![
+        stateless(
+            Method {
+                class: "sun.management.VMManagementImpl".to_string(),
+                name: "getVersion0".to_string(),
+                descriptor: MethodType::from_descriptor("()Ljava/lang/String;").unwrap(),
+            },
+            Arc::new(get_version_0),
+        ),
+        stateless(
+            Method {
+                class: "sun.management.VMManagementImpl".to_string(),
+                name: "initOptionalSupportFields".to_string(),
+                descriptor: MethodType::from_descriptor("()V").unwrap(),
+            },
+            Arc::new(init_optional_support_fields),
+        ),
+        stateless(
+            Method {
+                class: "sun.management.VMManagementImpl".to_string(),
+                name: "getVerboseGC".to_string(),
+                descriptor: MethodType::from_descriptor("()Z").unwrap(),
+            },
+            Arc::new(get_verbose_gc),
+        ),
+        stateless(
+            Method {
+                class: "sun.management.MemoryImpl".to_string(),
+                name: "getMemoryUsage0".to_string(),
+                descriptor: MethodType::from_descriptor("(Z)Ljava/lang/management/MemoryUsage;").unwrap(),
+            },
+            Arc::new(get_memory_usage_0),
+        ),
+        stateless(
+            Method {
+                class: "sun.management.MemoryImpl".to_string(),
+                name: "getMemoryPools0".to_string(),
+                descriptor: MethodType::from_descriptor("()[Ljava/lang/management/MemoryPoolMXBean;").unwrap(),
+            },
+            Arc::new(get_memory_pools_0),
+        ),
+        stateless(
+            Method {
+                class: "sun.management.MemoryImpl".to_string(),
+                name: "getMemoryManagers0".to_string(),
+                descriptor: MethodType::from_descriptor("()[Ljava/lang/management/MemoryManagerMXBean;").unwrap(),
+            },
+            Arc::new(get_memory_managers_0),
+        ),
+        stateless(
+            Method {
+                class: "sun.management.GarbageCollectorImpl".to_string(),
+                name: "getCollectionCount".to_string(),
+                descriptor: MethodType::from_descriptor("()J").unwrap(),
+            },
+            Arc::new(get_collection_count),
+        ),
+        stateless(
+            Method {
+                class: "sun.management.GarbageCollectorImpl".to_string(),
+                name: "getCollectionTime".to_string(),
+                descriptor: MethodType::from_descriptor("()J").unwrap(),
+            },
+            Arc::new(get_collection_time),
+        ),
+        stateless(
+            Method {
+                class: "sun.management.HotSpotDiagnostic".to_string(),
+                name: "dumpHeap0".to_string(),
+                descriptor: MethodType::from_descriptor("(Ljava/lang/String;Z)V").unwrap(),
+            },
+            Arc::new(dump_heap_0),
+        ),
+    ]
+}
+
+fn get_version_0(args: &Args) -> (Option<Value>, Option<Value>) {
+    let version = args.runtime.method_area.load_string(MANAGEMENT_VERSION);
+    (Some(Value::Reference(version)), None)
+}
+
+fn init_optional_support_fields(_: &Args) -> (Option<Value>, Option<Value>) {
+    // None of the optional monitoring is supported, which the fields default to.
+    (None, None)
+}
+
+fn get_verbose_gc(args: &Args) -> (Option<Value>, Option<Value>) {
+    let verbose = if args.runtime.options.verbose_gc { 1 } else { 0 };
+    (Some(Value::Int(Int(verbose))), None)
+}
+
+fn get_memory_usage_0(args: &Args) -> (Option<Value>, Option<Value>) {
+    let heap = args.params[1].int().0 != 0;
+
+    // The classes and code live outside the heap, and aren't accounted for.
+    let allocator = &args.runtime.heap.allocator;
+    let (init, used, committed, max) = if heap {
+        (allocator.initial(), allocator.used(), allocator.committed(), allocator.capacity() as i64)
+    } else {
+        (0, 0, 0, -1)
+    };
+
+    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+    let usage_class = args.runtime.method_area.load_class("java.lang.management.MemoryUsage");
+    if let Err(ex) = args.runtime.method_area.initialize(thread, &usage_class) {
+        return (None, Some(ex));
+    }
+    let usage_init = usage_class.find_method(&MethodKey {
+        class: "java.lang.management.MemoryUsage".to_string(),
+        name: "<init>".to_string(),
+        descriptor: MethodType::from_descriptor("(JJJJ)V").unwrap(),
+    }).unwrap();
+
+    let Ok(usage_ref) = args.runtime.heap.try_new_object(thread, usage_class.deref()) else {
+        return (None, Some(thread.out_of_memory_error()));
+    };
+
+    // The usage can move while it's initialized.
+    let usage = args.new_local(usage_ref);
+    let (_, ex) = thread.native_invoke(
+        usage_class.deref() as *const ObjectClass,
+        usage_init as *const method_area::Method,
+        vec![
+            Value::Reference(usage_ref),
+            Value::Long(Long(init as i64)),
+            Value::Long(Long(used as i64)),
+            Value::Long(Long(committed as i64)),
+            Value::Long(Long(max)),
+        ],
+    );
+    if ex.is_some() {
+        return (None, ex);
+    }
+
+    (Some(Value::Reference(args.local(usage))), None)
+}
+
+fn get_memory_pools_0(args: &Args) -> (Option<Value>, Option<Value>) {
+    let pool_class = args.runtime.method_area.load_outer_class("java.lang.management.MemoryPoolMXBean");
+    let pools = args.runtime.heap.new_array(pool_class, Int(0));
+    (Some(Value::Reference(pools)), None)
+}
+
+fn get_memory_managers_0(args: &Args) -> (Option<Value>, Option<Value>) {
+    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+
+    let helper_class = args.runtime.method_area.load_class("sun.management.ManagementFactoryHelper");
+    if let Err(ex) = args.runtime.method_area.initialize(thread, &helper_class) {
+        return (None, Some(ex));
+    }
+    let create_collector = helper_class.find_method(&MethodKey {
+        class: "sun.management.ManagementFactoryHelper".to_string(),
+        name: "createGarbageCollector".to_string(),
+        descriptor: MethodType::from_descriptor("(Ljava/lang/String;Ljava/lang/String;)Ljava/lang/management/GarbageCollectorMXBean;").unwrap(),
+    }).unwrap();
+
+    // Like HotSpot's serial collector, there's a bean for each generation's collections.
+    let mut collectors = vec![];
+    for name in [COPY, MARK_SWEEP_COMPACT] {
+        let name = args.runtime.method_area.load_string(name);
+        let (collector, ex) = thread.native_invoke(
+            helper_class.deref() as *const ObjectClass,
+            create_collector as *const method_area::Method,
+            vec![Value::Reference(name), Value::Reference(Reference(0))],
+        );
+        if ex.is_some() {
+            return (None, ex);
+        }
+        // The collectors are rooted, they can move while the next ones are created.
+        collectors.push(args.new_local(collector.unwrap().reference()));
+    }
+
+    let manager_class = args.runtime.method_area.load_outer_class("java.lang.management.MemoryManagerMXBean");
+    let Ok(managers_ref) = args.runtime.heap.try_new_array(manager_class, Int(collectors.len() as i32)) else {
+        return (None, Some(thread.out_of_memory_error()));
+    };
+    let managers = args.runtime.heap.get_array(managers_ref);
+    for (idx, collector) in collectors.iter().enumerate() {
+        managers.set_element(Int(idx as i32), Value::Reference(args.local(*collector)));
+    }
+    args.runtime.heap.write_barrier(Heaped::Array(managers));
+
+    (Some(Value::Reference(managers_ref)), None)
+}
+
+fn get_collection_count(args: &Args) -> (Option<Value>, Option<Value>) {
+    let (count, _) = args.runtime.heap.allocator.collector.statistics(is_full_collector(args));
+    (Some(Value::Long(Long(count as i64))), None)
+}
+
+fn get_collection_time(args: &Args) -> (Option<Value>, Option<Value>) {
+    let (_, time) = args.runtime.heap.allocator.collector.statistics(is_full_collector(args));
+    (Some(Value::Long(Long(time.as_millis() as i64))), None)
+}
+
+/// Whether the `GarbageCollectorImpl` the native was called on counts collections of the whole
+/// heap, rather than of the nursery.
+fn is_full_collector(args: &Args) -> bool {
+    let collector = args.runtime.heap.get_object(args.params[0].reference());
+    let name = collector.get_field(&FieldKey {
+        class: "sun.management.MemoryManagerImpl".to_string(),
+        name: "name".to_string(),
+        descriptor: FieldType::from_descriptor("Ljava/lang/String;").unwrap(),
+    }).reference();
+    args.runtime.heap.get_string(name) == MARK_SWEEP_COMPACT
+}
+
+fn dump_heap_0(args: &Args) -> (Option<Value>, Option<Value>) {
+    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+    let path = args.params[1].reference();
+    if path.0 == 0 {
+        return (None, Some(thread.new_throwable("java.lang.NullPointerException", "outputFile")));
+    }
+    let path = PathBuf::from(args.runtime.heap.get_string(path));
+    let live = args.params[2].int().0 != 0;
+
+    match args.runtime.heap.allocator.collector.dump_heap(args.runtime.clone(), path, live) {
+        Ok(_) => (None, None),
+        Err(error) => (None, Some(thread.new_throwable("java.io.IOException", &error.to_string()))),
+    }
+}
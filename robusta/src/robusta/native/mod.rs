@@ -2,22 +2,28 @@ use std::sync::Arc;
 
 use tracing::debug;
 
+use crate::heap::Global;
 use crate::java::{Reference, Value};
 use crate::log;
 use crate::method_area::Method;
 use crate::native::file_output_stream::file_output_stream_plugins;
 use crate::native::java_lang::java_lang_plugins;
+use crate::native::java_lang_invoke::java_lang_invoke_plugins;
 use crate::native::java_security::java_security_plugins;
+use crate::native::management::management_plugins;
 use crate::native::robusta::robusta_plugins;
 use crate::native::system::system_plugins;
 use crate::thread::Thread;
+use crate::thread::safepoint::ThreadState;
 
 mod robusta;
 mod stateless;
 mod java_lang;
+mod java_lang_invoke;
 mod java_security;
 mod system;
 mod file_output_stream;
+mod management;
 
 pub struct NativeMethods {
     plugins: Vec<Arc<dyn Plugin>>,
@@ -33,9 +39,11 @@ impl NativeMethods {
         plugins.push(Arc::new(RegisterNative {}) as _);
         plugins.append(&mut robusta_plugins());
         plugins.append(&mut java_lang_plugins());
+        plugins.append(&mut java_lang_invoke_plugins());
         plugins.append(&mut java_security_plugins());
         plugins.append(&mut system_plugins());
         plugins.append(&mut file_output_stream_plugins());
+        plugins.append(&mut management_plugins());
         NativeMethods { plugins }
     }
 
@@ -59,22 +67,54 @@ pub struct Args {
     pub params: Vec<Value>,
 }
 
+/// A local reference of a native method, see [`Args::new_local`].
+#[derive(Clone, Copy, Debug)]
+pub struct Local {
+    frame: usize,
+    index: usize,
+}
+
 impl Args {
-    /// TODO: Not used yet.
-    pub fn add_local(&self, reference: Reference) {
+    /// Hold a reference until the native method returns, like JNI's `NewLocalRef`.
+    ///
+    /// Allocating, or calling back into Java, can run the garbage collector, which moves
+    /// objects, so a native must read its references back from their locals afterwards.
+    pub fn new_local(&self, reference: Reference) -> Local {
         let thread = unsafe { self.thread.cast_mut().as_mut().unwrap() };
-        let frame = thread.stack.last_mut().unwrap();
-        frame.native_roots.insert(reference.0);
+        Local { frame: thread.stack.len() - 1, index: thread.push_root(reference) }
+    }
+
+    /// The current value of a local reference.
+    pub fn local(&self, local: Local) -> Reference {
+        let thread = unsafe { self.thread.as_ref().unwrap() };
+        thread.stack[local.frame].native_roots[local.index]
+    }
+
+    /// Hold a reference beyond the native method, until it's deleted, see
+    /// [`Heap::new_global`](crate::heap::Heap::new_global).
+    pub fn new_global(&self, reference: Reference) -> Global {
+        self.runtime.heap.new_global(reference)
     }
 
+    /// The current value of a global reference.
+    pub fn global(&self, global: Global) -> Reference {
+        self.runtime.heap.global(global)
+    }
+
+    pub fn delete_global(&self, global: Global) {
+        self.runtime.heap.delete_global(global)
+    }
+
+    /// Let safepoints go ahead while the native method runs code that doesn't use the heap,
+    /// see [`Thread::enter_safe`].
     pub fn enter_safe(&self) {
-        let thread = unsafe { self.thread.cast_mut().as_mut().unwrap() };
-        thread.safe.enter();
+        let thread = unsafe { self.thread.as_ref().unwrap() };
+        thread.enter_safe(ThreadState::InNative);
     }
 
     pub fn exit_safe(&self) {
-        let thread = unsafe { self.thread.cast_mut().as_mut().unwrap() };
-        thread.safe.exit();
+        let thread = unsafe { self.thread.as_ref().unwrap() };
+        thread.exit_safe();
     }
 }
 
@@ -1,9 +1,11 @@
+use std::env::join_paths;
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use maplit::hashmap;
 
+use crate::heap::Heaped;
 use crate::java::{FieldType, Int, Long, MethodType, Reference, Value};
 use crate::method_area;
 use crate::method_area::ObjectClass;
@@ -285,7 +287,10 @@ fn put_long(args: &Args) -> (Option<Value>, Option<Value>) {
 fn allocate_memory(args: &Args) -> (Option<Value>, Option<Value>) {
     let bytes = args.params[1].long().0 as usize;
 
-    let raw_ptr = args.runtime.heap.allocator.raw(bytes);
+    let Ok(raw_ptr) = args.runtime.heap.allocator.raw(bytes) else {
+        let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+        return (None, Some(thread.out_of_memory_error()));
+    };
 
     let ptr = raw_ptr as usize;
     let ptr = ptr as i64;
@@ -317,9 +322,7 @@ fn new_instance(args: &Args) -> (Option<Value>, Option<Value>) {
     let constr_obj = args.runtime.heap.get_object(constr_ref);
 
     let class_ref = constr_obj.get_ref("clazz");
-    let class = args.runtime.heap.get_object(class_ref);
-    let name = class.get_string("name", &args.runtime.heap);
-    let class = args.runtime.method_area.load_outer_class(&name);
+    let class = args.runtime.method_area.class_of(class_ref);
     let class = class.obj();
     let constr = if args_arr_ref.0 == 0 {
         class.find_method(&MethodKey {
@@ -344,10 +347,15 @@ fn new_instance(args: &Args) -> (Option<Value>, Option<Value>) {
         constr
     };
 
-    let object_ref = args.runtime.heap.new_object(class.deref());
+    // The arguments can move if the allocation collects garbage.
+    let args_arr = args.new_local(args_arr_ref);
+    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+    let Ok(object_ref) = args.runtime.heap.try_new_object(thread, class.deref()) else {
+        return (None, Some(thread.out_of_memory_error()));
+    };
 
     let constr_args = if constr.descriptor.parameters.len() > 0 {
-        let args_arr = args.runtime.heap.get_array(args_arr_ref);
+        let args_arr = args.runtime.heap.get_array(args.local(args_arr));
         let mut constr_args = vec![];
         constr_args.push(Value::Reference(object_ref));
         for idx in 0..args_arr.length().0 {
@@ -358,13 +366,13 @@ fn new_instance(args: &Args) -> (Option<Value>, Option<Value>) {
         vec![Value::Reference(object_ref)]
     };
 
-    let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
-
+    // The object can move while it's constructed.
+    let object = args.new_local(object_ref);
     let (_, ex) = thread.native_invoke(class.deref() as *const ObjectClass, constr as *const method_area::Method, constr_args);
     if ex.is_some() {
         (None, ex)
     } else {
-        (Some(Value::Reference(object_ref)), None)
+        (Some(Value::Reference(args.local(object))), None)
     }
 }
 
@@ -386,15 +394,22 @@ fn identity_hash_code(args: &Args) -> (Option<Value>, Option<Value>) {
 
 fn init_properties(args: &Args) -> (Option<Value>, Option<Value>) {
     // We need to insert some normal properties now!
-    let initial_props = hashmap! {
+    let class_path = join_paths(&args.runtime.options.class_path).unwrap_or_default();
+    let class_path = class_path.to_string_lossy();
+    let mut initial_props = hashmap! {
         "file.encoding" => "UTF-8",
         "file.separator" => "/",
         "line.separator" => "\n",
         "path.separator" => ":",
+        "java.class.path" => class_path.as_ref(),
         "java.home" => "/Users/kitch/Code/robusta/",
         "java.library.path" => "/Users/kitch/Code/robusta/target/debug",
         "sun.boot.library.path" => "/Users/kitch/Code/robusta/target/debug"
     };
+    // Properties from the command line override our own.
+    for (key, val) in &args.runtime.options.properties {
+        initial_props.insert(key.as_str(), val.as_str());
+    }
 
     let props = args.params[0].reference();
     let properties_class = args.runtime.method_area.load_class("java.util.Properties");
@@ -405,12 +420,14 @@ fn init_properties(args: &Args) -> (Option<Value>, Option<Value>) {
     }).unwrap();
 
     let thread = unsafe { args.thread.cast_mut().as_mut().unwrap() };
+    // The properties can move while each one is set.
+    let props = args.new_local(props);
 
     for (key, val) in initial_props {
         let key = args.runtime.method_area.load_string(key);
         let val = args.runtime.method_area.load_string(val);
         let (_, ex) = thread.native_invoke(properties_class.deref() as *const ObjectClass, properties_set as *const method_area::Method, vec![
-            Value::Reference(props),
+            Value::Reference(args.local(props)),
             Value::Reference(key),
             Value::Reference(val),
         ]);
@@ -433,6 +450,7 @@ fn set_in_0(args: &Args) -> (Option<Value>, Option<Value>) {
         name: "in".to_string(),
         descriptor: FieldType::from_descriptor("Ljava.io.InputStream;").unwrap(),
     }, Value::Reference(input_stream));
+    args.runtime.heap.write_barrier(Heaped::Object(static_obj));
 
     (None, None)
 }
@@ -449,6 +467,7 @@ fn set_out_0(args: &Args) -> (Option<Value>, Option<Value>) {
         name: "out".to_string(),
         descriptor: FieldType::from_descriptor("Ljava.io.PrintStream;").unwrap(),
     }, Value::Reference(print_stream));
+    args.runtime.heap.write_barrier(Heaped::Object(static_obj));
 
     (None, None)
 }
@@ -482,6 +501,7 @@ fn set_err_0(args: &Args) -> (Option<Value>, Option<Value>) {
         name: "err".to_string(),
         descriptor: FieldType::from_descriptor("Ljava.io.PrintStream;").unwrap(),
     }, Value::Reference(print_stream));
+    args.runtime.heap.write_barrier(Heaped::Object(static_obj));
 
     (None, None)
 }
@@ -1,28 +1,196 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 
 use crate::heap::Heap;
 use crate::method_area::MethodArea;
 use crate::native::NativeMethods;
 use crate::thread::Thread;
+use crate::thread::safepoint::Safepoint;
+use crate::thread::vm::VmThread;
+
+/// Which classes have their bytecode verified before they are initialized.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Verify {
+    /// Don't verify any classes, as with `-Xverify:none`.
+    None,
+    /// Verify all classes except those loaded from the boot class path, the default.
+    Remote,
+    /// Verify all classes, as with `-Xverify:all`.
+    All,
+}
+
+/// Which classes have assertions enabled, as with `-ea`, `-da`, `-esa` and `-dsa`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Assertions {
+    /// Whether assertions are enabled in application classes by default.
+    pub user: bool,
+    /// Whether assertions are enabled in system classes by default.
+    pub system: bool,
+    /// Packages that have assertions enabled or disabled, along with their sub packages.
+    ///
+    /// The unnamed package is the empty string.
+    pub packages: HashMap<String, bool>,
+    /// Classes that have assertions enabled or disabled.
+    pub classes: HashMap<String, bool>,
+}
+
+impl Assertions {
+    /// Whether a class has assertions enabled, the most specific option for the class is used.
+    pub fn enabled(&self, class_name: &str, is_system: bool) -> bool {
+        if let Some(enabled) = self.classes.get(class_name) {
+            return *enabled;
+        }
+
+        let mut package = class_name.rsplit_once('.').map_or("", |(package, _)| package);
+        loop {
+            if let Some(enabled) = self.packages.get(package) {
+                return *enabled;
+            }
+            match package.rsplit_once('.') {
+                Some((parent, _)) => package = parent,
+                // The unnamed package isn't a parent of named packages.
+                None => break,
+            }
+        }
+
+        if is_system { self.system } else { self.user }
+    }
+}
+
+/// Where a log is written, as with the output of `-Xlog`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LogOutput {
+    Stdout,
+    Stderr,
+    File(PathBuf),
+}
+
+/// What is written before each line of a unified log, as with the decorators of `-Xlog`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Decorations {
+    /// The time since the runtime started, in seconds.
+    pub uptime: bool,
+    /// The time since the runtime started, in milliseconds.
+    pub uptime_millis: bool,
+    /// The time since the runtime started, in nanoseconds.
+    pub uptime_nanos: bool,
+    pub level: bool,
+    pub tags: bool,
+}
+
+impl Default for Decorations {
+    /// The uptime, level and tags, like `java`.
+    fn default() -> Self {
+        Decorations { uptime: true, uptime_millis: false, uptime_nanos: false, level: true, tags: true }
+    }
+}
+
+/// A unified log of garbage collections, as configured with `-Xlog`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GcLogging {
+    /// Log a summary of each collection, as with `-Xlog:gc`.
+    pub gc: bool,
+    /// Log the start, generations and CPU time of each collection too, as with `-Xlog:gc*`.
+    pub details: bool,
+    /// Log how long the threads took to stop for each collection, as with `-Xlog:safepoint`.
+    pub safepoint: bool,
+    pub output: LogOutput,
+    pub decorations: Decorations,
+}
+
+/// The options used to configure a runtime.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Options {
+    pub verify: Verify,
+    /// The paths to load the Java platform classes from, `./classes/rt.jar` by default.
+    pub boot_class_path: Vec<PathBuf>,
+    /// The paths to load application classes from, `./classes` by default.
+    pub class_path: Vec<PathBuf>,
+    /// System properties, as set with `-D`, these override the runtime's own properties.
+    pub properties: HashMap<String, String>,
+    /// The maximum size of the heap in bytes, as set with `-Xmx`.
+    pub max_heap_size: Option<usize>,
+    /// The initial size of the heap in bytes, as set with `-Xms`.
+    pub initial_heap_size: Option<usize>,
+    pub assertions: Assertions,
+    /// Print each class as it is loaded, as with `-verbose:class`.
+    pub verbose_class: bool,
+    /// Print a summary of each garbage collection, as with `-verbose:gc`.
+    pub verbose_gc: bool,
+    /// Print how long the threads were stopped for each garbage collection, as with
+    /// `-XX:+PrintGCApplicationStoppedTime`.
+    pub print_gc_application_stopped_time: bool,
+    /// The unified logs of garbage collections, as with `-Xlog:gc`, each with its own output.
+    pub gc_logs: Vec<GcLogging>,
+    /// Collect garbage every `gc_stress_interval` allocations, as with `-XX:+GCStress`, to
+    /// shake out objects that the collector doesn't know are alive.
+    pub gc_stress: bool,
+    /// The number of allocations between stress collections, as set with
+    /// `-XX:GCStressInterval`.
+    pub gc_stress_interval: usize,
+    /// Check the heap is consistent after each garbage collection, as with `-XX:+VerifyHeap`.
+    pub verify_heap: bool,
+    /// Dump the heap the first time it's full, as with `-XX:+HeapDumpOnOutOfMemoryError`.
+    pub heap_dump_on_out_of_memory_error: bool,
+    /// The file or directory to dump the heap to when it's full, as set with
+    /// `-XX:HeapDumpPath`.
+    pub heap_dump_path: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            verify: Verify::Remote,
+            boot_class_path: vec![PathBuf::from("./classes/rt.jar")],
+            class_path: vec![PathBuf::from("./classes")],
+            properties: HashMap::new(),
+            max_heap_size: None,
+            initial_heap_size: None,
+            assertions: Assertions::default(),
+            verbose_class: false,
+            verbose_gc: false,
+            print_gc_application_stopped_time: false,
+            gc_logs: Vec::new(),
+            gc_stress: false,
+            gc_stress_interval: 1,
+            verify_heap: false,
+            heap_dump_on_out_of_memory_error: false,
+            heap_dump_path: None,
+        }
+    }
+}
 
 pub struct Runtime {
+    pub options: Options,
     pub heap: Box<Heap>,
     pub method_area: Box<MethodArea>,
     pub native: Box<NativeMethods>,
     pub threads2: RwLock<Vec<Arc<Thread>>>,
+    /// Stops the threads for the operations of the VM thread.
+    pub safepoint: Safepoint,
+    /// Runs the operations that need the threads stopped, such as garbage collections.
+    pub vm_thread: VmThread,
 }
 
 unsafe impl Send for Runtime {}
 
 impl Runtime {
     pub fn new() -> Arc<Self> {
-        let heap = Box::new(Heap::new());
-        let method_area = Box::new(MethodArea::new(heap.as_ref() as *const Heap));
+        Runtime::with_options(Options::default())
+    }
+
+    pub fn with_options(options: Options) -> Arc<Self> {
+        let heap = Box::new(Heap::new(&options));
+        let method_area = Box::new(MethodArea::new(heap.as_ref() as *const Heap, &options));
         let rt = Arc::new(Runtime {
+            options,
             heap,
             method_area,
             native: Box::new(NativeMethods::new()),
             threads2: RwLock::new(Vec::new()),
+            safepoint: Safepoint::new(),
+            vm_thread: VmThread::start(),
         });
         rt.heap.allocator.set_rt(rt.clone());
         rt
@@ -31,4 +199,46 @@ impl Runtime {
     pub fn clear(&self) {
         self.method_area.clear();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::hashmap;
+
+    use super::*;
+
+    #[test]
+    fn assertions_default() {
+        let assertions = Assertions { user: true, ..Assertions::default() };
+
+        assert!(assertions.enabled("Main", false));
+        assert!(assertions.enabled("com.example.Main", false));
+        assert!(!assertions.enabled("java.lang.Object", true));
+    }
+
+    #[test]
+    fn assertions_most_specific() {
+        let assertions = Assertions {
+            user: true,
+            system: false,
+            packages: hashmap! {
+                "".to_string() => false,
+                "com.example".to_string() => false,
+                "com.example.debug".to_string() => true,
+                "java.util".to_string() => true,
+            },
+            classes: hashmap! {
+                "com.example.Main".to_string() => true,
+            },
+        };
+
+        assert!(!assertions.enabled("Main", false));
+        assert!(assertions.enabled("com.Main", false));
+        assert!(assertions.enabled("com.example.Main", false));
+        assert!(!assertions.enabled("com.example.Other", false));
+        assert!(!assertions.enabled("com.example.inner.Other", false));
+        assert!(assertions.enabled("com.example.debug.Other", false));
+        assert!(assertions.enabled("java.util.HashMap", true));
+        assert!(!assertions.enabled("java.lang.Object", true));
+    }
+}
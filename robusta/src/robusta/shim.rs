@@ -2,7 +2,7 @@ use std::collections::HashMap;
 use nohash_hasher::BuildNoHashHasher;
 use crate::class_file::Code;
 use crate::collection::once::Once;
-use crate::java::MethodType;
+use crate::java::{MethodType, Reference};
 use crate::method_area::{ObjectClass, ClassFlags, Method};
 use crate::method_area::const_pool::{ClassKey, Const, ConstPool, MethodKey, SymbolicReference};
 
@@ -31,7 +31,7 @@ pub fn create_main_thread() -> ObjectClass {
     ObjectClass {
         name: "<main-thread>".to_string(),
         flags: ClassFlags { bits: 0 },
-        const_pool: ConstPool { pool },
+        const_pool: ConstPool { pool, loader: Reference(0) },
         super_class: None,
         interfaces: vec![],
         instance_fields: vec![],
@@ -86,5 +86,8 @@ pub fn create_main_thread() -> ObjectClass {
         instance_width: 0,
         static_width: 0,
         source_file: None,
+        loader: Reference(0),
+        reference_type: None,
+        has_finalizer: false,
     }
 }
\ No newline at end of file
@@ -0,0 +1,84 @@
+//! Thread dumps, with the state and stack of each Java thread, printed when the process is sent
+//! `SIGQUIT`, like `java` does.
+
+use std::fmt::Write;
+use std::io::{stdout, Write as _};
+use std::sync::Arc;
+use std::thread::Builder;
+use std::time::Instant;
+
+use signal_hook::consts::SIGQUIT;
+use signal_hook::iterator::Signals;
+use tracing::debug;
+
+use crate::log;
+use crate::runtime::Runtime;
+use crate::thread::Thread;
+use crate::thread::safepoint::ThreadState;
+use crate::thread::vm::{SafepointTiming, VmOperation};
+
+/// A dump of every Java thread, taken at a safepoint, so that the stacks are consistent.
+#[derive(Default)]
+pub struct ThreadDump {
+    pub dump: String,
+}
+
+impl VmOperation for ThreadDump {
+    fn name(&self) -> &'static str {
+        "ThreadDump"
+    }
+
+    fn doit(&mut self, _: &Arc<Runtime>, threads: &[Arc<Thread>], _: SafepointTiming) {
+        self.dump = format!("Full thread dump Robusta Java Virtual Machine ({}):\n\n", env!("CARGO_PKG_VERSION"));
+        for (idx, thread) in threads.iter().enumerate() {
+            write_thread(&mut self.dump, idx + 1, thread);
+        }
+    }
+
+    fn epilogue(&mut self, _: Instant) {}
+}
+
+/// Write a thread's name, state and stack, most recent frame first, skipping the frames of the
+/// runtime's own stubs.
+fn write_thread(dump: &mut String, serial: usize, thread: &Thread) {
+    let state = match thread.state() {
+        ThreadState::New => "new",
+        ThreadState::InJava => "runnable",
+        ThreadState::InNative => "in native",
+        ThreadState::Blocked => "blocked",
+        ThreadState::Terminated => "terminated",
+    };
+    writeln!(dump, "\"{}\" #{} {}", thread.name, serial, state).unwrap();
+
+    for frame in thread.stack.iter().rev().filter(|frame| !frame.class.starts_with('<')) {
+        let method = unsafe { frame.method.as_ref().unwrap() };
+        let class = unsafe { method.class.as_ref().unwrap() };
+        let location = match (&frame.native, &class.source_file, frame.line_number()) {
+            (Some(_), _, _) => "Native Method".to_string(),
+            (None, Some(file), Some(line)) => format!("{}:{}", file, line),
+            (None, Some(file), None) => file.clone(),
+            (None, None, _) => "Unknown Source".to_string(),
+        };
+        writeln!(dump, "\tat {}.{}({})", frame.class, method.name, location).unwrap();
+    }
+    dump.push('\n');
+}
+
+/// Print a thread dump every time the process is sent `SIGQUIT`, from a new thread that waits
+/// for the signal.
+pub fn dump_threads_on_quit(runtime: Arc<Runtime>) {
+    let Ok(mut signals) = Signals::new([SIGQUIT]) else {
+        debug!(target: log::THREAD, "Unable to handle SIGQUIT, threads won't be dumped");
+        return;
+    };
+    Builder::new()
+        .name("Signal Dispatcher".to_string())
+        .spawn(move || {
+            for _ in signals.forever() {
+                let dump = runtime.vm_thread.execute(runtime.clone(), ThreadDump::default()).dump;
+                let mut stdout = stdout().lock();
+                let _ = stdout.write_all(dump.as_bytes()).and_then(|_| stdout.flush());
+            }
+        })
+        .unwrap();
+}
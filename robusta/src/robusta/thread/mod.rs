@@ -1,11 +1,13 @@
-use std::collections::{HashMap, HashSet};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ptr;
 use std::sync::Arc;
 
 use nohash_hasher::BuildNoHashHasher;
-use parking_lot::Condvar;
-use parking_lot::lock_api::Mutex;
 use tracing::debug;
 
+use crate::heap::allocator::{using_reserve, with_reserve};
+use crate::heap::dump::dump_on_out_of_memory;
 use crate::heap::sync::Synchronized;
 use crate::instruction::instruction;
 use crate::java::{CategoryOne, FieldType, Int, MethodType, Reference, Value};
@@ -14,70 +16,21 @@ use crate::method_area::{Method, ObjectClass};
 use crate::method_area::const_pool::{ConstPool, FieldKey, MethodKey};
 use crate::native::{Args, Plugin};
 use crate::runtime::Runtime;
+use crate::thread::safepoint::{AtomicThreadState, ThreadState};
 
-pub struct Safe {
-    name: String,
-    state: parking_lot::Mutex<(bool, bool)>,
-    wait: Condvar,
-}
-
-impl Safe {
-    pub fn new(name: String) -> Self {
-        Safe {
-            name,
-            state: Mutex::new((false, false)),
-            wait: Condvar::new(),
-        }
-    }
-
-    /// Let GC know that we are ready to start GC!
-    pub fn enter(&self) {
-        let mut lock = self.state.lock();
-        lock.0 = true;
-        self.wait.notify_all();
-    }
-
-    pub fn start_gc(&self) {
-        let mut lock = self.state.lock();
-        // Wait for thread to become safe.
-        while !lock.0 {
-            self.wait.wait_while(&mut lock, |(thread, _)| {
-                !*thread
-            });
-        }
-        lock.1 = true;
-        debug!(target: log::GC, "Stopped thread {}", self.name.as_str());
-    }
-
-    /// Wait for GC to end.
-    pub fn exit(&self) {
-        let mut lock = self.state.lock();
-        while lock.1 {
-            self.wait.wait_while(&mut lock, |(_, gc)| {
-                *gc
-            });
-        }
-        lock.0 = false;
-    }
-
-    pub fn end_gc(&self) {
-        let mut lock = self.state.lock();
-        lock.1 = false;
-        self.wait.notify_all();
-    }
-
-    pub fn safe_region(&self) {
-        self.enter();
-        self.exit();
-    }
-}
+pub mod dump;
+pub mod safepoint;
+pub mod vm;
 
 /// A single Java thread in the running program.
 pub struct Thread {
     pub name: String,
     pub reference: Option<Reference>,
-    pub locks: HashMap<u32, Synchronized, BuildNoHashHasher<u32>>,
-    pub safe: Safe,
+    /// The monitors this thread holds, keyed by the [`id`](crate::heap::sync::ObjectLock::id)
+    /// of their lock, as the objects can move.
+    pub locks: HashMap<usize, Synchronized, BuildNoHashHasher<usize>>,
+    /// Whether the thread is running Java code, or is safe for a safepoint to go ahead.
+    state: AtomicThreadState,
     /// A reference to the common runtime areas that are shared across one instance of a
     /// running program.
     pub runtime: Arc<Runtime>,
@@ -91,30 +44,116 @@ unsafe impl Send for Thread {}
 
 unsafe impl Sync for Thread {}
 
+thread_local! {
+    /// The Java thread that this native thread is running, once it has started running it.
+    static CURRENT: RefCell<Option<Arc<Thread>>> = const { RefCell::new(None) };
+}
+
 impl Thread {
+    /// The Java thread that the current native thread is running, if it's running one.
+    pub fn current() -> Option<Arc<Thread>> {
+        CURRENT.with_borrow(|current| current.clone())
+    }
+
+    /// Whether this is the Java thread that the current native thread is running.
+    pub fn is_current(&self) -> bool {
+        CURRENT.with_borrow(|current| current.as_ref().is_some_and(|current| ptr::eq(current.as_ref(), self)))
+    }
+
+    /// Enter the monitor of an object, blocking until it's free.
+    ///
+    /// The thread can stop for a garbage collection while it's blocked, so the reference
+    /// mustn't be used again afterwards.
     pub fn enter_monitor(&mut self, object_ref: Reference) {
-        if self.locks.contains_key(&object_ref.0) {
-            let sync = self.locks.get_mut(&object_ref.0).unwrap();
+        let object = self.runtime.heap.get_object(object_ref);
+        let lock = &object.header().lock;
+        if let Some(sync) = self.locks.get_mut(&lock.id()) {
             sync.enter();
         } else {
-            let object = self.runtime.heap.get_object(object_ref);
-            let header = unsafe { object.header.as_ref().unwrap() };
-            self.safe.enter();
-            let sync = header.lock.lock();
-            self.safe.exit();
-            self.locks.insert(object_ref.0, sync);
+            // The object may move while we wait, but its lock doesn't.
+            let lock = lock.move_me();
+            self.enter_safe(ThreadState::Blocked);
+            let sync = lock.lock();
+            self.exit_safe();
+            self.locks.insert(lock.id(), sync);
         }
     }
 
     pub fn exit_monitor(&mut self, object_ref: Reference) {
-        let sync = self.locks.get_mut(&object_ref.0).unwrap();
+        let id = self.runtime.heap.get_object(object_ref).header().lock.id();
+        let sync = self.locks.get_mut(&id).unwrap();
         let should_remove = sync.exit();
         if should_remove {
-            let sync = self.locks.remove(&object_ref.0).unwrap();
+            let sync = self.locks.remove(&id).unwrap();
             drop(sync);
         }
     }
 
+    /// Enter the monitor of the method of the frame on top of the stack, if it's synchronized.
+    ///
+    /// The monitor is read from the frame, which is updated if the object moves while the
+    /// thread is blocked.
+    fn enter_method_monitor(&mut self) {
+        let frame = self.stack.last_mut().unwrap();
+        let method = unsafe { frame.method.as_ref().unwrap() };
+        if method.is_synchronized {
+            let monitor_ref = if method.is_static {
+                self.runtime.heap.get_static(unsafe { method.class.as_ref().unwrap() })
+            } else {
+                frame.local_vars.load_cat_one(0).reference()
+            };
+            self.enter_monitor(monitor_ref);
+        }
+    }
+
+    /// Keep a reference alive, and up to date, while the thread might stop for a garbage
+    /// collection, until it's popped with [`pop_root`](Thread::pop_root).
+    ///
+    /// Returns the index of the root, to read its current value with [`root`](Thread::root).
+    pub fn push_root(&mut self, reference: Reference) -> usize {
+        let roots = &mut self.stack.last_mut().unwrap().native_roots;
+        roots.push(reference);
+        roots.len() - 1
+    }
+
+    /// The current value of a root pushed onto the current frame.
+    pub fn root(&self, index: usize) -> Reference {
+        self.stack.last().unwrap().native_roots[index]
+    }
+
+    /// Pop the last root pushed onto the current frame, returning its current value.
+    pub fn pop_root(&mut self) -> Reference {
+        self.stack.last_mut().unwrap().native_roots.pop().unwrap()
+    }
+
+    /// What the thread is doing, as far as safepoints are concerned.
+    pub fn state(&self) -> ThreadState {
+        self.state.load()
+    }
+
+    /// Mark the thread as safe, before it blocks or runs native code that doesn't use the heap,
+    /// so that safepoints don't wait for it.
+    ///
+    /// The references the thread holds can move while it's safe, so it must not be holding any
+    /// that aren't visible to the collector.
+    pub fn enter_safe(&self, state: ThreadState) {
+        self.runtime.safepoint.enter(self, state);
+    }
+
+    /// Mark the thread as running Java code again, waiting for a safepoint in progress to end.
+    pub fn exit_safe(&self) {
+        self.runtime.safepoint.exit(self);
+    }
+
+    /// Stop the thread if a safepoint has been requested, until it ends, which the interpreter
+    /// checks at backward branches and method returns.
+    #[inline]
+    pub fn poll_safepoint(&self) {
+        if self.runtime.safepoint.is_requested() {
+            self.runtime.safepoint.block(self);
+        }
+    }
+
     pub fn as_mut<'a>(self: &'a Arc<Self>) -> &'a mut Thread {
         unsafe {
             let thread = self.as_ref() as *const Thread;
@@ -142,21 +181,12 @@ impl Thread {
             pc: 0,
             native: None,
             native_args: vec![],
-            native_roots: HashSet::with_hasher(BuildNoHashHasher::default()),
+            native_roots: vec![],
             native_ex: None,
         });
 
         let depth = self.stack.len();
 
-        if method2.is_synchronized {
-            let monitor_ref = if method2.is_static {
-                self.runtime.heap.get_static(class)
-            } else {
-                args[0].reference().clone()
-            };
-            self.enter_monitor(monitor_ref);
-        }
-
         self.push_frame(class.name.clone(), &class.const_pool as *const ConstPool, method, args);
 
 
@@ -190,7 +220,7 @@ impl Thread {
             pc: 0,
             native: None,
             native_args: vec![],
-            native_roots: HashSet::with_hasher(BuildNoHashHasher::default()),
+            native_roots: vec![],
             native_ex: None,
         };
         let mut i = 0;
@@ -203,7 +233,7 @@ impl Thread {
             name: name.clone(),
             reference,
             locks: HashMap::with_hasher(BuildNoHashHasher::default()),
-            safe: Safe::new(name.clone()),
+            state: AtomicThreadState::new(ThreadState::New),
             runtime: runtime.clone(),
             stack: vec![frame],
         });
@@ -213,7 +243,17 @@ impl Thread {
         thread
     }
 
-    pub fn run(&mut self) {
+    /// Run the thread on the current native thread until its stack is empty, and then
+    /// unregister it, as it can't stop for a safepoint again.
+    pub fn run(self: &Arc<Self>) {
+        CURRENT.set(Some(self.clone()));
+        self.as_mut().run_stack();
+        self.runtime.threads2.write().unwrap().retain(|thread| !Arc::ptr_eq(thread, self));
+        CURRENT.set(None);
+    }
+
+    fn run_stack(&mut self) {
+        self.exit_safe();
         self.reference.map(|r| self.runtime.heap.start_thread(r));
         let class_name = self.stack.last().unwrap().class.as_str();
         let method = unsafe { self.stack.last().unwrap().method.as_ref().unwrap() };
@@ -225,10 +265,8 @@ impl Thread {
         }
         debug!(target: log::THREAD, method=method_name, "Ended thread instructions");
 
-        // Forever safe!
-        self.safe.enter();
-
-        // Notify all waiting listeners
+        // Notify all waiting listeners, before becoming safe, as the thread object can move
+        // once the thread is safe.
         if let Some(thread_ref) = self.reference {
             let thread_obj = self.runtime.heap.get_object(thread_ref);
             thread_obj.set_field(&FieldKey {
@@ -239,12 +277,13 @@ impl Thread {
             thread_obj.header().lock.notify_all();
         }
 
+        // Forever safe!
+        self.enter_safe(ThreadState::Terminated);
+
         debug!(target: log::THREAD, method=method_name, "Ended thread");
     }
 
     pub fn next(&mut self) {
-        self.safe.safe_region();
-
         let curr_frame = self.stack.last_mut().unwrap();
         let method = unsafe { curr_frame.method.as_ref().unwrap() };
 
@@ -263,14 +302,7 @@ impl Thread {
             );
             self.stack.pop();
             if let Some(ex) = ex {
-                // Invoke throwing.
-                let robusta_class = self.runtime.method_area.load_class("com.jkitch.robusta.Robusta");
-                let throw_method = robusta_class.find_method(&MethodKey {
-                    class: "com.jkitch.robusta.Robusta".to_string(),
-                    name: "throwThrowable".to_string(),
-                    descriptor: MethodType::from_descriptor("(Ljava/lang/Throwable;)V").unwrap(),
-                }).unwrap();
-                self.push_frame(robusta_class.name.clone(), &robusta_class.const_pool as *const ConstPool, throw_method as *const Method, vec![ex]);
+                self.throw(ex);
             } else if let Some(result) = result {
                 let frame = self.stack.last_mut().unwrap();
                 frame.operand_stack.push(result);
@@ -282,7 +314,78 @@ impl Thread {
         instruction(self);
     }
 
-    /// Push a new frame onto the top of the stack.
+    /// Throw the throwable from the current frame.
+    ///
+    /// This pushes a frame that throws it, so it is thrown on the next instruction.
+    pub fn throw(&mut self, throwable: Value) {
+        let robusta_class = self.runtime.method_area.load_class("com.jkitch.robusta.Robusta");
+        let throw_method = robusta_class.find_method(&MethodKey {
+            class: "com.jkitch.robusta.Robusta".to_string(),
+            name: "throwThrowable".to_string(),
+            descriptor: MethodType::from_descriptor("(Ljava/lang/Throwable;)V").unwrap(),
+        }).unwrap();
+        self.push_frame(robusta_class.name.clone(), &robusta_class.const_pool as *const ConstPool, throw_method as *const Method, vec![throwable]);
+    }
+
+    /// Create a new instance of a throwable class with the given message, for the JVM to throw.
+    ///
+    /// If creating the throwable throws an exception itself, that exception is returned instead.
+    pub fn new_throwable(&mut self, class_name: &str, message: &str) -> Value {
+        let runtime = self.runtime.clone();
+        let class = runtime.method_area.load_class(class_name);
+        if let Err(ex) = runtime.method_area.initialize(self, &class) {
+            return ex;
+        }
+
+        let message = runtime.method_area.load_string(message);
+        let throwable = runtime.heap.new_object(&class);
+        let init = class.find_method(&MethodKey {
+            class: class_name.to_string(),
+            name: "<init>".to_string(),
+            descriptor: MethodType::from_descriptor("(Ljava/lang/String;)V").unwrap(),
+        }).unwrap();
+
+        self.push_root(throwable);
+        let (_, ex) = self.native_invoke(&*class as *const ObjectClass, init as *const Method,
+                                         vec![Value::Reference(throwable), Value::Reference(message)]);
+        let throwable = self.pop_root();
+        ex.unwrap_or(Value::Reference(throwable))
+    }
+
+    /// The `OutOfMemoryError` for the JVM to throw when the heap is full.
+    ///
+    /// The error is allocated when the JVM starts, and its stack trace is filled in using the
+    /// space the heap keeps in reserve, as there's no space left for Java code. With
+    /// `-XX:+HeapDumpOnOutOfMemoryError`, the heap is dumped first.
+    pub fn out_of_memory_error(&mut self) -> Value {
+        let runtime = self.runtime.clone();
+        let Some(error) = runtime.heap.out_of_memory_error() else {
+            return with_reserve(|| self.new_throwable("java.lang.OutOfMemoryError", "Java heap space"));
+        };
+        if using_reserve() {
+            // The reserve is full too, the error is thrown with its last stack trace.
+            return Value::Reference(error);
+        }
+        dump_on_out_of_memory(&runtime);
+
+        let throwable_class = runtime.method_area.load_class("java.lang.Throwable");
+        let fill_in_stack_trace = throwable_class.find_method(&MethodKey {
+            class: "java.lang.Throwable".to_string(),
+            name: "fillInStackTrace".to_string(),
+            descriptor: MethodType::from_descriptor("()Ljava/lang/Throwable;").unwrap(),
+        }).unwrap();
+
+        let (_, ex) = with_reserve(|| {
+            self.native_invoke(&*throwable_class as *const ObjectClass, fill_in_stack_trace as *const Method,
+                               vec![Value::Reference(error)])
+        });
+        // The error may have moved while its stack trace was filled in.
+        let error = runtime.heap.out_of_memory_error().unwrap();
+        ex.unwrap_or(Value::Reference(error))
+    }
+
+    /// Push a new frame onto the top of the stack, entering the monitor of a synchronized
+    /// method.
     pub fn push_frame(&mut self, class: String, const_pool: *const ConstPool, method: *const Method, args: Vec<Value>) {
         let mut frame = Frame {
             class,
@@ -293,7 +396,7 @@ impl Thread {
             method,
             native: None,
             native_args: vec![],
-            native_roots: HashSet::with_hasher(BuildNoHashHasher::default()),
+            native_roots: vec![],
             native_ex: None,
         };
 
@@ -304,8 +407,10 @@ impl Thread {
         }
 
         self.stack.push(frame);
+        self.enter_method_monitor();
     }
-    /// Push a new frame onto the top of the stack.
+    /// Push a new frame for a native method onto the top of the stack, entering the monitor of
+    /// a synchronized method.
     pub fn push_native(&mut self, class: String, const_pool: *const ConstPool, method: *const Method, args: Vec<Value>, plugin: Arc<dyn Plugin>) {
         let mut frame = Frame {
             class,
@@ -316,20 +421,18 @@ impl Thread {
             method,
             native: Some(plugin),
             native_args: args.clone(),
-            native_roots: HashSet::with_hasher(BuildNoHashHasher::default()),
+            native_roots: vec![],
             native_ex: None,
         };
 
         let mut idx = 0;
         for arg in args {
             frame.local_vars.store_value(idx, arg.clone());
-            if let Value::Reference(reference) = arg {
-                frame.native_roots.insert(reference.0);
-            }
             idx += arg.category() as u16;
         }
 
         self.stack.push(frame);
+        self.enter_method_monitor();
     }
 }
 
@@ -347,14 +450,27 @@ pub struct Frame {
 
     /// For native methods only.
     pub native: Option<Arc<dyn Plugin>>,
+    /// The arguments of the native method, which are updated when their objects move.
     pub native_args: Vec<Value>,
-    pub native_roots: HashSet<u32, BuildNoHashHasher<u32>>,
+    /// The references that the JVM keeps alive while it runs the frame, see
+    /// [`Thread::push_root`].
+    pub native_roots: Vec<Reference>,
     pub native_ex: Option<Reference>,
 }
 
 unsafe impl Send for Frame {}
 
 impl Frame {
+    /// The line of the source file that the frame is at, if the method has line numbers.
+    pub fn line_number(&self) -> Option<u16> {
+        let method = unsafe { self.method.as_ref().unwrap() };
+        let table = method.code.as_ref()?.line_number_table()?;
+        table.table.iter()
+            .filter(|line| line.start_pc as usize <= self.pc)
+            .last()
+            .map(|line| line.line_number)
+    }
+
     fn code(&self) -> &[u8] {
         let method = unsafe { self.method.as_ref().unwrap() };
         &method.code.as_ref().unwrap().code
@@ -417,16 +533,15 @@ impl OperandStack {
         OperandStack { stack: vec![] }
     }
 
-    /// Get the roots out of the operand stack.
-    pub fn roots(&self) -> HashSet<u32, BuildNoHashHasher<u32>> {
-        self.stack.iter()
+    /// Get the roots out of the operand stack, for GC to update if it moves their objects.
+    pub fn roots(&mut self) -> impl Iterator<Item=&mut Reference> {
+        self.stack.iter_mut()
             .filter_map(|v| {
                 match v {
-                    Value::Reference(reference) => Some(reference.0),
+                    Value::Reference(reference) => Some(reference),
                     _ => None
                 }
             })
-            .collect()
     }
 
     pub fn push(&mut self, value: Value) {
@@ -436,6 +551,37 @@ impl OperandStack {
     pub fn pop(&mut self) -> Value {
         self.stack.pop().unwrap()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// Pop values totalling `units` from the stack, returned in the order they were pushed.
+    ///
+    /// Category 2 values (`long` and `double`) count as two units, and category 1 values
+    /// as one, so `pop_units(2)` pops either one category 2 value or two category 1 values.
+    /// Splitting a category 2 value is an error.
+    ///
+    /// For further information, see [the spec](https://docs.oracle.com/javase/specs/jvms/se8/html/jvms-2.html#jvms-2.11.1).
+    pub fn pop_units(&mut self, units: usize) -> Vec<Value> {
+        let mut values = vec![];
+        let mut popped = 0;
+        while popped < units {
+            let value = self.pop();
+            popped += value.category();
+            values.push(value);
+        }
+        assert_eq!(popped, units, "Cannot split a category 2 value on the operand stack");
+        values.reverse();
+        values
+    }
+
+    /// Push each value onto the stack in order.
+    pub fn push_all(&mut self, values: &[Value]) {
+        for value in values {
+            self.stack.push(*value);
+        }
+    }
 }
 
 /// Each frame contains an array of variables called the local variables.
@@ -451,15 +597,15 @@ impl LocalVars {
         LocalVars { map: HashMap::with_hasher(BuildNoHashHasher::default()) }
     }
 
-    /// Get the roots from this local vars for GC.
-    pub fn roots(&self) -> HashSet<u32, BuildNoHashHasher<u32>> {
-        self.map.values()
+    /// Get the roots from this local vars for GC, to update if it moves their objects.
+    pub fn roots(&mut self) -> impl Iterator<Item=&mut Reference> {
+        self.map.values_mut()
             .filter_map(|v| {
                 match v {
-                    Value::Reference(reference) => Some(reference.0),
+                    Value::Reference(reference) => Some(reference),
                     _ => None
                 }
-            }).collect()
+            })
     }
 
     /// Store a value in the local vars.
@@ -0,0 +1,259 @@
+//! Safepoints, where every Java thread is stopped so that the VM thread can run an operation,
+//! such as a garbage collection, that needs the heap and the threads' stacks to stay still.
+//!
+//! Like HotSpot, the interpreter polls a global word at backward branches and method returns,
+//! which a thread running Java code reaches soon after a safepoint is requested, without any
+//! locking while there isn't one. A thread that is running native code, or is blocked, is
+//! already safe, as long as it checks for a safepoint before it runs Java code again.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use parking_lot::{Condvar, Mutex};
+use tracing::debug;
+
+use crate::log;
+use crate::thread::Thread;
+
+/// What a Java thread is doing, as far as safepoints are concerned.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u8)]
+pub enum ThreadState {
+    /// The thread has been created, but hasn't started running.
+    New,
+    /// The thread is running Java code, or the VM on its behalf, so can be using references
+    /// that aren't visible to the collector.
+    InJava,
+    /// The thread is running native code that doesn't use the heap.
+    InNative,
+    /// The thread is waiting, for a monitor, another thread, or the end of a safepoint.
+    Blocked,
+    /// The thread has finished running.
+    Terminated,
+}
+
+impl ThreadState {
+    /// Whether a safepoint can go ahead while a thread is in the state.
+    pub fn is_safe(&self) -> bool {
+        *self != ThreadState::InJava
+    }
+
+    fn from_u8(state: u8) -> Self {
+        match state {
+            0 => ThreadState::New,
+            1 => ThreadState::InJava,
+            2 => ThreadState::InNative,
+            3 => ThreadState::Blocked,
+            _ => ThreadState::Terminated,
+        }
+    }
+}
+
+/// The state of a thread, which the thread changes, and the VM thread reads.
+pub struct AtomicThreadState(AtomicU8);
+
+impl AtomicThreadState {
+    pub fn new(state: ThreadState) -> Self {
+        AtomicThreadState(AtomicU8::new(state as u8))
+    }
+
+    pub fn load(&self) -> ThreadState {
+        ThreadState::from_u8(self.0.load(Ordering::SeqCst))
+    }
+
+    fn store(&self, state: ThreadState) {
+        self.0.store(state as u8, Ordering::SeqCst)
+    }
+}
+
+/// Stops the Java threads of a runtime for the VM thread.
+///
+/// A thread's state is written before it reads the poll word, and the VM thread sets the poll
+/// word before it reads the states, so either the VM thread sees that a thread is running Java
+/// code, and waits for it, or the thread sees the poll word, and blocks.
+pub struct Safepoint {
+    /// The poll word, set while a safepoint is in progress.
+    poll: AtomicBool,
+    /// Guards waiting for the threads to stop, and for the safepoint to end.
+    lock: Mutex<()>,
+    /// Signalled when a thread becomes safe while the poll word is set, and when a safepoint
+    /// ends.
+    changed: Condvar,
+}
+
+impl Default for Safepoint {
+    fn default() -> Self {
+        Safepoint::new()
+    }
+}
+
+impl Safepoint {
+    pub fn new() -> Self {
+        Safepoint {
+            poll: AtomicBool::new(false),
+            lock: Mutex::new(()),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Whether a safepoint is in progress, which threads running Java code check often, so it's
+    /// kept as cheap as possible.
+    #[inline]
+    pub fn is_requested(&self) -> bool {
+        self.poll.load(Ordering::Acquire)
+    }
+
+    /// Set the poll word, and wait until every thread is safe.
+    ///
+    /// Only the VM thread may start a safepoint, and it must not be a Java thread itself.
+    pub fn begin(&self, threads: &[Arc<Thread>]) {
+        let mut lock = self.lock.lock();
+        self.poll.store(true, Ordering::SeqCst);
+        for thread in threads {
+            while !thread.state.load().is_safe() {
+                self.changed.wait(&mut lock);
+            }
+            debug!(target: log::THREAD, thread=thread.name.as_str(), "Stopped thread");
+        }
+    }
+
+    /// Clear the poll word, and restart the threads that are blocked by the safepoint.
+    pub fn end(&self) {
+        let _lock = self.lock.lock();
+        self.poll.store(false, Ordering::SeqCst);
+        self.changed.notify_all();
+    }
+
+    /// Mark a thread as safe, waking the VM thread if it's waiting for the thread to stop.
+    pub(super) fn enter(&self, thread: &Thread, state: ThreadState) {
+        debug_assert!(state.is_safe());
+        thread.state.store(state);
+        if self.poll.load(Ordering::SeqCst) {
+            let _lock = self.lock.lock();
+            self.changed.notify_all();
+        }
+    }
+
+    /// Mark a thread as running Java code again, first waiting for the safepoint in progress,
+    /// if any, to end.
+    pub(super) fn exit(&self, thread: &Thread) {
+        thread.state.store(ThreadState::InJava);
+        if self.poll.load(Ordering::SeqCst) {
+            self.block(thread);
+        }
+    }
+
+    /// Stop a thread that is running Java code, until the safepoint in progress ends.
+    pub(super) fn block(&self, thread: &Thread) {
+        let mut lock = self.lock.lock();
+        thread.state.store(ThreadState::Blocked);
+        self.changed.notify_all();
+        while self.poll.load(Ordering::SeqCst) {
+            self.changed.wait(&mut lock);
+        }
+        // The state changes while the lock is held, so that the next safepoint waits for it.
+        thread.state.store(ThreadState::InJava);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr::null;
+    use std::sync::atomic::AtomicBool;
+    use std::thread::{spawn, yield_now};
+    use std::time::Instant;
+
+    use crate::runtime::Runtime;
+    use crate::thread::vm::{SafepointTiming, VmOperation};
+
+    use super::*;
+
+    /// Record the states of the threads at a safepoint.
+    #[derive(Default)]
+    struct States(Vec<ThreadState>);
+
+    impl VmOperation for States {
+        fn name(&self) -> &'static str {
+            "States"
+        }
+
+        fn doit(&mut self, _: &Arc<Runtime>, threads: &[Arc<Thread>], _: SafepointTiming) {
+            self.0 = threads.iter().map(|thread| thread.state()).collect();
+        }
+
+        fn epilogue(&mut self, _: Instant) {}
+    }
+
+    fn thread(runtime: &Arc<Runtime>) -> Arc<Thread> {
+        Thread::new("worker".to_string(), None, runtime.clone(), "Worker".to_string(), null(), null(), vec![])
+    }
+
+    #[test]
+    fn safepoint_waits_for_threads_in_java_to_poll() {
+        let runtime = Runtime::new();
+        let thread = thread(&runtime);
+        let running = Arc::new(AtomicBool::new(true));
+
+        let worker = {
+            let (thread, running) = (thread.clone(), running.clone());
+            spawn(move || {
+                thread.exit_safe();
+                while running.load(Ordering::Relaxed) {
+                    thread.poll_safepoint();
+                }
+                thread.enter_safe(ThreadState::Terminated);
+            })
+        };
+        while thread.state() != ThreadState::InJava {
+            yield_now();
+        }
+
+        let states = runtime.vm_thread.execute(runtime.clone(), States::default());
+        assert_eq!(states.0, vec![ThreadState::Blocked]);
+
+        running.store(false, Ordering::Relaxed);
+        worker.join().unwrap();
+        assert_eq!(thread.state(), ThreadState::Terminated);
+    }
+
+    #[test]
+    fn threads_can_start_threads_while_a_safepoint_is_stopping_them() {
+        let runtime = Runtime::new();
+        let current = thread(&runtime);
+
+        let worker = {
+            let (runtime, current) = (runtime.clone(), current.clone());
+            spawn(move || {
+                current.exit_safe();
+                while !runtime.safepoint.is_requested() {
+                    yield_now();
+                }
+                thread(&runtime);
+                current.poll_safepoint();
+                current.enter_safe(ThreadState::Terminated);
+            })
+        };
+        while current.state() != ThreadState::InJava {
+            yield_now();
+        }
+
+        let states = runtime.vm_thread.execute(runtime.clone(), States::default());
+        assert_eq!(states.0, vec![ThreadState::Blocked, ThreadState::New]);
+        worker.join().unwrap();
+    }
+
+    #[test]
+    fn threads_in_native_are_already_safe() {
+        let runtime = Runtime::new();
+        let thread = thread(&runtime);
+        assert_eq!(thread.state(), ThreadState::New);
+
+        thread.exit_safe();
+        thread.enter_safe(ThreadState::InNative);
+        let states = runtime.vm_thread.execute(runtime.clone(), States::default());
+        assert_eq!(states.0, vec![ThreadState::InNative]);
+
+        thread.exit_safe();
+        assert_eq!(thread.state(), ThreadState::InJava);
+    }
+}
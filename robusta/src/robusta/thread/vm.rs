@@ -0,0 +1,131 @@
+//! The VM thread, which runs the operations that need every Java thread stopped, such as
+//! garbage collections and thread dumps, one at a time, in the order they're requested.
+
+use std::any::Any;
+use std::sync::Arc;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::Builder;
+use std::time::Instant;
+
+use tracing::debug;
+
+use crate::log;
+use crate::runtime::Runtime;
+use crate::thread::Thread;
+use crate::thread::safepoint::ThreadState;
+
+/// When the threads were asked to stop for a safepoint, and when they had all stopped.
+#[derive(Clone, Copy, Debug)]
+pub struct SafepointTiming {
+    pub stopping: Instant,
+    pub stopped: Instant,
+}
+
+/// An operation for the VM thread to run at a safepoint.
+pub trait VmOperation: Any + Send {
+    /// The name of the operation, as HotSpot names its operations.
+    fn name(&self) -> &'static str;
+
+    /// Whether the operation still needs to run, checked before the threads are stopped.
+    fn prologue(&mut self) -> bool {
+        true
+    }
+
+    /// Run the operation, while every Java thread is stopped.
+    fn doit(&mut self, runtime: &Arc<Runtime>, threads: &[Arc<Thread>], timing: SafepointTiming);
+
+    /// Finish the operation once the threads have been restarted, at the given time.
+    fn epilogue(&mut self, restarted: Instant);
+}
+
+/// A request for the VM thread to run an operation.
+struct Request {
+    runtime: Arc<Runtime>,
+    operation: Box<dyn VmOperation>,
+    /// Signalled with the operation once it has run.
+    done: Sender<Box<dyn VmOperation>>,
+}
+
+/// Queues operations for the VM thread.
+pub struct VmThread {
+    queue: Sender<Request>,
+}
+
+impl VmThread {
+    /// Start the VM thread.
+    pub fn start() -> Self {
+        let (queue, requests) = channel();
+        Builder::new()
+            .name("VM Thread".to_string())
+            .spawn(move || run(requests))
+            .unwrap();
+        VmThread { queue }
+    }
+
+    /// Run an operation on the VM thread, waiting for it to finish, returning the operation.
+    ///
+    /// A Java thread is safe while it waits, so it must not be holding any references that
+    /// aren't visible to the collector.
+    pub fn execute<O: VmOperation>(&self, runtime: Arc<Runtime>, operation: O) -> O {
+        let our_thread = Thread::current();
+        let (done, wait) = channel();
+
+        if let Some(thread) = &our_thread {
+            thread.enter_safe(ThreadState::Blocked);
+        }
+        self.queue.send(Request { runtime, operation: Box::new(operation), done }).unwrap();
+        let operation: Box<dyn Any> = wait.recv().unwrap();
+        if let Some(thread) = &our_thread {
+            thread.exit_safe();
+        }
+
+        *operation.downcast().unwrap()
+    }
+}
+
+fn run(requests: Receiver<Request>) {
+    for Request { runtime, mut operation, done } in requests {
+        if !operation.prologue() {
+            debug!(target: log::THREAD, operation=operation.name(), "Skipping VM operation");
+        } else {
+            let restarted = at_safepoint(&runtime, |threads, timing| operation.doit(&runtime, threads, timing));
+            operation.epilogue(restarted);
+        }
+        let _ = done.send(operation);
+    }
+}
+
+/// Stop every Java thread, run a function, and restart the threads, returning when they were
+/// restarted.
+///
+/// This is only for the VM thread, and for tests that stand in for it.
+pub fn at_safepoint(runtime: &Runtime, doit: impl FnOnce(&[Arc<Thread>], SafepointTiming)) -> Instant {
+    let stopping = Instant::now();
+
+    // The threads can't be locked while they're stopping, as a thread that is starting another
+    // needs to lock them to register it, so the threads that were registered while they were
+    // stopping are stopped too, until there aren't any. New threads are safe, and the thread
+    // list is then locked, so that no more are registered until the threads are restarted.
+    // Threads unregister themselves as they terminate, so the threads that were stopped are
+    // compared by identity, not counted.
+    let mut stopped: Option<Vec<Arc<Thread>>> = None;
+    let threads = loop {
+        let threads = runtime.threads2.read().unwrap();
+        let all_stopped = stopped.as_ref().is_some_and(|stopped| {
+            threads.iter().all(|thread| stopped.iter().any(|other| Arc::ptr_eq(thread, other)))
+        });
+        if all_stopped {
+            break threads;
+        }
+        let snapshot = threads.clone();
+        drop(threads);
+        runtime.safepoint.begin(&snapshot);
+        stopped = Some(snapshot);
+    };
+    debug!(target: log::THREAD, "All threads stopped");
+    doit(&threads, SafepointTiming { stopping, stopped: Instant::now() });
+    runtime.safepoint.end();
+    debug!(target: log::THREAD, "All threads restarted");
+
+    Instant::now()
+}
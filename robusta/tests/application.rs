@@ -2,9 +2,31 @@ extern crate assert_cmd;
 
 use assert_cmd::Command;
 
+/// The options that the `gc_stress` suite, which includes these tests, runs every program with,
+/// collecting garbage often and checking the heap after each collection.
+const GC_STRESS_OPTIONS: [&str; 3] = ["-XX:+GCStress", "-XX:GCStressInterval=100", "-XX:+VerifyHeap"];
+
+/// Whether the tests are being run by the `gc_stress` suite.
+fn gc_stress() -> bool {
+    env!("CARGO_CRATE_NAME") == "gc_stress"
+}
+
+/// The `robusta` command, with the stress options when run by the `gc_stress` suite, and the
+/// options in `ROBUSTA_TEST_OPTIONS`, before any others.
+fn robusta() -> Command {
+    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    if gc_stress() {
+        robusta.args(GC_STRESS_OPTIONS);
+    }
+    if let Ok(options) = std::env::var("ROBUSTA_TEST_OPTIONS") {
+        robusta.args(options.split_whitespace());
+    }
+    robusta
+}
+
 #[test]
 fn empty_main() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -18,7 +40,7 @@ fn empty_main() {
 
 #[test]
 fn hash_code() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -36,7 +58,7 @@ fn hash_code() {
 
 #[test]
 fn wait_and_notify() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -54,7 +76,7 @@ Notified!
 
 #[test]
 fn inheritance() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -74,35 +96,38 @@ Hello, I'm an animal and my name is Bird
 
 #[test]
 fn multi_threaded_oom() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
-        .arg("MultiThreadedOutOfMemory")
+        .args(["-Xmx16m", "MultiThreadedOutOfMemory"])
         .assert()
         .success()
         .code(0)
-        .stdout("")
+        .stdout("Caught 10 OutOfMemoryErrors\n")
         .stderr("");
 }
 
 #[test]
 fn oom() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
-        .arg("OutOfMemory")
+        .args(["-Xmx8m", "OutOfMemory"])
         .assert()
         .success()
         .code(0)
-        .stdout("")
-        .stderr("");
+        .stdout("Caught java.lang.OutOfMemoryError: Java heap space\n")
+        .stderr("Exception in thread \"main\" java.lang.OutOfMemoryError: Java heap space
+	at OutOfMemory.leak(OutOfMemory.java:24)
+	at OutOfMemory.main(OutOfMemory.java:18)
+");
 }
 
 #[test]
 fn print_args() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -118,9 +143,233 @@ D
         .stderr("");
 }
 
+#[test]
+fn class_path() {
+    let mut robusta = robusta();
+
+    robusta
+        .args("-Xbootclasspath:../classes/rt.jar -cp ../classes PrintArgs A B".split_whitespace())
+        .assert()
+        .success()
+        .code(0)
+        .stdout("A
+B
+")
+        .stderr("");
+}
+
+#[test]
+fn class_path_env() {
+    let mut robusta = robusta();
+
+    robusta
+        .env("CLASSPATH", "../classes")
+        .args("-Xbootclasspath:../classes/rt.jar PrintArgs A B".split_whitespace())
+        .assert()
+        .success()
+        .code(0)
+        .stdout("A
+B
+")
+        .stderr("");
+}
+
+#[test]
+fn class_path_wildcard() {
+    let mut robusta = robusta();
+
+    robusta
+        .args(["-Xbootclasspath:../classes/rt.jar", "-cp", "../classes/*", "EmptyMain"])
+        .assert()
+        .success()
+        .code(0)
+        .stdout("")
+        .stderr("");
+}
+
+#[test]
+fn class_path_not_found() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args("-cp classes/EmptyMain.jar PrintArgs".split_whitespace())
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr("Error: Could not find or load main class PrintArgs
+");
+}
+
+#[test]
+fn jar() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args("-jar classes/JarApp.jar A B".split_whitespace())
+        .assert()
+        .success()
+        .code(0)
+        .stdout("Hello from JarLib
+A
+B
+")
+        .stderr("");
+}
+
+#[test]
+fn jar_no_main_class() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args("-jar classes/EmptyMain.jar".split_whitespace())
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr("no main manifest attribute, in classes/EmptyMain.jar
+");
+}
+
+#[test]
+fn jar_not_found() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args("-jar classes/DoesNotExist.jar".split_whitespace())
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr("Error: Unable to access jarfile classes/DoesNotExist.jar
+");
+}
+
+#[test]
+fn launcher_options() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args("-Drobusta.test=hello -ea -- LauncherOptions".split_whitespace())
+        .assert()
+        .success()
+        .code(0)
+        .stdout("hello
+Assertions enabled
+")
+        .stderr("");
+}
+
+#[test]
+fn launcher_options_disabled() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args("-ea -da:LauncherOptions LauncherOptions".split_whitespace())
+        .assert()
+        .success()
+        .code(0)
+        .stdout("null
+Assertions disabled
+")
+        .stderr("");
+}
+
+#[test]
+fn version() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .arg("-version")
+        .assert()
+        .success()
+        .code(0)
+        .stdout("")
+        .stderr("robusta version \"0.3.0\"
+Robusta Java Virtual Machine
+");
+}
+
+#[test]
+fn help() {
+    let mut robusta = robusta();
+
+    let output = robusta
+        .current_dir("../")
+        .arg("-help")
+        .assert()
+        .success()
+        .code(0)
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.starts_with("Usage: robusta [options] <main class> [args...]"));
+    assert!(output.contains("-verbose:[class|gc]"));
+}
+
+#[test]
+fn no_main_class() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .arg("-ea")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr("Usage: robusta [options] <main class> [args...]
+   or  robusta [options] -jar <jar file> [args...]
+");
+}
+
+#[test]
+fn unrecognized_option() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args("-foo HelloWorld".split_whitespace())
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr("Unrecognized option: -foo
+Error: Could not create the Java Virtual Machine.
+Error: A fatal exception has occurred. Program will exit.
+");
+}
+
+#[test]
+fn invalid_heap_size() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args("-Xmx12q HelloWorld".split_whitespace())
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr("Invalid maximum heap size: -Xmx12q
+Error: Could not create the Java Virtual Machine.
+Error: A fatal exception has occurred. Program will exit.
+");
+}
+
 #[test]
 fn print_constants() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -136,7 +385,7 @@ fn print_constants() {
 
 #[test]
 fn maths_instructions() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -180,12 +429,461 @@ fn maths_instructions() {
 5642652.0
 5642652
 5642652
+2.0
+1.5
+3.5
+-1.75
+-5.25
+-10.5
+-2.5
+1.5
+2.5
+-0.0
+Infinity
+-Infinity
+NaN
+NaN
+NaN
+2.5
+NaN
+false
+false
+false
+true
+false
+true
+3.75
+3.75
+1.0
+2.25
+3.25
+-7.25
+-29.0
+-9.666666666666666
+-2.166666666666666
+1.75
+2.166666666666666
+-0.0
+Infinity
+Infinity
+NaN
+NaN
+NaN
+2.166666666666666
+NaN
+false
+false
+false
+true
+false
+true
+1.2380952380952377
+1.2380952380952377
+").stderr("");
+}
+
+#[test]
+fn conversions() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .arg("Conversions")
+        .assert()
+        .success()
+        .code(0)
+        .stdout("9.223372E18
+-9.223372E18
+1.23456791E11
+9.223372036854776E18
+-9.223372036854776E18
+1.23456789123E11
+0
+2147483647
+-2147483648
+2147483647
+-12345
+0
+9223372036854775807
+-9223372036854775808
+9223372036854775807
+-9223372036854775808
+-12345
+0
+NaN
+Infinity
+-0.0
+-12345.677734375
+0
+2147483647
+-2147483648
+2147483647
+-2147483648
+-98765
+0
+9223372036854775807
+-9223372036854775808
+9223372036854775807
+-9223372036854775808
+98765
+0
+NaN
+Infinity
+-0.0
+Infinity
+-Infinity
+0.0
+98765.43
 ").stderr("");
 }
 
+#[test]
+fn switches() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .arg("Switches")
+        .assert()
+        .success()
+        .code(0)
+        .stdout("other
+minus one
+zero
+one
+two
+three or four
+three or four
+five
+other
+minus one thousand
+other
+other
+one million
+other
+1
+1
+2
+1
+2
+0
+red
+green
+blue
+unknown
+1
+2
+3
+4
+5
+-1
+").stderr("");
+}
+
+#[test]
+fn lambdas() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .arg("Lambdas")
+        .assert()
+        .success()
+        .code(0)
+        .stdout("Hello from a lambda
+one
+two
+three
+captured value
+13
+49
+26
+0
+7
+9
+42
+10
+").stderr("");
+}
+
+#[test]
+fn verify_errors() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .arg("VerifyErrors")
+        .assert()
+        .success()
+        .code(0)
+        .stdout("Caught java.lang.VerifyError
+Caught java.lang.VerifyError
+Caught java.lang.VerifyError
+")
+        .stderr("");
+}
+
+#[test]
+fn verify_none() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args("-Xverify:none VerifyErrors".split_whitespace())
+        .assert()
+        .success()
+        .code(0)
+        .stdout("1
+2
+3
+")
+        .stderr("");
+}
+
+#[test]
+fn load_errors() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .arg("LoadErrors")
+        .assert()
+        .success()
+        .code(0)
+        .stdout("Caught java.lang.ClassNotFoundException: LoadErrors$DoesNotExist
+Caught java.lang.NoClassDefFoundError: LoadErrors$Missing
+Caught java.lang.ClassFormatError
+Caught java.lang.ClassFormatError: Invalid constant pool index 4 in class file LoadErrors$Malformed
+Caught java.lang.UnsupportedClassVersionError
+")
+        .stderr("");
+}
+
+#[test]
+fn class_loaders() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .arg("ClassLoaders")
+        .assert()
+        .success()
+        .code(0)
+        .stdout("null
+com.foo.Bar com.foo.Bar
+false
+true
+true
+false
+true
+true
+Bar version 1
+Bar version 2
+true
+null
+Caught java.lang.LinkageError
+Caught IndexOutOfBoundsException
+Caught java.lang.ClassNotFoundException: com.foo.Baz
+")
+        .stderr("");
+}
+
+#[test]
+fn class_unloading() {
+    let mut robusta = robusta();
+
+    let output = robusta
+        .current_dir("../")
+        .args(["-Xmx64m", "-verbose:class", "ClassUnloading"])
+        .assert()
+        .success()
+        .code(0)
+        .stderr("")
+        .get_output()
+        .stdout
+        .clone();
+
+    let stdout = String::from_utf8(output).unwrap();
+    assert_eq!(stdout.matches("Bar version 2\n").count(), 10);
+    assert!(stdout.contains("[Unloading class com.foo.Bar "));
+    assert!(stdout.ends_with("Bar version 1\ntrue\n"));
+}
+
+#[test]
+fn native_callbacks() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args(["-Xmx8m", "NativeCallbacks"])
+        .assert()
+        .success()
+        .code(0)
+        .stdout("Survived 20 callbacks
+Kept 7
+")
+        .stderr("");
+}
+
+#[test]
+fn references() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args(["-Xmx8m", "References"])
+        .assert()
+        .success()
+        .code(0)
+        .stdout("Enqueued 2 references
+Reachable true
+Cleared true
+Soft true
+Cached 1 kept
+")
+        .stderr("");
+}
+
+#[test]
+fn finalization() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args(["-Xmx8m", "Finalization"])
+        .assert()
+        .success()
+        .code(0)
+        .stdout("Finalized 10
+Resurrected 3
+")
+        .stderr("");
+}
+
+#[test]
+fn memory() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args(["-Xmx12m", "Memory"])
+        .assert()
+        .success()
+        .code(0)
+        .stdout("Max 10M
+Total within max true
+Free within total true
+Freed true
+Heap max true
+Heap used within committed true
+Copy collected true in true
+MarkSweepCompact collected true in true
+Collectors [Copy, MarkSweepCompact]
+")
+        .stderr("");
+}
+
+#[test]
+fn heap_dump() {
+    let path = std::env::temp_dir().join(format!("robusta-heap-dump-{}.hprof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .args(["HeapDump", path.to_str().unwrap()])
+        .assert()
+        .success()
+        .code(0)
+        .stdout("Dumped heap
+Caught java.io.IOException
+")
+        .stderr("");
+
+    let dump = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(dump.starts_with(b"JAVA PROFILE 1.0.2\0"));
+    assert!(dump.windows(17).any(|name| name == b"HeapDump$Retained"));
+}
+
+#[test]
+fn heap_dump_on_oom() {
+    let path = std::env::temp_dir().join(format!("robusta-oom-{}.hprof", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mut robusta = robusta();
+
+    let assert = robusta
+        .current_dir("../")
+        .args(["-Xmx8m", "-XX:+HeapDumpOnOutOfMemoryError", &format!("-XX:HeapDumpPath={}", path.display()), "OutOfMemory"])
+        .assert()
+        .success()
+        .code(0)
+        .stderr("Exception in thread \"main\" java.lang.OutOfMemoryError: Java heap space
+	at OutOfMemory.leak(OutOfMemory.java:24)
+	at OutOfMemory.main(OutOfMemory.java:18)
+");
+
+    // The heap is only dumped the first time it's full.
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3, "{}", stdout);
+    assert_eq!(lines[0], format!("Dumping heap to {} ...", path.display()));
+    assert!(lines[1].starts_with("Heap dump file created ["));
+    assert_eq!(lines[2], "Caught java.lang.OutOfMemoryError: Java heap space");
+
+    let dump = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(dump.starts_with(b"JAVA PROFILE 1.0.2\0"));
+}
+
+#[test]
+fn gc_log() {
+    // The stress collections would be logged instead of the ones the test expects.
+    if gc_stress() {
+        return;
+    }
+    let path = std::env::temp_dir().join(format!("robusta-gc-{}.log", std::process::id()));
+    let mut robusta = robusta();
+
+    let assert = robusta
+        .current_dir("../")
+        .args(["-Xmx12m", "-verbose:gc", &format!("-Xlog:gc*,safepoint:file={}", path.display()), "GcLog"])
+        .assert()
+        .success()
+        .code(0)
+        .stderr("");
+
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    assert!(stdout.lines().any(|line| line.starts_with("[GC (Allocation Failure)  ")), "{}", stdout);
+    assert!(stdout.lines().any(|line| line.starts_with("[Full GC (System.gc())  ")), "{}", stdout);
+    assert!(stdout.ends_with("Done\n"), "{}", stdout);
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(log.contains("[info][gc] GC(0) Pause Young (Allocation Failure) "), "{}", log);
+    assert!(log.contains(" Pause Full (System.gc()) "), "{}", log);
+    assert!(log.contains("[info][gc,heap] GC(0) DefNew: "), "{}", log);
+    assert!(log.contains("[info][safepoint] Safepoint \"GenCollectFull\", Time since last: "), "{}", log);
+}
+
+#[test]
+fn main_class_not_found() {
+    let mut robusta = robusta();
+
+    robusta
+        .current_dir("../")
+        .arg("DoesNotExist")
+        .assert()
+        .failure()
+        .code(1)
+        .stdout("")
+        .stderr("Error: Could not find or load main class DoesNotExist
+");
+}
+
 #[test]
 fn throws_none() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -208,7 +906,7 @@ Finishing main
 
 #[test]
 fn throws_foo() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -231,7 +929,7 @@ Finally main
 
 #[test]
 fn throws_bar() {
-    let mut robusta = Command::cargo_bin("robusta").unwrap();
+    let mut robusta = robusta();
 
     robusta
         .current_dir("../")
@@ -0,0 +1,4 @@
+// The application tests, with every program run with a garbage collection every hundred
+// allocations, and the heap checked after each one, to catch objects that the collector loses
+// track of.
+include!("application.rs");